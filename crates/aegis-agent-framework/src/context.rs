@@ -29,6 +29,9 @@ pub struct AgentContext {
     
     /// Timer for scheduling delayed tasks
     pub timer: Arc<dyn aegis_core::platform::concurrency::AsyncTimer>,
+    
+    /// Clock used for all timestamps produced by the agent
+    pub clock: Arc<dyn aegis_core::clock::Clock>,
 }
 
 impl AgentContext {
@@ -44,7 +47,7 @@ impl AgentContext {
     ///
     /// # Returns
     ///
-    /// A new `AgentContext` instance
+    /// A new `AgentContext` instance using the system clock
     pub fn new(
         agent_id: AgentID,
         config: Arc<aegis_core::config::AegisConfig>,
@@ -58,11 +61,25 @@ impl AgentContext {
             comms_client,
             spawner,
             timer,
+            clock: aegis_core::clock::system_clock(),
         }
     }
     
+    /// Replace the clock used by this context
+    ///
+    /// Tests can pass a `TestClock` here to control time deterministically.
+    pub fn with_clock(mut self, clock: Arc<dyn aegis_core::clock::Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
     /// Get the agent ID
     pub fn agent_id(&self) -> &str {
         &self.agent_id
     }
+    
+    /// Get the clock used by this agent
+    pub fn clock(&self) -> &dyn aegis_core::clock::Clock {
+        &*self.clock
+    }
 } 
\ No newline at end of file
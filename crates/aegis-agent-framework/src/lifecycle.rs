@@ -296,6 +296,7 @@ mod tests {
             comms_client: Arc::new(MockCommsClient),
             spawner: Arc::new(MockSpawner),
             timer: Arc::new(MockTimer),
+            clock: Arc::new(aegis_core::clock::TestClock::default()),
         }
     }
     
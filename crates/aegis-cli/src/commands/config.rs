@@ -0,0 +1,150 @@
+//! `aegis config` subcommands
+
+use clap::Subcommand;
+use std::path::Path;
+
+use aegis_core::config::{load_config, migrate_config_file, save_config, AegisConfig};
+use aegis_core::error::{AegisError, AegisResult};
+
+use super::{effective_config, print_json};
+
+/// Configuration commands
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Write a default configuration file
+    Init {
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Check that the configuration file can be loaded and is valid
+    Validate,
+
+    /// Print the configuration
+    Show {
+        /// Include defaults and AEGIS_* environment overrides
+        #[arg(long)]
+        effective: bool,
+    },
+
+    /// Upgrade the configuration file to the current config_version
+    Migrate {
+        /// Print the changes without writing the file
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// Run a configuration command
+pub fn run(path: &Path, cmd: ConfigCommand) -> AegisResult<()> {
+    match cmd {
+        ConfigCommand::Init { force } => init(path, force),
+        ConfigCommand::Validate => validate(path),
+        ConfigCommand::Show { effective } => show(path, effective),
+        ConfigCommand::Migrate { dry_run } => migrate(path, dry_run),
+    }
+}
+
+/// Write a default configuration file
+fn init(path: &Path, force: bool) -> AegisResult<()> {
+    if path.exists() && !force {
+        return Err(AegisError::Config(format!(
+            "{} already exists; use --force to overwrite it",
+            path.display()
+        )));
+    }
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    save_config(&AegisConfig::default(), &path.to_path_buf())?;
+    println!("Wrote default configuration to {}", path.display());
+
+    Ok(())
+}
+
+/// Validate a configuration file
+fn validate(path: &Path) -> AegisResult<()> {
+    let config = load_config(&path.to_path_buf())?;
+    config.validate()?;
+
+    println!("{} is valid", path.display());
+    Ok(())
+}
+
+/// Print a configuration file
+fn show(path: &Path, effective: bool) -> AegisResult<()> {
+    let config = if effective {
+        effective_config(path)?
+    } else {
+        load_config(&path.to_path_buf())?
+    };
+
+    print_json(&config)
+}
+
+/// Migrate a configuration file to the current version
+fn migrate(path: &Path, dry_run: bool) -> AegisResult<()> {
+    let report = migrate_config_file(path, dry_run)?;
+
+    if report.is_noop() {
+        println!("{} is already at config_version {}", path.display(), report.to_version);
+        return Ok(());
+    }
+
+    for step in &report.applied {
+        println!("{}", step);
+    }
+    print!("{}", report.diff());
+
+    if dry_run {
+        println!("Dry run: {} was not modified", path.display());
+    } else {
+        println!(
+            "Migrated {} to config_version {} (backup saved as {}.bak)",
+            path.display(),
+            report.to_version,
+            path.display()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_and_validate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("conf").join("aegis.json");
+
+        init(&path, false).unwrap();
+        assert!(path.exists());
+        assert!(validate(&path).is_ok());
+
+        // Refuses to overwrite without --force
+        assert!(init(&path, false).is_err());
+        assert!(init(&path, true).is_ok());
+    }
+
+    #[test]
+    fn test_migrate_legacy_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aegis.json");
+
+        let mut value = serde_json::to_value(AegisConfig::default()).unwrap();
+        value.as_object_mut().unwrap().remove("config_version");
+        std::fs::write(&path, value.to_string()).unwrap();
+
+        migrate(&path, true).unwrap();
+        assert!(!dir.path().join("aegis.json.bak").exists());
+
+        migrate(&path, false).unwrap();
+        assert!(dir.path().join("aegis.json.bak").exists());
+        assert!(validate(&path).is_ok());
+    }
+}
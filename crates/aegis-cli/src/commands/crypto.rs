@@ -0,0 +1,197 @@
+//! `aegis hash`, `aegis encrypt` and `aegis decrypt` commands
+
+use clap::Args;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use aegis_core::error::{AegisError, AegisResult};
+use aegis_core::keystore::{KeyAlgorithm, KeyStore};
+use aegis_core::utils::{decrypt_aes_gcm, encrypt_aes_gcm, format_hex, parse_hex, random_bytes, sha256_hash};
+
+use super::effective_config;
+
+/// Length of the AES-GCM nonce in bytes
+const NONCE_LEN: usize = 12;
+
+/// Length of the key version prefix in bytes
+const VERSION_LEN: usize = 4;
+
+/// Arguments for `aegis hash`
+#[derive(Debug, Args)]
+pub struct HashArgs {
+    /// File to hash; reads standard input when omitted
+    pub input: Option<PathBuf>,
+}
+
+/// Arguments for `aegis encrypt`
+#[derive(Debug, Args)]
+pub struct EncryptArgs {
+    /// Name of an aes256gcm key in the key store
+    #[arg(short, long)]
+    pub key: String,
+
+    /// File to encrypt; reads standard input when omitted
+    #[arg(short, long)]
+    pub input: Option<PathBuf>,
+
+    /// File to write the hex-encoded ciphertext to; writes standard output when omitted
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Arguments for `aegis decrypt`
+#[derive(Debug, Args)]
+pub struct DecryptArgs {
+    /// Name of the aes256gcm key used for encryption
+    #[arg(short, long)]
+    pub key: String,
+
+    /// File containing hex-encoded ciphertext; reads standard input when omitted
+    #[arg(short, long)]
+    pub input: Option<PathBuf>,
+
+    /// File to write the plaintext to; writes standard output when omitted
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Print the SHA-256 hash of the input
+pub fn hash(args: HashArgs) -> AegisResult<()> {
+    let data = read_input(args.input.as_deref())?;
+    let digest = format_hex(&sha256_hash(&data));
+
+    match &args.input {
+        Some(path) => println!("{}  {}", digest, path.display()),
+        None => println!("{}", digest),
+    }
+
+    Ok(())
+}
+
+/// Encrypt the input with the active version of a key
+pub fn encrypt(config_path: &Path, args: EncryptArgs) -> AegisResult<()> {
+    let store = open_store(config_path)?;
+    let plaintext = read_input(args.input.as_deref())?;
+
+    let sealed = seal(&store, &args.key, &plaintext)?;
+
+    let mut encoded = format_hex(&sealed);
+    encoded.push('\n');
+    write_output(args.output.as_deref(), encoded.as_bytes())
+}
+
+/// Decrypt input produced by `encrypt`
+pub fn decrypt(config_path: &Path, args: DecryptArgs) -> AegisResult<()> {
+    let store = open_store(config_path)?;
+    let input = read_input(args.input.as_deref())?;
+
+    let encoded = String::from_utf8(input)
+        .map_err(|_| AegisError::Security("Ciphertext must be hex encoded".to_string()))?;
+    let sealed = parse_hex(encoded.trim())?;
+
+    let plaintext = open(&store, &args.key, &sealed)?;
+    write_output(args.output.as_deref(), &plaintext)
+}
+
+/// Encrypt data, producing `key version || nonce || ciphertext`
+///
+/// The key version is recorded so that data can still be decrypted after
+/// the key has been rotated.
+fn seal(store: &KeyStore, key_name: &str, plaintext: &[u8]) -> AegisResult<Vec<u8>> {
+    let key = store.active_key(key_name)?;
+    require_aes_key(key.algorithm, key_name)?;
+
+    let nonce = random_bytes(NONCE_LEN);
+    let ciphertext = encrypt_aes_gcm(plaintext, &key.bytes, &nonce)?;
+
+    let mut sealed = Vec::with_capacity(VERSION_LEN + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(&key.version.to_be_bytes());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+
+    Ok(sealed)
+}
+
+/// Decrypt data produced by `seal`
+fn open(store: &KeyStore, key_name: &str, sealed: &[u8]) -> AegisResult<Vec<u8>> {
+    if sealed.len() < VERSION_LEN + NONCE_LEN {
+        return Err(AegisError::Security("Ciphertext is too short".to_string()));
+    }
+
+    let (version, rest) = sealed.split_at(VERSION_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let version = u32::from_be_bytes(version.try_into().unwrap_or_default());
+
+    let key = store.key_version(key_name, version)?;
+    require_aes_key(key.algorithm, key_name)?;
+
+    decrypt_aes_gcm(ciphertext, &key.bytes, nonce)
+}
+
+/// Ensure a key can be used for AES-GCM
+fn require_aes_key(algorithm: KeyAlgorithm, key_name: &str) -> AegisResult<()> {
+    if algorithm != KeyAlgorithm::Aes256Gcm {
+        return Err(AegisError::Security(format!(
+            "Key {} is an {} key; encryption requires an aes256gcm key",
+            key_name, algorithm
+        )));
+    }
+
+    Ok(())
+}
+
+/// Open the key store of the effective configuration
+fn open_store(config_path: &Path) -> AegisResult<KeyStore> {
+    KeyStore::from_config(&effective_config(config_path)?)
+}
+
+/// Read a file, or standard input when no path is given
+fn read_input(path: Option<&Path>) -> AegisResult<Vec<u8>> {
+    match path {
+        Some(path) => Ok(std::fs::read(path)?),
+        None => {
+            let mut data = Vec::new();
+            std::io::stdin().read_to_end(&mut data)?;
+            Ok(data)
+        }
+    }
+}
+
+/// Write to a file, or standard output when no path is given
+fn write_output(path: Option<&Path>, data: &[u8]) -> AegisResult<()> {
+    match path {
+        Some(path) => Ok(std::fs::write(path, data)?),
+        None => {
+            let mut stdout = std::io::stdout();
+            stdout.write_all(data)?;
+            stdout.flush()?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_across_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyStore::open(dir.path()).unwrap();
+        store.generate("data", KeyAlgorithm::Aes256Gcm).unwrap();
+
+        let sealed = seal(&store, "data", b"secret").unwrap();
+        store.rotate("data").unwrap();
+
+        assert_eq!(open(&store, "data", &sealed).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_rejects_signing_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyStore::open(dir.path()).unwrap();
+        store.generate("identity", KeyAlgorithm::Ed25519).unwrap();
+
+        assert!(seal(&store, "identity", b"secret").is_err());
+    }
+}
@@ -0,0 +1,291 @@
+//! `aegis doctor` command
+
+use std::fmt;
+use std::path::Path;
+
+use aegis_core::config::{AegisConfig, LogOutput};
+use aegis_core::error::{AegisError, AegisResult};
+use aegis_core::identity::IDENTITY_KEY_NAME;
+use aegis_core::keystore::keys_dir;
+
+use super::effective_config;
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CheckStatus {
+    /// Check passed
+    Ok,
+    /// Something looks wrong but the node can still run
+    Warn,
+    /// The node cannot run correctly
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Use `pad` so that width specifiers in the report are honoured
+        f.pad(match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Fail => "FAIL",
+        })
+    }
+}
+
+/// Result of a single check
+#[derive(Debug)]
+struct Check {
+    /// What was checked
+    name: &'static str,
+    /// Outcome
+    status: CheckStatus,
+    /// Human-readable explanation
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Run all checks and print a report
+pub fn run(config_path: &Path) -> AegisResult<()> {
+    let mut checks = vec![check_platform()];
+
+    let config = match load_for_doctor(config_path) {
+        Ok((config, check)) => {
+            checks.push(check);
+            config
+        }
+        Err(check) => {
+            checks.push(check);
+            print_report(&checks);
+            return Err(AegisError::Config("configuration could not be loaded".to_string()));
+        }
+    };
+
+    checks.push(check_base_dir(&config));
+    checks.extend(check_keys(&config));
+    checks.extend(check_tls(&config));
+    checks.extend(check_log_output(&config.logging.output));
+
+    print_report(&checks);
+
+    let failures = checks.iter().filter(|c| c.status == CheckStatus::Fail).count();
+    if failures > 0 {
+        return Err(AegisError::Generic(format!("doctor found {} problem(s)", failures)));
+    }
+
+    Ok(())
+}
+
+/// Print the check results
+fn print_report(checks: &[Check]) {
+    for check in checks {
+        println!("[{:>4}] {:<10} {}", check.status, check.name, check.detail);
+    }
+}
+
+/// Report the platform the tool is running on
+fn check_platform() -> Check {
+    let os = std::env::consts::OS;
+    let arch = std::env::consts::ARCH;
+    let supported = matches!(os, "linux" | "macos" | "windows");
+
+    Check::new(
+        "platform",
+        if supported { CheckStatus::Ok } else { CheckStatus::Warn },
+        format!(
+            "{} {} ({}){}",
+            os,
+            arch,
+            std::env::consts::FAMILY,
+            if supported { "" } else { " is not a tested platform" }
+        ),
+    )
+}
+
+/// Load and validate the configuration
+fn load_for_doctor(path: &Path) -> Result<(AegisConfig, Check), Check> {
+    let config = effective_config(path)
+        .map_err(|e| Check::new("config", CheckStatus::Fail, format!("{}: {}", path.display(), e)))?;
+
+    if let Err(e) = config.validate() {
+        return Err(Check::new("config", CheckStatus::Fail, e.to_string()));
+    }
+
+    let check = if path.exists() {
+        Check::new("config", CheckStatus::Ok, format!("{} is valid", path.display()))
+    } else {
+        Check::new(
+            "config",
+            CheckStatus::Warn,
+            format!("{} not found, using defaults", path.display()),
+        )
+    };
+
+    Ok((config, check))
+}
+
+/// Check that the base directory exists and is writable
+fn check_base_dir(config: &AegisConfig) -> Check {
+    let dir = &config.base_dir;
+
+    if !dir.exists() {
+        return Check::new(
+            "base_dir",
+            CheckStatus::Warn,
+            format!("{} does not exist yet", dir.display()),
+        );
+    }
+
+    if !dir.is_dir() {
+        return Check::new(
+            "base_dir",
+            CheckStatus::Fail,
+            format!("{} is not a directory", dir.display()),
+        );
+    }
+
+    let probe = dir.join(format!(".aegis-doctor-{}", std::process::id()));
+    match std::fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            Check::new("base_dir", CheckStatus::Ok, format!("{} is writable", dir.display()))
+        }
+        Err(e) => Check::new(
+            "base_dir",
+            CheckStatus::Fail,
+            format!("{} is not writable: {}", dir.display(), e),
+        ),
+    }
+}
+
+/// Check the key store directory and node identity
+fn check_keys(config: &AegisConfig) -> Vec<Check> {
+    let dir = keys_dir(config);
+
+    if !dir.exists() {
+        return vec![Check::new(
+            "keys",
+            CheckStatus::Warn,
+            format!("{} does not exist; run `aegis keys generate`", dir.display()),
+        )];
+    }
+
+    let mut checks = vec![check_key_permissions(&dir)];
+
+    let identity_path = dir.join(format!("{}.key.json", IDENTITY_KEY_NAME));
+    checks.push(if identity_path.exists() {
+        Check::new("identity", CheckStatus::Ok, "node identity key present")
+    } else {
+        Check::new(
+            "identity",
+            CheckStatus::Warn,
+            format!("no node identity; run `aegis keys generate {} --algorithm ed25519`", IDENTITY_KEY_NAME),
+        )
+    });
+
+    checks
+}
+
+/// Check that the key directory is not accessible to other users
+#[cfg(unix)]
+fn check_key_permissions(dir: &Path) -> Check {
+    use std::os::unix::fs::PermissionsExt;
+
+    match std::fs::metadata(dir) {
+        Ok(meta) if meta.permissions().mode() & 0o077 != 0 => Check::new(
+            "keys",
+            CheckStatus::Warn,
+            format!(
+                "{} is accessible to other users (mode {:o})",
+                dir.display(),
+                meta.permissions().mode() & 0o777
+            ),
+        ),
+        Ok(_) => Check::new("keys", CheckStatus::Ok, format!("{} is private", dir.display())),
+        Err(e) => Check::new("keys", CheckStatus::Fail, format!("{}: {}", dir.display(), e)),
+    }
+}
+
+/// Check that the key directory is not accessible to other users
+#[cfg(not(unix))]
+fn check_key_permissions(dir: &Path) -> Check {
+    Check::new("keys", CheckStatus::Ok, format!("{} exists", dir.display()))
+}
+
+/// Check that configured TLS files are readable
+fn check_tls(config: &AegisConfig) -> Vec<Check> {
+    let tls = match &config.network.tls {
+        Some(tls) => tls,
+        None => {
+            return vec![Check::new(
+                "tls",
+                CheckStatus::Warn,
+                "TLS is not configured; traffic is sent in cleartext",
+            )]
+        }
+    };
+
+    [("tls cert", &tls.cert_path), ("tls key", &tls.key_path)]
+        .into_iter()
+        .chain(tls.ca_path.iter().map(|path| ("tls ca", path)))
+        .chain(tls.crl_path.iter().map(|path| ("tls crl", path)))
+        .map(|(name, path)| match std::fs::File::open(path) {
+            Ok(_) => Check::new(name, CheckStatus::Ok, format!("{} is readable", path.display())),
+            Err(e) => Check::new(name, CheckStatus::Fail, format!("{}: {}", path.display(), e)),
+        })
+        .collect()
+}
+
+/// Check that log files can be created
+fn check_log_output(output: &LogOutput) -> Vec<Check> {
+    match output {
+        LogOutput::File { path } => {
+            let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            vec![if parent.is_dir() {
+                Check::new("log file", CheckStatus::Ok, format!("{}", path.display()))
+            } else {
+                Check::new(
+                    "log file",
+                    CheckStatus::Fail,
+                    format!("directory {} does not exist", parent.display()),
+                )
+            }]
+        }
+        LogOutput::Multiple { outputs } => outputs.iter().flat_map(check_log_output).collect(),
+        LogOutput::Stdout | LogOutput::Syslog => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_dir_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = AegisConfig::default();
+
+        config.base_dir = dir.path().to_path_buf();
+        assert_eq!(check_base_dir(&config).status, CheckStatus::Ok);
+
+        config.base_dir = dir.path().join("missing");
+        assert_eq!(check_base_dir(&config).status, CheckStatus::Warn);
+    }
+
+    #[test]
+    fn test_missing_log_directory_fails() {
+        let output = LogOutput::File {
+            path: "/nonexistent-aegis-dir/aegis.log".into(),
+        };
+        let checks = check_log_output(&output);
+        assert_eq!(checks[0].status, CheckStatus::Fail);
+    }
+}
@@ -0,0 +1,52 @@
+//! `aegis identity` subcommands
+
+use clap::Subcommand;
+use std::path::Path;
+
+use aegis_core::error::{AegisError, AegisResult};
+use aegis_core::identity::{NodeIdentity, IDENTITY_KEY_NAME};
+use aegis_core::keystore::KeyStore;
+
+use super::{effective_config, print_json};
+
+/// Node identity commands
+#[derive(Debug, Subcommand)]
+pub enum IdentityCommand {
+    /// Show the node identity
+    Show {
+        /// Print the identity as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Run a node identity command
+pub fn run(config_path: &Path, cmd: IdentityCommand) -> AegisResult<()> {
+    let config = effective_config(config_path)?;
+    let store = KeyStore::from_config(&config)?;
+
+    match cmd {
+        IdentityCommand::Show { json } => {
+            let identity = NodeIdentity::load(&store, &config.instance_id).map_err(|e| match e {
+                AegisError::NotFound(_) => AegisError::NotFound(format!(
+                    "no node identity in {}; create one with `aegis keys generate {} --algorithm ed25519`",
+                    store.root().display(),
+                    IDENTITY_KEY_NAME
+                )),
+                other => other,
+            })?;
+            let info = identity.info();
+
+            if json {
+                return print_json(&info);
+            }
+
+            println!("node id:     {}", info.node_id);
+            println!("instance id: {}", info.instance_id);
+            println!("public key:  {}", info.public_key);
+            println!("fingerprint: {}", info.fingerprint);
+            println!("key version: {}", info.key_version);
+            Ok(())
+        }
+    }
+}
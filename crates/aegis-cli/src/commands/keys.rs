@@ -0,0 +1,86 @@
+//! `aegis keys` subcommands
+
+use clap::Subcommand;
+use std::path::Path;
+
+use aegis_core::error::AegisResult;
+use aegis_core::keystore::{KeyAlgorithm, KeyInfo, KeyStore};
+
+use super::{effective_config, print_json};
+
+/// Key management commands
+#[derive(Debug, Subcommand)]
+pub enum KeysCommand {
+    /// Generate a new key
+    Generate {
+        /// Name of the key
+        name: String,
+
+        /// Key algorithm (aes256gcm or ed25519)
+        #[arg(long, default_value = "aes256gcm")]
+        algorithm: KeyAlgorithm,
+    },
+
+    /// Rotate a key, keeping previous versions for decryption
+    Rotate {
+        /// Name of the key
+        name: String,
+    },
+
+    /// List the keys in the key store
+    List {
+        /// Print the key list as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Run a key management command
+pub fn run(config_path: &Path, cmd: KeysCommand) -> AegisResult<()> {
+    let config = effective_config(config_path)?;
+    let store = KeyStore::from_config(&config)?;
+
+    match cmd {
+        KeysCommand::Generate { name, algorithm } => {
+            let info = store.generate(&name, algorithm)?;
+            println!("Generated key {} ({}), fingerprint {}", info.name, info.algorithm, info.fingerprint);
+            Ok(())
+        }
+        KeysCommand::Rotate { name } => {
+            let info = store.rotate(&name)?;
+            println!("Rotated key {} to version {}, fingerprint {}", info.name, info.version, info.fingerprint);
+            Ok(())
+        }
+        KeysCommand::List { json } => {
+            let keys = store.list()?;
+            if json {
+                print_json(&keys)
+            } else {
+                print_key_table(&keys);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Print keys as an aligned table
+fn print_key_table(keys: &[KeyInfo]) {
+    if keys.is_empty() {
+        println!("No keys found");
+        return;
+    }
+
+    let name_width = keys.iter().map(|k| k.name.len()).max().unwrap_or(0).max(4);
+
+    println!("{:<name_width$}  {:<9}  {:>7}  {:<32}  CREATED", "NAME", "ALGORITHM", "VERSION", "FINGERPRINT");
+    for key in keys {
+        println!(
+            "{:<name_width$}  {:<9}  {:>7}  {:<32}  {}",
+            key.name,
+            key.algorithm.to_string(),
+            key.version,
+            key.fingerprint,
+            key.created_at
+        );
+    }
+}
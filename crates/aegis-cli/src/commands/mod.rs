@@ -0,0 +1,35 @@
+//! Implementations of the `aegis` subcommands
+
+pub mod config;
+pub mod crypto;
+pub mod doctor;
+pub mod identity;
+pub mod keys;
+pub mod schema;
+pub mod version;
+
+use std::path::Path;
+
+use aegis_core::config::{load_config, AegisConfig};
+use aegis_core::error::AegisResult;
+
+/// Load the configuration that a node started with `path` would use
+///
+/// The file is optional: when it does not exist the defaults are used. In
+/// both cases `AEGIS_*` environment overrides are applied on top.
+pub fn effective_config(path: &Path) -> AegisResult<AegisConfig> {
+    let mut config = if path.exists() {
+        load_config(&path.to_path_buf())?
+    } else {
+        AegisConfig::default()
+    };
+
+    config.apply_env_overrides()?;
+    Ok(config)
+}
+
+/// Print a value as pretty JSON
+pub fn print_json<T: serde::Serialize>(value: &T) -> AegisResult<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
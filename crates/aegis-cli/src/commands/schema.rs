@@ -0,0 +1,144 @@
+//! `aegis schema` subcommands
+
+use clap::Subcommand;
+use std::path::PathBuf;
+
+use aegis_core::error::{AegisError, AegisResult};
+
+use super::print_json;
+
+/// A schema that can be printed or exported
+struct SchemaEntry {
+    /// Name used on the command line
+    name: &'static str,
+    /// Short description
+    description: &'static str,
+    /// Schema generator
+    generate: fn() -> serde_json::Value,
+}
+
+/// Schemas defined by aegis-core
+const CORE_SCHEMAS: &[SchemaEntry] = &[SchemaEntry {
+    name: "config",
+    description: "AegisConfig configuration file",
+    generate: aegis_core::schema::config_schema,
+}];
+
+/// Schemas defined by the agents, with the `agent-schemas` feature
+#[cfg(feature = "agent-schemas")]
+const AGENT_SCHEMAS: &[SchemaEntry] = &[
+    SchemaEntry {
+        name: "policy",
+        description: "Camplit policy",
+        generate: agent_camplit::policy_schema,
+    },
+    SchemaEntry {
+        name: "failure-details",
+        description: "Failure report handled by the recovery engine",
+        generate: agent_camplit::failure_details_schema,
+    },
+    SchemaEntry {
+        name: "camplit-message",
+        description: "Request sent to the Camplit agent",
+        generate: agent_camplit::camplit_message_schema,
+    },
+    SchemaEntry {
+        name: "camplit-response",
+        description: "Response sent by the Camplit agent",
+        generate: agent_camplit::camplit_response_schema,
+    },
+    SchemaEntry {
+        name: "reviezer-message",
+        description: "Request sent to the Reviezer agent",
+        generate: agent_reviezer::reviezer_message_schema,
+    },
+    SchemaEntry {
+        name: "reviezer-response",
+        description: "Response sent by the Reviezer agent",
+        generate: agent_reviezer::reviezer_response_schema,
+    },
+    SchemaEntry {
+        name: "audit-report",
+        description: "Audit report produced by the Reviezer agent",
+        generate: agent_reviezer::audit_report_schema,
+    },
+];
+
+#[cfg(not(feature = "agent-schemas"))]
+const AGENT_SCHEMAS: &[SchemaEntry] = &[];
+
+/// All schemas known to the CLI
+fn schemas() -> impl Iterator<Item = &'static SchemaEntry> {
+    CORE_SCHEMAS.iter().chain(AGENT_SCHEMAS)
+}
+
+/// JSON Schema commands
+#[derive(Debug, Subcommand)]
+pub enum SchemaCommand {
+    /// List the available schemas
+    List,
+
+    /// Print a schema
+    Show {
+        /// Schema name, as printed by `aegis schema list`
+        name: String,
+    },
+
+    /// Write every schema to `<dir>/<name>.schema.json`
+    Export {
+        /// Output directory
+        #[arg(long, default_value = "schemas")]
+        dir: PathBuf,
+    },
+}
+
+/// Run a schema command
+pub fn run(cmd: SchemaCommand) -> AegisResult<()> {
+    match cmd {
+        SchemaCommand::List => {
+            for entry in schemas() {
+                println!("{:<18} {}", entry.name, entry.description);
+            }
+            Ok(())
+        }
+        SchemaCommand::Show { name } => print_json(&(find(&name)?.generate)()),
+        SchemaCommand::Export { dir } => {
+            std::fs::create_dir_all(&dir)?;
+            for entry in schemas() {
+                let path = dir.join(format!("{}.schema.json", entry.name));
+                std::fs::write(&path, serde_json::to_string_pretty(&(entry.generate)())?)?;
+                println!("Wrote {}", path.display());
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Look up a schema by name
+fn find(name: &str) -> AegisResult<&'static SchemaEntry> {
+    schemas().find(|e| e.name == name).ok_or_else(|| {
+        AegisError::NotFound(format!(
+            "unknown schema {:?}; run `aegis schema list` to see the available schemas",
+            name
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_schemas_generate() {
+        for entry in schemas() {
+            let schema = (entry.generate)();
+            assert!(schema["title"].is_string(), "{} has no title", entry.name);
+        }
+    }
+
+    #[test]
+    fn test_unknown_schema() {
+        assert!(find("config").is_ok());
+        assert!(matches!(find("nope"), Err(AegisError::NotFound(_))));
+    }
+}
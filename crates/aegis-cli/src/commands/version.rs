@@ -0,0 +1,35 @@
+//! `aegis version` command
+
+use clap::Args;
+
+use aegis_core::error::AegisResult;
+use aegis_core::version::version_info;
+
+use super::print_json;
+
+/// Arguments for `aegis version`
+#[derive(Debug, Args)]
+pub struct VersionArgs {
+    /// Print the version information as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Print version information
+pub fn run(args: VersionArgs) -> AegisResult<()> {
+    let info = version_info();
+
+    if args.json {
+        return print_json(&info);
+    }
+
+    println!("{} {}", aegis_core::FRAMEWORK_ID, info.version);
+    println!("major:       {}", info.major);
+    println!("minor:       {}", info.minor);
+    println!("patch:       {}", info.patch);
+    if info.is_pre_release() {
+        println!("pre-release: {}", info.pre_release);
+    }
+
+    Ok(())
+}
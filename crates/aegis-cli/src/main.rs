@@ -0,0 +1,114 @@
+//! Aegis command-line tool
+//!
+//! The `aegis` binary lets operators manage configuration files, keys and the
+//! node identity, run the crypto utilities and diagnose a node without
+//! writing any Rust.
+
+#![forbid(unsafe_code)]
+
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use aegis_core::error::AegisResult;
+
+mod commands;
+
+/// Default configuration file path
+const DEFAULT_CONFIG_PATH: &str = "aegis.json";
+
+/// Aegis command-line tool
+#[derive(Debug, Parser)]
+#[command(name = "aegis", version, about = "Operate and diagnose Aegis nodes")]
+pub struct Cli {
+    /// Path to the configuration file
+    #[arg(short, long, global = true, default_value = DEFAULT_CONFIG_PATH)]
+    pub config: PathBuf,
+
+    /// Command to run
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// Top-level commands
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Create, validate and inspect configuration files
+    #[command(subcommand)]
+    Config(commands::config::ConfigCommand),
+
+    /// Manage keys in the node key store
+    #[command(subcommand)]
+    Keys(commands::keys::KeysCommand),
+
+    /// Inspect the node identity
+    #[command(subcommand)]
+    Identity(commands::identity::IdentityCommand),
+
+    /// Print JSON Schemas for configuration files and agent messages
+    #[command(subcommand)]
+    Schema(commands::schema::SchemaCommand),
+
+    /// Print version information
+    Version(commands::version::VersionArgs),
+
+    /// Compute the SHA-256 hash of a file or standard input
+    Hash(commands::crypto::HashArgs),
+
+    /// Encrypt a file or standard input with a key from the key store
+    Encrypt(commands::crypto::EncryptArgs),
+
+    /// Decrypt data produced by `aegis encrypt`
+    Decrypt(commands::crypto::DecryptArgs),
+
+    /// Check the platform, configuration and paths used by this node
+    Doctor,
+}
+
+/// Run a parsed command line
+fn run(cli: Cli) -> AegisResult<()> {
+    match cli.command {
+        Command::Config(cmd) => commands::config::run(&cli.config, cmd),
+        Command::Keys(cmd) => commands::keys::run(&cli.config, cmd),
+        Command::Identity(cmd) => commands::identity::run(&cli.config, cmd),
+        Command::Schema(cmd) => commands::schema::run(cmd),
+        Command::Version(args) => commands::version::run(args),
+        Command::Hash(args) => commands::crypto::hash(args),
+        Command::Encrypt(args) => commands::crypto::encrypt(&cli.config, args),
+        Command::Decrypt(args) => commands::crypto::decrypt(&cli.config, args),
+        Command::Doctor => commands::doctor::run(&cli.config),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_config_show_effective() {
+        let cli = Cli::parse_from(["aegis", "--config", "node.json", "config", "show", "--effective"]);
+        assert_eq!(cli.config, PathBuf::from("node.json"));
+        assert!(matches!(
+            cli.command,
+            Command::Config(commands::config::ConfigCommand::Show { effective: true })
+        ));
+    }
+}
@@ -0,0 +1,598 @@
+//! Certificate authority issuing node certificates
+
+use aegis_core::clock::{system_clock, Clock};
+use aegis_core::config::AegisConfig;
+use aegis_core::error::{AegisError, AegisResult};
+use aegis_core::identity::node_id_for;
+use aegis_core::keystore::{write_private_file, KeyAlgorithm, KeyStore};
+use aegis_core::utils::{format_hex, random_bytes};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rcgen::{
+    BasicConstraints, CertificateParams, CertificateRevocationListParams,
+    CertificateSigningRequestParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, IsCa,
+    Issuer, KeyIdMethod, KeyPair, KeyUsagePurpose, PublicKeyData, RevocationReason,
+    RevokedCertParams, SanType, SerialNumber,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::enrollment::{CertificateIssuer, EnrollmentRequest, RenewalRequest};
+use super::token::{self, BootstrapToken, TokenRecord};
+
+/// Name of the key store entry holding the CA signing key
+pub const CA_KEY_NAME: &str = "cluster-ca";
+
+/// File holding the CA certificate
+const CA_CERT_FILE: &str = "ca.pem";
+
+/// File holding the current revocation list
+const CRL_FILE: &str = "crl.pem";
+
+/// File holding issued certificates, tokens and the CRL number
+const STATE_FILE: &str = "state.json";
+
+/// Allowance for clock skew between nodes when setting `not_before`
+const CLOCK_SKEW: Duration = Duration::from_secs(5 * 60);
+
+/// PKCS#8 v1 prefix for an Ed25519 private key, followed by the 32-byte seed
+const ED25519_PKCS8_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+/// Configuration of the cluster CA
+#[derive(Debug, Clone)]
+pub struct CaConfig {
+    /// Directory holding the CA certificate, CRL and state
+    pub dir: PathBuf,
+    /// Trust domain used in node SPIFFE IDs
+    pub trust_domain: String,
+    /// Lifetime of issued node certificates
+    pub cert_ttl: Duration,
+    /// Lifetime of bootstrap tokens
+    pub token_ttl: Duration,
+    /// Lifetime of the CA certificate
+    pub ca_validity: Duration,
+    /// Time until a published CRL should be replaced
+    pub crl_validity: Duration,
+}
+
+impl CaConfig {
+    /// Create a configuration with default lifetimes
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            trust_domain: "aegis.local".to_string(),
+            cert_ttl: Duration::from_secs(24 * 60 * 60),
+            token_ttl: Duration::from_secs(60 * 60),
+            ca_validity: Duration::from_secs(10 * 365 * 24 * 60 * 60),
+            crl_validity: Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+
+    /// Configuration using `<base_dir>/ca`
+    pub fn from_config(config: &AegisConfig) -> Self {
+        Self::new(config.base_dir.join("ca"))
+    }
+
+    /// Set the trust domain
+    pub fn with_trust_domain(mut self, trust_domain: impl Into<String>) -> Self {
+        self.trust_domain = trust_domain.into();
+        self
+    }
+
+    /// Set the lifetime of issued node certificates
+    pub fn with_cert_ttl(mut self, ttl: Duration) -> Self {
+        self.cert_ttl = ttl;
+        self
+    }
+
+    /// Path of the CA certificate
+    pub fn ca_cert_path(&self) -> PathBuf {
+        self.dir.join(CA_CERT_FILE)
+    }
+
+    /// Path of the published revocation list
+    pub fn crl_path(&self) -> PathBuf {
+        self.dir.join(CRL_FILE)
+    }
+}
+
+/// A certificate issued to a node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedCertificate {
+    /// Serial number, hex encoded
+    pub serial: String,
+    /// Node the certificate was issued to
+    pub node_id: String,
+    /// SPIFFE ID in the certificate
+    pub spiffe_id: String,
+    /// Start of the validity period
+    pub not_before: DateTime<Utc>,
+    /// End of the validity period
+    pub not_after: DateTime<Utc>,
+    /// Node certificate, PEM encoded
+    pub certificate_pem: String,
+    /// CA certificate, PEM encoded
+    pub ca_pem: String,
+    /// Current revocation list, PEM encoded
+    pub crl_pem: String,
+}
+
+/// Record of a certificate issued by the CA
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateRecord {
+    /// Serial number, hex encoded
+    pub serial: String,
+    /// Node the certificate was issued to
+    pub node_id: String,
+    /// Node public key, hex encoded
+    pub public_key: String,
+    /// DNS names in the certificate
+    pub dns_names: Vec<String>,
+    /// Start of the validity period
+    pub not_before: DateTime<Utc>,
+    /// End of the validity period
+    pub not_after: DateTime<Utc>,
+    /// When the certificate was revoked
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Persistent state of the CA
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CaState {
+    /// Number of the last published CRL
+    crl_number: u64,
+    /// Issued certificates
+    certificates: Vec<CertificateRecord>,
+    /// Outstanding and used bootstrap tokens
+    tokens: Vec<TokenRecord>,
+}
+
+/// Embedded certificate authority for cluster nodes
+///
+/// New nodes enroll with a one-time bootstrap token and a CSR signed by their
+/// node identity key. The issued certificate is bound to that key: its
+/// subject is the node ID derived from the public key and it carries the
+/// SPIFFE ID `spiffe://<trust domain>/node/<node id>`. Certificates are
+/// short-lived and renewed by proving possession of the same key.
+/// Revocations are published as a signed CRL in [`CaConfig::crl_path`].
+pub struct CertificateAuthority {
+    config: CaConfig,
+    issuer: Issuer<'static, KeyPair>,
+    ca_pem: String,
+    state: Mutex<CaState>,
+    clock: Arc<dyn Clock>,
+}
+
+impl CertificateAuthority {
+    /// Open the CA, creating its key and certificate on first use
+    pub fn open(config: CaConfig, keystore: &KeyStore) -> AegisResult<Self> {
+        Self::open_with_clock(config, keystore, system_clock())
+    }
+
+    /// Open the CA using the given clock
+    pub fn open_with_clock(config: CaConfig, keystore: &KeyStore, clock: Arc<dyn Clock>) -> AegisResult<Self> {
+        std::fs::create_dir_all(&config.dir)?;
+
+        if !keystore.contains(CA_KEY_NAME) {
+            keystore.generate(CA_KEY_NAME, KeyAlgorithm::Ed25519)?;
+        }
+
+        let material = keystore.active_key(CA_KEY_NAME)?;
+        if material.algorithm != KeyAlgorithm::Ed25519 {
+            return Err(AegisError::Security(format!(
+                "CA key has algorithm {}, expected ed25519",
+                material.algorithm
+            )));
+        }
+
+        let cert_path = config.ca_cert_path();
+        let ca_pem = if cert_path.exists() {
+            std::fs::read_to_string(&cert_path)?
+        } else {
+            let key = ed25519_key_pair(&material.bytes)?;
+            let pem = self_signed_ca(&config, &key, clock.now())?;
+            std::fs::write(&cert_path, &pem)?;
+            pem
+        };
+
+        let key = ed25519_key_pair(&material.bytes)?;
+        let issuer = Issuer::from_ca_cert_pem(&ca_pem, key).map_err(ca_error)?;
+
+        let state = load_state(&config.dir.join(STATE_FILE))?;
+
+        let ca = Self {
+            config,
+            issuer,
+            ca_pem,
+            state: Mutex::new(state),
+            clock,
+        };
+
+        if !ca.config.crl_path().exists() {
+            let state = ca.state.lock().unwrap();
+            ca.publish_crl(&state)?;
+        }
+
+        Ok(ca)
+    }
+
+    /// CA configuration
+    pub fn config(&self) -> &CaConfig {
+        &self.config
+    }
+
+    /// CA certificate, PEM encoded
+    pub fn ca_pem(&self) -> &str {
+        &self.ca_pem
+    }
+
+    /// Create a one-time bootstrap token, optionally restricted to a node
+    ///
+    /// The enrolling node may not request DNS names; use
+    /// [`CertificateAuthority::create_token_with_dns_names`] for nodes that
+    /// serve under a host name.
+    pub fn create_token(&self, node_id: Option<&str>) -> AegisResult<BootstrapToken> {
+        self.create_token_with_dns_names(node_id, &[])
+    }
+
+    /// Create a one-time bootstrap token allowing the given DNS names
+    ///
+    /// The enrolling node may request any subset of `dns_names`.
+    pub fn create_token_with_dns_names(&self, node_id: Option<&str>, dns_names: &[&str]) -> AegisResult<BootstrapToken> {
+        let ttl = chrono_duration(self.config.token_ttl);
+        let now = self.clock.now();
+
+        let mut state = self.state.lock().unwrap();
+        state.tokens.retain(|t| !t.is_expired(now));
+
+        let (token, record) = TokenRecord::create(now, ttl, node_id, dns_names);
+        state.tokens.push(record);
+        self.save(&state)?;
+
+        Ok(token)
+    }
+
+    /// Issue a certificate to a new node
+    ///
+    /// DNS names requested in the CSR must be allowed by the token. The token
+    /// is used up only once the certificate is issued and recorded.
+    pub fn enroll(&self, request: &EnrollmentRequest) -> AegisResult<IssuedCertificate> {
+        let csr = parse_csr(&request.csr_pem)?;
+        let (node_id, public_key) = node_key(&csr)?;
+        let dns_names = csr_dns_names(&csr);
+        let now = self.clock.now();
+
+        let mut state = self.state.lock().unwrap();
+        let token = token::find_redeemable(&state.tokens, &request.token, &node_id, &dns_names, now)?;
+
+        let mut next = state.clone();
+        next.tokens[token].redeem(&node_id, now);
+        let issued = self.issue(&mut next, csr, node_id, public_key, dns_names, now)?;
+        *state = next;
+        Ok(issued)
+    }
+
+    /// Issue a new certificate for a node holding a valid one
+    ///
+    /// The CSR must be signed by the key of the certificate being renewed.
+    /// DNS names are carried over from that certificate, not taken from the
+    /// CSR.
+    pub fn renew(&self, request: &RenewalRequest) -> AegisResult<IssuedCertificate> {
+        let csr = parse_csr(&request.csr_pem)?;
+        let (node_id, public_key) = node_key(&csr)?;
+        let now = self.clock.now();
+
+        let mut state = self.state.lock().unwrap();
+        let current = state
+            .certificates
+            .iter()
+            .find(|c| c.serial == request.serial)
+            .ok_or_else(|| AegisError::NotFound(format!("Unknown certificate {}", request.serial)))?;
+
+        if current.revoked_at.is_some() {
+            return Err(AegisError::Security(format!("Certificate {} has been revoked", current.serial)));
+        }
+
+        if current.not_after <= now {
+            return Err(AegisError::Security(format!("Certificate {} has expired", current.serial)));
+        }
+
+        if current.public_key != public_key {
+            return Err(AegisError::Security(
+                "Renewal request is not signed by the certificate key".to_string(),
+            ));
+        }
+
+        let dns_names = current.dns_names.clone();
+        let mut next = state.clone();
+        let issued = self.issue(&mut next, csr, node_id, public_key, dns_names, now)?;
+        *state = next;
+        Ok(issued)
+    }
+
+    /// Revoke a certificate and publish a new CRL
+    pub fn revoke(&self, serial: &str) -> AegisResult<()> {
+        let now = self.clock.now();
+
+        let mut state = self.state.lock().unwrap();
+        let record = state
+            .certificates
+            .iter_mut()
+            .find(|c| c.serial == serial)
+            .ok_or_else(|| AegisError::NotFound(format!("Unknown certificate {}", serial)))?;
+
+        if record.revoked_at.is_none() {
+            record.revoked_at = Some(now);
+        }
+
+        state.crl_number += 1;
+        self.save(&state)?;
+        self.publish_crl(&state)?;
+
+        Ok(())
+    }
+
+    /// Revoke all certificates issued to a node
+    pub fn revoke_node(&self, node_id: &str) -> AegisResult<usize> {
+        let serials: Vec<String> = self
+            .certificates()
+            .into_iter()
+            .filter(|c| c.node_id == node_id && c.revoked_at.is_none())
+            .map(|c| c.serial)
+            .collect();
+
+        for serial in &serials {
+            self.revoke(serial)?;
+        }
+
+        Ok(serials.len())
+    }
+
+    /// Certificates issued by the CA
+    pub fn certificates(&self) -> Vec<CertificateRecord> {
+        self.state.lock().unwrap().certificates.clone()
+    }
+
+    /// Sign and publish a fresh CRL, returning it PEM encoded
+    ///
+    /// Should be called periodically, well within `crl_validity`.
+    pub fn refresh_crl(&self) -> AegisResult<String> {
+        let mut state = self.state.lock().unwrap();
+        state.crl_number += 1;
+        self.save(&state)?;
+        self.publish_crl(&state)
+    }
+
+    /// Current CRL, PEM encoded
+    pub fn crl_pem(&self) -> AegisResult<String> {
+        Ok(std::fs::read_to_string(self.config.crl_path())?)
+    }
+
+    /// Sign a certificate for a node, record it in `state` and write the state
+    ///
+    /// Callers pass a copy of the state and keep it only if this succeeds.
+    fn issue(
+        &self,
+        state: &mut CaState,
+        mut csr: CertificateSigningRequestParams,
+        node_id: String,
+        public_key: String,
+        dns_names: Vec<String>,
+        now: DateTime<Utc>,
+    ) -> AegisResult<IssuedCertificate> {
+        let spiffe_id = format!("spiffe://{}/node/{}", self.config.trust_domain, node_id);
+        let serial = random_serial();
+        let not_before = now - chrono_duration(CLOCK_SKEW);
+        let not_after = now + chrono_duration(self.config.cert_ttl);
+
+        let params = &mut csr.params;
+        params.distinguished_name = DistinguishedName::new();
+        params.distinguished_name.push(DnType::CommonName, node_id.as_str());
+        params.subject_alt_names = vec![SanType::URI(spiffe_id.clone().try_into().map_err(ca_error)?)];
+        for name in &dns_names {
+            params
+                .subject_alt_names
+                .push(SanType::DnsName(name.clone().try_into().map_err(ca_error)?));
+        }
+        params.is_ca = IsCa::ExplicitNoCa;
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.extended_key_usages = vec![
+            ExtendedKeyUsagePurpose::ServerAuth,
+            ExtendedKeyUsagePurpose::ClientAuth,
+        ];
+        params.serial_number = Some(SerialNumber::from_slice(&serial));
+        params.not_before = offset_date_time(not_before)?;
+        params.not_after = offset_date_time(not_after)?;
+        params.use_authority_key_identifier_extension = true;
+
+        let certificate = csr.signed_by(&self.issuer).map_err(ca_error)?;
+        let serial = format_hex(&serial);
+
+        state.certificates.push(CertificateRecord {
+            serial: serial.clone(),
+            node_id: node_id.clone(),
+            public_key,
+            dns_names,
+            not_before,
+            not_after,
+            revoked_at: None,
+        });
+        // Certificates that expired long ago no longer need to be tracked
+        state.certificates.retain(|c| c.not_after + chrono_duration(self.config.crl_validity) > now);
+        state.tokens.retain(|t| !t.is_expired(now));
+        let crl_pem = self.crl_pem()?;
+        self.save(state)?;
+
+        Ok(IssuedCertificate {
+            serial,
+            node_id,
+            spiffe_id,
+            not_before,
+            not_after,
+            certificate_pem: certificate.pem(),
+            ca_pem: self.ca_pem.clone(),
+            crl_pem,
+        })
+    }
+
+    /// Write the CA state
+    fn save(&self, state: &CaState) -> AegisResult<()> {
+        let path = self.config.dir.join(STATE_FILE);
+        let tmp = path.with_extension("json.tmp");
+        write_private_file(&tmp, &serde_json::to_vec_pretty(state)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Sign a CRL listing all revoked, unexpired certificates and write it
+    fn publish_crl(&self, state: &CaState) -> AegisResult<String> {
+        let now = self.clock.now();
+
+        let revoked_certs = state
+            .certificates
+            .iter()
+            .filter(|c| c.not_after > now)
+            .filter_map(|c| c.revoked_at.map(|at| (c, at)))
+            .map(|(c, at)| {
+                Ok(RevokedCertParams {
+                    serial_number: SerialNumber::from_slice(&parse_serial(&c.serial)?),
+                    revocation_time: offset_date_time(at)?,
+                    reason_code: Some(RevocationReason::Unspecified),
+                    invalidity_date: None,
+                })
+            })
+            .collect::<AegisResult<Vec<_>>>()?;
+
+        let params = CertificateRevocationListParams {
+            this_update: offset_date_time(now)?,
+            next_update: offset_date_time(now + chrono_duration(self.config.crl_validity))?,
+            crl_number: SerialNumber::from(state.crl_number.max(1)),
+            issuing_distribution_point: None,
+            revoked_certs,
+            key_identifier_method: KeyIdMethod::Sha256,
+        };
+
+        let pem = params.signed_by(&self.issuer).map_err(ca_error)?.pem().map_err(ca_error)?;
+
+        let path = self.config.crl_path();
+        let tmp = path.with_extension("pem.tmp");
+        std::fs::write(&tmp, &pem)?;
+        std::fs::rename(&tmp, &path)?;
+
+        Ok(pem)
+    }
+}
+
+#[async_trait]
+impl CertificateIssuer for CertificateAuthority {
+    async fn enroll(&self, request: &EnrollmentRequest) -> AegisResult<IssuedCertificate> {
+        CertificateAuthority::enroll(self, request)
+    }
+
+    async fn renew(&self, request: &RenewalRequest) -> AegisResult<IssuedCertificate> {
+        CertificateAuthority::renew(self, request)
+    }
+}
+
+/// Build an rcgen key pair from an Ed25519 seed
+pub(crate) fn ed25519_key_pair(seed: &[u8]) -> AegisResult<KeyPair> {
+    if seed.len() != 32 {
+        return Err(AegisError::Security("Invalid Ed25519 key length".to_string()));
+    }
+
+    let mut der = ED25519_PKCS8_PREFIX.to_vec();
+    der.extend_from_slice(seed);
+
+    KeyPair::from_pkcs8_der_and_sign_algo(&der.into(), &rcgen::PKCS_ED25519).map_err(ca_error)
+}
+
+/// Create the self-signed CA certificate
+fn self_signed_ca(config: &CaConfig, key: &KeyPair, now: DateTime<Utc>) -> AegisResult<String> {
+    let mut params = CertificateParams::default();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, format!("Aegis cluster CA ({})", config.trust_domain));
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    params.serial_number = Some(SerialNumber::from_slice(&random_serial()));
+    params.not_before = offset_date_time(now - chrono_duration(CLOCK_SKEW))?;
+    params.not_after = offset_date_time(now + chrono_duration(config.ca_validity))?;
+
+    Ok(params.self_signed(key).map_err(ca_error)?.pem())
+}
+
+/// Parse and verify a CSR
+fn parse_csr(pem: &str) -> AegisResult<CertificateSigningRequestParams> {
+    CertificateSigningRequestParams::from_pem(pem)
+        .map_err(|e| AegisError::Security(format!("Invalid certificate signing request: {}", e)))
+}
+
+/// Node ID and hex public key for the Ed25519 key in a CSR
+fn node_key(csr: &CertificateSigningRequestParams) -> AegisResult<(String, String)> {
+    if csr.public_key.algorithm() != &rcgen::PKCS_ED25519 {
+        return Err(AegisError::Security(
+            "Certificate signing request must use the node's Ed25519 identity key".to_string(),
+        ));
+    }
+
+    let public_key = csr.public_key.der_bytes();
+    Ok((node_id_for(public_key), format_hex(public_key)))
+}
+
+/// DNS names requested in a CSR
+fn csr_dns_names(csr: &CertificateSigningRequestParams) -> Vec<String> {
+    csr.params
+        .subject_alt_names
+        .iter()
+        .filter_map(|san| match san {
+            SanType::DnsName(name) => Some(name.as_str().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Random positive 16-byte serial number
+fn random_serial() -> Vec<u8> {
+    let mut serial = random_bytes(16);
+    serial[0] = (serial[0] & 0x3f) | 0x40;
+    serial
+}
+
+/// Decode a hex serial number
+fn parse_serial(serial: &str) -> AegisResult<Vec<u8>> {
+    aegis_core::utils::parse_hex(serial)
+        .map_err(|e| AegisError::Security(format!("Invalid serial {}: {}", serial, e)))
+}
+
+/// Load the CA state, or start with an empty one
+fn load_state(path: &Path) -> AegisResult<CaState> {
+    if !path.exists() {
+        return Ok(CaState::default());
+    }
+
+    Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+}
+
+/// Convert a standard duration to a chrono duration
+fn chrono_duration(duration: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration).unwrap_or_else(|_| chrono::Duration::zero())
+}
+
+/// Convert a chrono timestamp to the type used by rcgen
+fn offset_date_time(time: DateTime<Utc>) -> AegisResult<time::OffsetDateTime> {
+    time::OffsetDateTime::from_unix_timestamp(time.timestamp())
+        .map_err(|e| AegisError::Security(format!("Invalid certificate time: {}", e)))
+}
+
+/// Map an rcgen error
+fn ca_error(err: rcgen::Error) -> AegisError {
+    AegisError::Security(format!("Certificate authority error: {}", err))
+}
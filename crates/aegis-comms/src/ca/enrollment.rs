@@ -0,0 +1,251 @@
+//! Node-side certificate enrollment and renewal
+
+use aegis_core::clock::{system_clock, Clock};
+use aegis_core::config::TlsConfig;
+use aegis_core::error::{AegisError, AegisResult};
+use aegis_core::identity::NodeIdentity;
+use aegis_core::keystore::write_private_file;
+use aegis_core::logging::log_warn;
+use aegis_core::utils::format_hex;
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use rcgen::{CertificateParams, DnType};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::authority::{ed25519_key_pair, IssuedCertificate};
+
+/// Default fraction of the certificate lifetime after which it is renewed
+pub const DEFAULT_RENEWAL_THRESHOLD: f64 = 2.0 / 3.0;
+
+/// Request to enroll a new node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrollmentRequest {
+    /// One-time bootstrap token
+    pub token: String,
+    /// CSR signed by the node identity key, PEM encoded
+    pub csr_pem: String,
+}
+
+/// Request to renew a node certificate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenewalRequest {
+    /// Serial number of the certificate being renewed, hex encoded
+    pub serial: String,
+    /// CSR signed by the key of that certificate, PEM encoded
+    pub csr_pem: String,
+}
+
+/// Something that issues node certificates, local or remote
+#[async_trait]
+pub trait CertificateIssuer: Send + Sync {
+    /// Issue a certificate to a new node
+    async fn enroll(&self, request: &EnrollmentRequest) -> AegisResult<IssuedCertificate>;
+
+    /// Issue a new certificate for a node holding a valid one
+    async fn renew(&self, request: &RenewalRequest) -> AegisResult<IssuedCertificate>;
+}
+
+/// Details of an installed node certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateInfo {
+    /// Serial number, hex encoded
+    pub serial: String,
+    /// Subject common name
+    pub subject: String,
+    /// Start of the validity period
+    pub not_before: DateTime<Utc>,
+    /// End of the validity period
+    pub not_after: DateTime<Utc>,
+}
+
+impl CertificateInfo {
+    /// Parse the first certificate in a PEM string
+    pub fn from_pem(pem: &str) -> AegisResult<Self> {
+        let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes())
+            .map_err(|e| AegisError::Security(format!("Invalid certificate PEM: {}", e)))?;
+        let cert = pem
+            .parse_x509()
+            .map_err(|e| AegisError::Security(format!("Invalid certificate: {}", e)))?;
+
+        let subject = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
+        Ok(Self {
+            serial: format_hex(cert.raw_serial()),
+            subject,
+            not_before: timestamp(cert.validity().not_before.timestamp())?,
+            not_after: timestamp(cert.validity().not_after.timestamp())?,
+        })
+    }
+
+    /// Time at which the certificate should be renewed
+    pub fn renewal_due_at(&self, threshold: f64) -> DateTime<Utc> {
+        let lifetime = self.not_after - self.not_before;
+        let elapsed = lifetime.num_milliseconds() as f64 * threshold.clamp(0.0, 1.0);
+        self.not_before + chrono::Duration::milliseconds(elapsed as i64)
+    }
+}
+
+/// Create a certificate signing request for the node identity key
+pub fn create_csr(identity: &NodeIdentity, dns_names: &[String]) -> AegisResult<String> {
+    let key = ed25519_key_pair(identity.signing_key().as_bytes())?;
+    let mut params = CertificateParams::new(dns_names.to_vec())
+        .map_err(|e| AegisError::Security(format!("Invalid DNS name: {}", e)))?;
+    params.distinguished_name.push(DnType::CommonName, identity.node_id());
+
+    let csr = params
+        .serialize_request(&key)
+        .map_err(|e| AegisError::Security(format!("Failed to create CSR: {}", e)))?;
+    csr.pem()
+        .map_err(|e| AegisError::Security(format!("Failed to encode CSR: {}", e)))
+}
+
+/// Keeps a node's TLS certificate enrolled and renewed
+///
+/// Certificates, the CA certificate and the CRL are written to the paths in
+/// the node's [`TlsConfig`]; the private key is the node identity key.
+pub struct CertificateRenewer {
+    identity: Arc<NodeIdentity>,
+    tls: TlsConfig,
+    dns_names: Vec<String>,
+    threshold: f64,
+    clock: Arc<dyn Clock>,
+}
+
+impl CertificateRenewer {
+    /// Create a renewer writing to the paths in `tls`
+    pub fn new(identity: Arc<NodeIdentity>, tls: TlsConfig) -> Self {
+        Self {
+            identity,
+            tls,
+            dns_names: Vec::new(),
+            threshold: DEFAULT_RENEWAL_THRESHOLD,
+            clock: system_clock(),
+        }
+    }
+
+    /// Request these DNS names on enrollment
+    pub fn with_dns_names(mut self, dns_names: Vec<String>) -> Self {
+        self.dns_names = dns_names;
+        self
+    }
+
+    /// Renew once this fraction of the certificate lifetime has elapsed
+    pub fn with_renewal_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Use the given clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Details of the installed certificate, if any
+    pub fn current(&self) -> AegisResult<Option<CertificateInfo>> {
+        if !self.tls.cert_path.exists() {
+            return Ok(None);
+        }
+
+        let pem = std::fs::read_to_string(&self.tls.cert_path)?;
+        CertificateInfo::from_pem(&pem).map(Some)
+    }
+
+    /// Enroll with a bootstrap token and install the certificate
+    pub async fn enroll(&self, issuer: &dyn CertificateIssuer, token: &str) -> AegisResult<IssuedCertificate> {
+        let request = EnrollmentRequest {
+            token: token.to_string(),
+            csr_pem: create_csr(&self.identity, &self.dns_names)?,
+        };
+
+        let issued = issuer.enroll(&request).await?;
+        self.install(&issued)?;
+        Ok(issued)
+    }
+
+    /// Renew the installed certificate if it is due
+    ///
+    /// Returns `None` if the certificate is not yet due for renewal.
+    pub async fn renew_if_due(&self, issuer: &dyn CertificateIssuer) -> AegisResult<Option<IssuedCertificate>> {
+        let current = self
+            .current()?
+            .ok_or_else(|| AegisError::NotFound("No node certificate installed; enroll first".to_string()))?;
+
+        if self.clock.now() < current.renewal_due_at(self.threshold) {
+            return Ok(None);
+        }
+
+        let request = RenewalRequest {
+            serial: current.serial,
+            csr_pem: create_csr(&self.identity, &self.dns_names)?,
+        };
+
+        let issued = issuer.renew(&request).await?;
+        self.install(&issued)?;
+        Ok(Some(issued))
+    }
+
+    /// Check for renewal every `interval` until an unrecoverable error
+    ///
+    /// Failed renewals are logged and retried on the next check; only a
+    /// missing or revoked certificate stops the loop.
+    pub async fn run(&self, issuer: &dyn CertificateIssuer, interval: Duration) -> AegisResult<()> {
+        loop {
+            match self.renew_if_due(issuer).await {
+                Ok(_) => {}
+                Err(e @ (AegisError::NotFound(_) | AegisError::Security(_))) => return Err(e),
+                Err(e) => log_warn(&format!("Certificate renewal failed: {}", e)),
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Write an issued certificate and the files it depends on
+    fn install(&self, issued: &IssuedCertificate) -> AegisResult<()> {
+        let key = ed25519_key_pair(self.identity.signing_key().as_bytes())?;
+        if let Some(parent) = self.tls.key_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_private_file(&self.tls.key_path, key.serialize_pem().as_bytes())?;
+
+        if let Some(ca_path) = &self.tls.ca_path {
+            write_file(ca_path, &issued.ca_pem)?;
+        }
+
+        if let Some(crl_path) = &self.tls.crl_path {
+            write_file(crl_path, &issued.crl_pem)?;
+        }
+
+        // The certificate goes last so a reader never sees it without its key
+        write_file(&self.tls.cert_path, &issued.certificate_pem)
+    }
+}
+
+/// Replace a file, creating its parent directory
+fn write_file(path: &Path, contents: &str) -> AegisResult<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Convert a Unix timestamp
+fn timestamp(secs: i64) -> AegisResult<DateTime<Utc>> {
+    Utc.timestamp_opt(secs, 0)
+        .single()
+        .ok_or_else(|| AegisError::Security(format!("Invalid certificate time: {}", secs)))
+}
@@ -0,0 +1,19 @@
+//! Built-in cluster certificate authority
+//!
+//! A small CA that lets nodes join a cluster without an external PKI. An
+//! operator creates a one-time bootstrap token; the new node sends it along
+//! with a CSR signed by its node identity key and gets back a short-lived
+//! certificate carrying its node ID and SPIFFE ID. Nodes renew before expiry
+//! by signing a new CSR with the same key, and revoked certificates are
+//! published in a CRL that TLS peers check through `TlsConfig::crl_path`.
+
+mod authority;
+mod enrollment;
+mod token;
+
+pub use authority::{CaConfig, CertificateAuthority, CertificateRecord, IssuedCertificate, CA_KEY_NAME};
+pub use enrollment::{
+    create_csr, CertificateInfo, CertificateIssuer, CertificateRenewer, EnrollmentRequest, RenewalRequest,
+    DEFAULT_RENEWAL_THRESHOLD,
+};
+pub use token::BootstrapToken;
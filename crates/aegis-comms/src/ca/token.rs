@@ -0,0 +1,142 @@
+//! One-time bootstrap tokens for node enrollment
+
+use aegis_core::error::{AegisError, AegisResult};
+use aegis_core::utils::{format_hex, random_bytes, sha256_hash};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Prefix of bootstrap token strings
+const TOKEN_PREFIX: &str = "aegis-bt-";
+
+/// A one-time token authorizing a node to enroll
+///
+/// The token string is only returned when the token is created; the CA keeps
+/// just its hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootstrapToken {
+    /// Secret token string handed to the new node
+    pub token: String,
+    /// Time after which the token can no longer be used
+    pub expires_at: DateTime<Utc>,
+    /// Node the token is restricted to, if any
+    pub node_id: Option<String>,
+    /// DNS names the enrolling node may request
+    pub dns_names: Vec<String>,
+}
+
+/// Stored record of an issued token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TokenRecord {
+    /// SHA-256 of the token string, hex encoded
+    hash: String,
+    /// When the token was created
+    created_at: DateTime<Utc>,
+    /// When the token expires
+    expires_at: DateTime<Utc>,
+    /// Node the token is restricted to, if any
+    node_id: Option<String>,
+    /// DNS names the enrolling node may request
+    #[serde(default)]
+    dns_names: Vec<String>,
+    /// When the token was redeemed
+    used_at: Option<DateTime<Utc>>,
+    /// Node that redeemed the token
+    used_by: Option<String>,
+}
+
+impl TokenRecord {
+    /// Create a new token and the record to store for it
+    pub(crate) fn create(
+        now: DateTime<Utc>,
+        ttl: chrono::Duration,
+        node_id: Option<&str>,
+        dns_names: &[&str],
+    ) -> (BootstrapToken, TokenRecord) {
+        let token = format!("{}{}", TOKEN_PREFIX, format_hex(&random_bytes(32)));
+        let expires_at = now + ttl;
+
+        let record = TokenRecord {
+            hash: token_hash(&token),
+            created_at: now,
+            expires_at,
+            node_id: node_id.map(str::to_string),
+            dns_names: dns_names.iter().map(|name| name.to_ascii_lowercase()).collect(),
+            used_at: None,
+            used_by: None,
+        };
+
+        let token = BootstrapToken {
+            token,
+            expires_at,
+            node_id: record.node_id.clone(),
+            dns_names: record.dns_names.clone(),
+        };
+
+        (token, record)
+    }
+
+    /// Whether the record can be dropped from storage
+    pub(crate) fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+
+    /// Mark the token as used by `node_id`
+    pub(crate) fn redeem(&mut self, node_id: &str, now: DateTime<Utc>) {
+        self.used_at = Some(now);
+        self.used_by = Some(node_id.to_string());
+    }
+}
+
+/// Find the record of a token that `node_id` may redeem for `dns_names`
+///
+/// Fails if the token is unknown, expired, already used, restricted to a
+/// different node, or does not allow one of the DNS names. The token is not
+/// marked as used; see [`TokenRecord::redeem`].
+pub(crate) fn find_redeemable(
+    records: &[TokenRecord],
+    token: &str,
+    node_id: &str,
+    dns_names: &[String],
+    now: DateTime<Utc>,
+) -> AegisResult<usize> {
+    let hash = token_hash(token);
+    let index = records
+        .iter()
+        .position(|r| r.hash == hash)
+        .ok_or_else(|| AegisError::Security("Unknown bootstrap token".to_string()))?;
+    let record = &records[index];
+
+    if record.used_at.is_some() {
+        return Err(AegisError::Security("Bootstrap token has already been used".to_string()));
+    }
+
+    if record.is_expired(now) {
+        return Err(AegisError::Security("Bootstrap token has expired".to_string()));
+    }
+
+    if let Some(expected) = &record.node_id {
+        if expected != node_id {
+            return Err(AegisError::Security(format!(
+                "Bootstrap token is restricted to {}, not {}",
+                expected, node_id
+            )));
+        }
+    }
+
+    if let Some(name) = dns_names
+        .iter()
+        .find(|name| !record.dns_names.contains(&name.to_ascii_lowercase()))
+    {
+        return Err(AegisError::Security(format!(
+            "Bootstrap token does not allow the DNS name {}",
+            name
+        )));
+    }
+
+    Ok(index)
+}
+
+/// Hash under which a token is stored
+fn token_hash(token: &str) -> String {
+    format_hex(&sha256_hash(token.as_bytes()))
+}
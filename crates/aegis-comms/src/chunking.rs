@@ -0,0 +1,764 @@
+//! Chunked transfer of payloads larger than one frame
+//!
+//! Consensus snapshots and log exports can exceed the largest data frame. A
+//! [`ChunkSender`] splits such a payload into numbered [`FrameType::Chunk`]
+//! frames and a [`ChunkReceiver`] reassembles them. Chunk frames are ordinary
+//! frames on the connection, so other frames can be written between any two
+//! chunks; both sides route incoming frames by type.
+//!
+//! Every chunk carries the CRC32C of the payload up to and including it, so a
+//! receiver notices a mismatch at the chunk where it happens. The receiver
+//! acknowledges each chunk, and the sender keeps at most `window` chunks
+//! unacknowledged. A receiver that outlives the connection keeps its
+//! incomplete transfers: after reconnecting, the sender rewinds to the last
+//! acknowledged chunk and continues from there.
+//!
+//! Connections of [`ConnectionHandle`](crate::ConnectionHandle),
+//! [`MessageServer`](crate::MessageServer), RPC and
+//! [`MessageSender`](crate::MessageSender) carry messages larger than one
+//! chunk this way, configured by [`FramingConfig::chunking`]. A connection
+//! drops the transfers it was receiving when it reconnects, as their senders
+//! do not outlive the old connection. Servers keep the incomplete transfers
+//! of an authenticated peer for [`ChunkConfig::resume_timeout`] after its
+//! connection closes, so that they continue on the next connection of that
+//! peer.
+//!
+//! Chunk payload layout (all integers big-endian):
+//!
+//! | kind      | fields                                                     |
+//! |-----------|------------------------------------------------------------|
+//! | 0 (chunk) | transfer id (8), index (4), total length (8), CRC32C (4), data |
+//! | 1 (ack)   | transfer id (8), chunks received (4)                       |
+//! | 2 (abort) | transfer id (8)                                            |
+
+use crate::framing::{Frame, FrameType, FramingConfig};
+use byteorder::{BigEndian, ByteOrder};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::Duration;
+
+const KIND_CHUNK: u8 = 0;
+const KIND_ACK: u8 = 1;
+const KIND_ABORT: u8 = 2;
+
+/// Size of the header in front of the data of a chunk
+const CHUNK_HEADER_SIZE: usize = 25;
+
+/// Settings of chunked transfers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkConfig {
+    /// Bytes of payload per chunk; smaller chunks let other frames through sooner
+    pub chunk_size: usize,
+    /// Chunks sent before waiting for an acknowledgement
+    pub window: u32,
+    /// Largest payload sent or accepted
+    ///
+    /// The default of 64 MiB bounds what a peer can make the receiver buffer;
+    /// connections carrying snapshots or log exports raise it as needed.
+    pub max_transfer_size: u64,
+    /// Incomplete transfers a receiver keeps at once
+    pub max_pending_transfers: usize,
+    /// How long a server keeps the incomplete transfers of a disconnected peer
+    pub resume_timeout: Duration,
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1024 * 1024,
+            window: 8,
+            max_transfer_size: 64 * 1024 * 1024,
+            max_pending_transfers: 4,
+            resume_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Errors of a chunked transfer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkError {
+    /// The payload is larger than `max_transfer_size`
+    TooLarge(u64),
+    /// The chunk size does not fit in a chunk frame
+    InvalidChunkSize(usize),
+    /// A chunk frame could not be parsed or contradicts its transfer
+    Malformed(String),
+    /// The running checksum of a transfer does not match
+    ChecksumMismatch {
+        /// Transfer the chunk belongs to
+        transfer_id: u64,
+        /// Index of the chunk
+        index: u32,
+    },
+    /// The receiver already keeps `max_pending_transfers` incomplete transfers
+    TooManyTransfers,
+    /// The peer gave up on the transfer
+    Aborted(u64),
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkError::TooLarge(size) => write!(f, "Payload of {} bytes is too large to transfer", size),
+            ChunkError::InvalidChunkSize(size) => write!(f, "Chunk size {} does not fit in a frame", size),
+            ChunkError::Malformed(e) => write!(f, "Malformed chunk: {}", e),
+            ChunkError::ChecksumMismatch { transfer_id, index } => {
+                write!(f, "Checksum of transfer {:016x} does not match at chunk {}", transfer_id, index)
+            }
+            ChunkError::TooManyTransfers => write!(f, "Too many incomplete transfers"),
+            ChunkError::Aborted(transfer_id) => write!(f, "Transfer {:016x} was aborted by the peer", transfer_id),
+        }
+    }
+}
+
+impl std::error::Error for ChunkError {}
+
+/// A chunk payload, parsed
+enum ChunkMessage {
+    Chunk {
+        transfer_id: u64,
+        index: u32,
+        total_len: u64,
+        checksum: u32,
+        data: Bytes,
+    },
+    Ack {
+        transfer_id: u64,
+        received: u32,
+    },
+    Abort {
+        transfer_id: u64,
+    },
+}
+
+impl ChunkMessage {
+    fn parse(payload: &Bytes) -> Result<Self, ChunkError> {
+        let truncated = || ChunkError::Malformed("truncated chunk frame".to_string());
+        let (&kind, rest) = payload.split_first().ok_or_else(truncated)?;
+        if rest.len() < 8 {
+            return Err(truncated());
+        }
+        let transfer_id = BigEndian::read_u64(rest);
+
+        match kind {
+            KIND_CHUNK => {
+                if payload.len() < CHUNK_HEADER_SIZE {
+                    return Err(truncated());
+                }
+                Ok(ChunkMessage::Chunk {
+                    transfer_id,
+                    index: BigEndian::read_u32(&rest[8..12]),
+                    total_len: BigEndian::read_u64(&rest[12..20]),
+                    checksum: BigEndian::read_u32(&rest[20..24]),
+                    data: payload.slice(CHUNK_HEADER_SIZE..),
+                })
+            }
+            KIND_ACK => {
+                if rest.len() < 12 {
+                    return Err(truncated());
+                }
+                Ok(ChunkMessage::Ack {
+                    transfer_id,
+                    received: BigEndian::read_u32(&rest[8..12]),
+                })
+            }
+            KIND_ABORT => Ok(ChunkMessage::Abort { transfer_id }),
+            other => Err(ChunkError::Malformed(format!("unknown chunk frame kind {}", other))),
+        }
+    }
+
+    fn into_frame(self) -> Frame {
+        let mut buf = BytesMut::new();
+        match self {
+            ChunkMessage::Chunk {
+                transfer_id,
+                index,
+                total_len,
+                checksum,
+                data,
+            } => {
+                buf.reserve(CHUNK_HEADER_SIZE + data.len());
+                buf.put_u8(KIND_CHUNK);
+                buf.put_u64(transfer_id);
+                buf.put_u32(index);
+                buf.put_u64(total_len);
+                buf.put_u32(checksum);
+                buf.extend_from_slice(&data);
+            }
+            ChunkMessage::Ack { transfer_id, received } => {
+                buf.put_u8(KIND_ACK);
+                buf.put_u64(transfer_id);
+                buf.put_u32(received);
+            }
+            ChunkMessage::Abort { transfer_id } => {
+                buf.put_u8(KIND_ABORT);
+                buf.put_u64(transfer_id);
+            }
+        }
+        Frame::new(FrameType::Chunk, buf.freeze())
+    }
+}
+
+/// Sending side of one chunked transfer
+///
+/// The sender does no I/O: frames to write are taken from
+/// [`ChunkSender::next_frame`], and chunk frames read from the peer are
+/// passed to [`ChunkSender::receive`].
+#[derive(Debug)]
+pub struct ChunkSender {
+    transfer_id: u64,
+    payload: Bytes,
+    config: ChunkConfig,
+    chunk_count: u32,
+    /// Next chunk to send
+    next: u32,
+    /// Chunks the receiver confirmed
+    acked: u32,
+    /// Checksum of the payload before chunk `next`
+    checksum: u32,
+}
+
+impl ChunkSender {
+    /// Start a transfer of `payload` with a random transfer ID
+    ///
+    /// Chunks are sized by `framing.chunking` and must fit in the largest
+    /// chunk frame `framing` allows.
+    pub fn new(payload: Bytes, framing: &FramingConfig) -> Result<Self, ChunkError> {
+        Self::resume(rand::random(), payload, 0, framing)
+    }
+
+    /// Continue a transfer after `acknowledged` chunks were confirmed
+    ///
+    /// Used by a sender that restarted and kept the transfer ID and progress;
+    /// the receiver corrects the position if it has other chunks.
+    pub fn resume(
+        transfer_id: u64,
+        payload: Bytes,
+        acknowledged: u32,
+        framing: &FramingConfig,
+    ) -> Result<Self, ChunkError> {
+        let config = framing.chunking;
+        if payload.len() as u64 > config.max_transfer_size {
+            return Err(ChunkError::TooLarge(payload.len() as u64));
+        }
+        let max_chunk_size = (framing.max_frame_size(FrameType::Chunk) as usize).saturating_sub(CHUNK_HEADER_SIZE);
+        if config.chunk_size == 0 || config.chunk_size > max_chunk_size {
+            return Err(ChunkError::InvalidChunkSize(config.chunk_size));
+        }
+
+        // An empty payload is sent as one empty chunk
+        let chunk_count = u32::try_from(payload.len().div_ceil(config.chunk_size).max(1))
+            .map_err(|_| ChunkError::TooLarge(payload.len() as u64))?;
+        let mut sender = Self {
+            transfer_id,
+            payload,
+            config,
+            chunk_count,
+            next: 0,
+            acked: 0,
+            checksum: 0,
+        };
+        sender.seek(acknowledged.min(chunk_count));
+        sender.acked = sender.next;
+        Ok(sender)
+    }
+
+    /// ID of the transfer
+    pub fn transfer_id(&self) -> u64 {
+        self.transfer_id
+    }
+
+    /// Number of chunks the payload is split into
+    pub fn chunk_count(&self) -> u32 {
+        self.chunk_count
+    }
+
+    /// Chunks the receiver confirmed
+    pub fn acknowledged(&self) -> u32 {
+        self.acked
+    }
+
+    /// Whether the receiver confirmed every chunk
+    pub fn is_complete(&self) -> bool {
+        self.acked == self.chunk_count
+    }
+
+    /// Next chunk frame to write
+    ///
+    /// `None` once every chunk is sent or while `window` chunks await
+    /// acknowledgement.
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        if self.next >= self.chunk_count || self.next - self.acked >= self.config.window.max(1) {
+            return None;
+        }
+
+        let data = self.payload.slice(self.chunk_range(self.next));
+        self.checksum = crc32c::crc32c_append(self.checksum, &data);
+        let frame = ChunkMessage::Chunk {
+            transfer_id: self.transfer_id,
+            index: self.next,
+            total_len: self.payload.len() as u64,
+            checksum: self.checksum,
+            data,
+        }
+        .into_frame();
+        self.next += 1;
+        Some(frame)
+    }
+
+    /// Handle a chunk frame from the receiver
+    ///
+    /// Frames of other types or transfers are ignored.
+    pub fn receive(&mut self, frame: &Frame) -> Result<(), ChunkError> {
+        if frame.frame_type != FrameType::Chunk {
+            return Ok(());
+        }
+
+        match ChunkMessage::parse(&frame.payload)? {
+            ChunkMessage::Ack { transfer_id, received } if transfer_id == self.transfer_id => {
+                if received > self.chunk_count {
+                    return Err(ChunkError::Malformed(format!(
+                        "{} of {} chunks acknowledged",
+                        received, self.chunk_count
+                    )));
+                }
+                // The receiver has more than was sent on this connection, or
+                // lost what it had; either way continue where it is
+                if received > self.next || received < self.acked {
+                    self.seek(received);
+                }
+                self.acked = received;
+                Ok(())
+            }
+            ChunkMessage::Abort { transfer_id } if transfer_id == self.transfer_id => {
+                Err(ChunkError::Aborted(transfer_id))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Continue from the last acknowledged chunk, after reconnecting
+    pub fn rewind(&mut self) {
+        self.seek(self.acked);
+    }
+
+    /// Abort frame telling the receiver to drop the transfer
+    pub fn abort_frame(&self) -> Frame {
+        ChunkMessage::Abort {
+            transfer_id: self.transfer_id,
+        }
+        .into_frame()
+    }
+
+    fn chunk_range(&self, index: u32) -> std::ops::Range<usize> {
+        let start = index as usize * self.config.chunk_size;
+        start.min(self.payload.len())..(start + self.config.chunk_size).min(self.payload.len())
+    }
+
+    fn seek(&mut self, index: u32) {
+        let end = self.chunk_range(index).start;
+        self.checksum = crc32c::crc32c(&self.payload[..end]);
+        self.next = index;
+    }
+}
+
+/// A payload reassembled by a [`ChunkReceiver`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletedTransfer {
+    /// ID of the transfer
+    pub transfer_id: u64,
+    /// The complete payload
+    pub payload: Bytes,
+}
+
+/// An incomplete transfer
+#[derive(Debug)]
+struct Partial {
+    total_len: u64,
+    received: u32,
+    checksum: u32,
+    data: BytesMut,
+}
+
+/// Receiving side of chunked transfers
+///
+/// One receiver reassembles any number of transfers from a peer and should
+/// outlive its connections, so that transfers resume after reconnecting.
+/// Like [`ChunkSender`] it does no I/O: chunk frames read from the peer are
+/// passed to [`ChunkReceiver::receive`], and the acknowledgements to write
+/// are taken from [`ChunkReceiver::next_frame`].
+#[derive(Debug, Default)]
+pub struct ChunkReceiver {
+    config: ChunkConfig,
+    transfers: HashMap<u64, Partial>,
+    replies: VecDeque<Frame>,
+}
+
+impl ChunkReceiver {
+    /// Create a receiver with the given limits
+    pub fn new(config: ChunkConfig) -> Self {
+        Self {
+            config,
+            transfers: HashMap::new(),
+            replies: VecDeque::new(),
+        }
+    }
+
+    /// IDs of the incomplete transfers
+    pub fn pending(&self) -> Vec<u64> {
+        self.transfers.keys().copied().collect()
+    }
+
+    /// Forget an incomplete transfer
+    pub fn abandon(&mut self, transfer_id: u64) {
+        self.transfers.remove(&transfer_id);
+    }
+
+    /// Next frame to write back to the sender
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        self.replies.pop_front()
+    }
+
+    /// Handle a chunk frame from the sender
+    ///
+    /// Returns the payload once its last chunk arrived. Frames of other
+    /// types are ignored. A transfer that fails is dropped and the sender is
+    /// told to abort it.
+    pub fn receive(&mut self, frame: &Frame) -> Result<Option<CompletedTransfer>, ChunkError> {
+        if frame.frame_type != FrameType::Chunk {
+            return Ok(None);
+        }
+
+        match ChunkMessage::parse(&frame.payload)? {
+            ChunkMessage::Chunk {
+                transfer_id,
+                index,
+                total_len,
+                checksum,
+                data,
+            } => {
+                let result = self.receive_chunk(transfer_id, index, total_len, checksum, &data);
+                if result.is_err() {
+                    self.transfers.remove(&transfer_id);
+                    self.replies.push_back(ChunkMessage::Abort { transfer_id }.into_frame());
+                }
+                result
+            }
+            ChunkMessage::Abort { transfer_id } => {
+                self.transfers.remove(&transfer_id);
+                Ok(None)
+            }
+            ChunkMessage::Ack { .. } => Ok(None),
+        }
+    }
+
+    fn receive_chunk(
+        &mut self,
+        transfer_id: u64,
+        index: u32,
+        total_len: u64,
+        checksum: u32,
+        data: &[u8],
+    ) -> Result<Option<CompletedTransfer>, ChunkError> {
+        if total_len > self.config.max_transfer_size {
+            return Err(ChunkError::TooLarge(total_len));
+        }
+        if !self.transfers.contains_key(&transfer_id) && self.transfers.len() >= self.config.max_pending_transfers {
+            return Err(ChunkError::TooManyTransfers);
+        }
+
+        let transfer = self.transfers.entry(transfer_id).or_insert_with(|| Partial {
+            total_len,
+            received: 0,
+            checksum: 0,
+            data: BytesMut::new(),
+        });
+        if transfer.total_len != total_len {
+            return Err(ChunkError::Malformed(format!(
+                "transfer length changed from {} to {}",
+                transfer.total_len, total_len
+            )));
+        }
+
+        // Chunks sent again after a reconnect, or sent past a gap, are not
+        // stored; the acknowledgement moves the sender to the right chunk
+        if index != transfer.received {
+            let received = transfer.received;
+            self.acknowledge(transfer_id, received);
+            return Ok(None);
+        }
+
+        if transfer.data.len() as u64 + data.len() as u64 > total_len {
+            return Err(ChunkError::Malformed("chunks exceed the transfer length".to_string()));
+        }
+        let running = crc32c::crc32c_append(transfer.checksum, data);
+        if running != checksum {
+            return Err(ChunkError::ChecksumMismatch { transfer_id, index });
+        }
+
+        transfer.data.extend_from_slice(data);
+        transfer.checksum = running;
+        transfer.received += 1;
+        let received = transfer.received;
+        let complete = transfer.data.len() as u64 == total_len;
+        self.acknowledge(transfer_id, received);
+
+        if !complete {
+            return Ok(None);
+        }
+        let transfer = self.transfers.remove(&transfer_id).expect("transfer is pending");
+        Ok(Some(CompletedTransfer {
+            transfer_id,
+            payload: transfer.data.freeze(),
+        }))
+    }
+
+    fn acknowledge(&mut self, transfer_id: u64, received: u32) {
+        self.replies
+            .push_back(ChunkMessage::Ack { transfer_id, received }.into_frame());
+    }
+}
+
+/// An incomplete transfer of a closed connection
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+struct Parked {
+    transfer_id: u64,
+    partial: Partial,
+    since: std::time::Instant,
+}
+
+/// Incomplete incoming transfers of closed connections, by peer
+///
+/// A server parks the transfers of a connection from an authenticated peer
+/// when it closes, and a later connection of that peer takes them back once
+/// their sender continues them. Transfers parked for longer than
+/// `resume_timeout` are dropped.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ParkedTransfers {
+    peers: std::sync::Arc<std::sync::Mutex<HashMap<String, Vec<Parked>>>>,
+}
+
+#[cfg(feature = "tokio")]
+impl ParkedTransfers {
+    /// Keep the transfers of `peer`, at most `max_pending_transfers` of them
+    fn park(&self, peer: &str, transfers: impl Iterator<Item = (u64, Partial)>, config: &ChunkConfig) {
+        let mut peers = self.peers.lock().unwrap();
+        Self::expire(&mut peers, config.resume_timeout);
+        let parked = peers.entry(peer.to_string()).or_default();
+        let since = std::time::Instant::now();
+        parked.extend(transfers.map(|(transfer_id, partial)| Parked {
+            transfer_id,
+            partial,
+            since,
+        }));
+        let excess = parked.len().saturating_sub(config.max_pending_transfers);
+        parked.drain(..excess);
+        if parked.is_empty() {
+            peers.remove(peer);
+        }
+    }
+
+    /// Take back a transfer `peer` parked
+    fn take(&self, peer: &str, transfer_id: u64, config: &ChunkConfig) -> Option<Partial> {
+        let mut peers = self.peers.lock().unwrap();
+        Self::expire(&mut peers, config.resume_timeout);
+        let parked = peers.get_mut(peer)?;
+        let index = parked.iter().position(|parked| parked.transfer_id == transfer_id)?;
+        let partial = parked.remove(index).partial;
+        if parked.is_empty() {
+            peers.remove(peer);
+        }
+        Some(partial)
+    }
+
+    fn expire(peers: &mut HashMap<String, Vec<Parked>>, timeout: Duration) {
+        peers.retain(|_, parked| {
+            parked.retain(|parked| parked.since.elapsed() < timeout);
+            !parked.is_empty()
+        });
+    }
+}
+
+/// What [`Transfers::send`] did with a data frame
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub(crate) enum Sending {
+    /// The frame is small enough to write as it is
+    Frame(Frame),
+    /// The frame is queued in chunks as the transfer with this ID
+    Chunked(#[cfg_attr(not(feature = "rpc"), allow(dead_code))] u64),
+}
+
+/// Chunked transfers in both directions of one connection
+///
+/// Connection tasks pass outgoing frames through [`Transfers::send`], which
+/// queues those larger than one chunk, hand chunk frames from the peer
+/// to [`Transfers::receive`], and write whatever [`Transfers::next_frame`]
+/// returns, or [`Transfers::flush`] them. Queued transfers are sent one after
+/// another.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub(crate) struct Transfers {
+    framing: FramingConfig,
+    receiver: ChunkReceiver,
+    /// Outgoing transfers and the codec of their payload
+    sending: VecDeque<(ChunkSender, crate::codec::CodecId)>,
+    /// Where incomplete incoming transfers are parked when the connection
+    /// closes, and the peer they are parked for
+    parked: Option<(ParkedTransfers, String)>,
+}
+
+#[cfg(feature = "tokio")]
+impl Transfers {
+    pub(crate) fn new(framing: &FramingConfig) -> Self {
+        Self {
+            framing: framing.clone(),
+            receiver: ChunkReceiver::new(framing.chunking),
+            sending: VecDeque::new(),
+            parked: None,
+        }
+    }
+
+    /// Transfers of a server connection, resuming those `peer` started on
+    /// earlier connections
+    ///
+    /// Only authenticated peers can be told apart across connections; the
+    /// transfers of other peers end with their connection.
+    pub(crate) fn for_peer(framing: &FramingConfig, parked: &ParkedTransfers, peer: &crate::error::PeerInfo) -> Self {
+        let mut transfers = Self::new(framing);
+        transfers.parked = peer.identity.as_ref().map(|identity| (parked.clone(), identity.id().to_string()));
+        transfers
+    }
+
+    /// Queue a data frame in chunks if it is larger than one chunk
+    ///
+    /// Returns the frame to write as it is otherwise, and on connections
+    /// writing v1, which cannot carry chunk frames.
+    pub(crate) fn send(&mut self, frame: Frame, version: crate::framing::FrameVersion) -> Result<Sending, ChunkError> {
+        if version == crate::framing::FrameVersion::V1 || frame.payload.len() <= self.framing.chunking.chunk_size {
+            return Ok(Sending::Frame(frame));
+        }
+        let sender = ChunkSender::new(frame.payload, &self.framing)?;
+        let transfer_id = sender.transfer_id();
+        self.sending.push_back((sender, frame.codec));
+        Ok(Sending::Chunked(transfer_id))
+    }
+
+    /// Whether the outgoing transfer `transfer_id` is still queued
+    #[cfg(feature = "rpc")]
+    pub(crate) fn is_sending(&self, transfer_id: u64) -> bool {
+        self.sending.iter().any(|(sender, _)| sender.transfer_id() == transfer_id)
+    }
+
+    /// Whether `max_pending_transfers` outgoing transfers are queued
+    pub(crate) fn is_full(&self) -> bool {
+        self.sending.len() >= self.framing.chunking.max_pending_transfers.max(1)
+    }
+
+    /// Outgoing transfers not yet confirmed by the peer
+    pub(crate) fn outgoing(&self) -> usize {
+        self.sending.len()
+    }
+
+    /// Handle a frame from the peer
+    ///
+    /// Returns a reassembled message as a data frame with the codec of its
+    /// chunks. Incoming transfers that fail are aborted towards the peer and
+    /// skipped. An outgoing transfer the peer aborted, or acknowledged past
+    /// its last chunk, is dropped and reported as [`ChunkError::Aborted`].
+    pub(crate) fn receive(&mut self, frame: &Frame) -> Result<Option<Frame>, ChunkError> {
+        if frame.frame_type != FrameType::Chunk {
+            return Ok(None);
+        }
+        let outgoing = match ChunkMessage::parse(&frame.payload) {
+            Ok(ChunkMessage::Ack { transfer_id, .. } | ChunkMessage::Abort { transfer_id }) => self
+                .sending
+                .iter()
+                .position(|(sender, _)| sender.transfer_id() == transfer_id)
+                .map(|index| (index, transfer_id)),
+            Ok(ChunkMessage::Chunk { transfer_id, .. }) => {
+                self.unpark(transfer_id);
+                None
+            }
+            Err(_) => None,
+        };
+        if let Some((index, transfer_id)) = outgoing {
+            let (sender, _) = &mut self.sending[index];
+            let result = sender.receive(frame);
+            if result.is_err() || sender.is_complete() {
+                self.sending.remove(index);
+            }
+            return result.map(|()| None).map_err(|_| ChunkError::Aborted(transfer_id));
+        }
+
+        match self.receiver.receive(frame) {
+            Ok(Some(transfer)) => Ok(Some(Frame::data(transfer.payload).with_codec(frame.codec))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Next frame to write: acknowledgements first, then chunks
+    pub(crate) fn next_frame(&mut self) -> Option<Frame> {
+        if let Some(reply) = self.receiver.next_frame() {
+            return Some(reply);
+        }
+        while let Some((sender, codec)) = self.sending.front_mut() {
+            if let Some(frame) = sender.next_frame() {
+                return Some(frame.with_codec(*codec));
+            }
+            if !sender.is_complete() {
+                return None;
+            }
+            self.sending.pop_front();
+        }
+        None
+    }
+
+    /// Write every frame that is ready
+    pub(crate) async fn flush<S: crate::transport::MessageStream>(
+        &mut self,
+        framed: &mut crate::framing::FramedMessageStream<S>,
+    ) -> Result<(), crate::framing::FramingError> {
+        while let Some(frame) = self.next_frame() {
+            framed.write_frame(frame).await?;
+        }
+        Ok(())
+    }
+
+    /// Continue the outgoing transfers on a new connection
+    ///
+    /// Incoming transfers are dropped, as their senders ended with the old
+    /// connection.
+    pub(crate) fn rewind(&mut self) {
+        for (sender, _) in &mut self.sending {
+            sender.rewind();
+        }
+        self.receiver = ChunkReceiver::new(self.framing.chunking);
+    }
+
+    /// Take back an incoming transfer parked by an earlier connection
+    fn unpark(&mut self, transfer_id: u64) {
+        let Some((parked, peer)) = &self.parked else {
+            return;
+        };
+        let transfers = &mut self.receiver.transfers;
+        if transfers.contains_key(&transfer_id) || transfers.len() >= self.framing.chunking.max_pending_transfers {
+            return;
+        }
+        if let Some(partial) = parked.take(peer, transfer_id, &self.framing.chunking) {
+            transfers.insert(transfer_id, partial);
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Drop for Transfers {
+    fn drop(&mut self) {
+        if let Some((parked, peer)) = &self.parked {
+            parked.park(peer, self.receiver.transfers.drain(), &self.framing.chunking);
+        }
+    }
+}
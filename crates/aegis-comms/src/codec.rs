@@ -0,0 +1,194 @@
+//! Message codecs
+//!
+//! Every v2 data frame records the codec of its payload and the codecs its
+//! sender can decode, so each side of a connection encodes with its most
+//! preferred codec that the peer understands. Peers that advertise nothing,
+//! such as v1 peers, are sent bincode.
+
+use crate::error::CommsError;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Encoding of message payloads
+pub trait Codec {
+    /// Identifier of the codec on the wire
+    fn id(&self) -> CodecId;
+
+    /// Encode a value
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, CommsError>;
+
+    /// Decode a value; malformed input is reported as [`CommsError::InvalidMessage`]
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CommsError>;
+}
+
+/// Compact binary encoding used between Rust peers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    fn id(&self) -> CodecId {
+        CodecId::Bincode
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, CommsError> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CommsError> {
+        bincode::deserialize(bytes).map_err(|e| CommsError::InvalidMessage(e.to_string()))
+    }
+}
+
+/// JSON, readable and understood by every client
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn id(&self) -> CodecId {
+        CodecId::Json
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, CommsError> {
+        serde_json::to_vec(value).map_err(|e| CommsError::InvalidMessage(e.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CommsError> {
+        serde_json::from_slice(bytes).map_err(|e| CommsError::InvalidMessage(e.to_string()))
+    }
+}
+
+/// CBOR (RFC 8949)
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl Codec for CborCodec {
+    fn id(&self) -> CodecId {
+        CodecId::Cbor
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, CommsError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).map_err(|e| CommsError::InvalidMessage(e.to_string()))?;
+        Ok(bytes)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CommsError> {
+        ciborium::from_reader(bytes).map_err(|e| CommsError::InvalidMessage(e.to_string()))
+    }
+}
+
+/// MessagePack, with structs encoded as maps keyed by field name
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePackCodec;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePackCodec {
+    fn id(&self) -> CodecId {
+        CodecId::MessagePack
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, CommsError> {
+        rmp_serde::to_vec_named(value).map_err(|e| CommsError::InvalidMessage(e.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CommsError> {
+        rmp_serde::from_slice(bytes).map_err(|e| CommsError::InvalidMessage(e.to_string()))
+    }
+}
+
+/// Codec chosen at runtime, as recorded in the frame header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CodecId {
+    /// [`BincodeCodec`]
+    #[default]
+    Bincode = 0,
+    /// [`JsonCodec`]
+    Json = 1,
+    /// CBOR, with the `cbor` feature
+    Cbor = 2,
+    /// MessagePack, with the `msgpack` feature
+    MessagePack = 3,
+}
+
+impl CodecId {
+    /// Parse a codec from its wire representation
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(CodecId::Bincode),
+            1 => Some(CodecId::Json),
+            2 => Some(CodecId::Cbor),
+            3 => Some(CodecId::MessagePack),
+            _ => None,
+        }
+    }
+
+    /// Codecs compiled into this build, in default order of preference
+    pub fn supported() -> Vec<CodecId> {
+        let mut codecs = vec![CodecId::Bincode, CodecId::Json];
+        if cfg!(feature = "cbor") {
+            codecs.push(CodecId::Cbor);
+        }
+        if cfg!(feature = "msgpack") {
+            codecs.push(CodecId::MessagePack);
+        }
+        codecs
+    }
+
+    /// Bit of this codec in an advertised set
+    pub(crate) fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// Error for a codec that is not compiled in
+    fn unavailable(self) -> CommsError {
+        CommsError::InvalidMessage(format!("codec {:?} is not available in this build", self))
+    }
+}
+
+impl Codec for CodecId {
+    fn id(&self) -> CodecId {
+        *self
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, CommsError> {
+        match self {
+            CodecId::Bincode => BincodeCodec.encode(value),
+            CodecId::Json => JsonCodec.encode(value),
+            #[cfg(feature = "cbor")]
+            CodecId::Cbor => CborCodec.encode(value),
+            #[cfg(feature = "msgpack")]
+            CodecId::MessagePack => MessagePackCodec.encode(value),
+            #[allow(unreachable_patterns)]
+            other => Err(other.unavailable()),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CommsError> {
+        match self {
+            CodecId::Bincode => BincodeCodec.decode(bytes),
+            CodecId::Json => JsonCodec.decode(bytes),
+            #[cfg(feature = "cbor")]
+            CodecId::Cbor => CborCodec.decode(bytes),
+            #[cfg(feature = "msgpack")]
+            CodecId::MessagePack => MessagePackCodec.decode(bytes),
+            #[allow(unreachable_patterns)]
+            other => Err(other.unavailable()),
+        }
+    }
+}
+
+/// Preferred codec that is also in the peer's advertised set
+///
+/// A peer that advertised nothing is assumed to understand only bincode.
+pub(crate) fn negotiate(preference: &[CodecId], peer_accepted: u8) -> CodecId {
+    if peer_accepted == 0 {
+        return CodecId::Bincode;
+    }
+    preference
+        .iter()
+        .copied()
+        .find(|codec| peer_accepted & codec.bit() != 0)
+        .unwrap_or(CodecId::Bincode)
+}
@@ -0,0 +1,157 @@
+//! Payload compression for v2 frames
+//!
+//! Every v2 frame advertises the algorithms its sender can decompress, so the
+//! first frame in each direction doubles as the compression handshake. A frame
+//! is only compressed with an algorithm the peer advertised, when its payload
+//! reaches the configured threshold, and when compressing actually shrinks it.
+
+use crate::framing::FramingError;
+
+/// Algorithm used to compress a frame payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Compression {
+    /// Zstandard, better ratio for large batches
+    Zstd = 1,
+    /// LZ4 block format, cheaper to compress and decompress
+    Lz4 = 2,
+}
+
+impl Compression {
+    /// Parse an algorithm from its wire representation
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Compression::Zstd),
+            2 => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+
+    /// Algorithms compiled into this build, in default order of preference
+    pub fn supported() -> &'static [Compression] {
+        #[cfg(feature = "compression")]
+        {
+            &[Compression::Zstd, Compression::Lz4]
+        }
+        #[cfg(not(feature = "compression"))]
+        {
+            &[]
+        }
+    }
+
+    /// Bit of this algorithm in an advertised set
+    pub(crate) fn bit(self) -> u8 {
+        1 << (self as u8 - 1)
+    }
+
+    /// Compress `data`, or `None` when the algorithm is not compiled in
+    #[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+    pub(crate) fn compress(self, data: &[u8]) -> Option<Vec<u8>> {
+        #[cfg(feature = "compression")]
+        {
+            match self {
+                Compression::Zstd => zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL).ok(),
+                Compression::Lz4 => Some(lz4_flex::block::compress_prepend_size(data)),
+            }
+        }
+        #[cfg(not(feature = "compression"))]
+        {
+            None
+        }
+    }
+
+    /// Decompress `data`, failing if the result would exceed `limit` bytes
+    #[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+    pub(crate) fn decompress(self, data: &[u8], limit: usize) -> Result<Vec<u8>, FramingError> {
+        #[cfg(feature = "compression")]
+        {
+            match self {
+                Compression::Zstd => {
+                    // Check the declared size first so a bomb is refused before allocating
+                    let capacity = match zstd::zstd_safe::get_frame_content_size(data) {
+                        Ok(Some(size)) if size > limit as u64 => {
+                            return Err(FramingError::DecompressionLimitExceeded(limit as u32))
+                        }
+                        Ok(Some(size)) => size as usize,
+                        _ => limit,
+                    };
+                    zstd::bulk::decompress(data, capacity).map_err(|e| FramingError::Decompression(e.to_string()))
+                }
+                Compression::Lz4 => {
+                    if data.len() < 4 {
+                        return Err(FramingError::Decompression("missing lz4 size prefix".to_string()));
+                    }
+                    let size = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+                    if size > limit {
+                        return Err(FramingError::DecompressionLimitExceeded(limit as u32));
+                    }
+                    lz4_flex::block::decompress(&data[4..], size)
+                        .map_err(|e| FramingError::Decompression(e.to_string()))
+                }
+            }
+        }
+        #[cfg(not(feature = "compression"))]
+        {
+            Err(FramingError::UnknownCompression(self as u8))
+        }
+    }
+}
+
+/// Compression settings of a framed stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Algorithms this side accepts, in order of preference for outgoing frames
+    pub algorithms: Vec<Compression>,
+    /// Payloads smaller than this many bytes are sent uncompressed
+    pub threshold: usize,
+    /// Largest accepted ratio of decompressed to compressed size
+    ///
+    /// Incoming frames that would expand further are rejected as
+    /// decompression bombs, and outgoing frames that compress further are
+    /// sent uncompressed, so both ends should use the same value.
+    pub max_ratio: u32,
+}
+
+impl CompressionConfig {
+    /// Configuration that neither compresses nor accepts compressed frames
+    pub fn disabled() -> Self {
+        Self {
+            algorithms: Vec::new(),
+            ..Self::default()
+        }
+    }
+
+    /// Set of accepted algorithms as advertised in the frame header
+    pub(crate) fn accepted(&self) -> u8 {
+        self.algorithms.iter().fold(0, |set, algorithm| set | algorithm.bit())
+    }
+
+    /// Whether frames compressed with `algorithm` are accepted
+    pub(crate) fn accepts(&self, algorithm: Compression) -> bool {
+        self.algorithms.contains(&algorithm)
+    }
+
+    /// Preferred algorithm that is also in the peer's advertised set
+    pub(crate) fn negotiate(&self, peer_accepted: u8) -> Option<Compression> {
+        self.algorithms
+            .iter()
+            .copied()
+            .find(|algorithm| peer_accepted & algorithm.bit() != 0)
+    }
+
+    /// Largest decompressed size allowed for `compressed_len` bytes
+    pub(crate) fn decompressed_limit(&self, compressed_len: usize, max_frame_size: u32) -> usize {
+        compressed_len
+            .saturating_mul(self.max_ratio as usize)
+            .min(max_frame_size as usize)
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            algorithms: Compression::supported().to_vec(),
+            threshold: 1024,
+            max_ratio: 100,
+        }
+    }
+}
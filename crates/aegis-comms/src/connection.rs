@@ -0,0 +1,667 @@
+//! Typed connections that reconnect when the transport drops
+//!
+//! A [`ConnectionHandle`] keeps working across transport failures: its
+//! driver task reconnects with exponential backoff and jitter, outgoing
+//! messages wait in a bounded buffer while the connection is down, and
+//! state changes are published as [`ConnectionState`] events.
+//!
+//! Connections are flow controlled with credits. Each end grants its peer
+//! as many messages as fit in its receive window, and grants more as the
+//! application receives them. A sender out of credit stops writing, so its
+//! outgoing buffer fills and [`ConnectionHandle::send`] applies the
+//! configured [`Backpressure`]. Peers that never grant credit, such as
+//! older versions, are written to without limit. Credit is granted only once
+//! the peer has written a v2 frame, so v1 peers never receive control frames.
+//!
+//! Messages larger than the configured chunk size are sent in chunks, each
+//! costing one credit. A transfer interrupted by a reconnect continues from
+//! the last chunk the peer confirmed.
+
+use crate::chunking::{ChunkConfig, Sending, Transfers};
+use crate::codec::Codec;
+use crate::error::CommsError;
+use crate::framing::{Frame, FrameType, FrameVersion, FramedMessageStream, FramingConfig, FramingError};
+use crate::transport::{MessageStream, NetworkError};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Notify};
+
+/// Opens a new stream to the same peer
+pub(crate) type Reconnect = Arc<dyn Fn() -> BoxFuture<'static, Result<Box<dyn MessageStream>, NetworkError>> + Send + Sync>;
+
+/// Delays between reconnection attempts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Delay before the first attempt
+    pub initial_delay: Duration,
+    /// Longest delay between attempts
+    pub max_delay: Duration,
+    /// Factor the delay grows by after each failed attempt
+    pub multiplier: f64,
+    /// Fraction of the delay randomly added or removed, from 0.0 to 1.0
+    pub jitter: f64,
+    /// Attempts before giving up, unlimited if `None`
+    pub max_attempts: Option<u32>,
+}
+
+impl ReconnectPolicy {
+    /// Do not reconnect
+    pub fn never() -> Self {
+        Self {
+            max_attempts: Some(0),
+            ..Self::default()
+        }
+    }
+
+    /// Set the delay before the first attempt
+    pub fn with_initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Set the longest delay between attempts
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Set the jitter fraction
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Give up after `attempts` failed attempts
+    pub fn with_max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Delay before attempt number `attempt`, counting from 1
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(63) as i32;
+        let base = (self.initial_delay.as_secs_f64() * self.multiplier.max(1.0).powi(exponent))
+            .min(self.max_delay.as_secs_f64());
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter)
+        } else {
+            1.0
+        };
+
+        Duration::from_secs_f64((base * factor).min(self.max_delay.as_secs_f64()))
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+/// What happens to a message whose write fails when the connection drops
+///
+/// Only the failed write is covered. The peer does not acknowledge messages,
+/// so messages that were written but had not reached the peer when the
+/// connection dropped are lost in either mode, and are not counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeliveryMode {
+    /// The message is dropped and counted as lost; it is never sent twice
+    #[default]
+    AtMostOnce,
+    /// The message is written again after reconnecting, so the peer may
+    /// receive it twice; it is counted as lost if reconnecting gives up
+    AtLeastOnce,
+}
+
+/// What [`ConnectionHandle::send`] does when the outgoing buffer is full
+///
+/// The buffer fills while the connection is down or the peer has granted no
+/// credit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backpressure {
+    /// Wait for room in the buffer
+    #[default]
+    Wait,
+    /// Fail with [`CommsError::BufferFull`]
+    FailFast,
+    /// Drop [`Priority::Low`] messages and wait for room for the others
+    ShedLowPriority,
+}
+
+/// Importance of an outgoing message, used when shedding load
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Priority {
+    /// May be dropped under [`Backpressure::ShedLowPriority`]
+    Low,
+    /// Never shed
+    #[default]
+    Normal,
+}
+
+/// Options for connections opened by [`crate::CommsClient`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionOptions {
+    /// When and how often to reconnect
+    pub reconnect: ReconnectPolicy,
+    /// Delivery guarantee for messages in flight when the connection drops
+    pub delivery: DeliveryMode,
+    /// Outgoing messages buffered while the connection is down or out of credit
+    pub buffer_size: usize,
+    /// Received messages queued until the application takes them; the peer
+    /// is granted credit for this many
+    pub receive_window: usize,
+    /// Behaviour of [`ConnectionHandle::send`] when the outgoing buffer is full
+    pub backpressure: Backpressure,
+    /// Frame version written on each connection until the peer's is known
+    ///
+    /// A listener that only speaks v1 never writes first, so it cannot be
+    /// detected; use [`FrameVersion::V1`] to reach one.
+    pub frame_version: FrameVersion,
+    /// Chunked transfers of messages larger than one chunk
+    pub chunking: ChunkConfig,
+}
+
+impl ConnectionOptions {
+    /// Framing of each connection
+    fn framing(&self) -> FramingConfig {
+        FramingConfig {
+            write_version: self.frame_version,
+            chunking: self.chunking,
+            ..FramingConfig::default()
+        }
+    }
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            reconnect: ReconnectPolicy::default(),
+            delivery: DeliveryMode::default(),
+            buffer_size: 32,
+            receive_window: 32,
+            backpressure: Backpressure::default(),
+            frame_version: FrameVersion::V2,
+            chunking: ChunkConfig::default(),
+        }
+    }
+}
+
+/// State of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Reconnection attempt number `attempt` is under way
+    Connecting { attempt: u32 },
+    /// The connection is established
+    Up,
+    /// The connection dropped
+    Down,
+    /// Reconnection stopped after the last allowed attempt
+    GaveUp,
+}
+
+/// Connection-level messages carried in control frames
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum ControlMessage {
+    /// The sender may write this many more data frames
+    Credit(u32),
+}
+
+/// Handle for sending/receiving messages on a connection
+pub struct ConnectionHandle<T> {
+    tx: mpsc::Sender<T>,
+    rx: mpsc::Receiver<Result<T, CommsError>>,
+    state: watch::Receiver<ConnectionState>,
+    events: broadcast::Sender<ConnectionState>,
+    lost: Arc<AtomicU64>,
+    shed: AtomicU64,
+    backpressure: Backpressure,
+    /// Messages received since credit was last granted for them
+    consumed: Arc<AtomicU32>,
+    /// Wakes the driver to grant credit
+    grant: Arc<Notify>,
+    grant_batch: u32,
+    credit: watch::Receiver<Option<u32>>,
+    /// Stops the driver task when the handle is dropped
+    _closed: oneshot::Sender<()>,
+}
+
+impl<T: Serialize + DeserializeOwned + Send + 'static> ConnectionHandle<T> {
+    /// Handle for an accepted connection, which cannot be reconnected
+    pub fn from_stream(stream: Box<dyn MessageStream>) -> Self {
+        Self::from_stream_with_options(stream, ConnectionOptions::default())
+    }
+
+    /// Handle for an accepted connection with queue sizes and backpressure
+    ///
+    /// The reconnection policy in `options` is ignored.
+    pub fn from_stream_with_options(stream: Box<dyn MessageStream>, options: ConnectionOptions) -> Self {
+        let reconnect: Reconnect = Arc::new(|| Box::pin(async { Err(NetworkError::ConnectionClosed) }));
+        let options = ConnectionOptions {
+            reconnect: ReconnectPolicy::never(),
+            ..options
+        };
+        Self::spawn(stream, reconnect, options)
+    }
+
+    /// Start driving a connected stream
+    ///
+    /// `reconnect` opens a replacement stream when the connection drops.
+    pub(crate) fn spawn(stream: Box<dyn MessageStream>, reconnect: Reconnect, options: ConnectionOptions) -> Self {
+        let window = options.receive_window.clamp(1, u32::MAX as usize);
+        let (tx, outgoing) = mpsc::channel::<T>(options.buffer_size.max(1));
+        let (incoming, rx) = mpsc::channel::<Result<T, CommsError>>(window);
+        let (state_tx, state) = watch::channel(ConnectionState::Up);
+        let (events, _) = broadcast::channel(16);
+        let (closed_tx, closed) = oneshot::channel();
+        let (credit_tx, credit) = watch::channel(None);
+        let lost = Arc::new(AtomicU64::new(0));
+        let consumed = Arc::new(AtomicU32::new(0));
+        let grant = Arc::new(Notify::new());
+
+        let driver = Driver {
+            outgoing,
+            incoming,
+            state: state_tx,
+            events: events.clone(),
+            lost: lost.clone(),
+            closed,
+            reconnect,
+            options,
+            retry: None,
+            consumed: consumed.clone(),
+            grant: grant.clone(),
+            send_credit: SendCredit::default(),
+            credit: credit_tx,
+            transfers: Transfers::new(&options.framing()),
+        };
+        tokio::spawn(driver.run(stream));
+
+        Self {
+            tx,
+            rx,
+            state,
+            events,
+            lost,
+            shed: AtomicU64::new(0),
+            backpressure: options.backpressure,
+            consumed,
+            grant,
+            // Grant in batches of half the window to save control frames
+            grant_batch: (window as u32 / 2).max(1),
+            credit,
+            _closed: closed_tx,
+        }
+    }
+
+    /// Send a message through the connection
+    ///
+    /// While the connection is down or the peer has granted no credit, the
+    /// message waits in the outgoing buffer. When the buffer is full the
+    /// configured [`Backpressure`] applies.
+    pub async fn send(&self, msg: T) -> Result<(), CommsError> {
+        self.send_with_priority(msg, Priority::Normal).await
+    }
+
+    /// Send a message that may be shed when the outgoing buffer is full
+    ///
+    /// A shed message is counted by [`ConnectionHandle::shed_messages`] and
+    /// reported as sent.
+    pub async fn send_with_priority(&self, msg: T, priority: Priority) -> Result<(), CommsError> {
+        let msg = match self.tx.try_send(msg) {
+            Ok(()) => return Ok(()),
+            Err(mpsc::error::TrySendError::Closed(_)) => return Err(CommsError::ChannelClosed),
+            Err(mpsc::error::TrySendError::Full(msg)) => msg,
+        };
+
+        match (self.backpressure, priority) {
+            (Backpressure::FailFast, _) => Err(CommsError::BufferFull),
+            (Backpressure::ShedLowPriority, Priority::Low) => {
+                self.shed.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            _ => self.tx.send(msg).await.map_err(|_| CommsError::ChannelClosed),
+        }
+    }
+
+    /// Send a message without waiting for room in the buffer
+    pub fn try_send(&self, msg: T) -> Result<(), CommsError> {
+        self.tx.try_send(msg).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => CommsError::BufferFull,
+            mpsc::error::TrySendError::Closed(_) => CommsError::ChannelClosed,
+        })
+    }
+
+    /// Receive a message from the connection
+    ///
+    /// A message that cannot be decoded is reported as
+    /// [`CommsError::InvalidMessage`]; the connection stays usable.
+    pub async fn receive(&mut self) -> Result<Option<T>, CommsError> {
+        let received = self.rx.recv().await.ok_or(CommsError::ChannelClosed)?;
+
+        // The message left the receive window, so the peer may send another
+        if self.consumed.fetch_add(1, Ordering::AcqRel) + 1 >= self.grant_batch {
+            self.grant.notify_one();
+        }
+        received.map(Some)
+    }
+
+    /// Current state of the connection
+    pub fn state(&self) -> ConnectionState {
+        *self.state.borrow()
+    }
+
+    /// Subscribe to state changes
+    pub fn events(&self) -> broadcast::Receiver<ConnectionState> {
+        self.events.subscribe()
+    }
+
+    /// Messages dropped because the connection failed while writing them,
+    /// because they could not be encoded with the negotiated codec, or
+    /// because their chunked transfer failed
+    ///
+    /// With [`DeliveryMode::AtLeastOnce`], only a message still waiting to be
+    /// written again when reconnecting gave up is counted. Messages lost after
+    /// a successful write are not detected; see [`DeliveryMode`].
+    pub fn lost_messages(&self) -> u64 {
+        self.lost.load(Ordering::Relaxed)
+    }
+
+    /// Low-priority messages dropped because the outgoing buffer was full
+    pub fn shed_messages(&self) -> u64 {
+        self.shed.load(Ordering::Relaxed)
+    }
+
+    /// Messages the peer currently accepts before granting more credit
+    ///
+    /// `None` while the peer has not granted credit on the current
+    /// connection, in which case writes are not limited.
+    pub fn send_credit(&self) -> Option<u32> {
+        *self.credit.borrow()
+    }
+}
+
+/// Credit granted by the peer on the current connection
+#[derive(Debug, Default)]
+struct SendCredit {
+    /// Data frames that may still be written; `None` until the first grant
+    granted: Option<u32>,
+    /// Data frames written before the first grant, which it already covers
+    sent_before_grant: u32,
+}
+
+impl SendCredit {
+    fn can_send(&self) -> bool {
+        self.granted != Some(0)
+    }
+
+    fn spend(&mut self) {
+        match &mut self.granted {
+            Some(credit) => *credit = credit.saturating_sub(1),
+            None => self.sent_before_grant = self.sent_before_grant.saturating_add(1),
+        }
+    }
+
+    fn grant(&mut self, credit: u32) {
+        self.granted = Some(match self.granted {
+            Some(granted) => granted.saturating_add(credit),
+            // The first grant is the peer's whole window
+            None => credit.saturating_sub(self.sent_before_grant),
+        });
+    }
+}
+
+/// Task moving messages between a handle and the transport
+struct Driver<T> {
+    outgoing: mpsc::Receiver<T>,
+    incoming: mpsc::Sender<Result<T, CommsError>>,
+    state: watch::Sender<ConnectionState>,
+    events: broadcast::Sender<ConnectionState>,
+    lost: Arc<AtomicU64>,
+    closed: oneshot::Receiver<()>,
+    reconnect: Reconnect,
+    options: ConnectionOptions,
+    /// Message to write again after reconnecting
+    retry: Option<Frame>,
+    /// Received messages the handle took since credit was last granted
+    consumed: Arc<AtomicU32>,
+    grant: Arc<Notify>,
+    send_credit: SendCredit,
+    credit: watch::Sender<Option<u32>>,
+    /// Outlives sessions so that transfers continue after reconnecting
+    transfers: Transfers,
+}
+
+/// Why a connected session ended
+enum SessionEnd {
+    /// The transport failed or the peer closed the connection
+    Dropped,
+    /// The handle was dropped
+    Closed,
+}
+
+impl<T: Serialize + DeserializeOwned + Send + 'static> Driver<T> {
+    async fn run(mut self, stream: Box<dyn MessageStream>) {
+        let mut stream = stream;
+
+        loop {
+            let mut framed = FramedMessageStream::with_config(stream, self.options.framing());
+            let end = self.session(&mut framed).await;
+            let _ = framed.shutdown().await;
+
+            if let SessionEnd::Closed = end {
+                return;
+            }
+            self.set_state(ConnectionState::Down);
+
+            stream = match self.reconnect().await {
+                Some(stream) => stream,
+                None => {
+                    let unsent = self.transfers.outgoing() + usize::from(self.retry.take().is_some());
+                    self.lost.fetch_add(unsent as u64, Ordering::Relaxed);
+                    return;
+                }
+            };
+            self.set_state(ConnectionState::Up);
+        }
+    }
+
+    /// Move messages until the connection drops or the handle goes away
+    async fn session(&mut self, framed: &mut FramedMessageStream<Box<dyn MessageStream>>) -> SessionEnd {
+        // Credit starts over on every connection. The peer may send as many
+        // messages as there is room for, including room freed before now.
+        self.send_credit = SendCredit::default();
+        let _ = self.credit.send_replace(None);
+        self.consumed.store(0, Ordering::Release);
+        let window = self.incoming.capacity() as u32;
+        // Whether the peer was granted its window; not before it wrote a frame
+        // showing it reads control frames
+        let mut granted = false;
+        self.transfers.rewind();
+
+        // Replay the message whose write failed on the previous connection
+        if let Some(frame) = self.retry.take() {
+            if let Err(end) = self.write(framed, frame).await {
+                return end;
+            }
+        }
+
+        loop {
+            if self.transfers.flush(framed).await.is_err() {
+                return SessionEnd::Dropped;
+            }
+
+            tokio::select! {
+                msg = self.outgoing.recv(), if self.send_credit.can_send() && !self.transfers.is_full() => {
+                    let msg = match msg {
+                        Some(msg) => msg,
+                        None => return SessionEnd::Closed,
+                    };
+                    // Encoded with the codec negotiated on this connection
+                    let codec = framed.codec();
+                    let frame = match codec.encode(&msg) {
+                        Ok(bytes) => Frame::data(Bytes::from(bytes)).with_codec(codec),
+                        Err(_) => {
+                            self.lost.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                    };
+                    let frame = match self.transfers.send(frame, framed.write_version()) {
+                        Ok(Sending::Frame(frame)) => frame,
+                        Ok(Sending::Chunked(_)) => {
+                            self.spend_credit();
+                            continue;
+                        }
+                        Err(_) => {
+                            self.lost.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                    };
+                    if let Err(end) = self.write(framed, frame).await {
+                        return end;
+                    }
+                }
+                received = framed.read_frame() => {
+                    let frame = match received {
+                        Ok(Some(frame)) => frame,
+                        _ => return SessionEnd::Dropped,
+                    };
+                    if !granted && framed.peer_version() == Some(FrameVersion::V2) {
+                        granted = true;
+                        // Room freed since the session started is granted too
+                        let credit = window.saturating_add(self.consumed.swap(0, Ordering::AcqRel));
+                        if let Err(end) = self.write_control(framed, ControlMessage::Credit(credit)).await {
+                            return end;
+                        }
+                    }
+                    let data = match frame.frame_type {
+                        FrameType::Data => Some(frame),
+                        FrameType::Chunk => match self.transfers.receive(&frame) {
+                            Ok(data) => data,
+                            // The peer gave up on one of our messages
+                            Err(_) => {
+                                self.lost.fetch_add(1, Ordering::Relaxed);
+                                None
+                            }
+                        },
+                        FrameType::Control => {
+                            if let Ok(ControlMessage::Credit(credit)) = bincode::deserialize(&frame.payload) {
+                                self.send_credit.grant(credit);
+                                let _ = self.credit.send_replace(self.send_credit.granted);
+                            }
+                            None
+                        }
+                        FrameType::Heartbeat | FrameType::Error => None,
+                    };
+                    if let Some(frame) = data {
+                        let msg = frame.codec.decode::<T>(&frame.payload);
+                        if self.incoming.send(msg).await.is_err() {
+                            return SessionEnd::Closed;
+                        }
+                    }
+                }
+                _ = self.grant.notified(), if granted => {
+                    let consumed = self.consumed.swap(0, Ordering::AcqRel);
+                    if consumed > 0 {
+                        if let Err(end) = self.write_control(framed, ControlMessage::Credit(consumed)).await {
+                            return end;
+                        }
+                    }
+                }
+                // Needed while out of credit, when the outgoing buffer is not read
+                _ = &mut self.closed => return SessionEnd::Closed,
+            }
+        }
+    }
+
+    /// Write a control frame
+    async fn write_control(
+        &mut self,
+        framed: &mut FramedMessageStream<Box<dyn MessageStream>>,
+        message: ControlMessage,
+    ) -> Result<(), SessionEnd> {
+        let payload = match bincode::serialize(&message) {
+            Ok(payload) => Bytes::from(payload),
+            Err(_) => return Ok(()),
+        };
+
+        match framed.write_frame(Frame::control(payload)).await {
+            Ok(()) => Ok(()),
+            // A connection configured to write v1 cannot send control
+            // frames, so the peer is left without limit
+            Err(FramingError::UnsupportedVersion(_)) => Ok(()),
+            Err(_) => Err(SessionEnd::Dropped),
+        }
+    }
+
+    /// Write one message, keeping it for retry if the delivery mode asks
+    async fn write(
+        &mut self,
+        framed: &mut FramedMessageStream<Box<dyn MessageStream>>,
+        frame: Frame,
+    ) -> Result<(), SessionEnd> {
+        if framed.write_frame(frame.clone()).await.is_ok() {
+            self.spend_credit();
+            return Ok(());
+        }
+
+        match self.options.delivery {
+            DeliveryMode::AtLeastOnce => self.retry = Some(frame),
+            DeliveryMode::AtMostOnce => {
+                self.lost.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Err(SessionEnd::Dropped)
+    }
+
+    fn spend_credit(&mut self) {
+        self.send_credit.spend();
+        let _ = self.credit.send_replace(self.send_credit.granted);
+    }
+
+    /// Reconnect with backoff; `None` if the policy gave up or the handle went away
+    async fn reconnect(&mut self) -> Option<Box<dyn MessageStream>> {
+        let policy = self.options.reconnect;
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            if policy.max_attempts.is_some_and(|max| attempt > max) {
+                self.set_state(ConnectionState::GaveUp);
+                return None;
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(policy.delay(attempt)) => {}
+                _ = &mut self.closed => return None,
+            }
+
+            self.set_state(ConnectionState::Connecting { attempt });
+            tokio::select! {
+                result = (self.reconnect)() => {
+                    if let Ok(stream) = result {
+                        return Some(stream);
+                    }
+                }
+                _ = &mut self.closed => return None,
+            }
+        }
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        let _ = self.state.send(state);
+        let _ = self.events.send(state);
+    }
+}
@@ -0,0 +1,125 @@
+//! Dispatch of endpoints to transports by scheme
+
+use crate::endpoint::Endpoint;
+use crate::transport::{MessageStream, NetworkConnector, NetworkError, TransportAddr};
+use aegis_core::platform::Network;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Connectors for each endpoint scheme
+///
+/// Resolves host names through the configured [`Network`] and tries each
+/// resolved address in turn. Used as a [`NetworkConnector`] itself, it
+/// dispatches plain transport addresses by their kind.
+#[derive(Clone, Default)]
+pub struct ConnectorRegistry {
+    connectors: HashMap<String, Arc<dyn NetworkConnector>>,
+    network: Option<Arc<dyn Network>>,
+}
+
+impl ConnectorRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with the transports enabled by crate features
+    ///
+    /// Registers `tcp`, `unix` and `mem` (on the global memory network)
+    /// where available. TLS needs certificates and is registered separately.
+    pub fn standard() -> Self {
+        #[allow(unused_mut)]
+        let mut registry = Self::new();
+
+        #[cfg(feature = "platform_tokio_net")]
+        registry.register("tcp", crate::platform::tokio_impl::TokioConnector::new());
+
+        #[cfg(all(unix, feature = "unix"))]
+        registry.register("unix", crate::platform::unix_impl::TokioUnixConnector::new());
+
+        #[cfg(feature = "memory")]
+        registry.register("mem", crate::memory::MemoryNetwork::global().connector());
+
+        registry
+    }
+
+    /// Register the connector for a scheme, replacing any previous one
+    pub fn register(&mut self, scheme: impl Into<String>, connector: impl NetworkConnector + 'static) {
+        self.connectors.insert(scheme.into(), Arc::new(connector));
+    }
+
+    /// Add the connector for a scheme
+    pub fn with_connector(mut self, scheme: impl Into<String>, connector: impl NetworkConnector + 'static) -> Self {
+        self.register(scheme, connector);
+        self
+    }
+
+    /// Resolve host names through `network`
+    pub fn with_network(mut self, network: Arc<dyn Network>) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Registered schemes
+    pub fn schemes(&self) -> Vec<String> {
+        let mut schemes: Vec<String> = self.connectors.keys().cloned().collect();
+        schemes.sort();
+        schemes
+    }
+
+    /// Connector for a scheme
+    pub fn connector(&self, scheme: &str) -> Result<Arc<dyn NetworkConnector>, NetworkError> {
+        self.connectors
+            .get(scheme)
+            .cloned()
+            .ok_or_else(|| NetworkError::UnsupportedScheme(scheme.to_string()))
+    }
+
+    /// Transport addresses for an endpoint
+    pub async fn resolve(&self, endpoint: &Endpoint) -> Result<Vec<TransportAddr>, NetworkError> {
+        if let Some(addr) = endpoint.to_transport_addr() {
+            return Ok(vec![addr]);
+        }
+
+        match &self.network {
+            Some(network) => endpoint.resolve(network.as_ref()).await,
+            None => Err(NetworkError::Resolve(format!(
+                "{} needs name resolution but no network is configured",
+                endpoint
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+impl NetworkConnector for ConnectorRegistry {
+    async fn connect(&self, addr: TransportAddr) -> Result<Box<dyn MessageStream>, NetworkError> {
+        let scheme = match &addr {
+            TransportAddr::Tcp(_) => "tcp",
+            TransportAddr::Unix(_) => "unix",
+            TransportAddr::Memory(_) => "mem",
+        };
+
+        self.connector(scheme)?.connect(addr).await
+    }
+
+    async fn connect_endpoint(&self, endpoint: &Endpoint) -> Result<Box<dyn MessageStream>, NetworkError> {
+        let connector = self.connector(endpoint.scheme())?;
+        let mut last_error = None;
+
+        for addr in self.resolve(endpoint).await? {
+            let result = match endpoint.host() {
+                Some(host) => connector.connect_to_host(addr, host).await,
+                None => connector.connect(addr).await,
+            };
+
+            match result {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error.unwrap_or(NetworkError::ConnectionRefused))
+    }
+}
@@ -0,0 +1,235 @@
+//! Discovery of agents on the local network
+//!
+//! A [`DiscoveryService`] periodically announces its agent with an
+//! [`AgentDiscoveryMessage`] on a UDP multicast group and records the
+//! announcements of other agents in a [`PeerRegistry`], where they can be
+//! looked up by ID or capability. Agents that stop announcing are forgotten
+//! after a TTL.
+//!
+//! On networks that do not carry multicast, announcements are also sent to
+//! a static list of seed addresses. A service answers an agent it has not
+//! seen before with its own announcement, so seeds and newcomers learn about
+//! each other after a single exchange.
+
+use crate::error::CommsError;
+use crate::peers::PeerRegistry;
+use crate::protocol::{AegisMessage, AgentDiscoveryMessage};
+use crate::transport::NetworkError;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+/// Port used for discovery when none is configured
+pub const DEFAULT_DISCOVERY_PORT: u16 = 7947;
+
+/// Largest announcement accepted
+const MAX_ANNOUNCEMENT_SIZE: usize = 64 * 1024;
+
+/// Settings of a [`DiscoveryService`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveryConfig {
+    /// Multicast group announcements are sent to; `None` uses only the seeds
+    pub group: Option<SocketAddrV4>,
+    /// Local address announcements are received on
+    pub bind: SocketAddr,
+    /// Time between announcements
+    pub interval: Duration,
+    /// Time without an announcement after which an agent is forgotten
+    pub ttl: Duration,
+    /// `host:port` addresses announced to directly
+    pub seeds: Vec<String>,
+    /// Multicast hop limit; 1 keeps announcements on the local subnet
+    pub multicast_ttl: u32,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            group: Some(SocketAddrV4::new(Ipv4Addr::new(239, 255, 77, 77), DEFAULT_DISCOVERY_PORT)),
+            bind: SocketAddr::from((Ipv4Addr::UNSPECIFIED, DEFAULT_DISCOVERY_PORT)),
+            interval: Duration::from_secs(5),
+            ttl: Duration::from_secs(30),
+            seeds: Vec::new(),
+            multicast_ttl: 1,
+        }
+    }
+}
+
+impl DiscoveryConfig {
+    /// Settings from the `network.discovery` section of the configuration
+    pub fn from_config(config: &aegis_core::config::DiscoveryConfig) -> Result<Self, NetworkError> {
+        let group = match &config.multicast_group {
+            Some(group) => {
+                let group: SocketAddrV4 = group
+                    .parse()
+                    .map_err(|_| NetworkError::AddrParseError(format!("invalid multicast group: {}", group)))?;
+                if !group.ip().is_multicast() {
+                    return Err(NetworkError::AddrParseError(format!("{} is not a multicast address", group)));
+                }
+                Some(group)
+            }
+            None => None,
+        };
+        let port = group.map_or(DEFAULT_DISCOVERY_PORT, |group| group.port());
+
+        Ok(Self {
+            group,
+            bind: SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)),
+            interval: Duration::from_secs(config.interval_secs),
+            ttl: Duration::from_secs(config.ttl_secs),
+            seeds: config.seeds.clone(),
+            ..Self::default()
+        })
+    }
+}
+
+/// Announces an agent and records the agents it hears about
+pub struct DiscoveryService {
+    announcement: AgentDiscoveryMessage,
+    config: DiscoveryConfig,
+    peers: PeerRegistry,
+    socket: UdpSocket,
+}
+
+impl DiscoveryService {
+    /// Bind the discovery socket and join the multicast group
+    ///
+    /// Discovered agents are recorded in `peers`, so a [`crate::CommsClient`]
+    /// sharing the registry can reach them by ID.
+    pub async fn bind(
+        announcement: AgentDiscoveryMessage,
+        config: DiscoveryConfig,
+        peers: PeerRegistry,
+    ) -> Result<Self, NetworkError> {
+        let socket = Socket::new(Domain::for_address(config.bind), Type::DGRAM, Some(Protocol::UDP))
+            .map_err(NetworkError::IoError)?;
+        // Several agents on one host share the group port
+        socket.set_reuse_address(true).map_err(NetworkError::IoError)?;
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        socket.set_reuse_port(true).map_err(NetworkError::IoError)?;
+        socket.set_nonblocking(true).map_err(NetworkError::IoError)?;
+        socket.bind(&config.bind.into()).map_err(NetworkError::IoError)?;
+
+        if let Some(group) = config.group {
+            socket
+                .join_multicast_v4(group.ip(), &Ipv4Addr::UNSPECIFIED)
+                .map_err(NetworkError::IoError)?;
+            socket.set_multicast_ttl_v4(config.multicast_ttl).map_err(NetworkError::IoError)?;
+            socket.set_multicast_loop_v4(true).map_err(NetworkError::IoError)?;
+        }
+
+        let socket = UdpSocket::from_std(socket.into()).map_err(NetworkError::IoError)?;
+        Ok(Self {
+            announcement,
+            config,
+            peers,
+            socket,
+        })
+    }
+
+    /// Address announcements are received on
+    pub fn local_addr(&self) -> Result<SocketAddr, NetworkError> {
+        self.socket.local_addr().map_err(NetworkError::IoError)
+    }
+
+    /// Registry the discovered agents are recorded in
+    pub fn peers(&self) -> &PeerRegistry {
+        &self.peers
+    }
+
+    /// Send the announcement to the multicast group and every seed
+    ///
+    /// Fails only if the announcement could not be sent anywhere.
+    pub async fn announce(&self) -> Result<(), NetworkError> {
+        let packet = self.packet().map_err(|e| NetworkError::Other(e.to_string()))?;
+        let mut sent = false;
+        let mut last_error = None;
+
+        if let Some(group) = self.config.group {
+            match self.socket.send_to(&packet, SocketAddr::V4(group)).await {
+                Ok(_) => sent = true,
+                Err(e) => last_error = Some(NetworkError::IoError(e)),
+            }
+        }
+
+        for seed in &self.config.seeds {
+            let addrs = match tokio::net::lookup_host(seed.as_str()).await {
+                Ok(addrs) => addrs,
+                Err(e) => {
+                    last_error = Some(NetworkError::Resolve(format!("{}: {}", seed, e)));
+                    continue;
+                }
+            };
+            for addr in addrs.filter(|addr| addr.is_ipv4() == self.config.bind.is_ipv4()) {
+                match self.socket.send_to(&packet, addr).await {
+                    Ok(_) => sent = true,
+                    Err(e) => last_error = Some(NetworkError::IoError(e)),
+                }
+            }
+        }
+
+        match (sent, last_error) {
+            (false, Some(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Announcement encoded as a datagram
+    fn packet(&self) -> Result<Vec<u8>, CommsError> {
+        let message = AegisMessage::new(self.announcement.clone())?;
+        Ok(bincode::serialize(&message)?)
+    }
+
+    /// Record an announcement received from `from`
+    ///
+    /// Agents seen for the first time are answered directly, which lets a
+    /// seed introduce itself to a newcomer.
+    async fn receive(&self, datagram: &[u8], from: SocketAddr) {
+        let message = match bincode::deserialize::<AegisMessage>(datagram) {
+            Ok(message) => message,
+            Err(_) => return,
+        };
+        let announcement = match message.validate().and_then(|_| message.decode::<AgentDiscoveryMessage>()) {
+            Ok(announcement) => announcement,
+            Err(_) => return,
+        };
+        if announcement.agent_id == self.announcement.agent_id {
+            // Our own announcement looped back
+            return;
+        }
+
+        let is_new = self.peers.get(&announcement.agent_id).is_none();
+        if self.peers.record_discovery(&announcement) && is_new {
+            if let Ok(packet) = self.packet() {
+                let _ = self.socket.send_to(&packet, from).await;
+            }
+        }
+    }
+
+    /// Announce, listen and expire agents until the task is aborted
+    pub async fn run(self) {
+        let mut ticker = tokio::time::interval(self.config.interval);
+        let mut buf = vec![0u8; MAX_ANNOUNCEMENT_SIZE];
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    let _ = self.announce().await;
+                    self.peers.expire_discovered(self.config.ttl);
+                }
+                received = self.socket.recv_from(&mut buf) => {
+                    if let Ok((len, from)) = received {
+                        self.receive(&buf[..len], from).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run the service in a background task
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(self.run())
+    }
+}
@@ -0,0 +1,157 @@
+//! Routing of [`AegisMessage`]s to handlers by message type
+
+use crate::codec::{Codec, CodecId};
+use crate::envelope::{EnvelopeVerifier, SignedEnvelope};
+use crate::error::{CommsError, PeerInfo, SecurityViolation};
+use crate::framing::{FrameType, FramedMessageStream, FramingConfig};
+use crate::protocol::{AegisMessage, MessageHeader, MessageType, ProtocolMessage};
+use crate::transport::MessageStream;
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Where a dispatched message came from
+#[derive(Debug, Clone)]
+pub struct MessageContext {
+    /// Header of the message
+    pub header: MessageHeader,
+    /// Connection the message arrived on
+    pub peer: PeerInfo,
+    /// Node ID that signed the message, if it arrived in a verified
+    /// [`SignedEnvelope`]
+    pub sender: Option<String>,
+}
+
+/// Type-erased message handler
+type Handler = Arc<dyn Fn(AegisMessage, MessageContext) -> BoxFuture<'static, Result<(), CommsError>> + Send + Sync>;
+
+/// Routes messages to the handler registered for their type
+///
+/// Cloning yields a dispatcher with the same handlers.
+#[derive(Clone, Default)]
+pub struct MessageDispatcher {
+    handlers: HashMap<MessageType, Handler>,
+    verifier: Option<EnvelopeVerifier>,
+}
+
+impl MessageDispatcher {
+    /// Create a dispatcher without handlers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the handler for messages of type `M`, replacing any previous one
+    pub fn register<M, F, Fut>(&mut self, handler: F)
+    where
+        M: ProtocolMessage,
+        F: Fn(M, MessageContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        let erased: Handler = Arc::new(move |message: AegisMessage, context: MessageContext| {
+            let handler = handler.clone();
+            Box::pin(async move {
+                handler(message.decode::<M>()?, context).await;
+                Ok(())
+            })
+        });
+
+        self.handlers.insert(M::message_type(), erased);
+    }
+
+    /// Add the handler for messages of type `M`
+    pub fn with_handler<M, F, Fut>(mut self, handler: F) -> Self
+    where
+        M: ProtocolMessage,
+        F: Fn(M, MessageContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.register(handler);
+        self
+    }
+
+    /// Accept only messages in a [`SignedEnvelope`] that `verifier` accepts
+    ///
+    /// Envelopes are opened before dispatch, so handlers are registered for
+    /// the message types they carry. Unsigned messages are rejected and
+    /// reported to the verifier's subscribers.
+    pub fn with_verifier(mut self, verifier: EnvelopeVerifier) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// Whether a handler is registered for `message_type`
+    pub fn handles(&self, message_type: &MessageType) -> bool {
+        self.handlers.contains_key(message_type)
+    }
+
+    /// Message types with a registered handler
+    pub fn message_types(&self) -> Vec<MessageType> {
+        self.handlers.keys().cloned().collect()
+    }
+
+    /// Validate a message and pass it to the handler for its type
+    pub async fn dispatch(&self, message: AegisMessage, peer: PeerInfo) -> Result<(), CommsError> {
+        message.validate()?;
+
+        let (message, sender) = match &self.verifier {
+            Some(verifier) => {
+                if message.message_type() != &SignedEnvelope::message_type() {
+                    return Err(verifier.reject(None, SecurityViolation::Unsigned));
+                }
+                let envelope: SignedEnvelope = message.decode()?;
+                let sender = envelope.sender.clone();
+                let message = verifier.open(envelope)?;
+                message.validate()?;
+                (message, Some(sender))
+            }
+            None => (message, None),
+        };
+
+        let handler = self
+            .handlers
+            .get(message.message_type())
+            .ok_or_else(|| CommsError::UnhandledMessageType(message.message_type().clone()))?;
+
+        let context = MessageContext {
+            header: message.header.clone(),
+            peer,
+            sender,
+        };
+        handler(message, context).await
+    }
+
+    /// Decode a bincode encoded message and dispatch it
+    pub async fn dispatch_bytes(&self, bytes: &[u8], peer: PeerInfo) -> Result<(), CommsError> {
+        self.dispatch_encoded(CodecId::Bincode, bytes, peer).await
+    }
+
+    /// Decode a message encoded with `codec` and dispatch it
+    pub async fn dispatch_encoded(&self, codec: CodecId, bytes: &[u8], peer: PeerInfo) -> Result<(), CommsError> {
+        let message: AegisMessage = codec.decode(bytes)?;
+        self.dispatch(message, peer).await
+    }
+
+    /// Dispatch the messages arriving on a stream until it closes
+    ///
+    /// Messages are handled one at a time, in the order they arrive.
+    /// Messages that cannot be decoded or have no handler are skipped.
+    pub async fn serve_connection(&self, stream: Box<dyn MessageStream>) -> Result<(), CommsError> {
+        let peer = PeerInfo {
+            addr: stream.peer_addr()?,
+            identity: stream.peer_identity(),
+            credentials: stream.peer_credentials(),
+        };
+        let mut framed = FramedMessageStream::with_config(stream, FramingConfig::default());
+
+        while let Some(frame) = framed.read_frame().await? {
+            if frame.frame_type != FrameType::Data {
+                continue;
+            }
+            let _ = self.dispatch_encoded(frame.codec, &frame.payload, peer.clone()).await;
+        }
+
+        Ok(())
+    }
+}
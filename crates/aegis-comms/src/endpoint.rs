@@ -0,0 +1,217 @@
+//! Transport-agnostic endpoint addresses
+//!
+//! An [`Endpoint`] names where an agent can be reached, independently of
+//! the transport: `tcp://host:port`, `tls://host:port`, `unix:///path` or
+//! `mem://name`. Host names are resolved to [`TransportAddr`]s when
+//! connecting, through [`aegis_core::platform::Network::resolve_host`].
+
+use crate::transport::{NetworkError, TransportAddr};
+use aegis_core::platform::Network;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Address of an agent endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Endpoint {
+    /// Plain TCP, `tcp://host:port`
+    Tcp {
+        /// Host name or IP address
+        host: String,
+        /// TCP port
+        port: u16,
+    },
+    /// TLS over TCP, `tls://host:port`
+    Tls {
+        /// Host name or IP address, also verified against the server certificate
+        host: String,
+        /// TCP port
+        port: u16,
+    },
+    /// Unix domain socket, `unix:///path/to/socket`
+    Unix(PathBuf),
+    /// In-process endpoint, `mem://name`
+    Memory(String),
+}
+
+impl Endpoint {
+    /// TCP endpoint
+    pub fn tcp(host: impl Into<String>, port: u16) -> Self {
+        Endpoint::Tcp { host: host.into(), port }
+    }
+
+    /// TLS endpoint
+    pub fn tls(host: impl Into<String>, port: u16) -> Self {
+        Endpoint::Tls { host: host.into(), port }
+    }
+
+    /// Unix domain socket endpoint
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        Endpoint::Unix(path.into())
+    }
+
+    /// In-process endpoint
+    pub fn memory(name: impl Into<String>) -> Self {
+        Endpoint::Memory(name.into())
+    }
+
+    /// URI scheme of the endpoint
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Endpoint::Tcp { .. } => "tcp",
+            Endpoint::Tls { .. } => "tls",
+            Endpoint::Unix(_) => "unix",
+            Endpoint::Memory(_) => "mem",
+        }
+    }
+
+    /// Host name or IP address, for network endpoints
+    pub fn host(&self) -> Option<&str> {
+        match self {
+            Endpoint::Tcp { host, .. } | Endpoint::Tls { host, .. } => Some(host),
+            _ => None,
+        }
+    }
+
+    /// Port, for network endpoints
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            Endpoint::Tcp { port, .. } | Endpoint::Tls { port, .. } => Some(*port),
+            _ => None,
+        }
+    }
+
+    /// The transport address, if it can be determined without name resolution
+    pub fn to_transport_addr(&self) -> Option<TransportAddr> {
+        match self {
+            Endpoint::Tcp { host, port } | Endpoint::Tls { host, port } => host
+                .parse::<IpAddr>()
+                .ok()
+                .map(|ip| TransportAddr::Tcp(SocketAddr::new(ip, *port))),
+            Endpoint::Unix(path) => Some(TransportAddr::unix(path.clone())),
+            Endpoint::Memory(name) => Some(TransportAddr::Memory(name.clone())),
+        }
+    }
+
+    /// Resolve the endpoint to the transport addresses it stands for
+    ///
+    /// Host names are looked up through `network`; IP addresses, paths and
+    /// in-process names are returned as they are.
+    pub async fn resolve(&self, network: &dyn Network) -> Result<Vec<TransportAddr>, NetworkError> {
+        if let Some(addr) = self.to_transport_addr() {
+            return Ok(vec![addr]);
+        }
+
+        let (host, port) = match self {
+            Endpoint::Tcp { host, port } | Endpoint::Tls { host, port } => (host, *port),
+            _ => unreachable!("only host names need resolution"),
+        };
+
+        let addrs: Vec<TransportAddr> = network
+            .resolve_host(host)
+            .await
+            .map_err(|e| NetworkError::Resolve(format!("{}: {}", host, e)))?
+            .iter()
+            .filter_map(|ip| ip.parse::<IpAddr>().ok())
+            .map(|ip| TransportAddr::Tcp(SocketAddr::new(ip, port)))
+            .collect();
+
+        if addrs.is_empty() {
+            return Err(NetworkError::Resolve(format!("{} has no addresses", host)));
+        }
+
+        Ok(addrs)
+    }
+}
+
+impl From<SocketAddr> for Endpoint {
+    fn from(addr: SocketAddr) -> Self {
+        Endpoint::tcp(addr.ip().to_string(), addr.port())
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp { host, port } | Endpoint::Tls { host, port } if host.contains(':') => {
+                write!(f, "{}://[{}]:{}", self.scheme(), host, port)
+            }
+            Endpoint::Tcp { host, port } | Endpoint::Tls { host, port } => {
+                write!(f, "{}://{}:{}", self.scheme(), host, port)
+            }
+            Endpoint::Unix(path) => write!(f, "unix://{}", path.display()),
+            Endpoint::Memory(name) => write!(f, "mem://{}", name),
+        }
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = NetworkError;
+
+    /// Parse an endpoint URI; a bare `host:port` is taken as TCP
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| NetworkError::AddrParseError(format!("{}: {}", s, reason));
+
+        let (scheme, rest) = match s.split_once("://") {
+            Some((scheme, rest)) => (scheme, rest),
+            None => match s.split_once(':') {
+                Some(("unix", path)) => ("unix", path),
+                Some(("mem", name)) => ("mem", name),
+                _ => ("tcp", s),
+            },
+        };
+
+        match scheme {
+            "tcp" | "tls" => {
+                let (host, port) = parse_host_port(rest.trim_end_matches('/')).ok_or_else(|| invalid("expected host:port"))?;
+                Ok(if scheme == "tcp" {
+                    Endpoint::tcp(host, port)
+                } else {
+                    Endpoint::tls(host, port)
+                })
+            }
+            "unix" if !rest.is_empty() => Ok(Endpoint::unix(rest)),
+            "unix" => Err(invalid("missing socket path")),
+            "mem" if !rest.is_empty() && !rest.contains('/') => Ok(Endpoint::memory(rest)),
+            "mem" => Err(invalid("invalid endpoint name")),
+            other => Err(invalid(&format!("unknown scheme {}", other))),
+        }
+    }
+}
+
+impl TryFrom<String> for Endpoint {
+    type Error = NetworkError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Endpoint> for String {
+    fn from(endpoint: Endpoint) -> Self {
+        endpoint.to_string()
+    }
+}
+
+/// Split `host:port` or `[v6]:port`
+fn parse_host_port(s: &str) -> Option<(String, u16)> {
+    let (host, port) = match s.strip_prefix('[') {
+        Some(rest) => {
+            let (host, port) = rest.split_once("]:")?;
+            host.parse::<std::net::Ipv6Addr>().ok()?;
+            (host, port)
+        }
+        None => {
+            let (host, port) = s.rsplit_once(':')?;
+            if host.is_empty() || host.contains(['/', ':']) {
+                return None;
+            }
+            (host, port)
+        }
+    };
+
+    Some((host.to_string(), port.parse().ok()?))
+}
@@ -0,0 +1,337 @@
+//! Signed message envelopes with replay protection
+//!
+//! Gossip and pub/sub relay messages through other agents, so the identity of
+//! the connection a message arrives on says nothing about who wrote it. A
+//! [`SignedEnvelope`] carries a message together with its sender's node key, a
+//! per-sender sequence number, a random nonce and a timestamp, all covered by
+//! an Ed25519 signature made with the sender's [`NodeIdentity`].
+//!
+//! An [`EnvelopeVerifier`] checks the signature and rejects envelopes whose
+//! timestamp is outside the acceptance window or that it has seen before.
+//! Every rejection is reported to subscribers as a [`SecurityEvent`].
+
+use crate::error::{CommsError, SecurityViolation};
+use crate::protocol::{AegisMessage, ExtensionMessage};
+use aegis_core::clock::{system_clock, Clock};
+use aegis_core::identity::{node_id_for, verify_signature, NodeIdentity};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Prefix of the signed bytes, so envelope signatures cannot be confused
+/// with other signatures made by the node key
+const SIGNING_CONTEXT: &str = "aegis-envelope-v1";
+
+/// A message signed by the node that wrote it
+///
+/// Envelopes are sent as an extension message, so they travel wherever an
+/// [`AegisMessage`] does, including as the payload of a pub/sub message.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SignedEnvelope {
+    /// The signed message, header and body
+    pub message: AegisMessage,
+    /// Node ID of the sender, derived from `public_key`
+    pub sender: String,
+    /// Ed25519 public key of the sender
+    pub public_key: Vec<u8>,
+    /// Sequence number, increasing with every envelope of the sender
+    pub sequence: u64,
+    /// Random value distinguishing the envelope
+    pub nonce: u64,
+    /// Time of signing in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Signature over all other fields
+    pub signature: Vec<u8>,
+}
+
+impl ExtensionMessage for SignedEnvelope {
+    const MESSAGE_TYPE: &'static str = "aegis.signed";
+}
+
+impl SignedEnvelope {
+    /// Bytes covered by the signature
+    fn signed_bytes(&self) -> Result<Vec<u8>, CommsError> {
+        Ok(bincode::serialize(&(
+            SIGNING_CONTEXT,
+            &self.message.header,
+            &self.message.body,
+            &self.sender,
+            &self.public_key,
+            self.sequence,
+            self.nonce,
+            self.timestamp,
+        ))?)
+    }
+}
+
+/// Signs outgoing messages with the node identity
+///
+/// Cloning yields a signer sharing the same sequence.
+#[derive(Clone)]
+pub struct EnvelopeSigner {
+    identity: Arc<NodeIdentity>,
+    sequence: Arc<AtomicU64>,
+    clock: Arc<dyn Clock>,
+}
+
+impl EnvelopeSigner {
+    /// Create a signer using the system clock
+    pub fn new(identity: Arc<NodeIdentity>) -> Self {
+        Self::with_clock(identity, system_clock())
+    }
+
+    /// Create a signer using the given clock
+    ///
+    /// Sequence numbers start at the current time in microseconds, so they
+    /// keep increasing across restarts of the node.
+    pub fn with_clock(identity: Arc<NodeIdentity>, clock: Arc<dyn Clock>) -> Self {
+        let start = clock.now_ms().saturating_mul(1000);
+        Self {
+            identity,
+            sequence: Arc::new(AtomicU64::new(start)),
+            clock,
+        }
+    }
+
+    /// Node ID of the signing identity
+    pub fn node_id(&self) -> &str {
+        self.identity.node_id()
+    }
+
+    /// Sign a message
+    pub fn seal(&self, message: AegisMessage) -> Result<SignedEnvelope, CommsError> {
+        let mut envelope = SignedEnvelope {
+            message,
+            sender: self.identity.node_id().to_string(),
+            public_key: self.identity.public_key().to_vec(),
+            sequence: self.sequence.fetch_add(1, Ordering::Relaxed) + 1,
+            nonce: rand::random(),
+            timestamp: self.clock.now_ms(),
+            signature: Vec::new(),
+        };
+        envelope.signature = self.identity.sign(&envelope.signed_bytes()?);
+        Ok(envelope)
+    }
+}
+
+/// A message rejected by an [`EnvelopeVerifier`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecurityEvent {
+    /// Node ID the envelope claimed to come from; `None` for unsigned messages
+    pub sender: Option<String>,
+    /// Why the message was rejected
+    pub violation: SecurityViolation,
+    /// When the message was rejected, RFC 3339
+    pub detected_at: String,
+}
+
+/// Subscription to the events of an [`EnvelopeVerifier`]
+pub struct SecurityEvents {
+    rx: broadcast::Receiver<SecurityEvent>,
+}
+
+impl SecurityEvents {
+    /// Wait for the next event; `None` once the verifier is gone
+    ///
+    /// Events missed because the subscriber fell behind are skipped.
+    pub async fn recv(&mut self) -> Option<SecurityEvent> {
+        loop {
+            match self.rx.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Replay protection settings of an [`EnvelopeVerifier`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayConfig {
+    /// How far the timestamp of an envelope may be from the local clock,
+    /// in either direction
+    pub acceptance_window: Duration,
+    /// How far below the highest sequence seen from a sender an envelope may
+    /// be, for messages relayed along different paths
+    pub reorder_window: u64,
+    /// Nonces remembered per sender; at least `reorder_window` are kept
+    pub nonce_cache_size: usize,
+    /// Senders remembered at once
+    ///
+    /// Senders not heard from within the acceptance window are forgotten
+    /// first, which is safe because their envelopes are too old to replay.
+    /// Beyond that the least recently seen sender is forgotten, and its
+    /// envelopes still inside the acceptance window could be replayed.
+    pub max_senders: usize,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            acceptance_window: Duration::from_secs(30),
+            reorder_window: 64,
+            nonce_cache_size: 1024,
+            max_senders: 10_000,
+        }
+    }
+}
+
+/// What a verifier remembers about a sender
+#[derive(Debug, Default)]
+struct SenderWindow {
+    highest: u64,
+    nonces: HashSet<u64>,
+    order: VecDeque<u64>,
+    /// Local time of the last accepted envelope, in milliseconds
+    last_seen: u64,
+}
+
+impl SenderWindow {
+    /// Record an envelope; false if it is a replay
+    fn accept(&mut self, sequence: u64, nonce: u64, config: &ReplayConfig) -> bool {
+        if sequence.saturating_add(config.reorder_window) < self.highest || self.nonces.contains(&nonce) {
+            return false;
+        }
+
+        self.highest = self.highest.max(sequence);
+        self.nonces.insert(nonce);
+        self.order.push_back(nonce);
+        let capacity = config.nonce_cache_size.max(config.reorder_window as usize).max(1);
+        while self.order.len() > capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.nonces.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Verifies signed envelopes and rejects replays
+///
+/// Cloning yields a handle to the same verifier.
+#[derive(Clone)]
+pub struct EnvelopeVerifier {
+    config: ReplayConfig,
+    trusted: Option<Arc<HashSet<String>>>,
+    senders: Arc<Mutex<HashMap<String, SenderWindow>>>,
+    events: broadcast::Sender<SecurityEvent>,
+    clock: Arc<dyn Clock>,
+}
+
+impl EnvelopeVerifier {
+    /// Create a verifier using the system clock
+    pub fn new(config: ReplayConfig) -> Self {
+        Self::with_clock(config, system_clock())
+    }
+
+    /// Create a verifier using the given clock
+    pub fn with_clock(config: ReplayConfig, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            config,
+            trusted: None,
+            senders: Arc::new(Mutex::new(HashMap::new())),
+            events: broadcast::channel(256).0,
+            clock,
+        }
+    }
+
+    /// Accept envelopes only from the given node IDs
+    ///
+    /// Without this, any sender whose node ID matches its key is accepted.
+    pub fn with_trusted_senders(mut self, node_ids: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.trusted = Some(Arc::new(node_ids.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// Replay protection settings
+    pub fn config(&self) -> &ReplayConfig {
+        &self.config
+    }
+
+    /// Subscribe to rejected messages
+    pub fn subscribe(&self) -> SecurityEvents {
+        SecurityEvents {
+            rx: self.events.subscribe(),
+        }
+    }
+
+    /// Verify an envelope and return the message it carries
+    ///
+    /// Rejected envelopes are reported as a [`SecurityEvent`] and returned as
+    /// [`CommsError::Security`].
+    pub fn open(&self, envelope: SignedEnvelope) -> Result<AegisMessage, CommsError> {
+        if let Err(violation) = self.check(&envelope) {
+            return Err(self.reject(Some(envelope.sender), violation));
+        }
+        Ok(envelope.message)
+    }
+
+    /// Report a rejected message and return the error for it
+    pub(crate) fn reject(&self, sender: Option<String>, violation: SecurityViolation) -> CommsError {
+        let _ = self.events.send(SecurityEvent {
+            sender,
+            violation: violation.clone(),
+            detected_at: self.clock.now_rfc3339(),
+        });
+        CommsError::Security(violation)
+    }
+
+    fn check(&self, envelope: &SignedEnvelope) -> Result<(), SecurityViolation> {
+        // Nothing is recorded for a sender before its signature is verified
+        let signed = envelope.signed_bytes().map_err(|_| SecurityViolation::InvalidSignature)?;
+        verify_signature(&envelope.public_key, &signed, &envelope.signature)
+            .map_err(|_| SecurityViolation::InvalidSignature)?;
+        if node_id_for(&envelope.public_key) != envelope.sender {
+            return Err(SecurityViolation::KeyMismatch);
+        }
+        if let Some(trusted) = &self.trusted {
+            if !trusted.contains(&envelope.sender) {
+                return Err(SecurityViolation::UntrustedSender);
+            }
+        }
+
+        let now = self.clock.now_ms();
+        let window = self.config.acceptance_window.as_millis() as u64;
+        if envelope.timestamp.abs_diff(now) > window {
+            return Err(SecurityViolation::OutsideWindow {
+                timestamp: envelope.timestamp,
+            });
+        }
+
+        let mut senders = self.senders.lock().unwrap();
+        if !senders.contains_key(&envelope.sender) && senders.len() >= self.config.max_senders.max(1) {
+            evict_sender(&mut senders, now.saturating_sub(window));
+        }
+        let sender = senders.entry(envelope.sender.clone()).or_default();
+        if !sender.accept(envelope.sequence, envelope.nonce, &self.config) {
+            return Err(SecurityViolation::Replayed {
+                sequence: envelope.sequence,
+            });
+        }
+        sender.last_seen = now;
+        Ok(())
+    }
+}
+
+/// Make room for a sender
+///
+/// Drops every sender not seen since `expired`, or else the least recently
+/// seen one.
+fn evict_sender(senders: &mut HashMap<String, SenderWindow>, expired: u64) {
+    let before = senders.len();
+    senders.retain(|_, sender| sender.last_seen >= expired);
+    if senders.len() < before {
+        return;
+    }
+
+    let oldest = senders
+        .iter()
+        .min_by_key(|(_, sender)| sender.last_seen)
+        .map(|(node_id, _)| node_id.clone());
+    if let Some(node_id) = oldest {
+        senders.remove(&node_id);
+    }
+}
@@ -0,0 +1,142 @@
+//! Errors of the comms system and the peer information they refer to
+//!
+//! These types do not depend on an async runtime, so they are available
+//! whatever features are enabled.
+
+use crate::framing::FramingError;
+use crate::protocol::MessageType;
+use crate::transport::{NetworkError, PeerCredentials, PeerIdentity, TransportAddr};
+use aegis_core::error::AegisError;
+use std::fmt;
+
+/// Errors that can occur in the comms system
+#[derive(Debug)]
+pub enum CommsError {
+    Network(NetworkError),
+    Framing(FramingError),
+    Serialization(bincode::Error),
+    ChannelClosed,
+    /// The message was written for another protocol version
+    VersionMismatch { expected: u32, actual: u32 },
+    /// The message is of another type than requested
+    UnexpectedMessageType { expected: MessageType, actual: MessageType },
+    /// No handler is registered for the message type
+    UnhandledMessageType(MessageType),
+    /// The message body is malformed or does not match its header
+    InvalidMessage(String),
+    /// No endpoint is known for the agent
+    UnknownAgent(String),
+    /// The outgoing buffer of the connection is full
+    BufferFull,
+    /// The topic or topic pattern is malformed
+    InvalidTopic(String),
+    /// The message failed signature or replay checks
+    Security(SecurityViolation),
+    /// A local ID is not the node ID of the identity it was given
+    IdentityMismatch { id: String, node_id: String },
+}
+
+impl fmt::Display for CommsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommsError::Network(e) => write!(f, "Network error: {}", e),
+            CommsError::Framing(e) => write!(f, "Framing error: {:?}", e),
+            CommsError::Serialization(e) => write!(f, "Serialization error: {}", e),
+            CommsError::ChannelClosed => write!(f, "Connection closed"),
+            CommsError::VersionMismatch { expected, actual } => {
+                write!(f, "Protocol version {} is not supported, expected {}", actual, expected)
+            }
+            CommsError::UnexpectedMessageType { expected, actual } => {
+                write!(f, "Expected a {:?} message, got {:?}", expected, actual)
+            }
+            CommsError::UnhandledMessageType(t) => write!(f, "No handler for {:?} messages", t),
+            CommsError::InvalidMessage(e) => write!(f, "Invalid message: {}", e),
+            CommsError::UnknownAgent(agent_id) => write!(f, "No endpoint known for agent {}", agent_id),
+            CommsError::BufferFull => write!(f, "Outgoing buffer is full"),
+            CommsError::InvalidTopic(topic) => write!(f, "Invalid topic: {}", topic),
+            CommsError::Security(violation) => write!(f, "Message rejected: {}", violation),
+            CommsError::IdentityMismatch { id, node_id } => {
+                write!(f, "ID {} is not the node ID {} of the identity", id, node_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommsError {}
+
+impl From<CommsError> for AegisError {
+    fn from(err: CommsError) -> Self {
+        match err {
+            CommsError::UnknownAgent(_) => AegisError::NotFound(err.to_string()),
+            CommsError::Security(_) => AegisError::Security(err.to_string()),
+            other => AegisError::Communication(other.to_string()),
+        }
+    }
+}
+
+impl From<NetworkError> for CommsError {
+    fn from(err: NetworkError) -> Self {
+        CommsError::Network(err)
+    }
+}
+
+impl From<FramingError> for CommsError {
+    fn from(err: FramingError) -> Self {
+        CommsError::Framing(err)
+    }
+}
+
+impl From<bincode::Error> for CommsError {
+    fn from(err: bincode::Error) -> Self {
+        CommsError::Serialization(err)
+    }
+}
+
+/// Information about the peer that sent a message
+#[derive(Debug, Clone)]
+pub struct PeerInfo {
+    /// Remote address of the connection
+    pub addr: TransportAddr,
+    /// Verified identity of the peer, if the transport authenticates peers
+    pub identity: Option<PeerIdentity>,
+    /// Credentials of the peer process, for local transports
+    pub credentials: Option<PeerCredentials>,
+}
+
+/// Why a message was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecurityViolation {
+    /// The message was not signed, but signatures are required
+    Unsigned,
+    /// The signature does not match the envelope
+    InvalidSignature,
+    /// The sender ID does not belong to the public key
+    KeyMismatch,
+    /// The sender is not among the trusted senders
+    UntrustedSender,
+    /// The timestamp is outside the acceptance window
+    OutsideWindow {
+        /// Timestamp of the envelope
+        timestamp: u64,
+    },
+    /// The envelope was seen before, or is too far behind the sender's sequence
+    Replayed {
+        /// Sequence number of the envelope
+        sequence: u64,
+    },
+}
+
+impl fmt::Display for SecurityViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecurityViolation::Unsigned => write!(f, "message is not signed"),
+            SecurityViolation::InvalidSignature => write!(f, "signature verification failed"),
+            SecurityViolation::KeyMismatch => write!(f, "sender does not match its public key"),
+            SecurityViolation::UntrustedSender => write!(f, "sender is not trusted"),
+            SecurityViolation::OutsideWindow { timestamp } => {
+                write!(f, "timestamp {} is outside the acceptance window", timestamp)
+            }
+            SecurityViolation::Replayed { sequence } => write!(f, "sequence {} was replayed", sequence),
+        }
+    }
+}
@@ -1,11 +1,236 @@
-use crate::transport::{MessageStream, NetworkError};
+use crate::chunking::ChunkConfig;
+use crate::codec::{self, CodecId};
+use crate::compression::{Compression, CompressionConfig};
+use crate::transport::{MessageStream, NetworkError, PeerCredentials, PeerIdentity, TransportAddr};
 use bytes::{Bytes, BytesMut, BufMut};
 use byteorder::{BigEndian, ByteOrder};
-use std::net::SocketAddr;
 
-const MAX_MESSAGE_SIZE: u32 = 16 * 1024 * 1024; // 16MB
+pub(crate) const MAX_MESSAGE_SIZE: u32 = 16 * 1024 * 1024; // 16MB
 const LENGTH_PREFIX_SIZE: usize = 4;
 
+/// Magic number opening every v2 frame
+///
+/// Read as the high half of a v1 length prefix it gives a length far above
+/// `MAX_MESSAGE_SIZE`, so a v2 frame can never be mistaken for a valid v1 one.
+pub const FRAME_MAGIC: u16 = 0xAE61;
+
+/// Size of the v2 frame header in bytes
+///
+/// Layout (all integers big-endian):
+///
+/// | offset | size | field      |
+/// |--------|------|------------|
+/// | 0      | 2    | magic      |
+/// | 2      | 1    | version    |
+/// | 3      | 1    | flags      |
+/// | 4      | 1    | frame type |
+/// | 5      | 1    | compression|
+/// | 6      | 1    | accepted   |
+/// | 7      | 1    | codecs     |
+/// | 8      | 4    | length     |
+/// | 12     | 4    | CRC32C     |
+///
+/// The CRC32C covers the first 12 header bytes followed by the payload.
+/// The compression byte names the algorithm of a `COMPRESSED` payload, and
+/// the accepted byte is the set of algorithms the sender can decompress.
+/// The low half of the codecs byte is the codec of the payload and the high
+/// half the set of codecs the sender can decode.
+pub const FRAME_HEADER_SIZE: usize = 16;
+
+/// Offset of the checksum within the v2 header
+const CHECKSUM_OFFSET: usize = 12;
+
+/// Wire format version of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FrameVersion {
+    /// Bare 4-byte length prefix
+    V1 = 1,
+    /// 16-byte header with magic, flags, frame type and checksum
+    V2 = 2,
+}
+
+/// Flags carried in the v2 frame header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FrameFlags(u8);
+
+impl FrameFlags {
+    /// No flags set
+    pub const NONE: FrameFlags = FrameFlags(0);
+    /// Payload is compressed
+    pub const COMPRESSED: FrameFlags = FrameFlags(0x01);
+    /// Payload is encrypted
+    pub const ENCRYPTED: FrameFlags = FrameFlags(0x02);
+    /// Last frame of a stream
+    pub const END_OF_STREAM: FrameFlags = FrameFlags(0x04);
+
+    /// Create flags from their wire representation
+    pub fn from_bits(bits: u8) -> Self {
+        FrameFlags(bits)
+    }
+
+    /// Wire representation of the flags
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Whether all flags in `other` are set
+    pub fn contains(self, other: FrameFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Set the flags in `other`
+    pub fn insert(&mut self, other: FrameFlags) {
+        self.0 |= other.0;
+    }
+
+    /// Clear the flags in `other`
+    pub fn remove(&mut self, other: FrameFlags) {
+        self.0 &= !other.0;
+    }
+}
+
+impl std::ops::BitOr for FrameFlags {
+    type Output = FrameFlags;
+
+    fn bitor(self, rhs: FrameFlags) -> FrameFlags {
+        FrameFlags(self.0 | rhs.0)
+    }
+}
+
+/// Kind of payload carried by a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameType {
+    /// Application message
+    Data = 0,
+    /// Connection-level control message
+    Control = 1,
+    /// Liveness probe
+    Heartbeat = 2,
+    /// Error report from the peer
+    Error = 3,
+    /// Part of a payload too large for one frame, see [`ChunkSender`](crate::ChunkSender)
+    Chunk = 4,
+}
+
+impl FrameType {
+    /// Parse a frame type from its wire representation
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(FrameType::Data),
+            1 => Some(FrameType::Control),
+            2 => Some(FrameType::Heartbeat),
+            3 => Some(FrameType::Error),
+            4 => Some(FrameType::Chunk),
+            _ => None,
+        }
+    }
+}
+
+/// A single frame read from or written to a stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Kind of payload
+    pub frame_type: FrameType,
+    /// Header flags
+    pub flags: FrameFlags,
+    /// Codec of the payload
+    pub codec: CodecId,
+    /// Frame payload
+    pub payload: Bytes,
+}
+
+impl Frame {
+    /// Create a frame
+    pub fn new(frame_type: FrameType, payload: Bytes) -> Self {
+        Self {
+            frame_type,
+            flags: FrameFlags::NONE,
+            codec: CodecId::Bincode,
+            payload,
+        }
+    }
+
+    /// Create a data frame
+    pub fn data(payload: Bytes) -> Self {
+        Self::new(FrameType::Data, payload)
+    }
+
+    /// Create a control frame
+    pub fn control(payload: Bytes) -> Self {
+        Self::new(FrameType::Control, payload)
+    }
+
+    /// Set header flags
+    pub fn with_flags(mut self, flags: FrameFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Set the codec of the payload
+    pub fn with_codec(mut self, codec: CodecId) -> Self {
+        self.codec = codec;
+        self
+    }
+}
+
+/// Configuration for a framed stream
+#[derive(Debug, Clone)]
+pub struct FramingConfig {
+    /// Version used for outgoing frames
+    pub write_version: FrameVersion,
+    /// Whether v1 frames from the peer are accepted
+    pub accept_v1: bool,
+    /// Switch outgoing frames to v1 once the peer is seen speaking v1
+    pub fallback_to_v1: bool,
+    /// Maximum payload size of data and chunk frames
+    pub max_data_frame_size: u32,
+    /// Maximum payload size of control, heartbeat and error frames
+    pub max_control_frame_size: u32,
+    /// Whether checksums of incoming v2 frames are verified
+    pub verify_checksum: bool,
+    /// Payload compression of v2 frames
+    pub compression: CompressionConfig,
+    /// Codecs this side decodes, in order of preference for outgoing messages
+    pub codecs: Vec<CodecId>,
+    /// Chunked transfers of messages larger than one chunk; chunks must fit
+    /// in `max_data_frame_size`
+    pub chunking: ChunkConfig,
+}
+
+impl FramingConfig {
+    /// Configuration that reads and writes only the legacy v1 format
+    pub fn v1() -> Self {
+        Self {
+            write_version: FrameVersion::V1,
+            ..Self::default()
+        }
+    }
+
+    /// Maximum payload size for a frame type
+    pub fn max_frame_size(&self, frame_type: FrameType) -> u32 {
+        match frame_type {
+            FrameType::Data | FrameType::Chunk => self.max_data_frame_size,
+            _ => self.max_control_frame_size,
+        }
+    }
+}
+
+impl Default for FramingConfig {
+    fn default() -> Self {
+        Self {
+            write_version: FrameVersion::V2,
+            accept_v1: true,
+            fallback_to_v1: true,
+            max_data_frame_size: MAX_MESSAGE_SIZE,
+            max_control_frame_size: 64 * 1024,
+            verify_checksum: true,
+            compression: CompressionConfig::default(),
+            codecs: CodecId::supported(),
+            chunking: ChunkConfig::default(),
+        }
+    }
+}
+
 /// Errors specific to message framing
 #[derive(Debug)]
 pub enum FramingError {
@@ -17,6 +242,22 @@ pub enum FramingError {
     InvalidLengthPrefix,
     /// Incomplete message received
     IncompleteMessage,
+    /// Frame header carries a version this implementation does not support
+    UnsupportedVersion(u8),
+    /// Frame header carries an unknown frame type
+    UnknownFrameType(u8),
+    /// Frame checksum does not match its contents
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// Peer sent a v1 frame but v1 is not accepted
+    LegacyFrameRejected,
+    /// Frame is compressed with an algorithm that is not accepted
+    UnknownCompression(u8),
+    /// Compressed payload could not be decompressed
+    Decompression(String),
+    /// Decompressed payload would exceed the given size
+    DecompressionLimitExceeded(u32),
+    /// Frame payload is encoded with an unknown codec
+    UnknownCodec(u8),
 }
 
 impl From<NetworkError> for FramingError {
@@ -29,38 +270,86 @@ impl From<NetworkError> for FramingError {
 pub struct FramedMessageStream<S: MessageStream> {
     inner: S,
     read_buffer: BytesMut,
+    config: FramingConfig,
+    /// Version the peer was last seen speaking
+    peer_version: Option<FrameVersion>,
+    /// Compression algorithms the peer last advertised
+    peer_compression: u8,
+    /// Codecs the peer last advertised
+    peer_codecs: u8,
 }
 
 impl<S: MessageStream> FramedMessageStream<S> {
     /// Create a new framed stream
+    ///
+    /// The stream writes v1 frames for compatibility with existing peers and
+    /// reads both v1 and v2 frames. Use [`FramedMessageStream::with_config`]
+    /// to write v2 frames.
     pub fn new(inner: S) -> Self {
+        Self::with_config(inner, FramingConfig::v1())
+    }
+
+    /// Create a new framed stream with the given configuration
+    pub fn with_config(inner: S, config: FramingConfig) -> Self {
         Self {
             inner,
             read_buffer: BytesMut::with_capacity(8192),
+            config,
+            peer_version: None,
+            peer_compression: 0,
+            peer_codecs: 0,
+        }
+    }
+
+    /// Framing configuration of this stream
+    pub fn config(&self) -> &FramingConfig {
+        &self.config
+    }
+
+    /// Frame version the peer was last seen speaking, if any frame was read
+    pub fn peer_version(&self) -> Option<FrameVersion> {
+        self.peer_version
+    }
+
+    /// Algorithm used for outgoing frames above the threshold
+    ///
+    /// `None` until a v2 frame from the peer advertised an algorithm this side
+    /// also accepts.
+    pub fn compression(&self) -> Option<Compression> {
+        self.config.compression.negotiate(self.peer_compression)
+    }
+
+    /// Codec for outgoing messages
+    ///
+    /// Bincode until a v2 frame from the peer advertised the codecs it decodes.
+    pub fn codec(&self) -> CodecId {
+        codec::negotiate(&self.config.codecs, self.peer_codecs)
+    }
+
+    /// Version used for the next outgoing frame
+    pub fn write_version(&self) -> FrameVersion {
+        match self.peer_version {
+            Some(FrameVersion::V1) if self.config.fallback_to_v1 => FrameVersion::V1,
+            _ => self.config.write_version,
         }
     }
-    
+
     /// Read a complete framed message
+    ///
+    /// Returns the payload of the next frame regardless of its type; use
+    /// [`FramedMessageStream::read_frame`] to inspect the header.
     pub async fn read_framed_message(&mut self) -> Result<Option<Bytes>, FramingError> {
+        Ok(self.read_frame().await?.map(|frame| frame.payload))
+    }
+
+    /// Read a complete frame
+    pub async fn read_frame(&mut self) -> Result<Option<Frame>, FramingError> {
         loop {
-            // Check if we have a complete message in the buffer
-            if self.read_buffer.len() >= LENGTH_PREFIX_SIZE {
-                let message_len = BigEndian::read_u32(&self.read_buffer[..LENGTH_PREFIX_SIZE]) as usize;
-                
-                if message_len > MAX_MESSAGE_SIZE as usize {
-                    return Err(FramingError::MessageTooLarge(message_len as u32));
-                }
-                
-                let total_len = LENGTH_PREFIX_SIZE + message_len;
-                
-                if self.read_buffer.len() >= total_len {
-                    // We have a complete message
-                    let _ = self.read_buffer.split_to(LENGTH_PREFIX_SIZE);
-                    let message = self.read_buffer.split_to(message_len).freeze();
-                    return Ok(Some(message));
-                }
+            // Check if we have a complete frame in the buffer
+            if let Some(frame) = self.try_parse_frame()? {
+                return Ok(Some(frame));
             }
-            
+
             // Need more data
             match self.inner.read_message().await? {
                 Some(data) => {
@@ -76,29 +365,246 @@ impl<S: MessageStream> FramedMessageStream<S> {
             }
         }
     }
-    
+
+    /// Parse one frame from the read buffer if it is complete
+    fn try_parse_frame(&mut self) -> Result<Option<Frame>, FramingError> {
+        if self.read_buffer.len() < 2 {
+            return Ok(None);
+        }
+
+        if BigEndian::read_u16(&self.read_buffer[..2]) == FRAME_MAGIC {
+            self.try_parse_v2()
+        } else if self.config.accept_v1 {
+            self.try_parse_v1()
+        } else {
+            Err(FramingError::LegacyFrameRejected)
+        }
+    }
+
+    /// Parse a v1 length-prefixed frame
+    fn try_parse_v1(&mut self) -> Result<Option<Frame>, FramingError> {
+        if self.read_buffer.len() < LENGTH_PREFIX_SIZE {
+            return Ok(None);
+        }
+
+        let message_len = BigEndian::read_u32(&self.read_buffer[..LENGTH_PREFIX_SIZE]);
+
+        if message_len > self.config.max_data_frame_size {
+            return Err(FramingError::MessageTooLarge(message_len));
+        }
+
+        let total_len = LENGTH_PREFIX_SIZE + message_len as usize;
+        if self.read_buffer.len() < total_len {
+            return Ok(None);
+        }
+
+        let _ = self.read_buffer.split_to(LENGTH_PREFIX_SIZE);
+        let payload = self.read_buffer.split_to(message_len as usize).freeze();
+        self.peer_version = Some(FrameVersion::V1);
+
+        Ok(Some(Frame::data(payload)))
+    }
+
+    /// Parse a v2 frame
+    fn try_parse_v2(&mut self) -> Result<Option<Frame>, FramingError> {
+        if self.read_buffer.len() < FRAME_HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let header = &self.read_buffer[..FRAME_HEADER_SIZE];
+
+        let version = header[2];
+        if version != FrameVersion::V2 as u8 {
+            return Err(FramingError::UnsupportedVersion(version));
+        }
+
+        let mut flags = FrameFlags::from_bits(header[3]);
+        let frame_type = FrameType::from_u8(header[4])
+            .ok_or(FramingError::UnknownFrameType(header[4]))?;
+        let algorithm = header[5];
+        let peer_compression = header[6];
+        let codec = CodecId::from_u8(header[7] & 0x0f).ok_or(FramingError::UnknownCodec(header[7] & 0x0f))?;
+        let peer_codecs = header[7] >> 4;
+        let length = BigEndian::read_u32(&header[8..12]);
+        let expected = BigEndian::read_u32(&header[CHECKSUM_OFFSET..FRAME_HEADER_SIZE]);
+
+        if length > self.config.max_frame_size(frame_type) {
+            return Err(FramingError::MessageTooLarge(length));
+        }
+
+        let total_len = FRAME_HEADER_SIZE + length as usize;
+        if self.read_buffer.len() < total_len {
+            return Ok(None);
+        }
+
+        let frame_bytes = self.read_buffer.split_to(total_len);
+        let payload = &frame_bytes[FRAME_HEADER_SIZE..];
+
+        if self.config.verify_checksum {
+            let actual = frame_checksum(&frame_bytes[..CHECKSUM_OFFSET], payload);
+            if actual != expected {
+                return Err(FramingError::ChecksumMismatch { expected, actual });
+            }
+        }
+
+        self.peer_version = Some(FrameVersion::V2);
+        self.peer_compression = peer_compression;
+        self.peer_codecs = peer_codecs;
+
+        // Frames flagged by the application without an algorithm pass through as-is
+        let payload = if flags.contains(FrameFlags::COMPRESSED) && algorithm != 0 {
+            let compression = Compression::from_u8(algorithm)
+                .filter(|compression| self.config.compression.accepts(*compression))
+                .ok_or(FramingError::UnknownCompression(algorithm))?;
+            let limit = self
+                .config
+                .compression
+                .decompressed_limit(payload.len(), self.config.max_frame_size(frame_type));
+            flags.remove(FrameFlags::COMPRESSED);
+            Bytes::from(compression.decompress(payload, limit)?)
+        } else {
+            Bytes::copy_from_slice(payload)
+        };
+
+        Ok(Some(Frame {
+            frame_type,
+            flags,
+            codec,
+            payload,
+        }))
+    }
+
     /// Write a framed message
     pub async fn write_framed_message(&mut self, msg: Bytes) -> Result<(), FramingError> {
-        if msg.len() > MAX_MESSAGE_SIZE as usize {
-            return Err(FramingError::MessageTooLarge(msg.len() as u32));
-        }
-        
-        let mut length_prefix = [0u8; LENGTH_PREFIX_SIZE];
-        BigEndian::write_u32(&mut length_prefix, msg.len() as u32);
-        
-        self.inner.write_message(Bytes::copy_from_slice(&length_prefix)).await?;
-        self.inner.write_message(msg).await?;
-        
+        self.write_frame(Frame::data(msg)).await
+    }
+
+    /// Write a frame
+    ///
+    /// When writing v1, only the payload is sent; the frame type and flags
+    /// cannot be represented and must be left at their defaults.
+    pub async fn write_frame(&mut self, frame: Frame) -> Result<(), FramingError> {
+        let limit = self.config.max_frame_size(frame.frame_type);
+        if frame.payload.len() > limit as usize {
+            return Err(FramingError::MessageTooLarge(frame.payload.len() as u32));
+        }
+
+        match self.write_version() {
+            FrameVersion::V1 => {
+                if frame.frame_type != FrameType::Data || frame.flags != FrameFlags::NONE {
+                    return Err(FramingError::UnsupportedVersion(FrameVersion::V1 as u8));
+                }
+
+                let mut length_prefix = [0u8; LENGTH_PREFIX_SIZE];
+                BigEndian::write_u32(&mut length_prefix, frame.payload.len() as u32);
+
+                self.inner.write_message(Bytes::copy_from_slice(&length_prefix)).await?;
+                self.inner.write_message(frame.payload).await?;
+            }
+            FrameVersion::V2 => {
+                let (frame, algorithm) = self.compress(frame);
+                let accepted = Accepted {
+                    compression: self.config.compression.accepted(),
+                    codecs: self.config.codecs.iter().fold(0, |set, codec| set | codec.bit()),
+                };
+                let encoded = encode_v2(&frame, algorithm, accepted);
+                self.inner.write_message(encoded).await?;
+            }
+        }
+
         Ok(())
     }
-    
+
+    /// Compress a frame with the negotiated algorithm if that is worthwhile
+    ///
+    /// Returns the frame to send and the algorithm byte for its header.
+    fn compress(&self, frame: Frame) -> (Frame, u8) {
+        let config = &self.config.compression;
+        if frame.flags.contains(FrameFlags::COMPRESSED) || frame.payload.len() < config.threshold {
+            return (frame, 0);
+        }
+        let compression = match self.compression() {
+            Some(compression) => compression,
+            None => return (frame, 0),
+        };
+
+        match compression.compress(&frame.payload) {
+            // The peer would reject a frame expanding beyond its ratio limit
+            Some(compressed)
+                if compressed.len() < frame.payload.len()
+                    && config.decompressed_limit(compressed.len(), u32::MAX) >= frame.payload.len() =>
+            {
+                let flags = frame.flags | FrameFlags::COMPRESSED;
+                (
+                    Frame {
+                        payload: Bytes::from(compressed),
+                        flags,
+                        ..frame
+                    },
+                    compression as u8,
+                )
+            }
+            _ => (frame, 0),
+        }
+    }
+
     /// Get the peer address from the underlying stream
-    pub fn peer_addr(&self) -> Result<SocketAddr, NetworkError> {
+    pub fn peer_addr(&self) -> Result<TransportAddr, NetworkError> {
         self.inner.peer_addr()
     }
-    
+
+    /// Get the verified peer identity from the underlying stream
+    pub fn peer_identity(&self) -> Option<PeerIdentity> {
+        self.inner.peer_identity()
+    }
+
+    /// Get the peer process credentials from the underlying stream
+    pub fn peer_credentials(&self) -> Option<PeerCredentials> {
+        self.inner.peer_credentials()
+    }
+
     /// Shutdown the underlying stream
     pub async fn shutdown(&mut self) -> Result<(), NetworkError> {
         self.inner.shutdown().await
     }
-} 
\ No newline at end of file
+}
+
+/// Encode a frame in the v2 wire format
+///
+/// The frame is written as-is, without compression and without advertising
+/// any accepted compression algorithms or codecs.
+pub fn encode_v2_frame(frame: &Frame) -> Bytes {
+    encode_v2(frame, 0, Accepted::default())
+}
+
+/// What the sender of a frame advertises it can read
+#[derive(Default)]
+struct Accepted {
+    compression: u8,
+    codecs: u8,
+}
+
+/// Encode a v2 frame with the negotiation fields of the header
+fn encode_v2(frame: &Frame, compression: u8, accepted: Accepted) -> Bytes {
+    let mut buf = BytesMut::with_capacity(FRAME_HEADER_SIZE + frame.payload.len());
+
+    buf.put_u16(FRAME_MAGIC);
+    buf.put_u8(FrameVersion::V2 as u8);
+    buf.put_u8(frame.flags.bits());
+    buf.put_u8(frame.frame_type as u8);
+    buf.put_u8(compression);
+    buf.put_u8(accepted.compression);
+    buf.put_u8(accepted.codecs << 4 | frame.codec as u8);
+    buf.put_u32(frame.payload.len() as u32);
+
+    let checksum = frame_checksum(&buf[..CHECKSUM_OFFSET], &frame.payload);
+    buf.put_u32(checksum);
+    buf.extend_from_slice(&frame.payload);
+
+    buf.freeze()
+}
+
+/// CRC32C over the header fields and the payload
+fn frame_checksum(header: &[u8], payload: &[u8]) -> u32 {
+    crc32c::crc32c_append(crc32c::crc32c(header), payload)
+}
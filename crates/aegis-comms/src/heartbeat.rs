@@ -0,0 +1,409 @@
+//! Heartbeats and failure detection
+//!
+//! A [`HeartbeatService`] periodically sends an [`AgentHeartbeatMessage`]
+//! to every known peer. On the receiving side a [`HeartbeatMonitor`] keeps
+//! a phi-accrual failure detector per peer, which turns the observed arrival
+//! intervals into a suspicion level instead of a fixed timeout, and publishes
+//! a [`HealthEvent`] when a peer crosses the threshold.
+
+use crate::dispatch::{MessageContext, MessageDispatcher};
+use crate::manager::CommsClient;
+use crate::protocol::{AegisMessage, AgentHeartbeatMessage, AgentStatus};
+use aegis_core::clock::{system_clock, Clock};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+/// Settings of a [`PhiAccrualDetector`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetectorConfig {
+    /// Suspicion level at which a peer is considered failed
+    ///
+    /// A phi of 8 means a one in 10^8 chance that the peer is still alive.
+    pub threshold: f64,
+    /// Arrival intervals kept for the estimate
+    pub max_samples: usize,
+    /// Lower bound on the standard deviation, so very regular heartbeats do
+    /// not make the detector oversensitive
+    pub min_std_deviation: Duration,
+    /// Extra delay tolerated on top of the mean interval, e.g. for GC pauses
+    pub acceptable_pause: Duration,
+    /// Expected interval before any has been measured
+    pub first_heartbeat_estimate: Duration,
+}
+
+impl Default for DetectorConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 8.0,
+            max_samples: 200,
+            min_std_deviation: Duration::from_millis(100),
+            acceptable_pause: Duration::ZERO,
+            first_heartbeat_estimate: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Phi-accrual failure detector for a single peer
+///
+/// Heartbeat arrival times are recorded as offsets on a monotonic clock, see
+/// [`Clock::elapsed`]. The detector assumes intervals are normally
+/// distributed and reports phi, the negative base-10 logarithm of the
+/// probability that a heartbeat would arrive later than now.
+#[derive(Debug, Clone)]
+pub struct PhiAccrualDetector {
+    config: DetectorConfig,
+    /// Recent arrival intervals in milliseconds
+    intervals: VecDeque<f64>,
+    last_heartbeat: Option<Duration>,
+}
+
+impl PhiAccrualDetector {
+    /// Create a detector that has not seen a heartbeat yet
+    pub fn new(config: DetectorConfig) -> Self {
+        Self {
+            config,
+            intervals: VecDeque::new(),
+            last_heartbeat: None,
+        }
+    }
+
+    /// Record a heartbeat arriving at `now`
+    pub fn heartbeat(&mut self, now: Duration) {
+        match self.last_heartbeat {
+            Some(last) => self.push_interval(now.saturating_sub(last).as_secs_f64() * 1000.0),
+            None => {
+                // Seed the estimate so phi is meaningful before real samples exist
+                let mean = self.config.first_heartbeat_estimate.as_secs_f64() * 1000.0;
+                let std_deviation = mean / 4.0;
+                self.push_interval(mean - std_deviation);
+                self.push_interval(mean + std_deviation);
+            }
+        }
+        self.last_heartbeat = Some(now);
+    }
+
+    fn push_interval(&mut self, interval: f64) {
+        if self.intervals.len() >= self.config.max_samples.max(1) {
+            self.intervals.pop_front();
+        }
+        self.intervals.push_back(interval);
+    }
+
+    /// Suspicion level at `now`; zero before the first heartbeat
+    pub fn phi(&self, now: Duration) -> f64 {
+        let last = match self.last_heartbeat {
+            Some(last) => last,
+            None => return 0.0,
+        };
+
+        let count = self.intervals.len() as f64;
+        let mean = self.intervals.iter().sum::<f64>() / count;
+        let variance = self.intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / count;
+
+        let mean = mean + self.config.acceptable_pause.as_secs_f64() * 1000.0;
+        let std_deviation = variance
+            .sqrt()
+            .max(self.config.min_std_deviation.as_secs_f64() * 1000.0)
+            .max(f64::EPSILON);
+        let elapsed = now.saturating_sub(last).as_secs_f64() * 1000.0;
+
+        // Logistic approximation of the normal distribution's tail
+        let y = (elapsed - mean) / std_deviation;
+        let e = (-y * (1.5976 + 0.070566 * y * y)).exp();
+        if elapsed > mean {
+            -(e / (1.0 + e)).log10()
+        } else {
+            -(1.0 - 1.0 / (1.0 + e)).log10()
+        }
+    }
+
+    /// Whether phi at `now` is still below the threshold
+    pub fn is_available(&self, now: Duration) -> bool {
+        self.phi(now) < self.config.threshold
+    }
+
+    /// When the last heartbeat arrived
+    pub fn last_heartbeat(&self) -> Option<Duration> {
+        self.last_heartbeat
+    }
+}
+
+/// A peer whose heartbeats stopped arriving
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuspectedFailure {
+    /// ID of the silent agent
+    pub agent_id: String,
+    /// Suspicion level when the failure was detected
+    pub phi: f64,
+    /// Status reported in the last heartbeat
+    pub last_status: AgentStatus,
+    /// Time since the last heartbeat
+    pub silence: Duration,
+    /// When the failure was detected, in RFC 3339 format
+    pub detected_at: String,
+}
+
+/// Change in the health of a monitored peer
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthEvent {
+    /// The peer's suspicion level crossed the threshold
+    Suspected(SuspectedFailure),
+    /// Heartbeats from a suspected peer resumed
+    Recovered {
+        /// ID of the agent
+        agent_id: String,
+    },
+    /// The peer announced it was shutting down and then went silent
+    Departed {
+        /// ID of the agent
+        agent_id: String,
+    },
+}
+
+/// Subscription to the events of a [`HeartbeatMonitor`]
+pub struct HealthEvents {
+    rx: broadcast::Receiver<HealthEvent>,
+}
+
+impl HealthEvents {
+    /// Wait for the next event; `None` once the monitor is gone
+    ///
+    /// Events missed because the subscriber fell behind are skipped.
+    pub async fn recv(&mut self) -> Option<HealthEvent> {
+        loop {
+            match self.rx.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// What the monitor knows about a peer
+#[derive(Debug)]
+struct PeerHealth {
+    detector: PhiAccrualDetector,
+    status: AgentStatus,
+    suspected: bool,
+}
+
+/// Tracks heartbeats from peers and reports the ones that stop sending
+///
+/// Cloning yields a handle to the same monitor.
+#[derive(Clone)]
+pub struct HeartbeatMonitor {
+    config: DetectorConfig,
+    peers: Arc<Mutex<HashMap<String, PeerHealth>>>,
+    events: broadcast::Sender<HealthEvent>,
+    clock: Arc<dyn Clock>,
+}
+
+impl HeartbeatMonitor {
+    /// Create a monitor using the system clock
+    pub fn new(config: DetectorConfig) -> Self {
+        Self::with_clock(config, system_clock())
+    }
+
+    /// Create a monitor using the given clock
+    pub fn with_clock(config: DetectorConfig, clock: Arc<dyn Clock>) -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            config,
+            peers: Arc::new(Mutex::new(HashMap::new())),
+            events,
+            clock,
+        }
+    }
+
+    /// Subscribe to health events
+    pub fn subscribe(&self) -> HealthEvents {
+        HealthEvents {
+            rx: self.events.subscribe(),
+        }
+    }
+
+    /// Record a received heartbeat
+    pub fn record(&self, heartbeat: &AgentHeartbeatMessage) {
+        let now = self.clock.elapsed();
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers.entry(heartbeat.agent_id.clone()).or_insert_with(|| PeerHealth {
+            detector: PhiAccrualDetector::new(self.config),
+            status: heartbeat.status,
+            suspected: false,
+        });
+
+        peer.detector.heartbeat(now);
+        peer.status = heartbeat.status;
+        if peer.suspected {
+            peer.suspected = false;
+            let _ = self.events.send(HealthEvent::Recovered {
+                agent_id: heartbeat.agent_id.clone(),
+            });
+        }
+    }
+
+    /// Record heartbeats received by `dispatcher`
+    pub fn register(&self, dispatcher: &mut MessageDispatcher) {
+        let monitor = self.clone();
+        dispatcher.register(move |heartbeat: AgentHeartbeatMessage, _ctx: MessageContext| {
+            monitor.record(&heartbeat);
+            std::future::ready(())
+        });
+    }
+
+    /// Evaluate every peer and publish events for the ones that changed
+    ///
+    /// A peer is reported once when it crosses the threshold, and again only
+    /// after it has recovered. Peers that announced they were shutting down
+    /// are reported as departed and forgotten.
+    pub fn check(&self) -> Vec<HealthEvent> {
+        let now = self.clock.elapsed();
+        let mut peers = self.peers.lock().unwrap();
+        let mut events = Vec::new();
+
+        peers.retain(|agent_id, peer| {
+            let phi = peer.detector.phi(now);
+            if peer.suspected || phi < self.config.threshold {
+                return true;
+            }
+
+            if peer.status == AgentStatus::ShuttingDown {
+                events.push(HealthEvent::Departed {
+                    agent_id: agent_id.clone(),
+                });
+                return false;
+            }
+
+            peer.suspected = true;
+            let last = peer.detector.last_heartbeat().unwrap_or(now);
+            events.push(HealthEvent::Suspected(SuspectedFailure {
+                agent_id: agent_id.clone(),
+                phi,
+                last_status: peer.status,
+                silence: now.saturating_sub(last),
+                detected_at: self.clock.now_rfc3339(),
+            }));
+            true
+        });
+        drop(peers);
+
+        for event in &events {
+            let _ = self.events.send(event.clone());
+        }
+        events
+    }
+
+    /// Check peers every `interval` until the task is aborted
+    pub fn spawn(&self, interval: Duration) -> JoinHandle<()> {
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                monitor.check();
+            }
+        })
+    }
+
+    /// Current suspicion level of a peer
+    pub fn phi(&self, agent_id: &str) -> Option<f64> {
+        let now = self.clock.elapsed();
+        self.peers.lock().unwrap().get(agent_id).map(|peer| peer.detector.phi(now))
+    }
+
+    /// Status from the last heartbeat of a peer
+    pub fn status(&self, agent_id: &str) -> Option<AgentStatus> {
+        self.peers.lock().unwrap().get(agent_id).map(|peer| peer.status)
+    }
+
+    /// Whether a peer is currently suspected of having failed
+    pub fn is_suspected(&self, agent_id: &str) -> bool {
+        self.peers.lock().unwrap().get(agent_id).is_some_and(|peer| peer.suspected)
+    }
+
+    /// IDs of the monitored peers, sorted
+    pub fn peers(&self) -> Vec<String> {
+        let mut peers: Vec<String> = self.peers.lock().unwrap().keys().cloned().collect();
+        peers.sort();
+        peers
+    }
+}
+
+/// Periodically announces this agent's status to its peers
+///
+/// Cloning yields a handle sharing the same status.
+#[derive(Clone)]
+pub struct HeartbeatService {
+    agent_id: String,
+    interval: Duration,
+    status: Arc<Mutex<AgentStatus>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl HeartbeatService {
+    /// Create a service sending a heartbeat every `interval`
+    pub fn new(agent_id: impl Into<String>, interval: Duration) -> Self {
+        Self {
+            agent_id: agent_id.into(),
+            interval,
+            status: Arc::new(Mutex::new(AgentStatus::Active)),
+            clock: system_clock(),
+        }
+    }
+
+    /// Use the given clock for heartbeat timestamps
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Change the status sent in later heartbeats
+    pub fn set_status(&self, status: AgentStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+
+    /// Status sent in heartbeats
+    pub fn status(&self) -> AgentStatus {
+        *self.status.lock().unwrap()
+    }
+
+    /// Heartbeat describing the current status
+    pub fn heartbeat(&self) -> AgentHeartbeatMessage {
+        AgentHeartbeatMessage {
+            agent_id: self.agent_id.clone(),
+            timestamp: self.clock.now_ms(),
+            status: self.status(),
+        }
+    }
+
+    /// Send one heartbeat to every peer known to `client`
+    ///
+    /// Returns the number of peers the heartbeat was sent to; unreachable
+    /// peers are left for their monitors to notice.
+    pub async fn broadcast(&self, client: &CommsClient) -> usize {
+        let message = match AegisMessage::new(self.heartbeat()) {
+            Ok(message) => message,
+            Err(_) => return 0,
+        };
+
+        let peers: Vec<String> = client.peers().agents().into_iter().filter(|peer| *peer != self.agent_id).collect();
+        let sends = peers.iter().map(|peer| client.send_message(peer, &message));
+        futures::future::join_all(sends).await.into_iter().filter(Result::is_ok).count()
+    }
+
+    /// Send heartbeats every interval until the task is aborted
+    pub fn spawn(&self, client: Arc<CommsClient>) -> JoinHandle<()> {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(service.interval);
+            loop {
+                ticker.tick().await;
+                service.broadcast(&client).await;
+            }
+        })
+    }
+}
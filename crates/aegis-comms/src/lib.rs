@@ -2,18 +2,110 @@
 //! 
 //! This crate provides the communication layer for the Aegis agent framework,
 //! with platform-agnostic abstractions and implementations for different platforms.
+//!
+//! Without a feature enabling tokio, only the runtime-independent parts are
+//! built: framing, codecs, compression, chunking, endpoints and the protocol
+//! messages. Connections, clients and servers need tokio.
 
+#[cfg(feature = "ca")]
+pub mod ca;
+mod chunking;
+mod codec;
+mod compression;
+#[cfg(feature = "tokio")]
+mod connection;
+mod connectors;
+#[cfg(feature = "discovery")]
+mod discovery;
+#[cfg(feature = "tokio")]
+mod dispatch;
+mod endpoint;
+#[cfg(feature = "tokio")]
+mod envelope;
+mod error;
 mod framing;
+#[cfg(feature = "tokio")]
+mod heartbeat;
+#[cfg(feature = "tokio")]
 mod manager;
+#[cfg(feature = "memory")]
+mod memory;
+#[cfg(feature = "membership")]
+mod membership;
+mod peers;
 mod platform;
+#[cfg(feature = "tokio")]
+mod pool;
 mod protocol;
+#[cfg(feature = "pubsub")]
+mod pubsub;
+#[cfg(feature = "rpc")]
+mod rpc;
+#[cfg(feature = "tokio")]
+mod server;
 mod transport;
 
-pub use framing::{FramedMessageStream, FramingError};
-pub use manager::{CommsClient, CommsError, ConnectionHandle};
+pub use chunking::{ChunkConfig, ChunkError, ChunkReceiver, ChunkSender, CompletedTransfer};
+#[cfg(feature = "cbor")]
+pub use codec::CborCodec;
+pub use codec::{BincodeCodec, Codec, CodecId, JsonCodec};
+#[cfg(feature = "msgpack")]
+pub use codec::MessagePackCodec;
+pub use compression::{Compression, CompressionConfig};
+#[cfg(feature = "tokio")]
+pub use connection::{
+    Backpressure, ConnectionHandle, ConnectionOptions, ConnectionState, DeliveryMode, Priority, ReconnectPolicy,
+};
+pub use connectors::ConnectorRegistry;
+#[cfg(feature = "discovery")]
+pub use discovery::{DiscoveryConfig, DiscoveryService, DEFAULT_DISCOVERY_PORT};
+#[cfg(feature = "tokio")]
+pub use dispatch::{MessageContext, MessageDispatcher};
+pub use endpoint::Endpoint;
+#[cfg(feature = "tokio")]
+pub use envelope::{EnvelopeSigner, EnvelopeVerifier, ReplayConfig, SecurityEvent, SecurityEvents, SignedEnvelope};
+pub use error::{CommsError, PeerInfo, SecurityViolation};
+pub use framing::{
+    encode_v2_frame, Frame, FrameFlags, FrameType, FrameVersion, FramedMessageStream, FramingConfig,
+    FramingError, FRAME_HEADER_SIZE, FRAME_MAGIC,
+};
+#[cfg(feature = "tokio")]
+pub use heartbeat::{
+    DetectorConfig, HealthEvent, HealthEvents, HeartbeatMonitor, HeartbeatService, PhiAccrualDetector, SuspectedFailure,
+};
+#[cfg(feature = "tokio")]
+pub use manager::CommsClient;
+#[cfg(feature = "memory")]
+pub use memory::{memory_duplex, LinkConfig, MemoryConnector, MemoryListener, MemoryNetwork, MemoryStream};
+#[cfg(feature = "membership")]
+pub use membership::{Member, MemberMeta, MemberState, Membership, MembershipConfig, MembershipEvent, MembershipEvents};
+pub use peers::{PeerEntry, PeerRegistry, PeerSource};
+#[cfg(feature = "tokio")]
+pub use pool::{ConnectionPool, MessageSender, PoolConfig, PooledConnection};
 pub use protocol::*;
-pub use transport::{MessageListener, MessageStream, NetworkConnector, NetworkError};
+#[cfg(feature = "pubsub")]
+pub use pubsub::{
+    Broker, Delivery, QoS, SlowConsumerPolicy, Subscription, SubscriptionOptions, TopicMessage, TopicPattern,
+};
+#[cfg(feature = "rpc")]
+pub use rpc::{ErrorCode, RemoteError, RpcClient, RpcConnection, RpcContext, RpcError, RpcRequest, RpcServer};
+#[cfg(feature = "tokio")]
+pub use server::{MessageServer, ServerConfig, ServerHandle};
+pub use transport::{
+    MessageListener, MessageStream, NetworkConnector, NetworkError, PeerCredentials, PeerIdentity, TransportAddr,
+};
 
 // Re-export platform-specific implementations when enabled
 #[cfg(feature = "platform_tokio_net")]
 pub use platform::tokio_impl::{connect_tokio, listen_tokio, TokioConnector, TokioTcpListener};
+
+#[cfg(feature = "tls")]
+pub use platform::tls_impl::{
+    client_config, connect_tls, listen_tls, load_certs, load_crls, load_private_key, load_root_store,
+    peer_identity_from_der, server_config, TokioTlsConnector, TokioTlsListener, TokioTlsStream, DEFAULT_HANDSHAKE_TIMEOUT,
+};
+
+#[cfg(all(unix, feature = "unix"))]
+pub use platform::unix_impl::{
+    connect_unix, listen_unix, TokioUnixConnector, TokioUnixListener, TokioUnixStream, DEFAULT_SOCKET_MODE,
+};
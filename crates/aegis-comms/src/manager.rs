@@ -1,137 +1,226 @@
-use crate::framing::{FramedMessageStream, FramingError};
-use crate::transport::{MessageListener, MessageStream, NetworkConnector, NetworkError};
+use crate::connection::{ConnectionHandle, ConnectionOptions, Reconnect};
+use crate::endpoint::Endpoint;
+use crate::envelope::EnvelopeSigner;
+use crate::error::{CommsError, PeerInfo};
+use crate::peers::PeerRegistry;
+use crate::pool::{ConnectionPool, MessageSender, Outgoing, PoolConfig};
+use crate::protocol::AegisMessage;
+use crate::server::{MessageServer, ServerConfig};
+use crate::transport::{MessageListener, MessageStream, NetworkConnector, NetworkError, TransportAddr};
 use bytes::Bytes;
 use serde::{de::DeserializeOwned, Serialize};
-use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::mpsc;
 
-/// Errors that can occur in the comms system
-#[derive(Debug)]
-pub enum CommsError {
-    Network(NetworkError),
-    Framing(FramingError),
-    Serialization(bincode::Error),
-    ChannelClosed,
-}
-
-impl From<NetworkError> for CommsError {
-    fn from(err: NetworkError) -> Self {
-        CommsError::Network(err)
-    }
-}
-
-impl From<FramingError> for CommsError {
-    fn from(err: FramingError) -> Self {
-        CommsError::Framing(err)
-    }
-}
-
-impl From<bincode::Error> for CommsError {
-    fn from(err: bincode::Error) -> Self {
-        CommsError::Serialization(err)
-    }
-}
-
-/// Handle for sending/receiving messages on a connection
-pub struct ConnectionHandle<T> {
-    tx: mpsc::Sender<T>,
-    rx: mpsc::Receiver<T>,
-    _type: std::marker::PhantomData<T>,
-}
-
-impl<T: Serialize + DeserializeOwned + Send + 'static> ConnectionHandle<T> {
-    /// Send a message through the connection
-    pub async fn send(&self, msg: T) -> Result<(), CommsError> {
-        self.tx.send(msg).await.map_err(|_| CommsError::ChannelClosed)
-    }
-    
-    /// Receive a message from the connection
-    pub async fn receive(&mut self) -> Result<Option<T>, CommsError> {
-        self.rx.recv().await.ok_or(CommsError::ChannelClosed).map(Some)
-    }
+/// Where a connection handle reconnects to
+#[derive(Clone)]
+enum Target {
+    Addr(TransportAddr),
+    Endpoint(Endpoint),
 }
 
 /// High-level communications client
+///
+/// Messages can be addressed to agents by ID; their endpoints are looked up
+/// in the [`PeerRegistry`] and connections are kept in a pool for reuse.
 pub struct CommsClient {
     connector: Arc<dyn NetworkConnector>,
+    peers: PeerRegistry,
+    senders: ConnectionPool<MessageSender>,
+    connection_options: ConnectionOptions,
+    signer: Option<EnvelopeSigner>,
+    #[cfg(feature = "rpc")]
+    rpc: crate::rpc::RpcClient,
 }
 
 impl CommsClient {
     /// Create a new comms client with the given connector
     pub fn new(connector: impl NetworkConnector + 'static) -> Self {
+        Self::with_config(Arc::new(connector), PeerRegistry::new(), PoolConfig::default())
+    }
+    
+    /// Create a comms client with a peer registry and pool limits
+    pub fn with_config(connector: Arc<dyn NetworkConnector>, peers: PeerRegistry, pool: PoolConfig) -> Self {
         Self {
-            connector: Arc::new(connector),
+            senders: ConnectionPool::new(connector.clone(), pool),
+            connection_options: ConnectionOptions::default(),
+            signer: None,
+            #[cfg(feature = "rpc")]
+            rpc: crate::rpc::RpcClient::with_config(connector.clone(), pool),
+            connector,
+            peers,
+        }
+    }
+    
+    /// Use `peers` to look up agent endpoints
+    pub fn with_peers(mut self, peers: PeerRegistry) -> Self {
+        self.peers = peers;
+        self
+    }
+    
+    /// Set reconnection and delivery options for handles opened by
+    /// [`CommsClient::connect_to`] and [`CommsClient::connect_endpoint`]
+    pub fn with_connection_options(mut self, options: ConnectionOptions) -> Self {
+        self.connection_options = options;
+        self
+    }
+    
+    /// Sign every message sent with [`CommsClient::send_message`]
+    pub fn with_signer(mut self, signer: EnvelopeSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+    
+    /// Registry of agent endpoints
+    pub fn peers(&self) -> &PeerRegistry {
+        &self.peers
+    }
+    
+    /// Pooled connections used by [`CommsClient::send`]
+    pub fn pool(&self) -> &ConnectionPool<MessageSender> {
+        &self.senders
+    }
+    
+    /// Send a message to an agent
+    ///
+    /// The message is written as one frame on a pooled connection to the
+    /// agent's endpoint. If the pooled connection turns out to be closed, the
+    /// message is retried once on a new connection.
+    pub async fn send(&self, agent_id: &str, message: Bytes) -> Result<(), CommsError> {
+        self.deliver(agent_id, Outgoing::Raw(message)).await
+    }
+    
+    /// Send an [`AegisMessage`] to an agent
+    ///
+    /// The message is encoded with the codec negotiated on the connection,
+    /// which is bincode until the peer has advertised its codecs. With a
+    /// signer, the message is sent in a [`SignedEnvelope`](crate::SignedEnvelope).
+    pub async fn send_message(&self, agent_id: &str, message: &AegisMessage) -> Result<(), CommsError> {
+        let message = match &self.signer {
+            Some(signer) => AegisMessage::new(signer.seal(message.clone())?)?,
+            None => message.clone(),
+        };
+        self.deliver(agent_id, Outgoing::Message(Box::new(message))).await
+    }
+    
+    /// Queue a message on the pooled connection to an agent, retrying once
+    async fn deliver(&self, agent_id: &str, message: Outgoing) -> Result<(), CommsError> {
+        let endpoint = self
+            .peers
+            .lookup(agent_id)
+            .ok_or_else(|| CommsError::UnknownAgent(agent_id.to_string()))?;
+        
+        let sender = self.senders.get(&endpoint).await?;
+        if sender.queue(message.clone()).await.is_ok() {
+            return Ok(());
         }
+        
+        self.senders.evict(&endpoint);
+        self.senders.get(&endpoint).await?.queue(message).await
+    }
+    
+    /// Call an agent over RPC and wait for the response
+    #[cfg(feature = "rpc")]
+    pub async fn call<Req, Resp>(
+        &self,
+        agent_id: &str,
+        request: &Req,
+        deadline: std::time::Instant,
+    ) -> Result<Resp, crate::rpc::RpcError>
+    where
+        Req: crate::rpc::RpcRequest,
+        Resp: DeserializeOwned,
+    {
+        let endpoint = self.peers.lookup(agent_id).ok_or_else(|| {
+            crate::rpc::RpcError::Network(NetworkError::Resolve(format!(
+                "no endpoint known for agent {}",
+                agent_id
+            )))
+        })?;
+        
+        self.rpc.call(&endpoint, request, deadline).await
     }
     
     /// Connect to a remote address and get a typed connection handle
+    ///
+    /// The handle reconnects to the same address when the connection drops,
+    /// as configured by [`CommsClient::with_connection_options`].
     pub async fn connect_to<T: Serialize + DeserializeOwned + Send + 'static>(
         &self,
-        addr: SocketAddr,
+        addr: impl Into<TransportAddr>,
     ) -> Result<ConnectionHandle<T>, CommsError> {
-        let stream = self.connector.connect(addr).await?;
-        let framed = FramedMessageStream::new(stream);
-        
-        let (tx_raw, mut rx_raw) = mpsc::channel::<T>(32);
-        let (tx_processed, rx_processed) = mpsc::channel::<T>(32);
-        
-        // Spawn send task
-        let mut framed_clone = framed;
-        tokio::spawn(async move {
-            while let Some(msg) = rx_raw.recv().await {
-                let bytes = Bytes::from(bincode::serialize(&msg).unwrap());
-                if framed_clone.write_framed_message(bytes).await.is_err() {
-                    break;
-                }
-            }
-        });
-        
-        // Spawn receive task
-        let mut framed_clone = framed;
-        tokio::spawn(async move {
-            while let Ok(Some(bytes)) = framed_clone.read_framed_message().await {
-                if let Ok(msg) = bincode::deserialize::<T>(&bytes) {
-                    if tx_processed.send(msg).await.is_err() {
-                        break;
-                    }
+        let addr = addr.into();
+        let stream = self.connector.connect(addr.clone()).await?;
+        Ok(self.spawn_handle(stream, Target::Addr(addr)))
+    }
+    
+    /// Connect to an endpoint and get a typed connection handle
+    ///
+    /// Host names are resolved when the connector is a [`crate::ConnectorRegistry`].
+    pub async fn connect_endpoint<T: Serialize + DeserializeOwned + Send + 'static>(
+        &self,
+        endpoint: &Endpoint,
+    ) -> Result<ConnectionHandle<T>, CommsError> {
+        let stream = self.connector.connect_endpoint(endpoint).await?;
+        Ok(self.spawn_handle(stream, Target::Endpoint(endpoint.clone())))
+    }
+    
+    /// Start a reconnecting handle on a connected stream
+    fn spawn_handle<T: Serialize + DeserializeOwned + Send + 'static>(
+        &self,
+        stream: Box<dyn MessageStream>,
+        target: Target,
+    ) -> ConnectionHandle<T> {
+        let connector = self.connector.clone();
+        let reconnect: Reconnect = Arc::new(move || {
+            let connector = connector.clone();
+            let target = target.clone();
+            Box::pin(async move {
+                match target {
+                    Target::Addr(addr) => connector.connect(addr).await,
+                    Target::Endpoint(endpoint) => connector.connect_endpoint(&endpoint).await,
                 }
-            }
+            })
         });
         
-        Ok(ConnectionHandle {
-            tx: tx_raw,
-            rx: rx_processed,
-            _type: std::marker::PhantomData,
-        })
+        ConnectionHandle::spawn(stream, reconnect, self.connection_options)
     }
     
     /// Start a listener for incoming connections
+    ///
+    /// The handler receives each message together with the address,
+    /// verified identity and local credentials of the connection it arrived on.
+    /// `addr` must be the address `listener` is bound to. The listener runs
+    /// until accepting fails; use a [`MessageServer`] to be able to stop it
+    /// and to limit connections.
+    #[deprecated(note = "use MessageServer, which can be shut down")]
     pub async fn start_listener<T, F, Fut>(
         &self,
-        addr: SocketAddr,
+        addr: TransportAddr,
         listener: impl MessageListener,
-        mut handler: F,
+        handler: F,
     ) -> Result<(), CommsError>
     where
         T: Serialize + DeserializeOwned + Send + 'static,
-        F: FnMut(T) -> Fut + Send + 'static,
-        Fut: std::future::Future<Output = ()> + Send,
+        F: Fn(T, PeerInfo) -> Fut + Clone + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
     {
-        let mut listener = listener;
-        
-        loop {
-            let (stream, _addr) = listener.accept().await?;
-            let framed = FramedMessageStream::new(stream);
-            
-            tokio::spawn(async move {
-                let mut framed = framed;
-                while let Ok(Some(bytes)) = framed.read_framed_message().await {
-                    if let Ok(msg) = bincode::deserialize::<T>(&bytes) {
-                        handler(msg).await;
-                    }
-                }
-            });
+        let local = listener.local_addr()?;
+        if local != addr {
+            return Err(CommsError::Network(NetworkError::Other(format!(
+                "listener is bound to {}, not {}",
+                local, addr
+            ))));
         }
+        
+        unlimited_server().serve(listener, handler).await
     }
+}
+
+/// Server for the deprecated listeners, which had no limits
+fn unlimited_server() -> MessageServer {
+    MessageServer::new(ServerConfig {
+        max_connections: usize::MAX,
+        idle_timeout: None,
+        ..ServerConfig::default()
+    })
 } 
\ No newline at end of file
@@ -0,0 +1,857 @@
+//! Cluster membership with a SWIM-style gossip protocol
+//!
+//! Every protocol period a [`Membership`] probes one member, taken from a
+//! shuffled round-robin order. A member that does not answer a direct ping
+//! is probed indirectly through a few other members before it is suspected,
+//! and a suspected member that does not refute the suspicion in time is
+//! declared failed. Members refute by raising their incarnation number,
+//! which orders conflicting updates about the same member.
+//!
+//! Updates about joins, suspicions, failures, leaves and metadata changes are
+//! piggy-backed on probe requests and acknowledgements, each retransmitted a
+//! number of times that grows logarithmically with the cluster size. The
+//! messages travel over the RPC layer, so membership works on any transport.
+//!
+//! Updates are relayed by other members, so the connection an update arrives
+//! on says nothing about who made it. With [`Membership::with_identity`],
+//! member IDs are node IDs and every member signs the records only it may
+//! make: joining, refuting, changing metadata and leaving. Other members may
+//! then only report a known member suspected or failed at an incarnation it
+//! already published, which the member refutes if it is alive.
+//!
+//! Failed and departed members are remembered for
+//! [`MembershipConfig::tombstone_ttl`] to reject stale updates about them,
+//! then forgotten.
+
+use crate::endpoint::Endpoint;
+use crate::error::CommsError;
+use crate::peers::PeerRegistry;
+use crate::rpc::{RpcClient, RpcContext, RpcError, RpcRequest, RpcServer};
+use crate::transport::NetworkConnector;
+use aegis_core::clock::{system_clock, Clock};
+use aegis_core::error::{AegisError, AegisResult};
+use aegis_core::identity::{node_id_for, verify_signature, NodeIdentity};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+/// Prefix of the signed bytes, so member signatures cannot be confused with
+/// other signatures made by the node key
+const SIGNING_CONTEXT: &str = "aegis-member-v1";
+
+/// Timing and dissemination settings of a [`Membership`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MembershipConfig {
+    /// Time between probes
+    pub probe_interval: Duration,
+    /// Time to wait for the acknowledgement of a direct probe
+    pub probe_timeout: Duration,
+    /// Members asked to probe a member that did not answer directly
+    pub indirect_probes: usize,
+    /// Time a suspected member has to refute before it is declared failed
+    pub suspicion_timeout: Duration,
+    /// Scales how often each update is retransmitted
+    pub retransmit_multiplier: u32,
+    /// Most updates piggy-backed on one message
+    pub max_piggyback: usize,
+    /// Time failed and departed members are remembered to reject stale
+    /// updates about them; must exceed the time updates take to spread
+    pub tombstone_ttl: Duration,
+}
+
+impl Default for MembershipConfig {
+    fn default() -> Self {
+        Self {
+            probe_interval: Duration::from_secs(1),
+            probe_timeout: Duration::from_millis(500),
+            indirect_probes: 3,
+            suspicion_timeout: Duration::from_secs(5),
+            retransmit_multiplier: 4,
+            max_piggyback: 16,
+            tombstone_ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Metadata a member publishes about itself
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberMeta {
+    /// Capabilities the member offers
+    pub capabilities: Vec<String>,
+    /// Software version the member runs
+    pub version: String,
+}
+
+/// Liveness of a member
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemberState {
+    /// The member answers probes
+    Alive,
+    /// The member stopped answering and may be declared failed
+    Suspect,
+    /// The member did not refute a suspicion in time
+    Dead,
+    /// The member left the cluster
+    Left,
+}
+
+/// A member of the cluster as known to this node
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Member {
+    /// Agent ID of the member
+    pub id: String,
+    /// Where the member serves membership requests
+    pub endpoint: Endpoint,
+    /// Raised by the member to override older updates about itself
+    pub incarnation: u64,
+    /// Liveness of the member
+    pub state: MemberState,
+    /// Published metadata
+    pub meta: MemberMeta,
+}
+
+impl Member {
+    /// Alive member at its first incarnation
+    pub fn new(id: impl Into<String>, endpoint: Endpoint) -> Self {
+        Self {
+            id: id.into(),
+            endpoint,
+            incarnation: 0,
+            state: MemberState::Alive,
+            meta: MemberMeta::default(),
+        }
+    }
+
+    /// Set the published metadata
+    pub fn with_meta(mut self, meta: MemberMeta) -> Self {
+        self.meta = meta;
+        self
+    }
+
+    /// Whether the member is still considered part of the cluster
+    pub fn is_active(&self) -> bool {
+        matches!(self.state, MemberState::Alive | MemberState::Suspect)
+    }
+
+    /// Whether `self` is newer information than `current` about the same member
+    fn overrides(&self, current: &Member) -> bool {
+        use MemberState::*;
+
+        match (self.state, current.state) {
+            // Only a new incarnation brings a failed or departed member back
+            (_, Dead | Left) => self.state == Alive && self.incarnation > current.incarnation,
+            (Alive, _) => self.incarnation > current.incarnation,
+            (Suspect, Alive) => self.incarnation >= current.incarnation,
+            (Suspect, Suspect) => self.incarnation > current.incarnation,
+            (Dead | Left, _) => self.incarnation >= current.incarnation,
+        }
+    }
+}
+
+/// Change in cluster membership
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MembershipEvent {
+    /// A member joined, or rejoined after failing or leaving
+    Joined(Member),
+    /// A member changed its endpoint or metadata
+    Updated(Member),
+    /// A member stopped answering probes
+    Suspected(Member),
+    /// A suspected member refuted the suspicion
+    Recovered(Member),
+    /// A suspected member was declared failed
+    Failed(Member),
+    /// A member left the cluster
+    Left(Member),
+}
+
+/// Subscription to the events of a [`Membership`]
+pub struct MembershipEvents {
+    rx: broadcast::Receiver<MembershipEvent>,
+}
+
+impl MembershipEvents {
+    /// Wait for the next event; `None` once the membership is gone
+    ///
+    /// Events missed because the subscriber fell behind are skipped.
+    pub async fn recv(&mut self) -> Option<MembershipEvent> {
+        loop {
+            match self.rx.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Signature of a member over its own record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Proof {
+    public_key: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+/// A member record as gossiped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Update {
+    member: Member,
+    /// Present when the member signed the record itself
+    proof: Option<Proof>,
+}
+
+impl Update {
+    /// Record not signed by the member, such as a suspicion
+    fn unsigned(member: Member) -> Self {
+        Self { member, proof: None }
+    }
+
+    /// Bytes covered by the signature of `member`
+    fn signed_bytes(member: &Member) -> Option<Vec<u8>> {
+        bincode::serialize(&(SIGNING_CONTEXT, member)).ok()
+    }
+
+    /// Whether the update carries a valid signature of the member it describes
+    fn is_signed_by_member(&self) -> bool {
+        let Some(proof) = &self.proof else {
+            return false;
+        };
+        node_id_for(&proof.public_key) == self.member.id
+            && Self::signed_bytes(&self.member)
+                .is_some_and(|bytes| verify_signature(&proof.public_key, &bytes, &proof.signature).is_ok())
+    }
+}
+
+/// Direct probe, answered with an [`Ack`]
+#[derive(Debug, Serialize, Deserialize)]
+struct Ping {
+    /// Member the sender believes it is probing
+    target: String,
+    updates: Vec<Update>,
+}
+
+impl RpcRequest for Ping {
+    const METHOD: &'static str = "swim.ping";
+}
+
+/// Request to probe `target` on the sender's behalf
+#[derive(Debug, Serialize, Deserialize)]
+struct PingReq {
+    target: Member,
+    updates: Vec<Update>,
+}
+
+impl RpcRequest for PingReq {
+    const METHOD: &'static str = "swim.ping_req";
+}
+
+/// Acknowledgement of a probe
+#[derive(Debug, Serialize, Deserialize)]
+struct Ack {
+    updates: Vec<Update>,
+}
+
+/// Request to join, answered with every member the receiver knows
+#[derive(Debug, Serialize, Deserialize)]
+struct Join {
+    member: Update,
+}
+
+impl RpcRequest for Join {
+    const METHOD: &'static str = "swim.join";
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JoinResponse {
+    members: Vec<Update>,
+}
+
+/// A known member and when it became suspected or was removed
+struct Entry {
+    member: Member,
+    /// Signature of the member over `member`, passed on when relaying it
+    proof: Option<Proof>,
+    /// Offset on the membership's monotonic clock
+    suspected_at: Option<Duration>,
+    /// When the member failed or left, offset on the monotonic clock
+    removed_at: Option<Duration>,
+}
+
+impl Entry {
+    fn update(&self) -> Update {
+        Update {
+            member: self.member.clone(),
+            proof: self.proof.clone(),
+        }
+    }
+}
+
+/// An update waiting to be piggy-backed
+struct Broadcast {
+    update: Update,
+    transmits: u32,
+}
+
+struct State {
+    local: Member,
+    members: HashMap<String, Entry>,
+    queue: Vec<Broadcast>,
+    probe_order: Vec<String>,
+    next_probe: usize,
+}
+
+impl State {
+    /// Queue `update` for dissemination, replacing older updates about the member
+    fn enqueue(&mut self, update: Update) {
+        self.queue.retain(|broadcast| broadcast.update.member.id != update.member.id);
+        self.queue.push(Broadcast { update, transmits: 0 });
+    }
+
+    /// Updates to piggy-back on the next message
+    fn piggyback(&mut self, config: &MembershipConfig) -> Vec<Update> {
+        let cluster_size = self.members.values().filter(|entry| entry.member.is_active()).count() + 1;
+        let limit = config.retransmit_multiplier * ((cluster_size as f64 + 1.0).log10().ceil() as u32).max(1);
+
+        // Least transmitted first, so new updates spread quickly
+        self.queue.sort_by_key(|broadcast| broadcast.transmits);
+        let updates = self
+            .queue
+            .iter_mut()
+            .take(config.max_piggyback)
+            .map(|broadcast| {
+                broadcast.transmits += 1;
+                broadcast.update.clone()
+            })
+            .collect();
+        self.queue.retain(|broadcast| broadcast.transmits < limit);
+        updates
+    }
+
+    /// Next member to probe
+    fn next_target(&mut self) -> Option<Member> {
+        for _ in 0..=self.probe_order.len() {
+            if self.next_probe >= self.probe_order.len() {
+                self.probe_order = self
+                    .members
+                    .values()
+                    .filter(|entry| entry.member.is_active())
+                    .map(|entry| entry.member.id.clone())
+                    .collect();
+                self.probe_order.shuffle(&mut rand::thread_rng());
+                self.next_probe = 0;
+            }
+
+            let id = self.probe_order.get(self.next_probe)?.clone();
+            self.next_probe += 1;
+            if let Some(entry) = self.members.get(&id).filter(|entry| entry.member.is_active()) {
+                return Some(entry.member.clone());
+            }
+        }
+        None
+    }
+
+    /// Up to `count` random active members other than `exclude`
+    fn random_members(&self, count: usize, exclude: &str) -> Vec<Member> {
+        let mut members: Vec<Member> = self
+            .members
+            .values()
+            .filter(|entry| entry.member.is_active() && entry.member.id != exclude)
+            .map(|entry| entry.member.clone())
+            .collect();
+        members.shuffle(&mut rand::thread_rng());
+        members.truncate(count);
+        members
+    }
+}
+
+/// This node's view of the cluster
+///
+/// Cloning yields a handle to the same membership.
+#[derive(Clone)]
+pub struct Membership {
+    config: MembershipConfig,
+    state: Arc<Mutex<State>>,
+    rpc: Arc<RpcClient>,
+    events: broadcast::Sender<MembershipEvent>,
+    registry: Option<PeerRegistry>,
+    clock: Arc<dyn Clock>,
+    identity: Option<Arc<NodeIdentity>>,
+    rejected: Arc<AtomicU64>,
+}
+
+impl Membership {
+    /// Create a membership containing only `local`
+    pub fn new(local: Member, connector: Arc<dyn NetworkConnector>, config: MembershipConfig) -> Self {
+        let (events, _) = broadcast::channel(256);
+        let mut state = State {
+            local: local.clone(),
+            members: HashMap::new(),
+            queue: Vec::new(),
+            probe_order: Vec::new(),
+            next_probe: 0,
+        };
+        state.enqueue(Update::unsigned(local));
+
+        Self {
+            config,
+            state: Arc::new(Mutex::new(state)),
+            rpc: Arc::new(RpcClient::with_connector(connector)),
+            events,
+            registry: None,
+            clock: system_clock(),
+            identity: None,
+            rejected: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Time suspicions with the given clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sign this node's records with `identity` and accept only authenticated updates
+    ///
+    /// Every member of the cluster must use an identity, since unsigned
+    /// records of joins, refutations and leaves are rejected.
+    ///
+    /// Fails with [`CommsError::IdentityMismatch`] if the ID of the local
+    /// member is not the node ID of `identity`.
+    pub fn with_identity(mut self, identity: Arc<NodeIdentity>) -> Result<Self, CommsError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.local.id != identity.node_id() {
+                return Err(CommsError::IdentityMismatch {
+                    id: state.local.id.clone(),
+                    node_id: identity.node_id().to_string(),
+                });
+            }
+            self.identity = Some(identity);
+            let local = self.sign(state.local.clone());
+            state.enqueue(local);
+        }
+        Ok(self)
+    }
+
+    /// Mirror active members into `registry`, so they can be reached by ID
+    pub fn with_registry(mut self, registry: PeerRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// This node as currently published
+    pub fn local(&self) -> Member {
+        self.state.lock().unwrap().local.clone()
+    }
+
+    /// Every other member known, including failed and departed ones, sorted by ID
+    pub fn members(&self) -> Vec<Member> {
+        let mut members: Vec<Member> =
+            self.state.lock().unwrap().members.values().map(|entry| entry.member.clone()).collect();
+        members.sort_by(|a, b| a.id.cmp(&b.id));
+        members
+    }
+
+    /// Other members still part of the cluster, sorted by ID
+    pub fn active_members(&self) -> Vec<Member> {
+        self.members().into_iter().filter(Member::is_active).collect()
+    }
+
+    /// A member by ID
+    pub fn member(&self, id: &str) -> Option<Member> {
+        self.state.lock().unwrap().members.get(id).map(|entry| entry.member.clone())
+    }
+
+    /// Number of updates rejected because they were not authenticated
+    pub fn rejected_updates(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Subscribe to membership changes
+    pub fn subscribe(&self) -> MembershipEvents {
+        MembershipEvents {
+            rx: self.events.subscribe(),
+        }
+    }
+
+    /// Serve membership requests on `server`
+    pub fn register(&self, server: &mut RpcServer) {
+        let membership = self.clone();
+        server.register(move |ping: Ping, _ctx: RpcContext| {
+            let membership = membership.clone();
+            async move { membership.handle_ping(ping) }
+        });
+
+        let membership = self.clone();
+        server.register(move |request: PingReq, ctx: RpcContext| {
+            let membership = membership.clone();
+            async move { membership.handle_ping_req(request, ctx).await }
+        });
+
+        let membership = self.clone();
+        server.register(move |join: Join, _ctx: RpcContext| {
+            let membership = membership.clone();
+            async move { membership.handle_join(join) }
+        });
+    }
+
+    /// Join the cluster through the first seed that answers
+    ///
+    /// Returns the number of members learned from the seed.
+    pub async fn join(&self, seeds: &[Endpoint]) -> Result<usize, RpcError> {
+        let local = self.local();
+        let mut last_error = RpcError::Network(crate::transport::NetworkError::Other("no seeds given".to_string()));
+
+        for seed in seeds.iter().filter(|seed| **seed != local.endpoint) {
+            let request = Join {
+                member: self.sign(local.clone()),
+            };
+            match self.rpc.call_timeout::<_, JoinResponse>(seed, &request, self.config.probe_timeout).await {
+                Ok(response) => {
+                    let count = response.members.iter().filter(|update| update.member.id != local.id).count();
+                    self.merge(response.members);
+                    return Ok(count);
+                }
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Publish new metadata for this node
+    pub fn set_meta(&self, meta: MemberMeta) {
+        let mut state = self.state.lock().unwrap();
+        state.local.meta = meta;
+        state.local.incarnation = state.local.incarnation.saturating_add(1);
+        let local = self.sign(state.local.clone());
+        state.enqueue(local);
+    }
+
+    /// Leave the cluster
+    ///
+    /// The departure is sent directly to every active member, which spread
+    /// it further. The protocol task stops at its next period.
+    pub async fn leave(&self) {
+        let (members, updates) = {
+            let mut state = self.state.lock().unwrap();
+            state.local.state = MemberState::Left;
+            state.local.incarnation = state.local.incarnation.saturating_add(1);
+            let local = self.sign(state.local.clone());
+            state.enqueue(local.clone());
+
+            let members: Vec<Member> = state.members.values().map(|entry| entry.member.clone()).collect();
+            (members, vec![local])
+        };
+
+        let pings = members.iter().filter(|member| member.is_active()).map(|member| {
+            let ping = Ping {
+                target: member.id.clone(),
+                updates: updates.clone(),
+            };
+            async move {
+                self.rpc
+                    .call_timeout::<_, Ack>(&member.endpoint, &ping, self.config.probe_timeout)
+                    .await
+            }
+        });
+        futures::future::join_all(pings).await;
+    }
+
+    /// Run one protocol period: probe a member, expire suspicions and forget old tombstones
+    pub async fn probe(&self) {
+        let target = self.state.lock().unwrap().next_target();
+
+        if let Some(target) = target {
+            let mut reachable = self.ping(&target, self.config.probe_timeout).await;
+
+            if !reachable {
+                let helpers = self
+                    .state
+                    .lock()
+                    .unwrap()
+                    .random_members(self.config.indirect_probes, &target.id);
+                let probes = helpers.iter().map(|helper| self.ping_req(helper, &target));
+                reachable = futures::future::join_all(probes).await.into_iter().any(|ok| ok);
+            }
+
+            if !reachable {
+                self.merge(vec![Update::unsigned(Member {
+                    state: MemberState::Suspect,
+                    ..target
+                })]);
+            }
+        }
+
+        self.expire_suspicions();
+        self.prune_tombstones();
+    }
+
+    /// Probe every interval until this node leaves or the task is aborted
+    pub fn spawn(&self) -> JoinHandle<()> {
+        let membership = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(membership.config.probe_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                ticker.tick().await;
+                if membership.local().state == MemberState::Left {
+                    break;
+                }
+                membership.probe().await;
+            }
+        })
+    }
+
+    /// Ping `target` directly; true if it acknowledged in time
+    async fn ping(&self, target: &Member, timeout: Duration) -> bool {
+        let ping = Ping {
+            target: target.id.clone(),
+            updates: self.piggyback(),
+        };
+
+        match self.rpc.call_timeout::<_, Ack>(&target.endpoint, &ping, timeout).await {
+            Ok(ack) => {
+                self.merge(ack.updates);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Ask `helper` to probe `target`; true if the target acknowledged
+    async fn ping_req(&self, helper: &Member, target: &Member) -> bool {
+        let request = PingReq {
+            target: target.clone(),
+            updates: self.piggyback(),
+        };
+
+        // The helper needs time for its own probe of the target
+        let timeout = self.config.probe_timeout * 2;
+        match self.rpc.call_timeout::<_, Ack>(&helper.endpoint, &request, timeout).await {
+            Ok(ack) => {
+                self.merge(ack.updates);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn handle_ping(&self, ping: Ping) -> AegisResult<Ack> {
+        self.merge(ping.updates);
+
+        let local_id = self.local().id;
+        if ping.target != local_id {
+            return Err(AegisError::NotFound(format!(
+                "probe for {} reached {}",
+                ping.target, local_id
+            )));
+        }
+        Ok(Ack {
+            updates: self.piggyback(),
+        })
+    }
+
+    async fn handle_ping_req(&self, request: PingReq, ctx: RpcContext) -> AegisResult<Ack> {
+        self.merge(request.updates);
+
+        let timeout = self.config.probe_timeout.min(ctx.remaining());
+        if !self.ping(&request.target, timeout).await {
+            return Err(AegisError::Timeout(format!("{} did not answer", request.target.id)));
+        }
+        Ok(Ack {
+            updates: self.piggyback(),
+        })
+    }
+
+    fn handle_join(&self, join: Join) -> AegisResult<JoinResponse> {
+        self.merge(vec![join.member]);
+
+        let state = self.state.lock().unwrap();
+        let mut members: Vec<Update> = state.members.values().map(Entry::update).collect();
+        members.push(self.sign(state.local.clone()));
+        Ok(JoinResponse { members })
+    }
+
+    fn piggyback(&self) -> Vec<Update> {
+        self.state.lock().unwrap().piggyback(&self.config)
+    }
+
+    /// `member` as an update, signed if it is this node's record and an identity is set
+    fn sign(&self, member: Member) -> Update {
+        let proof = self.identity.as_ref().and_then(|identity| {
+            let bytes = Update::signed_bytes(&member)?;
+            Some(Proof {
+                public_key: identity.public_key().to_vec(),
+                signature: identity.sign(&bytes),
+            })
+        });
+        Update { member, proof }
+    }
+
+    /// Whether `update` may be applied, given what is known about the member
+    ///
+    /// Without an identity every update is accepted. With one, a member's own
+    /// records must carry its signature, and others may only report a known
+    /// member suspected or failed at an incarnation it already published.
+    fn is_authentic(&self, update: &Update, known: Option<&Member>) -> bool {
+        if self.identity.is_none() || update.is_signed_by_member() {
+            return true;
+        }
+        update.proof.is_none()
+            && matches!(update.member.state, MemberState::Suspect | MemberState::Dead)
+            && known.is_some_and(|known| update.member.incarnation <= known.incarnation)
+    }
+
+    /// Apply received updates, refuting suspicions about this node
+    fn merge(&self, updates: Vec<Update>) {
+        let mut state = self.state.lock().unwrap();
+        let now = self.clock.elapsed();
+
+        for update in updates {
+            let known = if update.member.id == state.local.id {
+                Some(&state.local)
+            } else {
+                state.members.get(&update.member.id).map(|entry| &entry.member)
+            };
+            if !self.is_authentic(&update, known) {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+
+            if update.member.id == state.local.id {
+                let local = &mut state.local;
+                if local.state != MemberState::Left
+                    && update.member.state != MemberState::Alive
+                    && update.member.incarnation >= local.incarnation
+                {
+                    local.incarnation = update.member.incarnation.saturating_add(1);
+                    let local = self.sign(local.clone());
+                    state.enqueue(local);
+                }
+                continue;
+            }
+
+            let member = &update.member;
+            let event = match state.members.get_mut(&member.id) {
+                Some(entry) if !member.overrides(&entry.member) => continue,
+                Some(entry) => {
+                    let previous = std::mem::replace(&mut entry.member, member.clone());
+                    entry.proof = update.proof.clone();
+                    entry.suspected_at = match member.state {
+                        MemberState::Suspect => entry.suspected_at.or(Some(now)),
+                        _ => None,
+                    };
+                    entry.removed_at = if member.is_active() {
+                        None
+                    } else {
+                        entry.removed_at.or(Some(now))
+                    };
+                    Self::transition(&previous, member)
+                }
+                None => {
+                    let suspected_at = (member.state == MemberState::Suspect).then_some(now);
+                    state.members.insert(
+                        member.id.clone(),
+                        Entry {
+                            member: member.clone(),
+                            proof: update.proof.clone(),
+                            suspected_at,
+                            removed_at: (!member.is_active()).then_some(now),
+                        },
+                    );
+                    // Failed or departed members are kept only to reject stale updates
+                    member.is_active().then(|| MembershipEvent::Joined(member.clone()))
+                }
+            };
+
+            state.enqueue(update);
+            if let Some(event) = event {
+                self.publish(event);
+            }
+        }
+    }
+
+    /// Event for a member changing from `previous` to `current`
+    fn transition(previous: &Member, current: &Member) -> Option<MembershipEvent> {
+        use MemberState::*;
+
+        let member = current.clone();
+        match (previous.state, current.state) {
+            (Dead | Left, Alive) => Some(MembershipEvent::Joined(member)),
+            (Suspect, Alive) => Some(MembershipEvent::Recovered(member)),
+            (Alive, Alive) if previous.meta != current.meta || previous.endpoint != current.endpoint => {
+                Some(MembershipEvent::Updated(member))
+            }
+            (Alive, Suspect) => Some(MembershipEvent::Suspected(member)),
+            (Alive | Suspect, Dead) => Some(MembershipEvent::Failed(member)),
+            (Alive | Suspect, Left) => Some(MembershipEvent::Left(member)),
+            _ => None,
+        }
+    }
+
+    /// Declare members failed whose suspicion timed out
+    fn expire_suspicions(&self) {
+        let now = self.clock.elapsed();
+        let expired: Vec<Update> = {
+            let state = self.state.lock().unwrap();
+            state
+                .members
+                .values()
+                .filter(|entry| {
+                    entry
+                        .suspected_at
+                        .is_some_and(|since| now.saturating_sub(since) >= self.config.suspicion_timeout)
+                })
+                .map(|entry| {
+                    Update::unsigned(Member {
+                        state: MemberState::Dead,
+                        ..entry.member.clone()
+                    })
+                })
+                .collect()
+        };
+
+        if !expired.is_empty() {
+            self.merge(expired);
+        }
+    }
+
+    /// Forget members that failed or left longer than the tombstone TTL ago
+    fn prune_tombstones(&self) {
+        let now = self.clock.elapsed();
+        let ttl = self.config.tombstone_ttl;
+        let mut state = self.state.lock().unwrap();
+        state
+            .members
+            .retain(|_, entry| entry.removed_at.is_none_or(|since| now.saturating_sub(since) < ttl));
+
+        let State { members, queue, local, .. } = &mut *state;
+        queue.retain(|broadcast| {
+            let id = &broadcast.update.member.id;
+            *id == local.id || members.contains_key(id)
+        });
+    }
+
+    fn publish(&self, event: MembershipEvent) {
+        if let Some(registry) = &self.registry {
+            match &event {
+                MembershipEvent::Joined(member)
+                | MembershipEvent::Updated(member)
+                | MembershipEvent::Recovered(member) => {
+                    registry.record_member(&member.id, member.endpoint.clone(), member.meta.capabilities.clone());
+                }
+                MembershipEvent::Failed(member) | MembershipEvent::Left(member) => {
+                    registry.remove_member(&member.id);
+                }
+                MembershipEvent::Suspected(_) => {}
+            }
+        }
+        let _ = self.events.send(event);
+    }
+}
@@ -0,0 +1,351 @@
+//! In-process memory transport
+//!
+//! Endpoints are registered by name in a [`MemoryNetwork`] and connected
+//! with duplex [`MemoryStream`]s. Links can emulate latency, bandwidth and
+//! an MTU, so the same transport serves as a test double for network code
+//! and as the transport between agents sharing one process.
+//!
+//! Connections carry no credentials, but a connector created with
+//! [`MemoryNetwork::connector_as`] presents a fixed [`PeerIdentity`] to the
+//! listener, standing in for the certificate of an authenticated transport.
+
+use crate::transport::{MessageListener, MessageStream, NetworkConnector, NetworkError, PeerIdentity, TransportAddr};
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+/// Properties of an emulated link, applied to each direction separately
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkConfig {
+    /// One-way delay added to every packet
+    pub latency: Duration,
+    /// Throughput limit in bytes per second, unlimited if `None`
+    pub bandwidth: Option<u64>,
+    /// Largest packet size; larger writes are split, unlimited if `None`
+    pub mtu: Option<usize>,
+}
+
+impl LinkConfig {
+    /// A link without delay or limits
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Set the one-way latency
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Set the bandwidth in bytes per second
+    pub fn with_bandwidth(mut self, bytes_per_sec: u64) -> Self {
+        self.bandwidth = Some(bytes_per_sec);
+        self
+    }
+
+    /// Set the MTU in bytes
+    pub fn with_mtu(mut self, mtu: usize) -> Self {
+        self.mtu = Some(mtu.max(1));
+        self
+    }
+
+    /// Time needed to put `len` bytes on the link
+    fn transmit_time(&self, len: usize) -> Duration {
+        match self.bandwidth {
+            Some(0) | None => Duration::ZERO,
+            Some(bps) => Duration::from_secs_f64(len as f64 / bps as f64),
+        }
+    }
+}
+
+/// Data in flight on a link
+struct Packet {
+    deliver_at: Instant,
+    data: Bytes,
+}
+
+/// Sending half of one link direction
+struct LinkSender {
+    tx: mpsc::UnboundedSender<Packet>,
+    link: LinkConfig,
+    /// When the link finishes transmitting what was already sent
+    busy_until: Instant,
+}
+
+impl LinkSender {
+    /// Queue `data` for delivery, split into MTU-sized packets
+    fn send(&mut self, data: Bytes) -> Result<(), NetworkError> {
+        // The field is public, so a zero MTU can get past `with_mtu`
+        let mtu = self.link.mtu.unwrap_or(usize::MAX).max(1);
+        let mut offset = 0;
+
+        while offset < data.len() {
+            let end = offset.saturating_add(mtu).min(data.len());
+            let chunk = data.slice(offset..end);
+            offset = end;
+
+            let start = self.busy_until.max(Instant::now());
+            self.busy_until = start + self.link.transmit_time(chunk.len());
+
+            let packet = Packet {
+                deliver_at: self.busy_until + self.link.latency,
+                data: chunk,
+            };
+            self.tx.send(packet).map_err(|_| NetworkError::ConnectionClosed)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// One end of an in-memory connection
+pub struct MemoryStream {
+    tx: Option<LinkSender>,
+    rx: mpsc::UnboundedReceiver<Packet>,
+    /// Packet received but not yet due, kept so reads are cancel-safe
+    pending: Option<Packet>,
+    peer_addr: TransportAddr,
+    peer_identity: Option<PeerIdentity>,
+}
+
+#[async_trait]
+impl MessageStream for MemoryStream {
+    async fn read_message(&mut self) -> Result<Option<Bytes>, NetworkError> {
+        if self.pending.is_none() {
+            match self.rx.recv().await {
+                Some(packet) => self.pending = Some(packet),
+                None => return Ok(None), // Peer closed
+            }
+        }
+
+        if let Some(packet) = &self.pending {
+            tokio::time::sleep_until(packet.deliver_at).await;
+        }
+        Ok(self.pending.take().map(|packet| packet.data))
+    }
+
+    async fn write_message(&mut self, msg: Bytes) -> Result<(), NetworkError> {
+        match &mut self.tx {
+            Some(tx) => tx.send(msg),
+            None => Err(NetworkError::ConnectionClosed),
+        }
+    }
+
+    fn peer_addr(&self) -> Result<TransportAddr, NetworkError> {
+        Ok(self.peer_addr.clone())
+    }
+
+    fn peer_identity(&self) -> Option<PeerIdentity> {
+        self.peer_identity.clone()
+    }
+
+    async fn shutdown(&mut self) -> Result<(), NetworkError> {
+        self.tx = None;
+        Ok(())
+    }
+}
+
+/// Create a connected pair of streams over a link
+///
+/// The first stream reports `b` as its peer and the second reports `a`.
+pub fn memory_duplex(a: TransportAddr, b: TransportAddr, link: LinkConfig) -> (MemoryStream, MemoryStream) {
+    let (a_tx, b_rx) = mpsc::unbounded_channel();
+    let (b_tx, a_rx) = mpsc::unbounded_channel();
+    let now = Instant::now();
+
+    let stream_a = MemoryStream {
+        tx: Some(LinkSender { tx: a_tx, link, busy_until: now }),
+        rx: a_rx,
+        pending: None,
+        peer_addr: b,
+        peer_identity: None,
+    };
+    let stream_b = MemoryStream {
+        tx: Some(LinkSender { tx: b_tx, link, busy_until: now }),
+        rx: b_rx,
+        pending: None,
+        peer_addr: a,
+        peer_identity: None,
+    };
+
+    (stream_a, stream_b)
+}
+
+/// Registered endpoints of a memory network
+struct NetworkInner {
+    endpoints: Mutex<HashMap<String, mpsc::UnboundedSender<MemoryStream>>>,
+    link: Mutex<LinkConfig>,
+    next_client: AtomicU64,
+}
+
+/// A set of named in-process endpoints
+///
+/// Cloning yields a handle to the same network. Tests should create their
+/// own network; [`MemoryNetwork::global`] is shared by the whole process.
+#[derive(Clone)]
+pub struct MemoryNetwork {
+    inner: Arc<NetworkInner>,
+}
+
+impl MemoryNetwork {
+    /// Create an empty network with unlimited links
+    pub fn new() -> Self {
+        Self::with_link(LinkConfig::unlimited())
+    }
+
+    /// Create an empty network whose connections use `link`
+    pub fn with_link(link: LinkConfig) -> Self {
+        Self {
+            inner: Arc::new(NetworkInner {
+                endpoints: Mutex::new(HashMap::new()),
+                link: Mutex::new(link),
+                next_client: AtomicU64::new(1),
+            }),
+        }
+    }
+
+    /// The process-wide network
+    pub fn global() -> Self {
+        static GLOBAL: OnceLock<MemoryNetwork> = OnceLock::new();
+        GLOBAL.get_or_init(MemoryNetwork::new).clone()
+    }
+
+    /// Change the link used by new connections
+    pub fn set_link(&self, link: LinkConfig) {
+        *self.inner.link.lock().unwrap() = link;
+    }
+
+    /// Register an endpoint and listen on it
+    pub fn bind(&self, name: impl Into<String>) -> Result<MemoryListener, NetworkError> {
+        let name = name.into();
+        let mut endpoints = self.inner.endpoints.lock().unwrap();
+
+        if endpoints.get(&name).is_some_and(|tx| !tx.is_closed()) {
+            return Err(NetworkError::IoError(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("mem:{} is already in use", name),
+            )));
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        endpoints.insert(name.clone(), tx);
+
+        Ok(MemoryListener {
+            name,
+            rx,
+            network: self.clone(),
+        })
+    }
+
+    /// Create a connector for this network
+    pub fn connector(&self) -> MemoryConnector {
+        MemoryConnector {
+            network: self.clone(),
+            identity: None,
+        }
+    }
+
+    /// Create a connector whose connections present `identity` to the listener
+    pub fn connector_as(&self, identity: PeerIdentity) -> MemoryConnector {
+        MemoryConnector {
+            network: self.clone(),
+            identity: Some(identity),
+        }
+    }
+
+    /// Names of the registered endpoints
+    pub fn endpoints(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.inner.endpoints.lock().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Open a connection to the endpoint `name`, presenting `identity`
+    fn connect(&self, name: &str, identity: Option<PeerIdentity>) -> Result<MemoryStream, NetworkError> {
+        let endpoint = self
+            .inner
+            .endpoints
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or(NetworkError::ConnectionRefused)?;
+
+        let client = format!("{}#{}", name, self.inner.next_client.fetch_add(1, Ordering::Relaxed));
+        let link = *self.inner.link.lock().unwrap();
+        let (client_end, mut server_end) = memory_duplex(
+            TransportAddr::Memory(client),
+            TransportAddr::Memory(name.to_string()),
+            link,
+        );
+
+        server_end.peer_identity = identity;
+        endpoint.send(server_end).map_err(|_| NetworkError::ConnectionRefused)?;
+        Ok(client_end)
+    }
+
+    /// Remove an endpoint when its listener goes away
+    fn unregister(&self, name: &str) {
+        let mut endpoints = self.inner.endpoints.lock().unwrap();
+        if endpoints.get(name).is_some_and(|tx| tx.is_closed()) {
+            endpoints.remove(name);
+        }
+    }
+}
+
+impl Default for MemoryNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Listener on a named in-process endpoint
+///
+/// The name is released when the listener is dropped.
+pub struct MemoryListener {
+    name: String,
+    rx: mpsc::UnboundedReceiver<MemoryStream>,
+    network: MemoryNetwork,
+}
+
+#[async_trait]
+impl MessageListener for MemoryListener {
+    async fn accept(&mut self) -> Result<(Box<dyn MessageStream>, TransportAddr), NetworkError> {
+        let stream = self.rx.recv().await.ok_or(NetworkError::ConnectionClosed)?;
+        let addr = stream.peer_addr.clone();
+        Ok((Box::new(stream), addr))
+    }
+
+    fn local_addr(&self) -> Result<TransportAddr, NetworkError> {
+        Ok(TransportAddr::Memory(self.name.clone()))
+    }
+}
+
+impl Drop for MemoryListener {
+    fn drop(&mut self) {
+        self.rx.close();
+        self.network.unregister(&self.name);
+    }
+}
+
+/// Connector for in-process addresses
+#[derive(Clone)]
+pub struct MemoryConnector {
+    network: MemoryNetwork,
+    identity: Option<PeerIdentity>,
+}
+
+#[async_trait]
+impl NetworkConnector for MemoryConnector {
+    async fn connect(&self, addr: TransportAddr) -> Result<Box<dyn MessageStream>, NetworkError> {
+        match addr {
+            TransportAddr::Memory(name) => Ok(Box::new(self.network.connect(&name, self.identity.clone())?)),
+            other => Err(NetworkError::UnsupportedAddress(other)),
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Mapping of agent IDs to the endpoints they are reachable at
+
+#[cfg(feature = "tokio")]
+use crate::dispatch::{MessageContext, MessageDispatcher};
+use crate::endpoint::Endpoint;
+use crate::protocol::AgentDiscoveryMessage;
+use crate::transport::NetworkError;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// How a peer became known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerSource {
+    /// Configured statically
+    Static,
+    /// Announced through discovery
+    Discovered,
+    /// Learned from the gossip membership protocol
+    Membership,
+}
+
+/// A known agent
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerEntry {
+    /// Where the agent accepts connections
+    pub endpoint: Endpoint,
+    /// Capabilities the agent announced
+    pub capabilities: Vec<String>,
+    /// How the entry was learned
+    pub source: PeerSource,
+    /// When the entry was last added or refreshed
+    pub updated_at: Instant,
+}
+
+/// Endpoints of known agents by agent ID
+///
+/// Cloning yields a handle to the same registry. Statically configured
+/// entries take precedence over membership ones, which take precedence over
+/// discovered ones.
+#[derive(Debug, Clone, Default)]
+pub struct PeerRegistry {
+    peers: Arc<RwLock<HashMap<String, PeerEntry>>>,
+}
+
+impl PeerRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry from configured agent IDs and endpoint URIs
+    pub fn from_config<'a>(peers: impl IntoIterator<Item = (&'a String, &'a String)>) -> Result<Self, NetworkError> {
+        let registry = Self::new();
+        for (agent_id, endpoint) in peers {
+            registry.insert(agent_id.clone(), endpoint.parse()?);
+        }
+        Ok(registry)
+    }
+
+    /// Add or replace a static entry
+    pub fn insert(&self, agent_id: impl Into<String>, endpoint: Endpoint) {
+        self.peers.write().unwrap().insert(
+            agent_id.into(),
+            PeerEntry {
+                endpoint,
+                capabilities: Vec::new(),
+                source: PeerSource::Static,
+                updated_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Record an agent announced through discovery
+    ///
+    /// Returns `false` if the agent is configured statically or tracked by
+    /// membership, in which case the announcement is ignored.
+    pub fn record_discovery(&self, message: &AgentDiscoveryMessage) -> bool {
+        let mut peers = self.peers.write().unwrap();
+
+        if peers.get(&message.agent_id).is_some_and(|peer| peer.source != PeerSource::Discovered) {
+            return false;
+        }
+
+        peers.insert(
+            message.agent_id.clone(),
+            PeerEntry {
+                endpoint: message.listen_addr.clone(),
+                capabilities: message.capabilities.clone(),
+                source: PeerSource::Discovered,
+                updated_at: Instant::now(),
+            },
+        );
+        true
+    }
+
+    /// Record an alive member of the cluster
+    ///
+    /// Returns `false` if the agent is configured statically, in which case
+    /// the member is ignored.
+    pub fn record_member(&self, agent_id: &str, endpoint: Endpoint, capabilities: Vec<String>) -> bool {
+        let mut peers = self.peers.write().unwrap();
+
+        if peers.get(agent_id).is_some_and(|peer| peer.source == PeerSource::Static) {
+            return false;
+        }
+
+        peers.insert(
+            agent_id.to_string(),
+            PeerEntry {
+                endpoint,
+                capabilities,
+                source: PeerSource::Membership,
+                updated_at: Instant::now(),
+            },
+        );
+        true
+    }
+
+    /// Forget a member that failed or left
+    ///
+    /// Entries learned any other way are kept.
+    pub fn remove_member(&self, agent_id: &str) -> Option<PeerEntry> {
+        let mut peers = self.peers.write().unwrap();
+
+        match peers.get(agent_id) {
+            Some(peer) if peer.source == PeerSource::Membership => peers.remove(agent_id),
+            _ => None,
+        }
+    }
+
+    /// Record discovery announcements received by `dispatcher`
+    #[cfg(feature = "tokio")]
+    pub fn register_discovery(&self, dispatcher: &mut MessageDispatcher) {
+        let registry = self.clone();
+        dispatcher.register(move |message: AgentDiscoveryMessage, _ctx: MessageContext| {
+            registry.record_discovery(&message);
+            std::future::ready(())
+        });
+    }
+
+    /// Forget an agent
+    pub fn remove(&self, agent_id: &str) -> Option<PeerEntry> {
+        self.peers.write().unwrap().remove(agent_id)
+    }
+
+    /// Forget discovered agents not announced within `ttl`
+    ///
+    /// Static and membership entries never expire. Returns the IDs of the
+    /// removed agents.
+    pub fn expire_discovered(&self, ttl: Duration) -> Vec<String> {
+        let mut peers = self.peers.write().unwrap();
+        let mut expired = Vec::new();
+
+        peers.retain(|agent_id, peer| {
+            let keep = peer.source != PeerSource::Discovered || peer.updated_at.elapsed() < ttl;
+            if !keep {
+                expired.push(agent_id.clone());
+            }
+            keep
+        });
+        expired.sort();
+        expired
+    }
+
+    /// IDs of the agents announcing `capability`, sorted
+    pub fn with_capability(&self, capability: &str) -> Vec<String> {
+        let mut agents: Vec<String> = self
+            .peers
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, peer)| peer.capabilities.iter().any(|c| c == capability))
+            .map(|(agent_id, _)| agent_id.clone())
+            .collect();
+        agents.sort();
+        agents
+    }
+
+    /// Endpoint of an agent
+    pub fn lookup(&self, agent_id: &str) -> Option<Endpoint> {
+        self.peers.read().unwrap().get(agent_id).map(|peer| peer.endpoint.clone())
+    }
+
+    /// Entry of an agent
+    pub fn get(&self, agent_id: &str) -> Option<PeerEntry> {
+        self.peers.read().unwrap().get(agent_id).cloned()
+    }
+
+    /// IDs of the known agents, sorted
+    pub fn agents(&self) -> Vec<String> {
+        let mut agents: Vec<String> = self.peers.read().unwrap().keys().cloned().collect();
+        agents.sort();
+        agents
+    }
+}
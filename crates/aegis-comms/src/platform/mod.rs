@@ -1,2 +1,8 @@
 #[cfg(feature = "platform_tokio_net")]
-pub mod tokio_impl; 
\ No newline at end of file
+pub mod tokio_impl;
+
+#[cfg(feature = "tls")]
+pub mod tls_impl;
+
+#[cfg(all(unix, feature = "unix"))]
+pub mod unix_impl;
@@ -0,0 +1,421 @@
+//! TLS transport built on tokio-rustls
+//!
+//! Certificates and keys are loaded from the PEM files named in
+//! `NetworkConfig.tls`. Clients verify the server certificate against the CA
+//! bundle in `ca_path` and the expected `server_name`, and present their own
+//! certificate so that servers with `require_client_cert` can authenticate
+//! them. The verified peer certificate is exposed as a [`PeerIdentity`].
+//! When `crl_path` is set, peer certificates listed in the revocation lists
+//! are rejected during the handshake.
+
+use crate::transport::{MessageStream, MessageListener, NetworkConnector, NetworkError, PeerIdentity, TransportAddr};
+use aegis_core::config::TlsConfig;
+use aegis_core::utils::{format_hex, sha256_hash};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_rustls::rustls::client::WebPkiServerVerifier;
+use tokio_rustls::rustls::pki_types::{CertificateDer, CertificateRevocationListDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::{TlsAcceptor, TlsStream};
+
+/// Default time allowed for a TLS handshake on accepted connections
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Message stream over a TLS connection
+pub struct TokioTlsStream {
+    stream: TlsStream<TcpStream>,
+    identity: Option<PeerIdentity>,
+}
+
+impl TokioTlsStream {
+    /// Wrap an established TLS stream, extracting the peer identity
+    ///
+    /// A peer certificate that cannot be parsed is treated as absent.
+    pub fn new(stream: TlsStream<TcpStream>) -> Self {
+        let identity = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .and_then(|cert| peer_identity_from_der(cert).ok());
+
+        Self { stream, identity }
+    }
+
+    /// Underlying TLS stream
+    pub fn get_ref(&self) -> &TlsStream<TcpStream> {
+        &self.stream
+    }
+}
+
+#[async_trait]
+impl MessageStream for TokioTlsStream {
+    async fn read_message(&mut self) -> Result<Option<Bytes>, NetworkError> {
+        let mut buf = BytesMut::with_capacity(8192);
+        match self.stream.read_buf(&mut buf).await {
+            Ok(0) => Ok(None), // Connection closed
+            Ok(_) => Ok(Some(buf.freeze())),
+            Err(e) => Err(NetworkError::IoError(e)),
+        }
+    }
+
+    async fn write_message(&mut self, msg: Bytes) -> Result<(), NetworkError> {
+        self.stream.write_all(&msg).await.map_err(NetworkError::IoError)?;
+        self.stream.flush().await.map_err(NetworkError::IoError)
+    }
+
+    fn peer_addr(&self) -> Result<TransportAddr, NetworkError> {
+        self.stream.get_ref().0.peer_addr().map(TransportAddr::Tcp).map_err(NetworkError::IoError)
+    }
+
+    fn peer_identity(&self) -> Option<PeerIdentity> {
+        self.identity.clone()
+    }
+
+    async fn shutdown(&mut self) -> Result<(), NetworkError> {
+        self.stream.shutdown().await.map_err(NetworkError::IoError)
+    }
+}
+
+/// Listener accepting TLS connections
+///
+/// Every accepted TCP connection gets its own handshake task, and `accept`
+/// returns whichever handshake completes first, so a client that stalls
+/// during the handshake does not hold up the others. Connections that fail
+/// or time out during the handshake are dropped and counted.
+pub struct TokioTlsListener {
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    handshake_timeout: Duration,
+    handshake_failures: Arc<AtomicU64>,
+    /// Handshakes in progress; each yields the stream if it succeeded
+    handshakes: JoinSet<Option<(TlsStream<TcpStream>, SocketAddr)>>,
+}
+
+impl TokioTlsListener {
+    /// Bind a TLS listener using the certificate and key from the config
+    pub async fn bind(addr: SocketAddr, tls: &TlsConfig) -> Result<Self, NetworkError> {
+        let config = server_config(tls)?;
+        Self::bind_with_config(addr, Arc::new(config)).await
+    }
+
+    /// Bind a TLS listener with a prepared rustls server configuration
+    pub async fn bind_with_config(addr: SocketAddr, config: Arc<ServerConfig>) -> Result<Self, NetworkError> {
+        let listener = TcpListener::bind(addr).await.map_err(NetworkError::IoError)?;
+
+        Ok(Self {
+            listener,
+            acceptor: TlsAcceptor::from(config),
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            handshake_failures: Arc::new(AtomicU64::new(0)),
+            handshakes: JoinSet::new(),
+        })
+    }
+
+    /// Set the time allowed for a TLS handshake
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Number of connections dropped because the handshake failed
+    pub fn handshake_failures(&self) -> u64 {
+        self.handshake_failures.load(Ordering::Relaxed)
+    }
+
+    /// Replace the server configuration used for new connections
+    ///
+    /// Used to pick up renewed certificates or an updated revocation list
+    /// without rebinding the socket.
+    pub fn set_server_config(&mut self, config: Arc<ServerConfig>) {
+        self.acceptor = TlsAcceptor::from(config);
+    }
+
+    /// Reload certificates and revocation lists from the TLS config
+    pub fn reload(&mut self, tls: &TlsConfig) -> Result<(), NetworkError> {
+        self.set_server_config(Arc::new(server_config(tls)?));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MessageListener for TokioTlsListener {
+    async fn accept(&mut self) -> Result<(Box<dyn MessageStream>, TransportAddr), NetworkError> {
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => {
+                    let (stream, addr) = accepted.map_err(NetworkError::IoError)?;
+                    let acceptor = self.acceptor.clone();
+                    let timeout = self.handshake_timeout;
+                    let failures = self.handshake_failures.clone();
+                    self.handshakes.spawn(async move {
+                        match tokio::time::timeout(timeout, acceptor.accept(stream)).await {
+                            Ok(Ok(tls)) => Some((TlsStream::Server(tls), addr)),
+                            Ok(Err(_)) | Err(_) => {
+                                failures.fetch_add(1, Ordering::Relaxed);
+                                None
+                            }
+                        }
+                    });
+                }
+                Some(done) = self.handshakes.join_next(), if !self.handshakes.is_empty() => {
+                    if let Ok(Some((tls, addr))) = done {
+                        return Ok((Box::new(TokioTlsStream::new(tls)), TransportAddr::Tcp(addr)));
+                    }
+                }
+            }
+        }
+    }
+
+    fn local_addr(&self) -> Result<TransportAddr, NetworkError> {
+        self.listener.local_addr().map(TransportAddr::Tcp).map_err(NetworkError::IoError)
+    }
+}
+
+/// Connector establishing TLS connections
+#[derive(Clone)]
+pub struct TokioTlsConnector {
+    connector: tokio_rustls::TlsConnector,
+    server_name: Option<String>,
+}
+
+impl TokioTlsConnector {
+    /// Create a connector that trusts the CA bundle from the config
+    pub fn from_config(tls: &TlsConfig) -> Result<Self, NetworkError> {
+        let config = client_config(tls)?;
+        Ok(Self::with_config(Arc::new(config), tls.server_name.clone()))
+    }
+
+    /// Create a connector with a prepared rustls client configuration
+    ///
+    /// Without a `server_name`, the host name of the endpoint being
+    /// connected to, or failing that its IP address, is verified against the
+    /// certificate instead.
+    pub fn with_config(config: Arc<ClientConfig>, server_name: Option<String>) -> Self {
+        Self {
+            connector: tokio_rustls::TlsConnector::from(config),
+            server_name,
+        }
+    }
+
+    /// Name verified against the server certificate for `addr`
+    fn server_name_for(&self, addr: SocketAddr, host: Option<&str>) -> Result<ServerName<'static>, NetworkError> {
+        match self.server_name.as_deref().or(host) {
+            Some(name) => ServerName::try_from(name.to_string())
+                .map_err(|e| NetworkError::Tls(format!("invalid server name {}: {}", name, e))),
+            None => Ok(ServerName::IpAddress(addr.ip().into())),
+        }
+    }
+
+    /// Connect and perform the TLS handshake
+    async fn handshake(&self, addr: TransportAddr, host: Option<&str>) -> Result<Box<dyn MessageStream>, NetworkError> {
+        let addr = match addr {
+            TransportAddr::Tcp(addr) => addr,
+            other => return Err(NetworkError::UnsupportedAddress(other)),
+        };
+        let server_name = self.server_name_for(addr, host)?;
+
+        let stream = TcpStream::connect(addr)
+            .await
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::ConnectionRefused => NetworkError::ConnectionRefused,
+                std::io::ErrorKind::TimedOut => NetworkError::Timeout,
+                _ => NetworkError::IoError(e),
+            })?;
+
+        let tls = self
+            .connector
+            .connect(server_name, stream)
+            .await
+            .map_err(|e| NetworkError::Tls(format!("handshake with {} failed: {}", addr, e)))?;
+
+        Ok(Box::new(TokioTlsStream::new(TlsStream::Client(tls))))
+    }
+}
+
+#[async_trait]
+impl NetworkConnector for TokioTlsConnector {
+    async fn connect(&self, addr: TransportAddr) -> Result<Box<dyn MessageStream>, NetworkError> {
+        self.handshake(addr, None).await
+    }
+
+    async fn connect_to_host(&self, addr: TransportAddr, host: &str) -> Result<Box<dyn MessageStream>, NetworkError> {
+        self.handshake(addr, Some(host)).await
+    }
+}
+
+/// Crypto provider used for all TLS configurations
+fn provider() -> Arc<rustls::crypto::CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Build a rustls server configuration from the TLS config
+///
+/// With `require_client_cert`, clients must present a certificate signed by
+/// a CA from `ca_path`.
+pub fn server_config(tls: &TlsConfig) -> Result<ServerConfig, NetworkError> {
+    let certs = load_certs(&tls.cert_path)?;
+    let key = load_private_key(&tls.key_path)?;
+
+    let builder = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| NetworkError::Tls(e.to_string()))?;
+
+    let builder = if tls.require_client_cert {
+        let ca_path = tls.ca_path.as_ref().ok_or_else(|| {
+            NetworkError::Tls("network.tls.ca_path is required to verify clients".to_string())
+        })?;
+        let mut verifier =
+            WebPkiClientVerifier::builder_with_provider(Arc::new(load_root_store(ca_path)?), provider());
+        if let Some(crl_path) = &tls.crl_path {
+            verifier = verifier
+                .with_crls(load_crls(crl_path)?)
+                .only_check_end_entity_revocation()
+                .allow_unknown_revocation_status();
+        }
+        let verifier = verifier
+            .build()
+            .map_err(|e| NetworkError::Tls(format!("invalid client verifier: {}", e)))?;
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+
+    builder
+        .with_single_cert(certs, key)
+        .map_err(|e| NetworkError::Tls(format!("invalid certificate or key: {}", e)))
+}
+
+/// Build a rustls client configuration from the TLS config
+///
+/// `ca_path` must name the CA bundle used to verify servers; there is no
+/// fallback to system roots, since nodes normally use a private CA.
+pub fn client_config(tls: &TlsConfig) -> Result<ClientConfig, NetworkError> {
+    let ca_path = tls
+        .ca_path
+        .as_ref()
+        .ok_or_else(|| NetworkError::Tls("network.tls.ca_path is required to verify servers".to_string()))?;
+
+    let mut verifier = WebPkiServerVerifier::builder_with_provider(Arc::new(load_root_store(ca_path)?), provider());
+    if let Some(crl_path) = &tls.crl_path {
+        verifier = verifier
+            .with_crls(load_crls(crl_path)?)
+            .only_check_end_entity_revocation()
+            .allow_unknown_revocation_status();
+    }
+    let verifier = verifier
+        .build()
+        .map_err(|e| NetworkError::Tls(format!("invalid server verifier: {}", e)))?;
+
+    let certs = load_certs(&tls.cert_path)?;
+    let key = load_private_key(&tls.key_path)?;
+
+    ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| NetworkError::Tls(e.to_string()))?
+        .with_webpki_verifier(verifier)
+        .with_client_auth_cert(certs, key)
+        .map_err(|e| NetworkError::Tls(format!("invalid certificate or key: {}", e)))
+}
+
+/// Extract the identity from a DER-encoded peer certificate
+pub fn peer_identity_from_der(der: &[u8]) -> Result<PeerIdentity, NetworkError> {
+    use x509_parser::extensions::GeneralName;
+
+    let (_, cert) = x509_parser::parse_x509_certificate(der)
+        .map_err(|e| NetworkError::Tls(format!("invalid peer certificate: {}", e)))?;
+
+    let subject = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| cert.subject().to_string());
+
+    let mut dns_names = Vec::new();
+    let mut uris = Vec::new();
+
+    if let Ok(Some(san)) = cert.subject_alternative_name() {
+        for name in &san.value.general_names {
+            match name {
+                GeneralName::DNSName(dns) => dns_names.push(dns.to_string()),
+                GeneralName::URI(uri) => uris.push(uri.to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    let spiffe_id = uris.iter().find(|uri| uri.starts_with("spiffe://")).cloned();
+
+    Ok(PeerIdentity {
+        subject,
+        dns_names,
+        uris,
+        spiffe_id,
+        fingerprint: format_hex(&sha256_hash(der)),
+    })
+}
+
+/// Load all certificates from a PEM file
+pub fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, NetworkError> {
+    let file = std::fs::File::open(path).map_err(NetworkError::IoError)?;
+    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(NetworkError::IoError)?;
+
+    if certs.is_empty() {
+        return Err(NetworkError::Tls(format!("no certificates found in {}", path.display())));
+    }
+
+    Ok(certs)
+}
+
+/// Load the first private key from a PEM file
+pub fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, NetworkError> {
+    let file = std::fs::File::open(path).map_err(NetworkError::IoError)?;
+
+    rustls_pemfile::private_key(&mut std::io::BufReader::new(file))
+        .map_err(NetworkError::IoError)?
+        .ok_or_else(|| NetworkError::Tls(format!("no private key found in {}", path.display())))
+}
+
+/// Load all certificate revocation lists from a PEM file
+pub fn load_crls(path: &Path) -> Result<Vec<CertificateRevocationListDer<'static>>, NetworkError> {
+    let file = std::fs::File::open(path).map_err(NetworkError::IoError)?;
+
+    rustls_pemfile::crls(&mut std::io::BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(NetworkError::IoError)
+}
+
+/// Load a CA bundle into a root certificate store
+pub fn load_root_store(path: &Path) -> Result<RootCertStore, NetworkError> {
+    let mut roots = RootCertStore::empty();
+
+    for cert in load_certs(path)? {
+        roots
+            .add(cert)
+            .map_err(|e| NetworkError::Tls(format!("invalid CA certificate in {}: {}", path.display(), e)))?;
+    }
+
+    Ok(roots)
+}
+
+// Helper functions to create TLS instances
+pub async fn listen_tls(addr: SocketAddr, tls: &TlsConfig) -> Result<TokioTlsListener, NetworkError> {
+    TokioTlsListener::bind(addr, tls).await
+}
+
+pub fn connect_tls(tls: &TlsConfig) -> Result<TokioTlsConnector, NetworkError> {
+    TokioTlsConnector::from_config(tls)
+}
@@ -1,4 +1,4 @@
-use crate::transport::{MessageStream, MessageListener, NetworkConnector, NetworkError};
+use crate::transport::{MessageStream, MessageListener, NetworkConnector, NetworkError, TransportAddr};
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
 use std::net::SocketAddr;
@@ -22,8 +22,8 @@ impl MessageStream for TokioTcpStream {
         self.0.write_all(&msg).await.map_err(NetworkError::IoError)
     }
     
-    fn peer_addr(&self) -> Result<SocketAddr, NetworkError> {
-        self.0.peer_addr().map_err(NetworkError::IoError)
+    fn peer_addr(&self) -> Result<TransportAddr, NetworkError> {
+        self.0.peer_addr().map(TransportAddr::Tcp).map_err(NetworkError::IoError)
     }
     
     async fn shutdown(&mut self) -> Result<(), NetworkError> {
@@ -44,13 +44,13 @@ impl TokioTcpListener {
 
 #[async_trait]
 impl MessageListener for TokioTcpListener {
-    async fn accept(&mut self) -> Result<(Box<dyn MessageStream>, SocketAddr), NetworkError> {
+    async fn accept(&mut self) -> Result<(Box<dyn MessageStream>, TransportAddr), NetworkError> {
         let (stream, addr) = self.0.accept().await.map_err(NetworkError::IoError)?;
-        Ok((Box::new(TokioTcpStream(stream)), addr))
+        Ok((Box::new(TokioTcpStream(stream)), TransportAddr::Tcp(addr)))
     }
     
-    fn local_addr(&self) -> Result<SocketAddr, NetworkError> {
-        self.0.local_addr().map_err(NetworkError::IoError)
+    fn local_addr(&self) -> Result<TransportAddr, NetworkError> {
+        self.0.local_addr().map(TransportAddr::Tcp).map_err(NetworkError::IoError)
     }
 }
 
@@ -65,7 +65,11 @@ impl TokioConnector {
 
 #[async_trait]
 impl NetworkConnector for TokioConnector {
-    async fn connect(&self, addr: SocketAddr) -> Result<Box<dyn MessageStream>, NetworkError> {
+    async fn connect(&self, addr: TransportAddr) -> Result<Box<dyn MessageStream>, NetworkError> {
+        let addr = match addr {
+            TransportAddr::Tcp(addr) => addr,
+            other => return Err(NetworkError::UnsupportedAddress(other)),
+        };
         let stream = TcpStream::connect(addr)
             .await
             .map_err(|e| match e.kind() {
@@ -0,0 +1,232 @@
+//! Unix domain socket transport for agents on the same host
+//!
+//! Listeners create their socket file with owner-only permissions by
+//! default and learn the uid, gid and pid of every connecting process from
+//! the kernel (`SO_PEERCRED`), which handlers can use in place of TLS
+//! identities for local peers.
+
+use crate::transport::{MessageListener, MessageStream, NetworkConnector, NetworkError, PeerCredentials, TransportAddr};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+
+/// Default permissions of the socket file: read and write for the owner only
+pub const DEFAULT_SOCKET_MODE: u32 = 0o600;
+
+/// Message stream over a Unix domain socket
+pub struct TokioUnixStream {
+    stream: UnixStream,
+    peer_path: Option<PathBuf>,
+    credentials: Option<PeerCredentials>,
+}
+
+impl TokioUnixStream {
+    /// Wrap a connected socket, reading the peer credentials
+    pub fn new(stream: UnixStream) -> Self {
+        let peer_path = stream.peer_addr().ok().and_then(|a| a.as_pathname().map(Path::to_path_buf));
+        Self::with_peer_path(stream, peer_path)
+    }
+
+    /// Wrap a socket connected to `peer_path`
+    ///
+    /// Listeners bind under a temporary name before moving the socket into
+    /// place, so the kernel reports that name rather than the path dialed.
+    fn with_peer_path(stream: UnixStream, peer_path: Option<PathBuf>) -> Self {
+        let credentials = stream.peer_cred().ok().map(|cred| PeerCredentials {
+            uid: cred.uid(),
+            gid: cred.gid(),
+            pid: cred.pid(),
+        });
+
+        Self {
+            stream,
+            peer_path,
+            credentials,
+        }
+    }
+}
+
+#[async_trait]
+impl MessageStream for TokioUnixStream {
+    async fn read_message(&mut self) -> Result<Option<Bytes>, NetworkError> {
+        let mut buf = BytesMut::with_capacity(8192);
+        match self.stream.read_buf(&mut buf).await {
+            Ok(0) => Ok(None), // Connection closed
+            Ok(_) => Ok(Some(buf.freeze())),
+            Err(e) => Err(NetworkError::IoError(e)),
+        }
+    }
+
+    async fn write_message(&mut self, msg: Bytes) -> Result<(), NetworkError> {
+        self.stream.write_all(&msg).await.map_err(NetworkError::IoError)
+    }
+
+    fn peer_addr(&self) -> Result<TransportAddr, NetworkError> {
+        Ok(TransportAddr::Unix(self.peer_path.clone()))
+    }
+
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        self.credentials
+    }
+
+    async fn shutdown(&mut self) -> Result<(), NetworkError> {
+        self.stream.shutdown().await.map_err(NetworkError::IoError)
+    }
+}
+
+/// Listener on a Unix domain socket
+///
+/// The socket file is removed when the listener is dropped.
+pub struct TokioUnixListener {
+    listener: UnixListener,
+    path: PathBuf,
+    allowed_uids: Option<Vec<u32>>,
+    rejected: AtomicU64,
+}
+
+impl TokioUnixListener {
+    /// Bind to `path` with owner-only permissions
+    pub fn bind(path: impl AsRef<Path>) -> Result<Self, NetworkError> {
+        Self::bind_with_mode(path, DEFAULT_SOCKET_MODE)
+    }
+
+    /// Bind to `path`, creating the socket file with `mode`
+    ///
+    /// The socket is bound under a temporary name and renamed into place
+    /// after its permissions are set, so it is never reachable with looser
+    /// permissions. A stale socket left by a previous process is replaced;
+    /// a socket with a live listener is not.
+    pub fn bind_with_mode(path: impl AsRef<Path>, mode: u32) -> Result<Self, NetworkError> {
+        let path = path.as_ref().to_path_buf();
+
+        if path.exists() {
+            if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+                return Err(NetworkError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::AddrInUse,
+                    format!("{} is already in use", path.display()),
+                )));
+            }
+            std::fs::remove_file(&path).map_err(NetworkError::IoError)?;
+        }
+
+        let tmp = path.with_file_name(format!(
+            ".{}.{}.tmp",
+            path.file_name().and_then(|n| n.to_str()).unwrap_or("aegis.sock"),
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&tmp);
+
+        let listener = UnixListener::bind(&tmp).map_err(NetworkError::IoError)?;
+        let placed = std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(mode))
+            .and_then(|_| std::fs::rename(&tmp, &path));
+        if let Err(e) = placed {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(NetworkError::IoError(e));
+        }
+
+        Ok(Self {
+            listener,
+            path,
+            allowed_uids: None,
+            rejected: AtomicU64::new(0),
+        })
+    }
+
+    /// Only accept connections from processes running as one of `uids`
+    pub fn with_allowed_uids(mut self, uids: Vec<u32>) -> Self {
+        self.allowed_uids = Some(uids);
+        self
+    }
+
+    /// Path of the socket file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of connections rejected by the uid filter
+    pub fn rejected_connections(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Whether a peer passes the uid filter
+    fn is_allowed(&self, credentials: Option<PeerCredentials>) -> bool {
+        match (&self.allowed_uids, credentials) {
+            (None, _) => true,
+            (Some(uids), Some(cred)) => uids.contains(&cred.uid),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+#[async_trait]
+impl MessageListener for TokioUnixListener {
+    async fn accept(&mut self) -> Result<(Box<dyn MessageStream>, TransportAddr), NetworkError> {
+        loop {
+            let (stream, _) = self.listener.accept().await.map_err(NetworkError::IoError)?;
+            let stream = TokioUnixStream::new(stream);
+
+            if !self.is_allowed(stream.credentials) {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+
+            let addr = TransportAddr::Unix(stream.peer_path.clone());
+            return Ok((Box::new(stream), addr));
+        }
+    }
+
+    fn local_addr(&self) -> Result<TransportAddr, NetworkError> {
+        Ok(TransportAddr::unix(self.path.clone()))
+    }
+}
+
+impl Drop for TokioUnixListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Connector for Unix domain socket addresses
+#[derive(Clone, Default)]
+pub struct TokioUnixConnector;
+
+impl TokioUnixConnector {
+    /// Create a connector
+    pub fn new() -> Self {
+        TokioUnixConnector
+    }
+}
+
+#[async_trait]
+impl NetworkConnector for TokioUnixConnector {
+    async fn connect(&self, addr: TransportAddr) -> Result<Box<dyn MessageStream>, NetworkError> {
+        let path = match addr {
+            TransportAddr::Unix(Some(path)) => path,
+            other => return Err(NetworkError::UnsupportedAddress(other)),
+        };
+
+        let stream = UnixStream::connect(&path)
+            .await
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::NotFound => {
+                    NetworkError::ConnectionRefused
+                }
+                _ => NetworkError::IoError(e),
+            })?;
+        Ok(Box::new(TokioUnixStream::with_peer_path(stream, Some(path))))
+    }
+}
+
+/// Listen on a Unix domain socket with owner-only permissions
+pub fn listen_unix(path: impl AsRef<Path>) -> Result<TokioUnixListener, NetworkError> {
+    TokioUnixListener::bind(path)
+}
+
+/// Create a connector for Unix domain sockets
+pub fn connect_unix() -> TokioUnixConnector {
+    TokioUnixConnector::new()
+}
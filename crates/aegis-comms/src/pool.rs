@@ -0,0 +1,297 @@
+//! Reuse of connections to endpoints
+
+use crate::chunking::{ChunkConfig, Sending, Transfers};
+use crate::codec::Codec;
+use crate::endpoint::Endpoint;
+use crate::error::CommsError;
+use crate::framing::{Frame, FrameVersion, FramedMessageStream, FramingConfig};
+use crate::protocol::AegisMessage;
+use crate::transport::{MessageStream, NetworkConnector, NetworkError};
+use aegis_core::clock::{system_clock, Clock};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// A connection that can be kept in a [`ConnectionPool`]
+pub trait PooledConnection: Send + Sync + 'static {
+    /// Start using a newly connected stream, framed with `framing`
+    fn open(stream: Box<dyn MessageStream>, framing: FramingConfig) -> Self;
+
+    /// Whether the connection can still carry messages
+    fn is_open(&self) -> bool;
+}
+
+/// Limits of a [`ConnectionPool`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+    /// Connections unused for this long are closed
+    pub idle_timeout: Duration,
+    /// Most connections kept open; the least recently used is closed first
+    pub max_connections: usize,
+    /// Frame version written on new connections until the peer's is known
+    ///
+    /// A listener that only speaks v1 never writes first, so it cannot be
+    /// detected; use [`FrameVersion::V1`] to reach one.
+    pub frame_version: FrameVersion,
+    /// Chunked transfers of messages larger than one chunk
+    pub chunking: ChunkConfig,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(300),
+            max_connections: 64,
+            frame_version: FrameVersion::V2,
+            chunking: ChunkConfig::default(),
+        }
+    }
+}
+
+/// A pooled connection and when it was last handed out
+struct PoolEntry<C> {
+    connection: Arc<C>,
+    /// Offset on the pool's monotonic clock
+    last_used: Duration,
+}
+
+/// Open connections by endpoint
+///
+/// Connections are health-checked before reuse: closed connections are
+/// replaced, idle ones are closed, and the least recently used connection
+/// is closed when the pool is full.
+pub struct ConnectionPool<C> {
+    connector: Arc<dyn NetworkConnector>,
+    config: PoolConfig,
+    entries: Mutex<HashMap<Endpoint, PoolEntry<C>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<C: PooledConnection> ConnectionPool<C> {
+    /// Create a pool connecting through `connector`
+    pub fn new(connector: Arc<dyn NetworkConnector>, config: PoolConfig) -> Self {
+        Self {
+            connector,
+            config,
+            entries: Mutex::new(HashMap::new()),
+            clock: system_clock(),
+        }
+    }
+
+    /// Measure idle time with the given clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Limits of the pool
+    pub fn config(&self) -> &PoolConfig {
+        &self.config
+    }
+
+    /// Open connection to `endpoint`, connecting if there is none
+    pub async fn get(&self, endpoint: &Endpoint) -> Result<Arc<C>, NetworkError> {
+        if let Some(connection) = self.checkout(endpoint) {
+            return Ok(connection);
+        }
+
+        // Connect without holding the lock so other endpoints are not blocked
+        let stream = self.connector.connect_endpoint(endpoint).await?;
+        let framing = FramingConfig {
+            write_version: self.config.frame_version,
+            chunking: self.config.chunking,
+            ..FramingConfig::default()
+        };
+        let connection = Arc::new(C::open(stream, framing));
+
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(endpoint) {
+            if entry.connection.is_open() {
+                // Another caller connected first; use theirs
+                entry.last_used = self.clock.elapsed();
+                return Ok(entry.connection.clone());
+            }
+        }
+
+        if entries.len() >= self.config.max_connections.max(1) && !entries.contains_key(endpoint) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(endpoint, _)| endpoint.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+
+        entries.insert(
+            endpoint.clone(),
+            PoolEntry {
+                connection: connection.clone(),
+                last_used: self.clock.elapsed(),
+            },
+        );
+        Ok(connection)
+    }
+
+    /// Reusable connection to `endpoint`, if there is one
+    fn checkout(&self, endpoint: &Endpoint) -> Option<Arc<C>> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = self.clock.elapsed();
+
+        match entries.get_mut(endpoint) {
+            Some(entry) if entry.connection.is_open() && now.saturating_sub(entry.last_used) < self.config.idle_timeout => {
+                entry.last_used = now;
+                Some(entry.connection.clone())
+            }
+            Some(_) => {
+                entries.remove(endpoint);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Close the connection to `endpoint`
+    ///
+    /// Callers holding the connection can keep using it; it is just no
+    /// longer handed out.
+    pub fn evict(&self, endpoint: &Endpoint) {
+        self.entries.lock().unwrap().remove(endpoint);
+    }
+
+    /// Close connections that are closed or have been idle too long
+    ///
+    /// Returns the number of connections removed.
+    pub fn evict_idle(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let now = self.clock.elapsed();
+        let before = entries.len();
+
+        entries.retain(|_, entry| entry.connection.is_open() && now.saturating_sub(entry.last_used) < self.config.idle_timeout);
+        before - entries.len()
+    }
+
+    /// Endpoints with a pooled connection
+    pub fn endpoints(&self) -> Vec<Endpoint> {
+        self.entries.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Number of pooled connections
+    pub fn connection_count(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+/// Sends framed messages over a connection
+///
+/// Messages larger than one chunk are sent in chunks. Messages
+/// arriving from the peer are discarded; they are read only for the
+/// acknowledgements of chunks and to notice when the connection closes.
+pub struct MessageSender {
+    tx: mpsc::Sender<Outgoing>,
+    lost: Arc<AtomicU64>,
+}
+
+/// Message queued on a [`MessageSender`]
+#[derive(Debug, Clone)]
+pub(crate) enum Outgoing {
+    /// Already encoded payload, sent as-is
+    Raw(Bytes),
+    /// Message encoded with the codec negotiated on the connection
+    Message(Box<AegisMessage>),
+}
+
+impl MessageSender {
+    /// Queue a message for sending
+    pub async fn send(&self, message: Bytes) -> Result<(), CommsError> {
+        self.queue(Outgoing::Raw(message)).await
+    }
+
+    /// Queue an [`AegisMessage`], encoded with the codec negotiated with the peer
+    pub async fn send_message(&self, message: AegisMessage) -> Result<(), CommsError> {
+        self.queue(Outgoing::Message(Box::new(message))).await
+    }
+
+    pub(crate) async fn queue(&self, message: Outgoing) -> Result<(), CommsError> {
+        self.tx.send(message).await.map_err(|_| CommsError::ChannelClosed)
+    }
+
+    /// Queued messages dropped because they could not be encoded with the
+    /// codec negotiated on the connection, or because their chunked transfer
+    /// failed
+    pub fn lost_messages(&self) -> u64 {
+        self.lost.load(Ordering::Relaxed)
+    }
+}
+
+impl PooledConnection for MessageSender {
+    fn open(stream: Box<dyn MessageStream>, framing: FramingConfig) -> Self {
+        let (tx, mut rx) = mpsc::channel::<Outgoing>(32);
+        let mut transfers = Transfers::new(&framing);
+        let mut framed = FramedMessageStream::with_config(stream, framing);
+        let lost = Arc::new(AtomicU64::new(0));
+
+        let lost_by_task = lost.clone();
+        tokio::spawn(async move {
+            // Set once the sender is dropped; chunked messages still being sent are finished
+            let mut closed = false;
+            loop {
+                if transfers.flush(&mut framed).await.is_err() || (closed && transfers.outgoing() == 0) {
+                    break;
+                }
+
+                tokio::select! {
+                    message = rx.recv(), if !closed && !transfers.is_full() => {
+                        let frame = match message {
+                            Some(Outgoing::Raw(bytes)) => Frame::data(bytes),
+                            Some(Outgoing::Message(message)) => {
+                                let codec = framed.codec();
+                                match codec.encode(&message) {
+                                    Ok(bytes) => Frame::data(Bytes::from(bytes)).with_codec(codec),
+                                    Err(_) => {
+                                        lost_by_task.fetch_add(1, Ordering::Relaxed);
+                                        continue;
+                                    }
+                                }
+                            }
+                            None => {
+                                closed = true;
+                                continue;
+                            }
+                        };
+                        let frame = match transfers.send(frame, framed.write_version()) {
+                            Ok(Sending::Frame(frame)) => frame,
+                            Ok(Sending::Chunked(_)) => continue,
+                            Err(_) => {
+                                lost_by_task.fetch_add(1, Ordering::Relaxed);
+                                continue;
+                            }
+                        };
+                        if framed.write_frame(frame).await.is_err() {
+                            break;
+                        }
+                    }
+                    incoming = framed.read_frame() => {
+                        let frame = match incoming {
+                            Ok(Some(frame)) => frame,
+                            _ => break,
+                        };
+                        if transfers.receive(&frame).is_err() {
+                            lost_by_task.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+            }
+            let _ = framed.shutdown().await;
+        });
+
+        Self { tx, lost }
+    }
+
+    fn is_open(&self) -> bool {
+        !self.tx.is_closed()
+    }
+}
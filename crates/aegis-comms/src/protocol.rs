@@ -1,20 +1,34 @@
+use crate::endpoint::Endpoint;
+use crate::error::CommsError;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
 
 /// Protocol version for compatibility checks
-pub const PROTOCOL_VERSION: u32 = 1;
+pub const PROTOCOL_VERSION: u32 = 3;
 
 /// Base header included in all messages
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct MessageHeader {
     pub version: u32,
     pub message_type: MessageType,
-    pub source: Option<SocketAddr>,
-    pub destination: Option<SocketAddr>,
+    pub source: Option<Endpoint>,
+    pub destination: Option<Endpoint>,
+}
+
+impl MessageHeader {
+    /// Header for a message of the given type at the current protocol version
+    pub fn new(message_type: MessageType) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            message_type,
+            source: None,
+            destination: None,
+        }
+    }
 }
 
 /// Enum defining all possible message types in the protocol
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum MessageType {
     ConsensusVoteRequest,
     ConsensusVoteResponse,
@@ -22,12 +36,195 @@ pub enum MessageType {
     AgentDiscovery,
     AgentHeartbeat,
     Error,
+    /// Application message type registered through [`ExtensionMessage`]
+    Extension(String),
+}
+
+/// A message with its header, as sent on the wire
+///
+/// One connection can carry every message type; receivers route on
+/// `header.message_type`, for example with a
+/// [`MessageDispatcher`](crate::MessageDispatcher).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AegisMessage {
+    pub header: MessageHeader,
+    pub body: MessageBody,
 }
 
+impl AegisMessage {
+    /// Wrap a message in an envelope with a header for its type
+    pub fn new<M: ProtocolMessage>(message: M) -> Result<Self, CommsError> {
+        Ok(Self {
+            header: MessageHeader::new(M::message_type()),
+            body: message.into_body()?,
+        })
+    }
+
+    /// Set the endpoint the message comes from
+    pub fn with_source(mut self, source: Endpoint) -> Self {
+        self.header.source = Some(source);
+        self
+    }
+
+    /// Set the endpoint the message is meant for
+    pub fn with_destination(mut self, destination: Endpoint) -> Self {
+        self.header.destination = Some(destination);
+        self
+    }
+
+    /// Type of the message
+    pub fn message_type(&self) -> &MessageType {
+        &self.header.message_type
+    }
+
+    /// Check the protocol version and that the body matches the header
+    pub fn validate(&self) -> Result<(), CommsError> {
+        if self.header.version != PROTOCOL_VERSION {
+            return Err(CommsError::VersionMismatch {
+                expected: PROTOCOL_VERSION,
+                actual: self.header.version,
+            });
+        }
+
+        let consistent = match (&self.header.message_type, self.body.message_type()) {
+            (MessageType::Extension(_), None) => true,
+            (header, Some(body)) => *header == body,
+            _ => false,
+        };
+        if !consistent {
+            return Err(CommsError::InvalidMessage(format!(
+                "body does not match message type {:?}",
+                self.header.message_type
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Extract the body as a message of type `M`
+    pub fn decode<M: ProtocolMessage>(self) -> Result<M, CommsError> {
+        let expected = M::message_type();
+        if self.header.message_type != expected {
+            return Err(CommsError::UnexpectedMessageType {
+                expected,
+                actual: self.header.message_type,
+            });
+        }
+
+        M::from_body(self.body)
+    }
+}
+
+/// Body of an [`AegisMessage`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum MessageBody {
+    ConsensusVoteRequest(ConsensusVoteRequest),
+    ConsensusVoteResponse(ConsensusVoteResponse),
+    StateUpdate(StateUpdateMessage),
+    AgentDiscovery(AgentDiscoveryMessage),
+    AgentHeartbeat(AgentHeartbeatMessage),
+    Error(ErrorMessage),
+    /// JSON encoded extension message, named by the header
+    Extension(Vec<u8>),
+}
+
+impl MessageBody {
+    /// Type of a built-in body; `None` for extensions
+    pub fn message_type(&self) -> Option<MessageType> {
+        match self {
+            MessageBody::ConsensusVoteRequest(_) => Some(MessageType::ConsensusVoteRequest),
+            MessageBody::ConsensusVoteResponse(_) => Some(MessageType::ConsensusVoteResponse),
+            MessageBody::StateUpdate(_) => Some(MessageType::StateUpdate),
+            MessageBody::AgentDiscovery(_) => Some(MessageType::AgentDiscovery),
+            MessageBody::AgentHeartbeat(_) => Some(MessageType::AgentHeartbeat),
+            MessageBody::Error(_) => Some(MessageType::Error),
+            MessageBody::Extension(_) => None,
+        }
+    }
+}
+
+/// A message that can be carried in an [`AegisMessage`]
+///
+/// Implemented for the built-in messages and for every [`ExtensionMessage`].
+pub trait ProtocolMessage: Sized + Send + 'static {
+    /// Type recorded in the header
+    fn message_type() -> MessageType;
+
+    /// Convert into a message body
+    fn into_body(self) -> Result<MessageBody, CommsError>;
+
+    /// Extract from a message body
+    fn from_body(body: MessageBody) -> Result<Self, CommsError>;
+}
+
+/// An application message type carried alongside the built-in messages
+///
+/// Extension bodies are JSON encoded, so types holding `serde_json::Value`
+/// can be sent.
+pub trait ExtensionMessage: Serialize + DeserializeOwned + Send + 'static {
+    /// Name of the message type; must be unique among extensions
+    const MESSAGE_TYPE: &'static str;
+}
+
+impl<T: ExtensionMessage> ProtocolMessage for T {
+    fn message_type() -> MessageType {
+        MessageType::Extension(T::MESSAGE_TYPE.to_string())
+    }
+
+    fn into_body(self) -> Result<MessageBody, CommsError> {
+        serde_json::to_vec(&self)
+            .map(MessageBody::Extension)
+            .map_err(|e| CommsError::InvalidMessage(e.to_string()))
+    }
+
+    fn from_body(body: MessageBody) -> Result<Self, CommsError> {
+        match body {
+            MessageBody::Extension(payload) => {
+                serde_json::from_slice(&payload).map_err(|e| CommsError::InvalidMessage(e.to_string()))
+            }
+            _ => Err(CommsError::InvalidMessage(format!(
+                "expected a body for extension {}",
+                T::MESSAGE_TYPE
+            ))),
+        }
+    }
+}
+
+macro_rules! builtin_message {
+    ($message:ident, $variant:ident) => {
+        impl ProtocolMessage for $message {
+            fn message_type() -> MessageType {
+                MessageType::$variant
+            }
+
+            fn into_body(self) -> Result<MessageBody, CommsError> {
+                Ok(MessageBody::$variant(self))
+            }
+
+            fn from_body(body: MessageBody) -> Result<Self, CommsError> {
+                match body {
+                    MessageBody::$variant(message) => Ok(message),
+                    other => Err(CommsError::InvalidMessage(format!(
+                        "expected a {:?} body, got {:?}",
+                        MessageType::$variant,
+                        other.message_type()
+                    ))),
+                }
+            }
+        }
+    };
+}
+
+builtin_message!(ConsensusVoteRequest, ConsensusVoteRequest);
+builtin_message!(ConsensusVoteResponse, ConsensusVoteResponse);
+builtin_message!(StateUpdateMessage, StateUpdate);
+builtin_message!(AgentDiscoveryMessage, AgentDiscovery);
+builtin_message!(AgentHeartbeatMessage, AgentHeartbeat);
+builtin_message!(ErrorMessage, Error);
+
 /// Message for requesting votes in consensus
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ConsensusVoteRequest {
-    pub header: MessageHeader,
     pub proposal_id: u64,
     pub proposal_data: Vec<u8>,
 }
@@ -35,7 +232,6 @@ pub struct ConsensusVoteRequest {
 /// Message for responding to vote requests
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ConsensusVoteResponse {
-    pub header: MessageHeader,
     pub proposal_id: u64,
     pub vote: bool,
     pub voter_id: String,
@@ -44,7 +240,6 @@ pub struct ConsensusVoteResponse {
 /// Message for state updates between agents
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct StateUpdateMessage {
-    pub header: MessageHeader,
     pub state_version: u64,
     pub state_data: Vec<u8>,
     pub is_delta: bool,
@@ -53,23 +248,21 @@ pub struct StateUpdateMessage {
 /// Message for agent discovery and registration
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct AgentDiscoveryMessage {
-    pub header: MessageHeader,
     pub agent_id: String,
     pub capabilities: Vec<String>,
-    pub listen_addr: SocketAddr,
+    pub listen_addr: Endpoint,
 }
 
 /// Message for agent heartbeat/liveness
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct AgentHeartbeatMessage {
-    pub header: MessageHeader,
     pub agent_id: String,
     pub timestamp: u64,
     pub status: AgentStatus,
 }
 
 /// Status information included in heartbeats
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum AgentStatus {
     Active,
     Busy,
@@ -80,7 +273,6 @@ pub enum AgentStatus {
 /// Error message for protocol errors
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ErrorMessage {
-    pub header: MessageHeader,
     pub error_code: u32,
     pub error_message: String,
-} 
\ No newline at end of file
+}
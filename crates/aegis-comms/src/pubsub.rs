@@ -0,0 +1,840 @@
+//! Topic-based publish/subscribe
+//!
+//! A [`Broker`] delivers each message published on a topic to every
+//! subscription whose pattern matches the topic. Topics are dot-separated
+//! words such as `policy.changed`; in patterns `*` matches exactly one word
+//! and `#` matches any number of words, so `findings.#` matches
+//! `findings.high` and `findings.high.network`.
+//!
+//! Every subscription has a bounded queue. When a subscriber falls behind,
+//! its [`SlowConsumerPolicy`] decides whether the oldest or the newest
+//! message is dropped, or the subscription is closed. With
+//! [`QoS::AtLeastOnce`] each delivery must be acknowledged, or it is
+//! delivered again once the acknowledgement timeout passes.
+//!
+//! Brokers on different nodes are linked over the RPC layer. A broker asks a
+//! peer to forward the messages matching a pattern, and the peer forwards
+//! them through a subscription of its own, with the same queueing and
+//! acknowledgement rules. Messages received from another node are delivered
+//! locally but not forwarded again, so brokers subscribed to each other do
+//! not loop.
+//!
+//! Requests between brokers must come from authenticated peers. A broker
+//! forwards only to the endpoint its [`PeerRegistry`] holds for the
+//! subscriber, only lets a subscriber change its own subscriptions, and
+//! accepts forwarded messages only from brokers it subscribed to.
+//!
+//! A broker given an [`EnvelopeSigner`] signs the messages it forwards, which
+//! are always messages published on its own node. A broker given an
+//! [`EnvelopeVerifier`] accepts forwarded messages only in an envelope that
+//! passes its signature and replay checks, and reports the rest to the
+//! verifier's subscribers.
+
+use crate::endpoint::Endpoint;
+use crate::envelope::{EnvelopeSigner, EnvelopeVerifier, SignedEnvelope};
+use crate::error::{CommsError, PeerInfo, SecurityViolation};
+use crate::peers::PeerRegistry;
+use crate::protocol::{AegisMessage, ExtensionMessage};
+use crate::rpc::{RpcClient, RpcContext, RpcError, RpcRequest, RpcServer};
+use crate::transport::{NetworkConnector, PeerIdentity};
+use aegis_core::clock::{system_clock, Clock};
+use aegis_core::error::{AegisError, AegisResult};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Pattern matching a set of topics
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TopicPattern {
+    pattern: String,
+}
+
+impl TopicPattern {
+    /// Parse a pattern of dot-separated words, `*` and `#`
+    pub fn parse(pattern: &str) -> Result<Self, CommsError> {
+        let valid = pattern
+            .split('.')
+            .all(|word| word == "*" || word == "#" || is_valid_word(word));
+        if !valid {
+            return Err(CommsError::InvalidTopic(pattern.to_string()));
+        }
+        Ok(Self {
+            pattern: pattern.to_string(),
+        })
+    }
+
+    /// Whether `topic` matches the pattern
+    pub fn matches(&self, topic: &str) -> bool {
+        let pattern: Vec<&str> = self.pattern.split('.').collect();
+        let topic: Vec<&str> = topic.split('.').collect();
+        matches_words(&pattern, &topic)
+    }
+
+    /// The pattern as written
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl FromStr for TopicPattern {
+    type Err = CommsError;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        Self::parse(pattern)
+    }
+}
+
+impl fmt::Display for TopicPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+fn is_valid_word(word: &str) -> bool {
+    !word.is_empty() && !word.contains(['*', '#'])
+}
+
+fn validate_topic(topic: &str) -> Result<(), CommsError> {
+    if topic.split('.').all(is_valid_word) {
+        Ok(())
+    } else {
+        Err(CommsError::InvalidTopic(topic.to_string()))
+    }
+}
+
+fn matches_words(pattern: &[&str], topic: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => topic.is_empty(),
+        Some((&"#", rest)) => (0..=topic.len()).any(|skip| matches_words(rest, &topic[skip..])),
+        Some((word, rest)) => topic
+            .split_first()
+            .is_some_and(|(topic_word, topic)| (*word == "*" || word == topic_word) && matches_words(rest, topic)),
+    }
+}
+
+/// Delivery guarantee of a subscription
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QoS {
+    /// Each message is delivered at most once and may be lost
+    AtMostOnce,
+    /// Each message is delivered until acknowledged and may be duplicated
+    AtLeastOnce,
+}
+
+/// What happens when a message arrives for a subscription with a full queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlowConsumerPolicy {
+    /// Drop the oldest queued message to make room
+    DropOldest,
+    /// Drop the arriving message
+    DropNewest,
+    /// Close the subscription
+    Disconnect,
+}
+
+/// Settings of a [`Subscription`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubscriptionOptions {
+    /// Delivery guarantee
+    pub qos: QoS,
+    /// Most messages queued or awaiting acknowledgement
+    pub queue_size: usize,
+    /// Handling of messages arriving while the queue is full
+    pub policy: SlowConsumerPolicy,
+    /// Time after which an unacknowledged delivery is repeated
+    pub ack_timeout: Duration,
+}
+
+impl Default for SubscriptionOptions {
+    fn default() -> Self {
+        Self {
+            qos: QoS::AtMostOnce,
+            queue_size: 256,
+            policy: SlowConsumerPolicy::DropOldest,
+            ack_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A message published on a topic
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopicMessage {
+    /// Topic the message was published on
+    pub topic: String,
+    /// JSON-encoded body
+    pub payload: Vec<u8>,
+}
+
+impl ExtensionMessage for TopicMessage {
+    const MESSAGE_TYPE: &'static str = "aegis.topic";
+}
+
+impl TopicMessage {
+    /// Decode the body
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, CommsError> {
+        serde_json::from_slice(&self.payload).map_err(|e| CommsError::InvalidMessage(e.to_string()))
+    }
+}
+
+/// A message handed to a subscriber
+#[derive(Debug)]
+pub struct Delivery {
+    /// The message
+    pub message: TopicMessage,
+    /// Whether the message was delivered before without being acknowledged
+    pub redelivered: bool,
+    tag: u64,
+    slot: Arc<Slot>,
+}
+
+impl Delivery {
+    /// Topic the message was published on
+    pub fn topic(&self) -> &str {
+        &self.message.topic
+    }
+
+    /// Decode the body
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, CommsError> {
+        self.message.decode()
+    }
+
+    /// Acknowledge the delivery, so it is not delivered again
+    ///
+    /// Does nothing for at-most-once subscriptions.
+    pub fn ack(self) {
+        self.slot.queue.lock().unwrap().unacked.remove(&self.tag);
+    }
+}
+
+/// A message waiting to be delivered
+#[derive(Debug)]
+struct Pending {
+    tag: u64,
+    message: TopicMessage,
+}
+
+/// A delivered message waiting for its acknowledgement
+#[derive(Debug)]
+struct Unacked {
+    message: TopicMessage,
+    /// Offset on the broker's monotonic clock
+    deadline: Duration,
+}
+
+#[derive(Debug, Default)]
+struct SlotQueue {
+    pending: VecDeque<Pending>,
+    unacked: HashMap<u64, Unacked>,
+    next_tag: u64,
+    dropped: u64,
+    closed: bool,
+}
+
+/// Queue of one subscription, shared by the broker and the subscriber
+#[derive(Debug)]
+struct Slot {
+    pattern: TopicPattern,
+    options: SubscriptionOptions,
+    /// Forwards to another node, so messages from other nodes are not offered
+    forwards: bool,
+    queue: Mutex<SlotQueue>,
+    notify: Notify,
+    clock: Arc<dyn Clock>,
+}
+
+impl Slot {
+    /// Queue `message`, applying the slow-consumer policy; true if queued
+    fn offer(&self, message: TopicMessage) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.closed {
+            return false;
+        }
+
+        if queue.pending.len() + queue.unacked.len() >= self.options.queue_size {
+            match self.options.policy {
+                // Unacknowledged deliveries are not dropped, so with only
+                // those queued the arriving message is
+                SlowConsumerPolicy::DropOldest if queue.pending.pop_front().is_some() => queue.dropped += 1,
+                SlowConsumerPolicy::DropOldest | SlowConsumerPolicy::DropNewest => {
+                    queue.dropped += 1;
+                    return false;
+                }
+                SlowConsumerPolicy::Disconnect => {
+                    queue.closed = true;
+                    drop(queue);
+                    self.notify.notify_one();
+                    return false;
+                }
+            }
+        }
+
+        let tag = queue.next_tag;
+        queue.next_tag += 1;
+        queue.pending.push_back(Pending { tag, message });
+        drop(queue);
+        self.notify.notify_one();
+        true
+    }
+
+    fn close(&self) {
+        self.queue.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.queue.lock().unwrap().closed
+    }
+}
+
+/// Messages published on the topics matching a pattern
+///
+/// Dropping the subscription unsubscribes.
+pub struct Subscription {
+    id: u64,
+    slot: Arc<Slot>,
+    broker: Weak<Inner>,
+}
+
+impl Subscription {
+    /// Pattern of the subscription
+    pub fn pattern(&self) -> &TopicPattern {
+        &self.slot.pattern
+    }
+
+    /// Messages dropped because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.slot.queue.lock().unwrap().dropped
+    }
+
+    /// Whether the subscription was closed as a slow consumer or the broker is gone
+    pub fn is_closed(&self) -> bool {
+        self.slot.is_closed()
+    }
+
+    /// Wait for the next delivery
+    ///
+    /// Unacknowledged deliveries whose timeout passed are repeated before
+    /// new messages. Returns `None` once the subscription is closed and its
+    /// queue is drained.
+    pub async fn recv(&mut self) -> Option<Delivery> {
+        loop {
+            let wait_until = {
+                let mut queue = self.slot.queue.lock().unwrap();
+                let now = self.slot.clock.elapsed();
+                let ack_timeout = self.slot.options.ack_timeout;
+
+                let expired = queue
+                    .unacked
+                    .iter()
+                    .filter(|(_, unacked)| unacked.deadline <= now)
+                    .map(|(tag, _)| *tag)
+                    .min();
+                if let Some(tag) = expired {
+                    let unacked = queue.unacked.get_mut(&tag).unwrap();
+                    unacked.deadline = now + ack_timeout;
+                    return Some(self.delivery(tag, unacked.message.clone(), true));
+                }
+
+                if let Some(pending) = queue.pending.pop_front() {
+                    if self.slot.options.qos == QoS::AtLeastOnce {
+                        queue.unacked.insert(
+                            pending.tag,
+                            Unacked {
+                                message: pending.message.clone(),
+                                deadline: now + ack_timeout,
+                            },
+                        );
+                    }
+                    return Some(self.delivery(pending.tag, pending.message, false));
+                }
+
+                if queue.closed {
+                    return None;
+                }
+                queue.unacked.values().map(|unacked| unacked.deadline).min()
+            };
+
+            match wait_until {
+                Some(deadline) => {
+                    let wait = deadline.saturating_sub(self.slot.clock.elapsed());
+                    let _ = tokio::time::timeout(wait, self.slot.notify.notified()).await;
+                }
+                None => self.slot.notify.notified().await,
+            }
+        }
+    }
+
+    fn delivery(&self, tag: u64, message: TopicMessage, redelivered: bool) -> Delivery {
+        Delivery {
+            message,
+            redelivered,
+            tag,
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(broker) = self.broker.upgrade() {
+            broker.slots.lock().unwrap().remove(&self.id);
+        }
+    }
+}
+
+/// Request to forward messages matching `pattern` to `endpoint`
+#[derive(Debug, Serialize, Deserialize)]
+struct RemoteSubscribe {
+    subscriber: String,
+    endpoint: Endpoint,
+    pattern: String,
+    options: SubscriptionOptions,
+}
+
+impl RpcRequest for RemoteSubscribe {
+    const METHOD: &'static str = "pubsub.subscribe";
+}
+
+/// Request to stop forwarding messages matching `pattern`
+#[derive(Debug, Serialize, Deserialize)]
+struct RemoteUnsubscribe {
+    subscriber: String,
+    pattern: String,
+}
+
+impl RpcRequest for RemoteUnsubscribe {
+    const METHOD: &'static str = "pubsub.unsubscribe";
+}
+
+/// A message forwarded from another node; the response acknowledges it
+#[derive(Debug, Serialize, Deserialize)]
+enum Forward {
+    /// Sent by a broker without a signer
+    Plain(TopicMessage),
+    /// Signed by the node the message was published on
+    Signed(Box<SignedEnvelope>),
+}
+
+impl RpcRequest for Forward {
+    const METHOD: &'static str = "pubsub.forward";
+}
+
+/// Identity of a broker linked to other nodes
+struct Node {
+    id: String,
+    endpoint: Endpoint,
+    rpc: Arc<RpcClient>,
+    /// Endpoints of the other nodes, by node ID
+    peers: PeerRegistry,
+    /// Patterns subscribed to at other brokers, by their endpoint
+    upstreams: Mutex<HashMap<Endpoint, HashSet<String>>>,
+}
+
+impl Node {
+    /// The authenticated identity of the caller, or an error if there is none
+    fn caller<'a>(&self, peer: &'a PeerInfo) -> AegisResult<&'a PeerIdentity> {
+        peer.identity.as_ref().ok_or_else(|| {
+            AegisError::Security(format!("pub/sub requests from {} require an authenticated peer", peer.addr))
+        })
+    }
+
+    /// Check that the caller is `subscriber`
+    fn authorize_subscriber(&self, peer: &PeerInfo, subscriber: &str) -> AegisResult<()> {
+        let caller = self.caller(peer)?;
+        if !caller.names().any(|name| name == subscriber) {
+            return Err(AegisError::Security(format!(
+                "peer {} may not act for subscriber {}",
+                caller, subscriber
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that the caller is a broker this node subscribed to
+    fn authorize_forward(&self, peer: &PeerInfo) -> AegisResult<()> {
+        let caller = self.caller(peer)?;
+        let upstreams = self.upstreams.lock().unwrap();
+        let known = caller
+            .names()
+            .filter_map(|name| self.peers.lookup(name))
+            .any(|endpoint| upstreams.contains_key(&endpoint));
+        if !known {
+            return Err(AegisError::Security(format!(
+                "peer {} is not a broker this node subscribed to",
+                caller
+            )));
+        }
+        Ok(())
+    }
+
+    /// Drop `pattern` from the patterns subscribed to at `peer`
+    fn forget_upstream(&self, peer: &Endpoint, pattern: &str) {
+        let mut upstreams = self.upstreams.lock().unwrap();
+        if let Some(patterns) = upstreams.get_mut(peer) {
+            patterns.remove(pattern);
+            if patterns.is_empty() {
+                upstreams.remove(peer);
+            }
+        }
+    }
+}
+
+struct Inner {
+    slots: Mutex<HashMap<u64, Arc<Slot>>>,
+    next_id: AtomicU64,
+    node: Option<Node>,
+    /// Tasks forwarding to other nodes, by subscriber and pattern
+    forwarders: Mutex<HashMap<(String, String), Forwarder>>,
+    clock: Arc<dyn Clock>,
+    signer: Option<EnvelopeSigner>,
+    verifier: Option<EnvelopeVerifier>,
+}
+
+/// Task forwarding the deliveries of one subscription to another node
+struct Forwarder {
+    subscription: u64,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        for forwarder in self.forwarders.get_mut().unwrap().values() {
+            forwarder.task.abort();
+        }
+        for slot in self.slots.get_mut().unwrap().values() {
+            slot.close();
+        }
+    }
+}
+
+/// Routes published messages to subscriptions
+///
+/// Cloning yields a handle to the same broker.
+#[derive(Clone)]
+pub struct Broker {
+    inner: Arc<Inner>,
+}
+
+impl Default for Broker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Broker {
+    /// Create a broker delivering only within this node
+    pub fn new() -> Self {
+        Self::with_node(None)
+    }
+
+    /// Create a broker that can be linked to the brokers of other nodes
+    ///
+    /// `endpoint` is where the RPC server the broker is registered with
+    /// listens; peers forward messages to it. Other nodes are known by the
+    /// endpoints `peers` holds for them, under the names their transport
+    /// authenticates them by.
+    pub fn with_connector(
+        node_id: impl Into<String>,
+        endpoint: Endpoint,
+        connector: Arc<dyn NetworkConnector>,
+        peers: PeerRegistry,
+    ) -> Self {
+        Self::with_node(Some(Node {
+            id: node_id.into(),
+            endpoint,
+            rpc: Arc::new(RpcClient::with_connector(connector)),
+            peers,
+            upstreams: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    fn with_node(node: Option<Node>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                slots: Mutex::new(HashMap::new()),
+                next_id: AtomicU64::new(0),
+                node,
+                forwarders: Mutex::new(HashMap::new()),
+                clock: system_clock(),
+                signer: None,
+                verifier: None,
+            }),
+        }
+    }
+
+    /// Time acknowledgements with the given clock
+    ///
+    /// # Panics
+    ///
+    /// If the broker was already cloned or subscribed to.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("the clock is set before the broker is shared")
+            .clock = clock;
+        self
+    }
+
+    /// Sign the messages forwarded to other nodes
+    ///
+    /// # Panics
+    ///
+    /// If the broker was already cloned or subscribed to.
+    pub fn with_signer(mut self, signer: EnvelopeSigner) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("the signer is set before the broker is shared")
+            .signer = Some(signer);
+        self
+    }
+
+    /// Accept messages forwarded by other nodes only if `verifier` opens them
+    ///
+    /// # Panics
+    ///
+    /// If the broker was already cloned or subscribed to.
+    pub fn with_verifier(mut self, verifier: EnvelopeVerifier) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("the verifier is set before the broker is shared")
+            .verifier = Some(verifier);
+        self
+    }
+
+    /// Subscribe to the topics matching `pattern`
+    pub fn subscribe(&self, pattern: &str, options: SubscriptionOptions) -> Result<Subscription, CommsError> {
+        Ok(self.add_subscription(TopicPattern::parse(pattern)?, options, false))
+    }
+
+    fn add_subscription(&self, pattern: TopicPattern, options: SubscriptionOptions, forwards: bool) -> Subscription {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let slot = Arc::new(Slot {
+            pattern,
+            options,
+            forwards,
+            queue: Mutex::new(SlotQueue::default()),
+            notify: Notify::new(),
+            clock: self.inner.clock.clone(),
+        });
+        self.inner.slots.lock().unwrap().insert(id, slot.clone());
+
+        Subscription {
+            id,
+            slot,
+            broker: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Publish `message` on `topic`
+    ///
+    /// Returns the number of subscriptions, including those of other nodes,
+    /// that queued the message.
+    pub fn publish<T: Serialize>(&self, topic: &str, message: &T) -> Result<usize, CommsError> {
+        let payload = serde_json::to_vec(message).map_err(|e| CommsError::InvalidMessage(e.to_string()))?;
+        self.publish_raw(topic, payload)
+    }
+
+    /// Publish an already encoded body on `topic`
+    pub fn publish_raw(&self, topic: &str, payload: Vec<u8>) -> Result<usize, CommsError> {
+        validate_topic(topic)?;
+        Ok(self.route(
+            TopicMessage {
+                topic: topic.to_string(),
+                payload,
+            },
+            true,
+        ))
+    }
+
+    /// Offer `message` to the matching subscriptions
+    fn route(&self, message: TopicMessage, forward: bool) -> usize {
+        let mut slots = self.inner.slots.lock().unwrap();
+
+        let queued = slots
+            .values()
+            .filter(|slot| (forward || !slot.forwards) && slot.pattern.matches(&message.topic))
+            .filter(|slot| slot.offer(message.clone()))
+            .count();
+        // Slow consumers disconnected by this message
+        slots.retain(|_, slot| !slot.is_closed());
+        queued
+    }
+
+    /// Number of subscriptions, including those of other nodes
+    pub fn subscriptions(&self) -> usize {
+        self.inner.slots.lock().unwrap().len()
+    }
+
+    /// Serve subscriptions of other nodes and accept their messages on `server`
+    pub fn register(&self, server: &mut RpcServer) {
+        let broker = self.clone();
+        server.register(move |request: RemoteSubscribe, ctx: RpcContext| {
+            let broker = broker.clone();
+            async move { broker.handle_subscribe(request, &ctx.peer) }
+        });
+
+        let broker = self.clone();
+        server.register(move |request: RemoteUnsubscribe, ctx: RpcContext| {
+            let broker = broker.clone();
+            async move {
+                broker.linked()?.authorize_subscriber(&ctx.peer, &request.subscriber)?;
+                broker.stop_forwarder(&request.subscriber, &request.pattern);
+                Ok::<_, AegisError>(())
+            }
+        });
+
+        let broker = self.clone();
+        server.register(move |request: Forward, ctx: RpcContext| {
+            let broker = broker.clone();
+            async move {
+                broker.linked()?.authorize_forward(&ctx.peer)?;
+                let message = broker.open(request)?;
+                validate_topic(&message.topic)?;
+                broker.route(message, false);
+                Ok::<_, AegisError>(())
+            }
+        });
+    }
+
+    /// Have the broker at `peer` forward the messages matching `pattern` here
+    ///
+    /// The peer queues them under `options`; local subscriptions receive them
+    /// like messages published on this node. Subscribing again with the same
+    /// pattern replaces the options.
+    pub async fn subscribe_remote(
+        &self,
+        peer: &Endpoint,
+        pattern: &str,
+        options: SubscriptionOptions,
+    ) -> Result<(), RpcError> {
+        let node = self.node()?;
+        let request = RemoteSubscribe {
+            subscriber: node.id.clone(),
+            endpoint: node.endpoint.clone(),
+            pattern: pattern.to_string(),
+            options,
+        };
+        // Forwards may arrive before the response
+        let added = node
+            .upstreams
+            .lock()
+            .unwrap()
+            .entry(peer.clone())
+            .or_default()
+            .insert(pattern.to_string());
+        let result = node.rpc.call_timeout(peer, &request, options.ack_timeout).await;
+        if result.is_err() && added {
+            node.forget_upstream(peer, pattern);
+        }
+        result
+    }
+
+    /// Stop the forwarding requested by [`Broker::subscribe_remote`]
+    pub async fn unsubscribe_remote(&self, peer: &Endpoint, pattern: &str, timeout: Duration) -> Result<(), RpcError> {
+        let node = self.node()?;
+        let request = RemoteUnsubscribe {
+            subscriber: node.id.clone(),
+            pattern: pattern.to_string(),
+        };
+        node.rpc.call_timeout::<_, ()>(peer, &request, timeout).await?;
+        node.forget_upstream(peer, pattern);
+        Ok(())
+    }
+
+    fn node(&self) -> Result<&Node, RpcError> {
+        self.inner.node.as_ref().ok_or_else(|| {
+            RpcError::Network(crate::transport::NetworkError::Other(
+                "broker is not linked to other nodes".to_string(),
+            ))
+        })
+    }
+
+    /// The node of a linked broker, for serving other nodes
+    fn linked(&self) -> AegisResult<&Node> {
+        self.inner
+            .node
+            .as_ref()
+            .ok_or_else(|| AegisError::Config("broker is not linked to other nodes".to_string()))
+    }
+
+    fn handle_subscribe(&self, request: RemoteSubscribe, peer: &PeerInfo) -> AegisResult<()> {
+        let node = self.linked()?;
+        node.authorize_subscriber(peer, &request.subscriber)?;
+        // Forwarding to an endpoint the caller named would let it flood any node
+        if node.peers.lookup(&request.subscriber).as_ref() != Some(&request.endpoint) {
+            return Err(AegisError::Security(format!(
+                "{} is not the endpoint of subscriber {}",
+                request.endpoint, request.subscriber
+            )));
+        }
+        let rpc = node.rpc.clone();
+        let signer = self.inner.signer.clone();
+        let pattern = TopicPattern::parse(&request.pattern)?;
+        let mut subscription = self.add_subscription(pattern, request.options, true);
+        let id = subscription.id;
+
+        let endpoint = request.endpoint;
+        let timeout = request.options.ack_timeout;
+        let forwarder = tokio::spawn(async move {
+            while let Some(delivery) = subscription.recv().await {
+                // Sealed again for every attempt, so retries are not replays
+                let forward = match &signer {
+                    Some(signer) => match AegisMessage::new(delivery.message.clone()).and_then(|m| signer.seal(m)) {
+                        Ok(envelope) => Forward::Signed(Box::new(envelope)),
+                        // Retrying would fail the same way
+                        Err(_) => {
+                            delivery.ack();
+                            continue;
+                        }
+                    },
+                    None => Forward::Plain(delivery.message.clone()),
+                };
+                // Unacknowledged at-least-once deliveries are repeated by the subscription
+                if rpc.call_timeout::<_, ()>(&endpoint, &forward, timeout).await.is_ok() {
+                    delivery.ack();
+                }
+            }
+        });
+
+        let key = (request.subscriber, request.pattern);
+        let forwarder = Forwarder {
+            subscription: id,
+            task: forwarder,
+        };
+        let previous = self.inner.forwarders.lock().unwrap().insert(key, forwarder);
+        if let Some(previous) = previous {
+            self.abort_forwarder(previous);
+        }
+        Ok(())
+    }
+
+    /// The message carried by a forward, verified if the broker has a verifier
+    fn open(&self, forward: Forward) -> Result<TopicMessage, CommsError> {
+        match (&self.inner.verifier, forward) {
+            (Some(verifier), Forward::Signed(envelope)) => verifier.open(*envelope)?.decode(),
+            (Some(verifier), Forward::Plain(_)) => Err(verifier.reject(None, SecurityViolation::Unsigned)),
+            (None, Forward::Signed(envelope)) => envelope.message.decode(),
+            (None, Forward::Plain(message)) => Ok(message),
+        }
+    }
+
+    fn stop_forwarder(&self, subscriber: &str, pattern: &str) {
+        let key = (subscriber.to_string(), pattern.to_string());
+        let forwarder = self.inner.forwarders.lock().unwrap().remove(&key);
+        if let Some(forwarder) = forwarder {
+            self.abort_forwarder(forwarder);
+        }
+    }
+
+    /// Stop a forwarder, unsubscribing right away rather than when the task ends
+    fn abort_forwarder(&self, forwarder: Forwarder) {
+        forwarder.task.abort();
+        if let Some(slot) = self.inner.slots.lock().unwrap().remove(&forwarder.subscription) {
+            slot.close();
+        }
+    }
+}
@@ -0,0 +1,742 @@
+//! Request/response RPC over message streams
+//!
+//! Each request carries a correlation ID and the time left until its
+//! deadline, so servers stop working on requests the caller has given up
+//! on and can pass the remaining budget on to calls they make themselves.
+//! Dropping a pending call cancels it on the server. Errors returned by
+//! handlers travel back as a [`RemoteError`] with an [`ErrorCode`] and are
+//! mapped to [`AegisError`] variants on the calling side.
+//!
+//! Envelopes and payloads are encoded with the codec negotiated on the
+//! connection. A response payload uses the codec of its request, which a
+//! request type can fix with [`RpcRequest::CODEC`]. Requests and responses
+//! larger than one chunk are sent in chunks.
+
+use crate::chunking::{ChunkError, Sending, Transfers};
+use crate::codec::{Codec, CodecId};
+use crate::endpoint::Endpoint;
+use crate::error::PeerInfo;
+use crate::framing::{Frame, FrameType, FramedMessageStream, FramingConfig};
+use crate::pool::{ConnectionPool, PoolConfig, PooledConnection};
+use crate::server::{requested, ServerConfig};
+use crate::transport::{MessageStream, NetworkConnector, NetworkError};
+use aegis_core::error::{AegisError, AegisResult};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::AbortHandle;
+
+/// A request type that can be sent over RPC
+pub trait RpcRequest: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Method name the server routes the request by
+    const METHOD: &'static str;
+
+    /// Codec of the request and response payloads, instead of the negotiated one
+    ///
+    /// Types holding `serde_json::Value` need a self-describing codec such as
+    /// [`CodecId::Json`].
+    const CODEC: Option<CodecId> = None;
+}
+
+/// Category of an error returned by a remote handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorCode {
+    /// No handler is registered for the method
+    UnknownMethod,
+    /// The request could not be decoded or was rejected as invalid
+    InvalidRequest,
+    /// The requested item does not exist
+    NotFound,
+    /// The caller is not allowed to make the request
+    PermissionDenied,
+    /// The deadline passed before the handler finished
+    DeadlineExceeded,
+    /// The server could not reach a service it depends on
+    Unavailable,
+    /// Any other failure
+    Internal,
+}
+
+/// Error returned by a remote handler
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteError {
+    /// Error category
+    pub code: ErrorCode,
+    /// Description from the server
+    pub message: String,
+}
+
+impl RemoteError {
+    /// Create a remote error
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<&AegisError> for RemoteError {
+    fn from(err: &AegisError) -> Self {
+        let code = match err {
+            AegisError::NotFound(_) => ErrorCode::NotFound,
+            AegisError::PermissionDenied(_) | AegisError::Security(_) => ErrorCode::PermissionDenied,
+            AegisError::Timeout(_) => ErrorCode::DeadlineExceeded,
+            AegisError::Config(_) | AegisError::Serialization(_) => ErrorCode::InvalidRequest,
+            AegisError::Communication(_) => ErrorCode::Unavailable,
+            _ => ErrorCode::Internal,
+        };
+
+        RemoteError::new(code, err.to_string())
+    }
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+/// Errors from an RPC call
+#[derive(Debug)]
+pub enum RpcError {
+    /// The deadline passed before a response arrived
+    DeadlineExceeded,
+    /// The connection closed before a response arrived
+    Disconnected,
+    /// The connection could not be established
+    Network(NetworkError),
+    /// The request or response could not be encoded or decoded
+    Codec(String),
+    /// The remote handler returned an error
+    Remote(RemoteError),
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::DeadlineExceeded => write!(f, "Deadline exceeded"),
+            RpcError::Disconnected => write!(f, "Connection closed before the response arrived"),
+            RpcError::Network(e) => write!(f, "Network error: {}", e),
+            RpcError::Codec(e) => write!(f, "Codec error: {}", e),
+            RpcError::Remote(e) => write!(f, "Remote error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<NetworkError> for RpcError {
+    fn from(err: NetworkError) -> Self {
+        RpcError::Network(err)
+    }
+}
+
+impl From<RemoteError> for RpcError {
+    fn from(err: RemoteError) -> Self {
+        match err.code {
+            ErrorCode::DeadlineExceeded => RpcError::DeadlineExceeded,
+            _ => RpcError::Remote(err),
+        }
+    }
+}
+
+impl From<RpcError> for AegisError {
+    fn from(err: RpcError) -> Self {
+        match err {
+            RpcError::DeadlineExceeded => AegisError::Timeout("RPC deadline exceeded".to_string()),
+            RpcError::Remote(remote) => match remote.code {
+                ErrorCode::NotFound => AegisError::NotFound(remote.message),
+                ErrorCode::PermissionDenied => AegisError::PermissionDenied(remote.message),
+                ErrorCode::Unavailable => AegisError::Communication(remote.message),
+                _ => AegisError::Generic(remote.to_string()),
+            },
+            other => AegisError::Communication(other.to_string()),
+        }
+    }
+}
+
+/// Messages exchanged on an RPC connection
+#[derive(Debug, Serialize, Deserialize)]
+enum RpcFrame {
+    /// Call a method
+    Request {
+        id: u64,
+        method: String,
+        /// Milliseconds left until the caller's deadline
+        timeout_ms: u64,
+        /// Codec of the payload; the response payload uses it too
+        codec: u8,
+        payload: Vec<u8>,
+    },
+    /// Result of a call
+    Response {
+        id: u64,
+        result: Result<Vec<u8>, RemoteError>,
+    },
+    /// The caller no longer wants the result
+    Cancel { id: u64 },
+}
+
+/// Calls waiting for a response, by correlation ID
+type PendingCalls = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Vec<u8>, RpcError>>>>>;
+
+/// Client side of an RPC connection
+///
+/// Any number of calls can be in flight at once; responses are matched to
+/// calls by correlation ID.
+pub struct RpcConnection {
+    outgoing: mpsc::UnboundedSender<RpcFrame>,
+    pending: PendingCalls,
+    next_id: AtomicU64,
+    /// Codec negotiated with the server, updated by the connection task
+    codec: Arc<AtomicU8>,
+}
+
+impl RpcConnection {
+    /// Start making calls over a connected stream
+    pub fn new(stream: Box<dyn MessageStream>) -> Self {
+        Self::with_config(stream, FramingConfig::default())
+    }
+
+    /// Start making calls over a connected stream with the given framing
+    pub fn with_config(stream: Box<dyn MessageStream>, framing: FramingConfig) -> Self {
+        let (outgoing, rx) = mpsc::unbounded_channel();
+        let pending = PendingCalls::default();
+        let codec = Arc::new(AtomicU8::new(CodecId::default() as u8));
+
+        let transfers = Transfers::new(&framing);
+        let framed = FramedMessageStream::with_config(stream, framing);
+        tokio::spawn(client_loop(framed, transfers, rx, pending.clone(), codec.clone()));
+
+        Self {
+            outgoing,
+            pending,
+            next_id: AtomicU64::new(1),
+            codec,
+        }
+    }
+
+    /// Codec negotiated with the server
+    ///
+    /// Bincode until the server's first frame advertised the codecs it decodes.
+    pub fn codec(&self) -> CodecId {
+        CodecId::from_u8(self.codec.load(Ordering::Relaxed)).unwrap_or_default()
+    }
+
+    /// Whether the underlying connection has closed
+    pub fn is_closed(&self) -> bool {
+        self.outgoing.is_closed()
+    }
+
+    /// Call the handler for `Req` and wait for its response
+    pub async fn call<Req, Resp>(&self, request: &Req, deadline: Instant) -> Result<Resp, RpcError>
+    where
+        Req: RpcRequest,
+        Resp: DeserializeOwned,
+    {
+        let codec = Req::CODEC.unwrap_or_else(|| self.codec());
+        let payload = codec.encode(request).map_err(|e| RpcError::Codec(e.to_string()))?;
+        let response = self.call_raw(Req::METHOD, codec, payload, deadline).await?;
+        codec.decode(&response).map_err(|e| RpcError::Codec(e.to_string()))
+    }
+
+    /// Call a method with a payload encoded with `codec`
+    ///
+    /// The response payload is encoded with the same codec.
+    pub async fn call_raw(
+        &self,
+        method: &str,
+        codec: CodecId,
+        payload: Vec<u8>,
+        deadline: Instant,
+    ) -> Result<Vec<u8>, RpcError> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        if timeout.is_zero() {
+            return Err(RpcError::DeadlineExceeded);
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
+        // Cancels the call on the server unless a response arrives
+        let mut guard = CallGuard {
+            id,
+            pending: &self.pending,
+            outgoing: &self.outgoing,
+            finished: false,
+        };
+
+        self.outgoing
+            .send(RpcFrame::Request {
+                id,
+                method: method.to_string(),
+                timeout_ms: (timeout.as_millis() as u64).max(1),
+                codec: codec as u8,
+                payload,
+            })
+            .map_err(|_| RpcError::Disconnected)?;
+
+        match tokio::time::timeout_at(deadline.into(), rx).await {
+            Ok(Ok(result)) => {
+                guard.finished = true;
+                result
+            }
+            Ok(Err(_)) => {
+                guard.finished = true;
+                Err(RpcError::Disconnected)
+            }
+            Err(_) => Err(RpcError::DeadlineExceeded),
+        }
+    }
+}
+
+impl PooledConnection for RpcConnection {
+    fn open(stream: Box<dyn MessageStream>, framing: FramingConfig) -> Self {
+        RpcConnection::with_config(stream, framing)
+    }
+
+    fn is_open(&self) -> bool {
+        !self.is_closed()
+    }
+}
+
+/// Removes a call from the pending set and cancels it if it did not finish
+struct CallGuard<'a> {
+    id: u64,
+    pending: &'a PendingCalls,
+    outgoing: &'a mpsc::UnboundedSender<RpcFrame>,
+    finished: bool,
+}
+
+impl Drop for CallGuard<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.pending.lock().unwrap().remove(&self.id);
+            let _ = self.outgoing.send(RpcFrame::Cancel { id: self.id });
+        }
+    }
+}
+
+/// Write requests and deliver responses until either side closes
+///
+/// A request that cannot be encoded or sent in chunks fails only its own
+/// call; the connection is given up only when writing to it fails.
+async fn client_loop(
+    mut framed: FramedMessageStream<Box<dyn MessageStream>>,
+    mut transfers: Transfers,
+    mut outgoing: mpsc::UnboundedReceiver<RpcFrame>,
+    pending: PendingCalls,
+    codec: Arc<AtomicU8>,
+) {
+    // Calls whose request is being sent in chunks, by transfer ID
+    let mut chunked: HashMap<u64, u64> = HashMap::new();
+    let fail = |id: u64, error: RpcError| {
+        if let Some(tx) = pending.lock().unwrap().remove(&id) {
+            let _ = tx.send(Err(error));
+        }
+    };
+
+    loop {
+        if transfers.flush(&mut framed).await.is_err() {
+            break;
+        }
+
+        tokio::select! {
+            frame = outgoing.recv(), if !transfers.is_full() => {
+                let frame = match frame {
+                    Some(frame) => frame,
+                    None => break,
+                };
+                let id = match &frame {
+                    RpcFrame::Request { id, .. } => Some(*id),
+                    _ => None,
+                };
+                match write_frame(&mut framed, &mut transfers, &frame).await {
+                    Ok(Some(transfer_id)) => {
+                        if let Some(id) = id {
+                            chunked.insert(transfer_id, id);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(RpcError::Disconnected) => break,
+                    Err(e) => {
+                        if let Some(id) = id {
+                            fail(id, e);
+                        }
+                    }
+                }
+            }
+            message = framed.read_frame() => {
+                let frame = match message {
+                    Ok(Some(frame)) => frame,
+                    _ => break,
+                };
+                // Any frame may have advertised the server's codecs
+                codec.store(framed.codec() as u8, Ordering::Relaxed);
+                let frame = match reassemble(&mut transfers, frame) {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => {
+                        chunked.retain(|transfer_id, _| transfers.is_sending(*transfer_id));
+                        continue;
+                    }
+                    Err(e) => {
+                        if let ChunkError::Aborted(transfer_id) = e {
+                            if let Some(id) = chunked.remove(&transfer_id) {
+                                fail(id, RpcError::Codec(e.to_string()));
+                            }
+                        }
+                        continue;
+                    }
+                };
+                if let Some(RpcFrame::Response { id, result }) = decode_frame(&frame) {
+                    if let Some(tx) = pending.lock().unwrap().remove(&id) {
+                        let _ = tx.send(result.map_err(RpcError::from));
+                    }
+                }
+            }
+        }
+    }
+
+    // Fail the calls still waiting
+    pending.lock().unwrap().clear();
+    let _ = framed.shutdown().await;
+}
+
+/// Encode one frame with the negotiated codec and write it
+///
+/// Frames larger than one chunk are queued in `transfers` instead, and the ID
+/// of their transfer is returned. Only [`RpcError::Disconnected`] means the
+/// connection failed; other errors concern `frame` alone.
+async fn write_frame(
+    framed: &mut FramedMessageStream<Box<dyn MessageStream>>,
+    transfers: &mut Transfers,
+    frame: &RpcFrame,
+) -> Result<Option<u64>, RpcError> {
+    let codec = framed.codec();
+    let bytes = codec.encode(frame).map_err(|e| RpcError::Codec(e.to_string()))?;
+    let data = Frame::data(Bytes::from(bytes)).with_codec(codec);
+    match transfers.send(data, framed.write_version()) {
+        Ok(Sending::Frame(data)) => framed
+            .write_frame(data)
+            .await
+            .map(|()| None)
+            .map_err(|_| RpcError::Disconnected),
+        Ok(Sending::Chunked(transfer_id)) => Ok(Some(transfer_id)),
+        Err(e) => Err(RpcError::Codec(e.to_string())),
+    }
+}
+
+/// The data frame carried by `frame`, once all of its chunks arrived
+///
+/// Fails if the peer aborted one of the outgoing transfers.
+fn reassemble(transfers: &mut Transfers, frame: Frame) -> Result<Option<Frame>, ChunkError> {
+    match frame.frame_type {
+        FrameType::Chunk => transfers.receive(&frame),
+        _ => Ok(Some(frame)),
+    }
+}
+
+/// Decode a data frame with the codec recorded in its header
+fn decode_frame(frame: &Frame) -> Option<RpcFrame> {
+    if frame.frame_type != FrameType::Data {
+        return None;
+    }
+    frame.codec.decode(&frame.payload).ok()
+}
+
+/// Calls endpoints, keeping a pooled connection open per endpoint
+pub struct RpcClient {
+    pool: ConnectionPool<RpcConnection>,
+}
+
+impl RpcClient {
+    /// Create a client connecting through `connector`
+    pub fn new(connector: impl NetworkConnector + 'static) -> Self {
+        Self::with_connector(Arc::new(connector))
+    }
+
+    /// Create a client sharing a connector
+    pub fn with_connector(connector: Arc<dyn NetworkConnector>) -> Self {
+        Self::with_config(connector, PoolConfig::default())
+    }
+
+    /// Create a client sharing a connector, with pool limits
+    pub fn with_config(connector: Arc<dyn NetworkConnector>, config: PoolConfig) -> Self {
+        Self {
+            pool: ConnectionPool::new(connector, config),
+        }
+    }
+
+    /// Pooled connections of the client
+    pub fn pool(&self) -> &ConnectionPool<RpcConnection> {
+        &self.pool
+    }
+
+    /// Call the handler for `Req` at `endpoint` and wait for its response
+    ///
+    /// Connecting counts against the deadline. Dropping the returned future
+    /// cancels the call.
+    pub async fn call<Req, Resp>(&self, endpoint: &Endpoint, request: &Req, deadline: Instant) -> Result<Resp, RpcError>
+    where
+        Req: RpcRequest,
+        Resp: DeserializeOwned,
+    {
+        let connection = tokio::time::timeout_at(deadline.into(), self.connection(endpoint))
+            .await
+            .map_err(|_| RpcError::DeadlineExceeded)??;
+
+        connection.call(request, deadline).await
+    }
+
+    /// Call with a deadline `timeout` from now
+    pub async fn call_timeout<Req, Resp>(
+        &self,
+        endpoint: &Endpoint,
+        request: &Req,
+        timeout: Duration,
+    ) -> Result<Resp, RpcError>
+    where
+        Req: RpcRequest,
+        Resp: DeserializeOwned,
+    {
+        self.call(endpoint, request, Instant::now() + timeout).await
+    }
+
+    /// Open connection to `endpoint`, connecting if needed
+    pub async fn connection(&self, endpoint: &Endpoint) -> Result<Arc<RpcConnection>, RpcError> {
+        Ok(self.pool.get(endpoint).await?)
+    }
+}
+
+/// Information about the call a handler is serving
+#[derive(Debug, Clone)]
+pub struct RpcContext {
+    /// Correlation ID of the request
+    pub request_id: u64,
+    /// Connection the request arrived on
+    pub peer: PeerInfo,
+    /// When the caller stops waiting for the response
+    pub deadline: Instant,
+}
+
+impl RpcContext {
+    /// Time left until the deadline
+    ///
+    /// Pass [`RpcContext::deadline`] to calls made while handling a request
+    /// so they do not outlive it.
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+}
+
+/// Type-erased request handler
+type Handler =
+    Arc<dyn Fn(CodecId, Vec<u8>, RpcContext) -> BoxFuture<'static, Result<Vec<u8>, RemoteError>> + Send + Sync>;
+
+/// Serves RPC requests with registered handlers
+#[derive(Clone, Default)]
+pub struct RpcServer {
+    handlers: HashMap<String, Handler>,
+}
+
+impl RpcServer {
+    /// Create a server without handlers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the handler for `Req`, replacing any previous one
+    ///
+    /// Errors returned by the handler are sent to the caller as a
+    /// [`RemoteError`]. The handler is aborted if the caller cancels or the
+    /// deadline passes.
+    pub fn register<Req, Resp, F, Fut>(&mut self, handler: F)
+    where
+        Req: RpcRequest,
+        Resp: Serialize + Send + 'static,
+        F: Fn(Req, RpcContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = AegisResult<Resp>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        let erased: Handler = Arc::new(move |codec: CodecId, payload: Vec<u8>, context: RpcContext| {
+            let handler = handler.clone();
+            Box::pin(async move {
+                let request: Req = codec
+                    .decode(&payload)
+                    .map_err(|e| RemoteError::new(ErrorCode::InvalidRequest, e.to_string()))?;
+                let response = handler(request, context).await.map_err(|e| RemoteError::from(&e))?;
+                codec
+                    .encode(&response)
+                    .map_err(|e| RemoteError::new(ErrorCode::Internal, e.to_string()))
+            })
+        });
+
+        self.handlers.insert(Req::METHOD.to_string(), erased);
+    }
+
+    /// Registered method names
+    pub fn methods(&self) -> Vec<String> {
+        let mut methods: Vec<String> = self.handlers.keys().cloned().collect();
+        methods.sort();
+        methods
+    }
+
+    /// Serve requests arriving on one connection until it closes
+    pub async fn serve_connection(&self, stream: Box<dyn MessageStream>) {
+        let peer = match stream.peer_addr() {
+            Ok(addr) => PeerInfo {
+                addr,
+                identity: stream.peer_identity(),
+                credentials: stream.peer_credentials(),
+            },
+            Err(_) => return,
+        };
+
+        // Never asked to shut down while the sender is alive
+        let (_shutdown, never) = watch::channel(false);
+        let config = ServerConfig {
+            idle_timeout: None,
+            ..ServerConfig::default()
+        };
+        self.serve_stream(stream, peer, config, never).await;
+    }
+
+    /// Serve requests on one connection until it closes, idles or shutdown
+    ///
+    /// After shutdown was requested no requests are served; the connection
+    /// closes once the running calls have sent their responses and the
+    /// chunks of those responses were acknowledged.
+    pub(crate) async fn serve_stream(
+        &self,
+        stream: Box<dyn MessageStream>,
+        peer: PeerInfo,
+        config: ServerConfig,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        let framing = config.framing();
+        let mut transfers = Transfers::new(&framing);
+        let mut framed = FramedMessageStream::with_config(stream, framing);
+        let (responses, mut completed) = mpsc::unbounded_channel::<RpcFrame>();
+        let mut running: HashMap<u64, AbortHandle> = HashMap::new();
+        let mut draining = false;
+
+        loop {
+            if transfers.flush(&mut framed).await.is_err() {
+                break;
+            }
+            let sending = transfers.outgoing() > 0;
+            if draining && running.is_empty() && !sending {
+                break;
+            }
+
+            tokio::select! {
+                _ = requested(&mut shutdown), if !draining => draining = true,
+                // Restarted by every frame and response
+                _ = idle(config.idle_timeout), if running.is_empty() && !sending && !draining => break,
+                Some(frame) = completed.recv(), if !transfers.is_full() => {
+                    let id = match &frame {
+                        RpcFrame::Response { id, .. } => *id,
+                        _ => continue,
+                    };
+                    running.remove(&id);
+                    let error = match write_frame(&mut framed, &mut transfers, &frame).await {
+                        Ok(_) => continue,
+                        Err(RpcError::Disconnected) => break,
+                        Err(e) => RemoteError::new(ErrorCode::Internal, format!("response not sent: {}", e)),
+                    };
+                    // The caller learns why instead of waiting for its deadline
+                    let frame = RpcFrame::Response { id, result: Err(error) };
+                    if let Err(RpcError::Disconnected) = write_frame(&mut framed, &mut transfers, &frame).await {
+                        break;
+                    }
+                }
+                // While draining, frames are read only for acknowledgements
+                message = framed.read_frame(), if !draining || sending => {
+                    let frame = match message {
+                        Ok(Some(frame)) => frame,
+                        _ => break,
+                    };
+                    let frame = match reassemble(&mut transfers, frame) {
+                        Ok(Some(frame)) if !draining => frame,
+                        _ => continue,
+                    };
+                    match decode_frame(&frame) {
+                        Some(RpcFrame::Request { id, method, timeout_ms, codec, payload }) => {
+                            let handler = match self.handlers.get(&method) {
+                                Some(handler) => handler.clone(),
+                                None => {
+                                    let error = RemoteError::new(
+                                        ErrorCode::UnknownMethod,
+                                        format!("no handler for {}", method),
+                                    );
+                                    let _ = responses.send(RpcFrame::Response { id, result: Err(error) });
+                                    continue;
+                                }
+                            };
+
+                            let codec = match CodecId::from_u8(codec) {
+                                Some(codec) => codec,
+                                None => {
+                                    let error = RemoteError::new(
+                                        ErrorCode::InvalidRequest,
+                                        format!("unknown payload codec {}", codec),
+                                    );
+                                    let _ = responses.send(RpcFrame::Response { id, result: Err(error) });
+                                    continue;
+                                }
+                            };
+
+                            let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+                            let context = RpcContext {
+                                request_id: id,
+                                peer: peer.clone(),
+                                deadline,
+                            };
+                            let responses = responses.clone();
+
+                            let task = tokio::spawn(async move {
+                                let result = tokio::time::timeout_at(deadline.into(), handler(codec, payload, context))
+                                    .await
+                                    .unwrap_or_else(|_| {
+                                        Err(RemoteError::new(ErrorCode::DeadlineExceeded, "deadline exceeded"))
+                                    });
+                                let _ = responses.send(RpcFrame::Response { id, result });
+                            });
+                            running.insert(id, task.abort_handle());
+                        }
+                        Some(RpcFrame::Cancel { id }) => {
+                            if let Some(task) = running.remove(&id) {
+                                task.abort();
+                            }
+                        }
+                        // Responses are not expected here; undecodable frames are skipped
+                        Some(RpcFrame::Response { .. }) | None => {}
+                    }
+                }
+            }
+        }
+
+        for task in running.values() {
+            task.abort();
+        }
+        let _ = framed.shutdown().await;
+    }
+}
+
+/// Resolves after `timeout`, or never without one
+async fn idle(timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => tokio::time::sleep(timeout).await,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,371 @@
+//! Listener with connection limits and graceful shutdown
+//!
+//! A [`MessageServer`] accepts connections and runs each on its own task,
+//! decoding the messages that arrive and passing them to a handler, a
+//! [`MessageDispatcher`] or an [`RpcServer`](crate::RpcServer). It can be
+//! stopped through a [`ServerHandle`]: the server stops accepting, lets every
+//! connection finish the message it is handling, and waits for them up to the
+//! drain timeout before aborting whatever is left.
+//!
+//! Connections are flow controlled like a
+//! [`ConnectionHandle`](crate::ConnectionHandle): peers that write v2 frames
+//! are granted credit for the receive window and more as their messages are
+//! handled. Messages sent in chunks are handled once reassembled; those of
+//! an authenticated peer continue on its next connection if one closes
+//! midway.
+
+use crate::chunking::{ChunkConfig, ParkedTransfers, Transfers};
+use crate::codec::Codec;
+use crate::connection::ControlMessage;
+use crate::dispatch::MessageDispatcher;
+use crate::error::{CommsError, PeerInfo};
+use crate::framing::{Frame, FrameType, FrameVersion, FramedMessageStream, FramingConfig};
+use crate::protocol::AegisMessage;
+use crate::transport::{MessageListener, MessageStream};
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+/// Limits of a [`MessageServer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// Most connections served at once; further connections are closed
+    /// right after they are accepted
+    pub max_connections: usize,
+    /// Connections that deliver no frame for this long are closed
+    pub idle_timeout: Option<Duration>,
+    /// How long shutdown waits for connections to finish before aborting them
+    pub drain_timeout: Duration,
+    /// Messages a peer may send ahead of the handler on each connection
+    pub receive_window: u32,
+    /// Chunked transfers of messages larger than one chunk
+    pub chunking: ChunkConfig,
+}
+
+impl ServerConfig {
+    /// Framing of each connection
+    pub(crate) fn framing(&self) -> FramingConfig {
+        FramingConfig {
+            chunking: self.chunking,
+            ..FramingConfig::default()
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 1024,
+            idle_timeout: Some(Duration::from_secs(300)),
+            drain_timeout: Duration::from_secs(30),
+            receive_window: 32,
+            chunking: ChunkConfig::default(),
+        }
+    }
+}
+
+/// State shared between a server and its handles
+struct Shared {
+    /// Set once shutdown was requested
+    shutdown: watch::Sender<bool>,
+    /// True while no `serve` call is running
+    stopped: watch::Sender<bool>,
+    active: AtomicUsize,
+    rejected: AtomicU64,
+    /// Incomplete transfers of closed connections, resumed by their peers
+    parked: ParkedTransfers,
+}
+
+/// Controls a running [`MessageServer`]
+#[derive(Clone)]
+pub struct ServerHandle {
+    shared: Arc<Shared>,
+}
+
+impl ServerHandle {
+    /// Connections currently being served
+    pub fn active_connections(&self) -> usize {
+        self.shared.active.load(Ordering::Acquire)
+    }
+
+    /// Connections closed because the server was at its limit
+    pub fn rejected_connections(&self) -> u64 {
+        self.shared.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Whether shutdown was requested
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shared.shutdown.borrow()
+    }
+
+    /// Stop accepting connections without waiting for the drain
+    pub fn stop(&self) {
+        self.shared.shutdown.send_replace(true);
+    }
+
+    /// Stop accepting connections and wait until the server has drained
+    ///
+    /// Returns immediately if the server is not serving.
+    pub async fn shutdown(&self) {
+        self.stop();
+        let mut stopped = self.shared.stopped.subscribe();
+        let _ = stopped.wait_for(|stopped| *stopped).await;
+    }
+}
+
+/// Serves typed messages from accepted connections until shut down
+///
+/// A server serves one listener; once shut down it does not serve again.
+pub struct MessageServer {
+    config: ServerConfig,
+    shared: Arc<Shared>,
+}
+
+impl MessageServer {
+    /// Create a server with the given limits
+    pub fn new(config: ServerConfig) -> Self {
+        Self {
+            config,
+            shared: Arc::new(Shared {
+                shutdown: watch::channel(false).0,
+                stopped: watch::channel(true).0,
+                active: AtomicUsize::new(0),
+                rejected: AtomicU64::new(0),
+                parked: ParkedTransfers::default(),
+            }),
+        }
+    }
+
+    /// Limits of the server
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// Handle for stopping the server and observing its connections
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Accept connections and pass their messages to `handler`
+    ///
+    /// Messages are decoded with the codec recorded in their frame and handled
+    /// one at a time per connection; messages that cannot be decoded are
+    /// skipped. Returns once shut down and drained, or with the error of a
+    /// failed accept after draining.
+    pub async fn serve<T, F, Fut>(&self, listener: impl MessageListener, handler: F) -> Result<(), CommsError>
+    where
+        T: DeserializeOwned + Send + 'static,
+        F: Fn(T, PeerInfo) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let config = self.config;
+        let parked = self.shared.parked.clone();
+        self.run(listener, move |stream, peer, shutdown| {
+            let transfers = Transfers::for_peer(&config.framing(), &parked, &peer);
+            serve_connection(stream, peer, handler.clone(), config, transfers, shutdown)
+        })
+        .await
+    }
+
+    /// Accept connections and route their messages with `dispatcher`
+    ///
+    /// Messages of any type may arrive on a connection; those that cannot be
+    /// decoded or have no handler are skipped.
+    pub async fn serve_dispatcher(
+        &self,
+        listener: impl MessageListener,
+        dispatcher: MessageDispatcher,
+    ) -> Result<(), CommsError> {
+        self.serve(listener, move |message: AegisMessage, peer| {
+            let dispatcher = dispatcher.clone();
+            async move {
+                let _ = dispatcher.dispatch(message, peer).await;
+            }
+        })
+        .await
+    }
+
+    /// Accept connections and serve their calls with `rpc`
+    ///
+    /// On shutdown a connection stops taking requests, sends the responses of
+    /// the calls still running and closes. The idle timeout applies only while
+    /// no call is running.
+    #[cfg(feature = "rpc")]
+    pub async fn serve_rpc(
+        &self,
+        listener: impl MessageListener,
+        rpc: crate::rpc::RpcServer,
+    ) -> Result<(), CommsError> {
+        let config = self.config;
+        self.run(listener, move |stream, peer, shutdown| {
+            let rpc = rpc.clone();
+            async move { rpc.serve_stream(stream, peer, config, shutdown).await }
+        })
+        .await
+    }
+
+    /// Accept connections and run `serve` on each until shut down
+    async fn run<S, Fut>(&self, listener: impl MessageListener, serve: S) -> Result<(), CommsError>
+    where
+        S: Fn(Box<dyn MessageStream>, PeerInfo, watch::Receiver<bool>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut listener = listener;
+        let mut shutdown = self.shared.shutdown.subscribe();
+        let mut connections = JoinSet::new();
+        self.shared.stopped.send_replace(false);
+
+        let result = loop {
+            tokio::select! {
+                _ = requested(&mut shutdown) => break Ok(()),
+                // Finished connections are reaped so they free their slot
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
+                accepted = listener.accept() => {
+                    let (mut stream, addr) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => break Err(CommsError::from(e)),
+                    };
+                    if connections.len() >= self.config.max_connections {
+                        self.shared.rejected.fetch_add(1, Ordering::Relaxed);
+                        let _ = stream.shutdown().await;
+                        continue;
+                    }
+
+                    let peer = PeerInfo {
+                        addr,
+                        identity: stream.peer_identity(),
+                        credentials: stream.peer_credentials(),
+                    };
+                    connections.spawn(serve(stream, peer, self.shared.shutdown.subscribe()));
+                }
+            }
+            self.shared.active.store(connections.len(), Ordering::Release);
+        };
+
+        self.drain(connections).await;
+        self.shared.stopped.send_replace(true);
+        result
+    }
+
+    /// Wait for connections to finish, aborting them after the drain timeout
+    async fn drain(&self, mut connections: JoinSet<()>) {
+        let deadline = tokio::time::sleep(self.config.drain_timeout);
+        tokio::pin!(deadline);
+
+        loop {
+            tokio::select! {
+                joined = connections.join_next() => {
+                    if joined.is_none() {
+                        break;
+                    }
+                }
+                _ = &mut deadline => {
+                    connections.abort_all();
+                    while connections.join_next().await.is_some() {}
+                    break;
+                }
+            }
+            self.shared.active.store(connections.len(), Ordering::Release);
+        }
+        self.shared.active.store(0, Ordering::Release);
+    }
+}
+
+/// Handle the messages of one connection until it closes, idles or shutdown
+async fn serve_connection<T, F, Fut>(
+    stream: Box<dyn MessageStream>,
+    peer: PeerInfo,
+    handler: F,
+    config: ServerConfig,
+    mut transfers: Transfers,
+    mut shutdown: watch::Receiver<bool>,
+) where
+    T: DeserializeOwned,
+    F: Fn(T, PeerInfo) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut framed = FramedMessageStream::with_config(stream, config.framing());
+    // Handled messages not yet granted back, once the window was granted
+    let mut consumed: Option<u32> = None;
+    let batch = (config.receive_window / 2).max(1);
+
+    loop {
+        // Acknowledge the chunks read so far
+        if transfers.flush(&mut framed).await.is_err() {
+            break;
+        }
+
+        // A handler that is running is never interrupted by shutdown
+        let frame = tokio::select! {
+            _ = requested(&mut shutdown) => break,
+            frame = read_frame(&mut framed, config.idle_timeout) => match frame {
+                Some(frame) => frame,
+                None => break,
+            },
+        };
+        // Credit only once the peer showed it reads control frames
+        if consumed.is_none() && framed.peer_version() == Some(FrameVersion::V2) {
+            if grant(&mut framed, config.receive_window).await.is_err() {
+                break;
+            }
+            consumed = Some(0);
+        }
+        // Credit granted to the server is ignored; it never writes messages
+        let frame = match frame.frame_type {
+            FrameType::Data => frame,
+            FrameType::Chunk => match transfers.receive(&frame) {
+                Ok(Some(frame)) => frame,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        if let Ok(msg) = frame.codec.decode::<T>(&frame.payload) {
+            handler(msg, peer.clone()).await;
+        }
+
+        // Undecodable messages free their room too
+        if let Some(handled) = consumed.as_mut() {
+            *handled += 1;
+            if *handled >= batch {
+                if grant(&mut framed, *handled).await.is_err() {
+                    break;
+                }
+                *handled = 0;
+            }
+        }
+    }
+
+    let _ = framed.shutdown().await;
+}
+
+/// Grant the peer credit for `credit` more messages
+async fn grant(framed: &mut FramedMessageStream<Box<dyn MessageStream>>, credit: u32) -> Result<(), CommsError> {
+    let payload = bincode::serialize(&ControlMessage::Credit(credit))?;
+    framed.write_frame(Frame::control(Bytes::from(payload))).await?;
+    Ok(())
+}
+
+/// Next frame; `None` once the connection closed, failed or idled
+async fn read_frame(
+    framed: &mut FramedMessageStream<Box<dyn MessageStream>>,
+    idle_timeout: Option<Duration>,
+) -> Option<Frame> {
+    let read = match idle_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, framed.read_frame()).await.ok()?,
+        None => framed.read_frame().await,
+    };
+    read.ok().flatten()
+}
+
+/// Resolves once shutdown was requested
+pub(crate) async fn requested(shutdown: &mut watch::Receiver<bool>) {
+    // The guard is not held across the await of the caller
+    let _ = shutdown.wait_for(|shutdown| *shutdown).await;
+}
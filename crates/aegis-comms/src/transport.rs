@@ -1,7 +1,10 @@
+use crate::endpoint::Endpoint;
 use async_trait::async_trait;
 use bytes::Bytes;
+use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 use std::fmt;
+use std::path::{Path, PathBuf};
 
 /// Errors that can occur during network operations
 #[derive(Debug)]
@@ -18,6 +21,14 @@ pub enum NetworkError {
     Timeout,
     /// Connection was closed
     ConnectionClosed,
+    /// TLS configuration or handshake error
+    Tls(String),
+    /// Address of a kind this transport cannot handle
+    UnsupportedAddress(TransportAddr),
+    /// No transport is registered for an endpoint scheme
+    UnsupportedScheme(String),
+    /// Host name could not be resolved
+    Resolve(String),
     /// Other error with description
     Other(String),
 }
@@ -31,6 +42,10 @@ impl fmt::Display for NetworkError {
             NetworkError::AddrParseError(e) => write!(f, "Address parse error: {}", e),
             NetworkError::Timeout => write!(f, "Operation timed out"),
             NetworkError::ConnectionClosed => write!(f, "Connection closed"),
+            NetworkError::Tls(e) => write!(f, "TLS error: {}", e),
+            NetworkError::UnsupportedAddress(addr) => write!(f, "Unsupported address: {}", addr),
+            NetworkError::UnsupportedScheme(scheme) => write!(f, "No transport for scheme: {}", scheme),
+            NetworkError::Resolve(e) => write!(f, "Name resolution failed: {}", e),
             NetworkError::Other(e) => write!(f, "Other error: {}", e),
         }
     }
@@ -38,6 +53,127 @@ impl fmt::Display for NetworkError {
 
 impl std::error::Error for NetworkError {}
 
+/// Address of a connection endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TransportAddr {
+    /// TCP socket address
+    Tcp(SocketAddr),
+    /// Unix domain socket path, `None` for an unnamed socket
+    Unix(Option<PathBuf>),
+    /// Named in-process endpoint
+    Memory(String),
+}
+
+impl TransportAddr {
+    /// Address of a Unix domain socket at `path`
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        TransportAddr::Unix(Some(path.into()))
+    }
+
+    /// The TCP socket address, if this is one
+    pub fn as_socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            TransportAddr::Tcp(addr) => Some(*addr),
+            _ => None,
+        }
+    }
+
+    /// The Unix socket path, if this is a named Unix socket
+    pub fn as_unix_path(&self) -> Option<&Path> {
+        match self {
+            TransportAddr::Unix(path) => path.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The endpoint name, if this is an in-process address
+    pub fn as_memory_name(&self) -> Option<&str> {
+        match self {
+            TransportAddr::Memory(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for TransportAddr {
+    fn from(addr: SocketAddr) -> Self {
+        TransportAddr::Tcp(addr)
+    }
+}
+
+impl fmt::Display for TransportAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportAddr::Tcp(addr) => write!(f, "{}", addr),
+            TransportAddr::Unix(Some(path)) => write!(f, "unix:{}", path.display()),
+            TransportAddr::Unix(None) => f.write_str("unix:(unnamed)"),
+            TransportAddr::Memory(name) => write!(f, "mem:{}", name),
+        }
+    }
+}
+
+/// Credentials of the process at the other end of a local connection
+///
+/// Reported by the kernel for Unix domain sockets (`SO_PEERCRED`), so they
+/// cannot be forged by the peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerCredentials {
+    /// User ID of the peer process
+    pub uid: u32,
+    /// Group ID of the peer process
+    pub gid: u32,
+    /// Process ID, where the platform reports it
+    pub pid: Option<i32>,
+}
+
+/// Authenticated identity of the remote end of a connection
+///
+/// Populated from the peer certificate verified during a mutual TLS
+/// handshake. Transports without authentication report no identity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerIdentity {
+    /// Subject common name, or the full subject when it has no common name
+    pub subject: String,
+    /// DNS names from the subject alternative name extension
+    pub dns_names: Vec<String>,
+    /// URIs from the subject alternative name extension
+    pub uris: Vec<String>,
+    /// SPIFFE ID, the first `spiffe://` URI if any
+    pub spiffe_id: Option<String>,
+    /// SHA-256 fingerprint of the certificate, hex encoded
+    pub fingerprint: String,
+}
+
+impl PeerIdentity {
+    /// Preferred identifier: the SPIFFE ID, then the first DNS name, then the subject
+    pub fn id(&self) -> &str {
+        self.spiffe_id
+            .as_deref()
+            .or_else(|| self.dns_names.first().map(String::as_str))
+            .unwrap_or(&self.subject)
+    }
+
+    /// All names this peer can be identified by
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.uris
+            .iter()
+            .chain(self.dns_names.iter())
+            .map(String::as_str)
+            .chain(std::iter::once(self.subject.as_str()))
+    }
+
+    /// Whether any of the peer's names appears in `allowed`
+    pub fn matches_any(&self, allowed: &[String]) -> bool {
+        self.names().any(|name| allowed.iter().any(|a| a == name))
+    }
+}
+
+impl fmt::Display for PeerIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
 /// Trait for stream-based message transport
 #[async_trait]
 pub trait MessageStream: Send + Unpin {
@@ -48,25 +184,86 @@ pub trait MessageStream: Send + Unpin {
     async fn write_message(&mut self, msg: Bytes) -> Result<(), NetworkError>;
     
     /// Get the peer's address
-    fn peer_addr(&self) -> Result<SocketAddr, NetworkError>;
+    fn peer_addr(&self) -> Result<TransportAddr, NetworkError>;
+    
+    /// Get the peer's verified identity, if the transport authenticates peers
+    fn peer_identity(&self) -> Option<PeerIdentity> {
+        None
+    }
+    
+    /// Get the peer process credentials, if the transport is local
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        None
+    }
     
     /// Shutdown the stream gracefully
     async fn shutdown(&mut self) -> Result<(), NetworkError>;
 }
 
+#[async_trait]
+impl MessageStream for Box<dyn MessageStream> {
+    async fn read_message(&mut self) -> Result<Option<Bytes>, NetworkError> {
+        (**self).read_message().await
+    }
+    
+    async fn write_message(&mut self, msg: Bytes) -> Result<(), NetworkError> {
+        (**self).write_message(msg).await
+    }
+    
+    fn peer_addr(&self) -> Result<TransportAddr, NetworkError> {
+        (**self).peer_addr()
+    }
+    
+    fn peer_identity(&self) -> Option<PeerIdentity> {
+        (**self).peer_identity()
+    }
+    
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        (**self).peer_credentials()
+    }
+    
+    async fn shutdown(&mut self) -> Result<(), NetworkError> {
+        (**self).shutdown().await
+    }
+}
+
 /// Trait for accepting incoming connections
 #[async_trait]
 pub trait MessageListener: Send + Unpin {
     /// Accept a new connection
-    async fn accept(&mut self) -> Result<(Box<dyn MessageStream>, SocketAddr), NetworkError>;
+    async fn accept(&mut self) -> Result<(Box<dyn MessageStream>, TransportAddr), NetworkError>;
     
     /// Get the local address being listened on
-    fn local_addr(&self) -> Result<SocketAddr, NetworkError>;
+    fn local_addr(&self) -> Result<TransportAddr, NetworkError>;
 }
 
 /// Trait for initiating connections
 #[async_trait]
 pub trait NetworkConnector: Send + Sync {
     /// Connect to a remote address
-    async fn connect(&self, addr: SocketAddr) -> Result<Box<dyn MessageStream>, NetworkError>;
+    async fn connect(&self, addr: TransportAddr) -> Result<Box<dyn MessageStream>, NetworkError>;
+    
+    /// Connect to `addr`, which was resolved from the host name `host`
+    ///
+    /// Transports that authenticate the server by name, such as TLS, verify
+    /// it against `host`. Others ignore it.
+    async fn connect_to_host(&self, addr: TransportAddr, host: &str) -> Result<Box<dyn MessageStream>, NetworkError> {
+        let _ = host;
+        self.connect(addr).await
+    }
+    
+    /// Connect to an endpoint
+    ///
+    /// The default handles endpoints that need no name resolution;
+    /// [`crate::ConnectorRegistry`] resolves host names and picks the
+    /// transport by scheme.
+    async fn connect_endpoint(&self, endpoint: &Endpoint) -> Result<Box<dyn MessageStream>, NetworkError> {
+        let addr = endpoint
+            .to_transport_addr()
+            .ok_or_else(|| NetworkError::Resolve(format!("{} needs name resolution", endpoint)))?;
+        match endpoint.host() {
+            Some(host) => self.connect_to_host(addr, host).await,
+            None => self.connect(addr).await,
+        }
+    }
 } 
\ No newline at end of file
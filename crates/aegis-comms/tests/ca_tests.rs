@@ -0,0 +1,188 @@
+#![cfg(feature = "ca")]
+
+use aegis_comms::ca::*;
+use aegis_core::clock::TestClock;
+use aegis_core::identity::NodeIdentity;
+use aegis_core::keystore::KeyStore;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn setup(dir: &std::path::Path) -> (CertificateAuthority, Arc<TestClock>) {
+    let keystore = KeyStore::open(dir.join("keys")).unwrap();
+    let clock = Arc::new(TestClock::new(chrono::Utc::now()));
+    let ca = CertificateAuthority::open_with_clock(CaConfig::new(dir.join("ca")), &keystore, clock.clone()).unwrap();
+    (ca, clock)
+}
+
+fn node(dir: &std::path::Path, name: &str) -> NodeIdentity {
+    let keystore = KeyStore::open(dir.join(name)).unwrap();
+    NodeIdentity::load_or_create(&keystore, name).unwrap()
+}
+
+#[test]
+fn test_enroll_binds_certificate_to_node_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let (ca, _) = setup(dir.path());
+    let identity = node(dir.path(), "node-a");
+
+    let token = ca.create_token_with_dns_names(None, &["node-a.aegis.test"]).unwrap();
+    let issued = ca
+        .enroll(&EnrollmentRequest {
+            token: token.token.clone(),
+            csr_pem: create_csr(&identity, &["node-a.aegis.test".to_string()]).unwrap(),
+        })
+        .unwrap();
+
+    assert_eq!(issued.node_id, identity.node_id());
+    assert_eq!(issued.spiffe_id, format!("spiffe://aegis.local/node/{}", identity.node_id()));
+
+    let info = CertificateInfo::from_pem(&issued.certificate_pem).unwrap();
+    assert_eq!(info.serial, issued.serial);
+    assert_eq!(info.subject, identity.node_id());
+
+    // Tokens are single use
+    let again = ca.enroll(&EnrollmentRequest {
+        token: token.token,
+        csr_pem: create_csr(&identity, &[]).unwrap(),
+    });
+    assert!(again.is_err());
+}
+
+#[test]
+fn test_token_restricted_to_node() {
+    let dir = tempfile::tempdir().unwrap();
+    let (ca, clock) = setup(dir.path());
+    let node_a = node(dir.path(), "node-a");
+    let node_b = node(dir.path(), "node-b");
+
+    let token = ca.create_token(Some(node_a.node_id())).unwrap();
+    let wrong_node = ca.enroll(&EnrollmentRequest {
+        token: token.token.clone(),
+        csr_pem: create_csr(&node_b, &[]).unwrap(),
+    });
+    assert!(wrong_node.is_err());
+
+    clock.advance(Duration::from_secs(2 * 60 * 60));
+    let expired = ca.enroll(&EnrollmentRequest {
+        token: token.token,
+        csr_pem: create_csr(&node_a, &[]).unwrap(),
+    });
+    assert!(expired.is_err());
+}
+
+#[test]
+fn test_dns_names_must_be_allowed_by_token() {
+    let dir = tempfile::tempdir().unwrap();
+    let (ca, _) = setup(dir.path());
+    let identity = node(dir.path(), "node-a");
+    let token = ca.create_token_with_dns_names(None, &["node-a.aegis.test"]).unwrap();
+
+    let hijack = ca.enroll(&EnrollmentRequest {
+        token: token.token.clone(),
+        csr_pem: create_csr(&identity, &["node-a.aegis.test".to_string(), "ca.aegis.test".to_string()]).unwrap(),
+    });
+    assert!(hijack.unwrap_err().to_string().contains("ca.aegis.test"));
+    assert!(ca.certificates().is_empty());
+
+    // A rejected enrollment does not use up the token
+    ca.enroll(&EnrollmentRequest {
+        token: token.token,
+        csr_pem: create_csr(&identity, &["node-a.aegis.test".to_string()]).unwrap(),
+    })
+    .unwrap();
+
+    // Tokens without DNS names allow none
+    let plain = ca.create_token(None).unwrap();
+    assert!(ca
+        .enroll(&EnrollmentRequest {
+            token: plain.token,
+            csr_pem: create_csr(&identity, &["node-a.aegis.test".to_string()]).unwrap(),
+        })
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_renewer_renews_and_revocation_blocks_renewal() {
+    let dir = tempfile::tempdir().unwrap();
+    let (ca, clock) = setup(dir.path());
+    let identity = Arc::new(node(dir.path(), "node-a"));
+
+    let tls = aegis_core::config::TlsConfig {
+        cert_path: dir.path().join("tls/node.pem"),
+        key_path: dir.path().join("tls/node.key"),
+        ca_path: Some(dir.path().join("tls/ca.pem")),
+        server_name: None,
+        require_client_cert: true,
+        crl_path: Some(dir.path().join("tls/crl.pem")),
+    };
+    let renewer = CertificateRenewer::new(identity, tls).with_clock(clock.clone());
+
+    let token = ca.create_token(None).unwrap();
+    let first = renewer.enroll(&ca, &token.token).await.unwrap();
+    assert!(dir.path().join("tls/ca.pem").exists());
+    assert!(dir.path().join("tls/crl.pem").exists());
+
+    // Not yet due
+    assert!(renewer.renew_if_due(&ca).await.unwrap().is_none());
+
+    clock.advance(Duration::from_secs(20 * 60 * 60));
+    let second = renewer.renew_if_due(&ca).await.unwrap().unwrap();
+    assert_ne!(first.serial, second.serial);
+    assert_eq!(renewer.current().unwrap().unwrap().serial, second.serial);
+
+    ca.revoke(&second.serial).unwrap();
+    assert!(ca.crl_pem().unwrap().contains("BEGIN X509 CRL"));
+
+    clock.advance(Duration::from_secs(20 * 60 * 60));
+    assert!(renewer.renew_if_due(&ca).await.is_err());
+}
+
+fn node_tls(dir: &std::path::Path, name: &str) -> aegis_core::config::TlsConfig {
+    aegis_core::config::TlsConfig {
+        cert_path: dir.join(name).join("node.pem"),
+        key_path: dir.join(name).join("node.key"),
+        ca_path: Some(dir.join(name).join("ca.pem")),
+        server_name: Some("server.aegis.test".to_string()),
+        require_client_cert: true,
+        crl_path: None,
+    }
+}
+
+#[tokio::test]
+async fn test_mutual_tls_rejects_revoked_client() {
+    use aegis_comms::{connect_tls, listen_tls, MessageListener, NetworkConnector};
+    use bytes::Bytes;
+
+    let dir = tempfile::tempdir().unwrap();
+    let keystore = KeyStore::open(dir.path().join("keys")).unwrap();
+    let ca = CertificateAuthority::open(CaConfig::new(dir.path().join("ca")), &keystore).unwrap();
+
+    let server = CertificateRenewer::new(Arc::new(node(dir.path(), "server")), node_tls(dir.path(), "server"))
+        .with_dns_names(vec!["server.aegis.test".to_string()]);
+    let token = ca.create_token_with_dns_names(None, &["server.aegis.test"]).unwrap();
+    server.enroll(&ca, &token.token).await.unwrap();
+
+    let client_identity = Arc::new(node(dir.path(), "client"));
+    let client_tls = node_tls(dir.path(), "client");
+    let client = CertificateRenewer::new(client_identity.clone(), client_tls.clone());
+    let issued = client.enroll(&ca, &ca.create_token(None).unwrap().token).await.unwrap();
+
+    ca.revoke(&issued.serial).unwrap();
+
+    let mut server_tls = node_tls(dir.path(), "server");
+    server_tls.crl_path = Some(ca.config().crl_path());
+    let mut listener = listen_tls("127.0.0.1:0".parse().unwrap(), &server_tls).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        if let Ok((mut stream, _)) = listener.accept().await {
+            let _ = stream.write_message(Bytes::from_static(b"hello")).await;
+        }
+    });
+
+    // TLS 1.3 reports client authentication failures on first use
+    let result = match connect_tls(&client_tls).unwrap().connect(addr).await {
+        Ok(mut stream) => stream.read_message().await.ok().flatten(),
+        Err(_) => None,
+    };
+    assert!(result.is_none());
+}
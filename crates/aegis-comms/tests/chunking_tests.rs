@@ -0,0 +1,552 @@
+#![cfg(feature = "memory")]
+
+use aegis_comms::{
+    memory_duplex, AegisMessage, ChunkConfig, ChunkError, ChunkReceiver, ChunkSender, Codec, CodecId, CommsClient,
+    CompletedTransfer, ConnectionHandle, ConnectionOptions, Endpoint, FrameType, FramedMessageStream, FramingConfig,
+    FramingError, LinkConfig, MemoryNetwork, MemoryStream, MessageListener, MessageServer, MessageStream,
+    NetworkConnector, PeerIdentity, PeerRegistry, PoolConfig, ServerConfig, StateUpdateMessage, TransportAddr,
+};
+use bytes::Bytes;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+type Framed = FramedMessageStream<MemoryStream>;
+
+fn pair() -> (Framed, Framed) {
+    let (a, b) = memory_duplex(
+        TransportAddr::Memory("leader".to_string()),
+        TransportAddr::Memory("follower".to_string()),
+        LinkConfig::default(),
+    );
+    (
+        FramedMessageStream::with_config(a, FramingConfig::default()),
+        FramedMessageStream::with_config(b, FramingConfig::default()),
+    )
+}
+
+fn snapshot(len: usize) -> Bytes {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect::<Vec<_>>().into()
+}
+
+fn framing(chunking: ChunkConfig) -> FramingConfig {
+    FramingConfig {
+        chunking,
+        ..FramingConfig::default()
+    }
+}
+
+fn small_chunks() -> ChunkConfig {
+    ChunkConfig {
+        chunk_size: 1024,
+        window: 4,
+        ..ChunkConfig::default()
+    }
+}
+
+/// What the receiving side read during a transfer
+struct Received {
+    transfer: Option<CompletedTransfer>,
+    chunks: usize,
+    messages: usize,
+}
+
+/// Exchange frames until the transfer completes or `rounds` window rounds passed
+///
+/// With `interleave`, a small data frame is written after every chunk.
+async fn exchange(
+    sender: &mut ChunkSender,
+    receiver: &mut ChunkReceiver,
+    a: &mut Framed,
+    b: &mut Framed,
+    interleave: bool,
+    rounds: usize,
+) -> Received {
+    let mut received = Received {
+        transfer: None,
+        chunks: 0,
+        messages: 0,
+    };
+
+    for _ in 0..rounds {
+        let mut written = 0;
+        while let Some(frame) = sender.next_frame() {
+            a.write_frame(frame).await.unwrap();
+            written += 1;
+            if interleave {
+                a.write_framed_message(Bytes::from_static(b"heartbeat")).await.unwrap();
+                written += 1;
+            }
+        }
+        assert!(written > 0, "sender stalled");
+
+        for _ in 0..written {
+            let frame = b.read_frame().await.unwrap().unwrap();
+            match frame.frame_type {
+                FrameType::Chunk => {
+                    received.chunks += 1;
+                    if let Some(transfer) = receiver.receive(&frame).unwrap() {
+                        received.transfer = Some(transfer);
+                    }
+                }
+                _ => received.messages += 1,
+            }
+        }
+
+        let mut replies = 0;
+        while let Some(reply) = receiver.next_frame() {
+            b.write_frame(reply).await.unwrap();
+            replies += 1;
+        }
+        for _ in 0..replies {
+            sender.receive(&a.read_frame().await.unwrap().unwrap()).unwrap();
+        }
+
+        if received.transfer.is_some() {
+            assert!(sender.is_complete());
+            break;
+        }
+    }
+    received
+}
+
+#[tokio::test]
+async fn test_payload_beyond_frame_limit_is_streamed() {
+    let (mut a, mut b) = pair();
+    let payload = snapshot(17 * 1024 * 1024 + 5);
+    assert!(matches!(
+        a.write_framed_message(payload.clone()).await,
+        Err(FramingError::MessageTooLarge(_))
+    ));
+
+    let mut sender = ChunkSender::new(payload.clone(), &FramingConfig::default()).unwrap();
+    let mut receiver = ChunkReceiver::new(ChunkConfig::default());
+    assert_eq!(sender.chunk_count(), 18);
+
+    let received = exchange(&mut sender, &mut receiver, &mut a, &mut b, true, 100).await;
+    let transfer = received.transfer.unwrap();
+    assert_eq!(transfer.transfer_id, sender.transfer_id());
+    assert_eq!(transfer.payload, payload);
+    assert_eq!(received.chunks, 18);
+    // Messages written between chunks were delivered while the transfer ran
+    assert_eq!(received.messages, 18);
+    assert!(receiver.pending().is_empty());
+}
+
+#[tokio::test]
+async fn test_transfer_resumes_from_last_acknowledged_chunk() {
+    let payload = snapshot(10 * 1024 + 100);
+    let mut sender = ChunkSender::new(payload.clone(), &framing(small_chunks())).unwrap();
+    let mut receiver = ChunkReceiver::new(small_chunks());
+    assert_eq!(sender.chunk_count(), 11);
+
+    // The connection drops after the receiver read two of four chunks
+    let (mut a, mut b) = pair();
+    for _ in 0..4 {
+        a.write_frame(sender.next_frame().unwrap()).await.unwrap();
+    }
+    assert!(sender.next_frame().is_none(), "window is full");
+    for _ in 0..2 {
+        assert!(receiver.receive(&b.read_frame().await.unwrap().unwrap()).unwrap().is_none());
+    }
+    while let Some(reply) = receiver.next_frame() {
+        b.write_frame(reply).await.unwrap();
+    }
+    sender.receive(&a.read_frame().await.unwrap().unwrap()).unwrap();
+    sender.receive(&a.read_frame().await.unwrap().unwrap()).unwrap();
+    assert_eq!(sender.acknowledged(), 2);
+    drop((a, b));
+
+    // Only the missing chunks are sent on the new connection
+    sender.rewind();
+    let (mut a, mut b) = pair();
+    let received = exchange(&mut sender, &mut receiver, &mut a, &mut b, false, 10).await;
+    assert_eq!(received.transfer.unwrap().payload, payload);
+    assert_eq!(received.chunks, 9);
+}
+
+#[tokio::test]
+async fn test_receiver_corrects_sender_position() {
+    let payload = snapshot(6 * 1024);
+    let (mut a, mut b) = pair();
+
+    // A restarted sender resumes at an acknowledgement the receiver never kept
+    let mut sender = ChunkSender::resume(7, payload.clone(), 3, &framing(small_chunks())).unwrap();
+    let mut receiver = ChunkReceiver::new(small_chunks());
+    let received = exchange(&mut sender, &mut receiver, &mut a, &mut b, false, 10).await;
+    assert_eq!(received.transfer.unwrap().payload, payload);
+
+    // A receiver ahead of the sender moves it forward
+    let mut first = ChunkSender::resume(8, payload.clone(), 0, &framing(small_chunks())).unwrap();
+    let mut receiver = ChunkReceiver::new(small_chunks());
+    for _ in 0..4 {
+        receiver.receive(&first.next_frame().unwrap()).unwrap();
+    }
+    let mut sender = ChunkSender::resume(8, payload.clone(), 1, &framing(small_chunks())).unwrap();
+    receiver.receive(&sender.next_frame().unwrap()).unwrap();
+    while let Some(reply) = receiver.next_frame() {
+        sender.receive(&reply).unwrap();
+    }
+    assert_eq!(sender.acknowledged(), 4);
+    let received = exchange(&mut sender, &mut receiver, &mut a, &mut b, false, 10).await;
+    assert_eq!(received.transfer.unwrap().payload, payload);
+    assert_eq!(received.chunks, 2);
+}
+
+#[test]
+fn test_checksum_mismatch_aborts_transfer() {
+    let mut sender = ChunkSender::resume(42, snapshot(4096), 0, &framing(small_chunks())).unwrap();
+    let mut receiver = ChunkReceiver::new(small_chunks());
+    for _ in 0..2 {
+        receiver.receive(&sender.next_frame().unwrap()).unwrap();
+    }
+
+    // The payload changed between connections
+    let mut changed = ChunkSender::resume(42, snapshot(4097).slice(1..), 2, &framing(small_chunks())).unwrap();
+    let err = receiver.receive(&changed.next_frame().unwrap()).unwrap_err();
+    assert_eq!(err, ChunkError::ChecksumMismatch { transfer_id: 42, index: 2 });
+    assert!(receiver.pending().is_empty());
+
+    let mut abort = None;
+    while let Some(reply) = receiver.next_frame() {
+        abort = Some(reply);
+    }
+    assert_eq!(changed.receive(&abort.unwrap()), Err(ChunkError::Aborted(42)));
+}
+
+#[test]
+fn test_limits_are_enforced() {
+    let limited = ChunkConfig {
+        max_transfer_size: 2048,
+        max_pending_transfers: 1,
+        ..small_chunks()
+    };
+    assert_eq!(
+        ChunkSender::new(snapshot(4096), &framing(limited)).unwrap_err(),
+        ChunkError::TooLarge(4096)
+    );
+    let oversized = ChunkConfig {
+        chunk_size: 16 * 1024 * 1024,
+        ..ChunkConfig::default()
+    };
+    assert!(matches!(
+        ChunkSender::new(snapshot(10), &framing(oversized)),
+        Err(ChunkError::InvalidChunkSize(_))
+    ));
+    // Chunks must fit in the configured frame size, header included
+    let small_frames = FramingConfig {
+        max_data_frame_size: 1024,
+        ..framing(small_chunks())
+    };
+    assert_eq!(
+        ChunkSender::new(snapshot(10), &small_frames).unwrap_err(),
+        ChunkError::InvalidChunkSize(1024)
+    );
+
+    let mut receiver = ChunkReceiver::new(limited);
+    let mut large = ChunkSender::new(snapshot(4096), &framing(small_chunks())).unwrap();
+    assert_eq!(
+        receiver.receive(&large.next_frame().unwrap()),
+        Err(ChunkError::TooLarge(4096))
+    );
+
+    let mut first = ChunkSender::new(snapshot(2048), &framing(small_chunks())).unwrap();
+    let mut second = ChunkSender::new(snapshot(2048), &framing(small_chunks())).unwrap();
+    receiver.receive(&first.next_frame().unwrap()).unwrap();
+    let next = second.next_frame().unwrap();
+    assert_eq!(receiver.receive(&next), Err(ChunkError::TooManyTransfers));
+    receiver.abandon(first.transfer_id());
+    receiver.receive(&next).unwrap();
+    assert_eq!(receiver.pending(), vec![second.transfer_id()]);
+
+    // Empty payloads are one empty chunk
+    let mut empty = ChunkSender::new(Bytes::new(), &framing(small_chunks())).unwrap();
+    let mut receiver = ChunkReceiver::new(small_chunks());
+    let transfer = receiver.receive(&empty.next_frame().unwrap()).unwrap().unwrap();
+    assert!(transfer.payload.is_empty());
+}
+
+fn state_update(len: usize) -> AegisMessage {
+    AegisMessage::new(StateUpdateMessage {
+        state_version: 1,
+        state_data: snapshot(len).to_vec(),
+        is_delta: false,
+    })
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_connections_send_messages_beyond_frame_limit() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("follower").unwrap();
+    let client = CommsClient::new(network.connector());
+    let handle = client
+        .connect_to::<String>(TransportAddr::Memory("follower".to_string()))
+        .await
+        .unwrap();
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut peer = ConnectionHandle::<String>::from_stream_with_options(stream, ConnectionOptions::default());
+
+    let large: String = (0..17 * 1024 * 1024 + 5).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+    handle.send(large.clone()).await.unwrap();
+    handle.send("small".to_string()).await.unwrap();
+
+    let mut received = Vec::new();
+    for _ in 0..2 {
+        let message = tokio::time::timeout(Duration::from_secs(10), peer.receive()).await.unwrap();
+        received.push(message.unwrap().unwrap());
+    }
+    assert!(received.contains(&large));
+    assert!(received.contains(&"small".to_string()));
+    assert_eq!(handle.lost_messages(), 0);
+}
+
+#[tokio::test]
+async fn test_message_sender_chunks_large_messages() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("follower").unwrap();
+    let pool = PoolConfig {
+        chunking: small_chunks(),
+        ..PoolConfig::default()
+    };
+    let client = CommsClient::with_config(Arc::new(network.connector()), PeerRegistry::new(), pool);
+    client.peers().insert("follower", Endpoint::memory("follower"));
+    let message = state_update(10 * 1024);
+    client.send_message("follower", &message).await.unwrap();
+
+    // The sender waits for acknowledgements after its window of chunks
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut framed = FramedMessageStream::with_config(stream, FramingConfig::default());
+    let mut receiver = ChunkReceiver::new(small_chunks());
+    let transfer = loop {
+        let frame = framed.read_frame().await.unwrap().unwrap();
+        assert_eq!(frame.frame_type, FrameType::Chunk);
+        let transfer = receiver.receive(&frame).unwrap();
+        while let Some(reply) = receiver.next_frame() {
+            framed.write_frame(reply).await.unwrap();
+        }
+        if let Some(transfer) = transfer {
+            break transfer;
+        }
+    };
+    let received: AegisMessage = CodecId::Bincode.decode(&transfer.payload).unwrap();
+    assert_eq!(received, message);
+}
+
+#[tokio::test]
+async fn test_server_reassembles_chunked_messages() {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("follower").unwrap();
+    let server = MessageServer::new(ServerConfig {
+        chunking: small_chunks(),
+        ..ServerConfig::default()
+    });
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        server
+            .serve(listener, move |message: AegisMessage, _peer| {
+                let tx = tx.clone();
+                async move {
+                    let _ = tx.send(message);
+                }
+            })
+            .await
+    });
+
+    let stream = network.connector().connect_endpoint(&Endpoint::memory("follower")).await.unwrap();
+    let mut framed = FramedMessageStream::with_config(stream, FramingConfig::default());
+    let message = state_update(10 * 1024);
+    let payload = Bytes::from(CodecId::Bincode.encode(&message).unwrap());
+    let mut sender = ChunkSender::new(payload, &framing(small_chunks())).unwrap();
+
+    // Credit granted by the server is read past while waiting for acknowledgements
+    while !sender.is_complete() {
+        while let Some(frame) = sender.next_frame() {
+            framed.write_frame(frame).await.unwrap();
+        }
+        sender.receive(&framed.read_frame().await.unwrap().unwrap()).unwrap();
+    }
+    let received = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap();
+    assert_eq!(received, Some(message));
+}
+
+/// Read past credit grants to the next chunk frame and pass it to `sender`
+async fn receive_ack(sender: &mut ChunkSender, framed: &mut FramedMessageStream<Box<dyn MessageStream>>) {
+    loop {
+        let frame = framed.read_frame().await.unwrap().unwrap();
+        if frame.frame_type == FrameType::Chunk {
+            sender.receive(&frame).unwrap();
+            return;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_server_resumes_transfers_after_reconnect() {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("follower").unwrap();
+    let server = MessageServer::new(ServerConfig {
+        chunking: small_chunks(),
+        ..ServerConfig::default()
+    });
+    let handle = server.handle();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        server
+            .serve(listener, move |message: AegisMessage, _peer| {
+                let tx = tx.clone();
+                async move {
+                    let _ = tx.send(message);
+                }
+            })
+            .await
+    });
+
+    let connector = network.connector_as(PeerIdentity {
+        subject: "leader".to_string(),
+        dns_names: Vec::new(),
+        uris: Vec::new(),
+        spiffe_id: None,
+        fingerprint: String::new(),
+    });
+    let message = state_update(10 * 1024);
+    let payload = Bytes::from(CodecId::Bincode.encode(&message).unwrap());
+    let mut sender = ChunkSender::new(payload, &framing(small_chunks())).unwrap();
+
+    // The connection closes after the first window of chunks was acknowledged
+    let stream = connector.connect_endpoint(&Endpoint::memory("follower")).await.unwrap();
+    let mut framed = FramedMessageStream::with_config(stream, FramingConfig::default());
+    while let Some(frame) = sender.next_frame() {
+        framed.write_frame(frame).await.unwrap();
+    }
+    while sender.acknowledged() < 4 {
+        receive_ack(&mut sender, &mut framed).await;
+    }
+    drop(framed);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while handle.active_connections() > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    // The server continues from the acknowledged chunks instead of chunk 0
+    sender.rewind();
+    let stream = connector.connect_endpoint(&Endpoint::memory("follower")).await.unwrap();
+    let mut framed = FramedMessageStream::with_config(stream, FramingConfig::default());
+    framed.write_frame(sender.next_frame().unwrap()).await.unwrap();
+    receive_ack(&mut sender, &mut framed).await;
+    assert_eq!(sender.acknowledged(), 5);
+
+    while !sender.is_complete() {
+        while let Some(frame) = sender.next_frame() {
+            framed.write_frame(frame).await.unwrap();
+        }
+        receive_ack(&mut sender, &mut framed).await;
+    }
+    let received = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap();
+    assert_eq!(received, Some(message));
+}
+
+#[cfg(feature = "rpc")]
+#[tokio::test]
+async fn test_rpc_chunks_large_requests_and_responses() {
+    use aegis_comms::{RpcClient, RpcContext, RpcRequest, RpcServer};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Export {
+        data: Vec<u8>,
+    }
+
+    impl RpcRequest for Export {
+        const METHOD: &'static str = "export";
+    }
+
+    let mut rpc = RpcServer::new();
+    rpc.register(|req: Export, _ctx: RpcContext| async move { Ok(req.data) });
+    let network = MemoryNetwork::new();
+    let listener = network.bind("rpc").unwrap();
+    let server = MessageServer::new(ServerConfig {
+        chunking: small_chunks(),
+        ..ServerConfig::default()
+    });
+    tokio::spawn(async move { server.serve_rpc(listener, rpc).await });
+
+    let pool = PoolConfig {
+        chunking: small_chunks(),
+        ..PoolConfig::default()
+    };
+    let client = RpcClient::with_config(Arc::new(network.connector()), pool);
+    let data = snapshot(10 * 1024).to_vec();
+    let response: Vec<u8> = client
+        .call_timeout(&Endpoint::memory("rpc"), &Export { data: data.clone() }, Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(response, data);
+}
+
+#[cfg(feature = "rpc")]
+#[tokio::test]
+async fn test_failed_rpc_transfers_fail_only_their_call() {
+    use aegis_comms::{RpcClient, RpcContext, RpcError, RpcRequest, RpcServer};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Import {
+        data: Vec<u8>,
+        delay_ms: u64,
+    }
+
+    impl RpcRequest for Import {
+        const METHOD: &'static str = "import";
+    }
+
+    let mut rpc = RpcServer::new();
+    rpc.register(|req: Import, _ctx: RpcContext| async move {
+        tokio::time::sleep(Duration::from_millis(req.delay_ms)).await;
+        Ok(req.data.len())
+    });
+    let network = MemoryNetwork::new();
+    let listener = network.bind("rpc").unwrap();
+    let server = MessageServer::new(ServerConfig {
+        chunking: ChunkConfig {
+            max_transfer_size: 8 * 1024,
+            ..small_chunks()
+        },
+        ..ServerConfig::default()
+    });
+    tokio::spawn(async move { server.serve_rpc(listener, rpc).await });
+
+    let pool = PoolConfig {
+        chunking: ChunkConfig {
+            max_transfer_size: 16 * 1024,
+            ..small_chunks()
+        },
+        ..PoolConfig::default()
+    };
+    let client = Arc::new(RpcClient::with_config(Arc::new(network.connector()), pool));
+    let endpoint = Endpoint::memory("rpc");
+    let call = |len: usize, delay_ms: u64| {
+        let client = client.clone();
+        let endpoint = endpoint.clone();
+        async move {
+            let request = Import {
+                data: vec![7; len],
+                delay_ms,
+            };
+            client.call_timeout::<_, usize>(&endpoint, &request, Duration::from_secs(5)).await
+        }
+    };
+    let slow = tokio::spawn(call(16, 300));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Too large to send at all, then refused by the server midway
+    assert!(matches!(call(32 * 1024, 0).await, Err(RpcError::Codec(_))));
+    assert!(matches!(call(12 * 1024, 0).await, Err(RpcError::Codec(_))));
+
+    // The connection and the call running on it are unaffected
+    assert_eq!(slow.await.unwrap().unwrap(), 16);
+    assert_eq!(call(4 * 1024, 0).await.unwrap(), 4 * 1024);
+}
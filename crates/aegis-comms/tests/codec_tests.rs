@@ -0,0 +1,192 @@
+#![cfg(feature = "memory")]
+
+use aegis_comms::{
+    memory_duplex, AegisMessage, BincodeCodec, Codec, CodecId, CommsClient, ConnectionHandle, Endpoint, Frame,
+    FrameType, FramedMessageStream, FramingConfig, FramingError, JsonCodec, LinkConfig, MemoryNetwork,
+    MessageListener, MessageStream, StateUpdateMessage, TransportAddr, FRAME_HEADER_SIZE, FRAME_MAGIC,
+};
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct LogEntry {
+    level: String,
+    message: String,
+    fields: Vec<(String, u64)>,
+}
+
+fn entry() -> LogEntry {
+    LogEntry {
+        level: "warn".to_string(),
+        message: "policy evaluated".to_string(),
+        fields: vec![("rules".to_string(), 12)],
+    }
+}
+
+fn framing(codecs: &[CodecId]) -> FramingConfig {
+    FramingConfig {
+        codecs: codecs.to_vec(),
+        ..FramingConfig::default()
+    }
+}
+
+fn pair(
+    a: FramingConfig,
+    b: FramingConfig,
+) -> (FramedMessageStream<impl MessageStream>, FramedMessageStream<impl MessageStream>) {
+    let (a_stream, b_stream) = memory_duplex(
+        TransportAddr::Memory("a".to_string()),
+        TransportAddr::Memory("b".to_string()),
+        LinkConfig::default(),
+    );
+    (
+        FramedMessageStream::with_config(a_stream, a),
+        FramedMessageStream::with_config(b_stream, b),
+    )
+}
+
+#[test]
+fn test_codecs_round_trip() {
+    for codec in CodecId::supported() {
+        let bytes = codec.encode(&entry()).unwrap();
+        assert_eq!(codec.decode::<LogEntry>(&bytes).unwrap(), entry(), "{:?}", codec);
+        assert_eq!(codec.id(), codec);
+    }
+
+    // The codec types encode the same way as their identifiers
+    assert_eq!(BincodeCodec.encode(&entry()).unwrap(), CodecId::Bincode.encode(&entry()).unwrap());
+    assert_eq!(
+        JsonCodec.decode::<serde_json::Value>(&CodecId::Json.encode(&entry()).unwrap()).unwrap()["level"],
+        "warn"
+    );
+    assert!(JsonCodec.decode::<LogEntry>(b"{").is_err());
+}
+
+#[cfg(all(feature = "cbor", feature = "msgpack"))]
+#[test]
+fn test_cbor_and_messagepack_are_standard() {
+    use aegis_comms::{CborCodec, MessagePackCodec};
+
+    // Structs are maps keyed by field name, as other languages expect
+    let cbor: serde_json::Value = CborCodec.decode(&CborCodec.encode(&entry()).unwrap()).unwrap();
+    assert_eq!(cbor["message"], "policy evaluated");
+    let msgpack = MessagePackCodec.encode(&entry()).unwrap();
+    assert_eq!(msgpack[0], 0x83, "fixmap with three entries");
+    let decoded: serde_json::Value = MessagePackCodec.decode(&msgpack).unwrap();
+    assert_eq!(decoded["level"], "warn");
+}
+
+#[tokio::test]
+async fn test_codec_is_negotiated_from_advertised_sets() {
+    let (mut a, mut b) = pair(framing(&[CodecId::Bincode, CodecId::Json]), framing(&[CodecId::Json]));
+
+    // Nothing advertised yet, so bincode is assumed
+    assert_eq!(a.codec(), CodecId::Bincode);
+    a.write_framed_message(Bytes::from_static(b"hello")).await.unwrap();
+    b.read_frame().await.unwrap();
+    b.write_framed_message(Bytes::from_static(b"hello")).await.unwrap();
+    a.read_frame().await.unwrap();
+
+    // A prefers bincode but B only decodes JSON
+    assert_eq!(a.codec(), CodecId::Json);
+    assert_eq!(b.codec(), CodecId::Json);
+
+    let payload = CodecId::Json.encode(&entry()).unwrap();
+    a.write_frame(Frame::data(Bytes::from(payload)).with_codec(a.codec())).await.unwrap();
+    let frame = b.read_frame().await.unwrap().unwrap();
+    assert_eq!(frame.codec, CodecId::Json);
+    assert_eq!(frame.codec.decode::<LogEntry>(&frame.payload).unwrap(), entry());
+
+    // Between two default peers the first preference wins
+    let (mut a, mut b) = pair(FramingConfig::default(), FramingConfig::default());
+    b.write_framed_message(Bytes::from_static(b"hello")).await.unwrap();
+    a.read_frame().await.unwrap();
+    assert_eq!(a.codec(), CodecId::Bincode);
+}
+
+#[tokio::test]
+async fn test_unknown_codec_is_rejected() {
+    let (a_stream, b_stream) = memory_duplex(
+        TransportAddr::Memory("a".to_string()),
+        TransportAddr::Memory("b".to_string()),
+        LinkConfig::default(),
+    );
+    let mut raw = a_stream;
+    let mut b = FramedMessageStream::with_config(b_stream, FramingConfig::default());
+
+    let mut buf = BytesMut::new();
+    buf.put_u16(FRAME_MAGIC);
+    buf.put_u8(2);
+    buf.put_u8(0);
+    buf.put_u8(FrameType::Data as u8);
+    buf.put_bytes(0, 2);
+    buf.put_u8(0x0f);
+    buf.put_u32(2);
+    let checksum = crc32c::crc32c_append(crc32c::crc32c(&buf[..12]), b"hi");
+    buf.put_u32(checksum);
+    buf.extend_from_slice(b"hi");
+    assert_eq!(buf.len(), FRAME_HEADER_SIZE + 2);
+
+    raw.write_message(buf.freeze()).await.unwrap();
+    assert!(matches!(b.read_frame().await, Err(FramingError::UnknownCodec(0x0f))));
+}
+
+#[tokio::test]
+async fn test_connection_handle_speaks_peer_codec() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("python").unwrap();
+    let client = CommsClient::new(network.connector());
+    let mut handle: ConnectionHandle<LogEntry> = client
+        .connect_to(TransportAddr::Memory("python".to_string()))
+        .await
+        .unwrap();
+
+    // A peer that only speaks JSON
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut peer = FramedMessageStream::with_config(stream, framing(&[CodecId::Json]));
+    let json = serde_json::to_vec(&entry()).unwrap();
+    peer.write_frame(Frame::data(Bytes::from(json)).with_codec(CodecId::Json))
+        .await
+        .unwrap();
+    assert_eq!(handle.receive().await.unwrap(), Some(entry()));
+
+    handle.send(entry()).await.unwrap();
+    let frame = loop {
+        let frame = peer.read_frame().await.unwrap().unwrap();
+        if frame.frame_type == FrameType::Data {
+            break frame;
+        }
+    };
+    assert_eq!(frame.codec, CodecId::Json);
+    assert_eq!(serde_json::from_slice::<LogEntry>(&frame.payload).unwrap(), entry());
+}
+
+#[tokio::test]
+async fn test_send_message_uses_negotiated_codec() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("reviezer").unwrap();
+    let client = CommsClient::new(network.connector());
+    client.peers().insert("reviezer", Endpoint::memory("reviezer"));
+
+    let message = AegisMessage::new(StateUpdateMessage {
+        state_version: 2,
+        state_data: b"policy".to_vec(),
+        is_delta: false,
+    })
+    .unwrap();
+    client.send_message("reviezer", &message).await.unwrap();
+
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut peer = FramedMessageStream::with_config(stream, framing(&[CodecId::Json]));
+    let first = peer.read_frame().await.unwrap().unwrap();
+    assert_eq!(first.codec, CodecId::Bincode);
+    assert_eq!(first.codec.decode::<AegisMessage>(&first.payload).unwrap(), message);
+
+    // Once the peer has advertised JSON, messages follow
+    peer.write_framed_message(Bytes::from_static(b"hello")).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    client.send_message("reviezer", &message).await.unwrap();
+    let second = peer.read_frame().await.unwrap().unwrap();
+    assert_eq!(second.codec, CodecId::Json);
+    assert_eq!(serde_json::from_slice::<AegisMessage>(&second.payload).unwrap(), message);
+}
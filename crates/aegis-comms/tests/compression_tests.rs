@@ -0,0 +1,220 @@
+#![cfg(all(feature = "compression", feature = "memory"))]
+
+use aegis_comms::{
+    memory_duplex, Compression, CompressionConfig, FrameFlags, FramedMessageStream, FramingConfig, FramingError,
+    LinkConfig, MemoryStream, MessageStream, NetworkError, TransportAddr, FRAME_HEADER_SIZE, FRAME_MAGIC,
+};
+use async_trait::async_trait;
+use bytes::{BufMut, Bytes, BytesMut};
+use std::sync::{Arc, Mutex};
+
+/// Stream that records every message written through it
+struct Tap {
+    inner: MemoryStream,
+    written: Arc<Mutex<Vec<Bytes>>>,
+}
+
+#[async_trait]
+impl MessageStream for Tap {
+    async fn read_message(&mut self) -> Result<Option<Bytes>, NetworkError> {
+        self.inner.read_message().await
+    }
+
+    async fn write_message(&mut self, msg: Bytes) -> Result<(), NetworkError> {
+        self.written.lock().unwrap().push(msg.clone());
+        self.inner.write_message(msg).await
+    }
+
+    fn peer_addr(&self) -> Result<TransportAddr, NetworkError> {
+        self.inner.peer_addr()
+    }
+
+    async fn shutdown(&mut self) -> Result<(), NetworkError> {
+        self.inner.shutdown().await
+    }
+}
+
+fn framing(compression: CompressionConfig) -> FramingConfig {
+    FramingConfig {
+        compression,
+        ..FramingConfig::default()
+    }
+}
+
+fn algorithms(algorithms: &[Compression]) -> CompressionConfig {
+    CompressionConfig {
+        algorithms: algorithms.to_vec(),
+        ..CompressionConfig::default()
+    }
+}
+
+/// Tapped stream on one side and a plain raw stream on the other
+fn tapped_pair() -> (Tap, Arc<Mutex<Vec<Bytes>>>, MemoryStream) {
+    let (a, b) = memory_duplex(
+        TransportAddr::Memory("a".to_string()),
+        TransportAddr::Memory("b".to_string()),
+        LinkConfig::default(),
+    );
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let tap = Tap {
+        inner: a,
+        written: written.clone(),
+    };
+    (tap, written, b)
+}
+
+/// A batch of log lines that compresses well
+fn log_batch(lines: usize) -> Bytes {
+    let mut batch = String::new();
+    for i in 0..lines {
+        batch.push_str(&format!(
+            "{{\"level\":\"info\",\"agent\":\"camplit\",\"message\":\"policy evaluated\",\"seq\":{}}}\n",
+            i
+        ));
+    }
+    Bytes::from(batch)
+}
+
+/// Compressed data frame with the given algorithm byte
+fn compressed_frame(algorithm: u8, payload: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    buf.put_u16(FRAME_MAGIC);
+    buf.put_u8(2);
+    buf.put_u8(FrameFlags::COMPRESSED.bits());
+    buf.put_u8(0);
+    buf.put_u8(algorithm);
+    buf.put_bytes(0, 2);
+    buf.put_u32(payload.len() as u32);
+    let checksum = crc32c::crc32c_append(crc32c::crc32c(&buf[..12]), payload);
+    buf.put_u32(checksum);
+    buf.extend_from_slice(payload);
+    buf.freeze()
+}
+
+/// Whether a written frame went out compressed, and with which algorithm
+fn compression_of(frame: &Bytes) -> Option<Compression> {
+    if frame[3] & FrameFlags::COMPRESSED.bits() == 0 {
+        return None;
+    }
+    Compression::from_u8(frame[5])
+}
+
+#[tokio::test]
+async fn test_frames_are_compressed_after_handshake() {
+    let (tap, written, b) = tapped_pair();
+    let mut a = FramedMessageStream::with_config(tap, FramingConfig::default());
+    let mut b = FramedMessageStream::with_config(b, FramingConfig::default());
+    let batch = log_batch(100);
+
+    // Nothing is known about the peer before its first frame
+    assert_eq!(a.compression(), None);
+    a.write_framed_message(batch.clone()).await.unwrap();
+    assert_eq!(compression_of(&written.lock().unwrap()[0]), None);
+    assert_eq!(b.read_framed_message().await.unwrap(), Some(batch.clone()));
+    assert_eq!(b.compression(), Some(Compression::Zstd));
+
+    b.write_framed_message(Bytes::from_static(b"ack")).await.unwrap();
+    a.read_framed_message().await.unwrap();
+    assert_eq!(a.compression(), Some(Compression::Zstd));
+
+    a.write_framed_message(batch.clone()).await.unwrap();
+    let frame = written.lock().unwrap()[1].clone();
+    assert_eq!(compression_of(&frame), Some(Compression::Zstd));
+    assert!(frame.len() < batch.len() / 4);
+    // The receiver sees the original payload without the flag
+    let received = b.read_frame().await.unwrap().unwrap();
+    assert_eq!(received.payload, batch);
+    assert!(!received.flags.contains(FrameFlags::COMPRESSED));
+
+    // Small frames stay uncompressed
+    a.write_framed_message(log_batch(2)).await.unwrap();
+    assert_eq!(compression_of(&written.lock().unwrap()[2]), None);
+    assert_eq!(b.read_framed_message().await.unwrap(), Some(log_batch(2)));
+
+    // So do frames that would not shrink
+    let noise: Vec<u8> = (0..4096).map(|_| rand::random()).collect();
+    a.write_framed_message(Bytes::from(noise.clone())).await.unwrap();
+    assert_eq!(compression_of(&written.lock().unwrap()[3]), None);
+    assert_eq!(b.read_framed_message().await.unwrap(), Some(Bytes::from(noise)));
+}
+
+#[tokio::test]
+async fn test_algorithm_follows_preference_and_peer_support() {
+    let (tap, written, b) = tapped_pair();
+    let mut a = FramedMessageStream::with_config(tap, framing(algorithms(&[Compression::Lz4, Compression::Zstd])));
+    let mut b = FramedMessageStream::with_config(b, FramingConfig::default());
+
+    a.write_framed_message(Bytes::from_static(b"hello")).await.unwrap();
+    b.read_framed_message().await.unwrap();
+    b.write_framed_message(Bytes::from_static(b"hello")).await.unwrap();
+    a.read_framed_message().await.unwrap();
+
+    // Each side picks its own preference among what the other accepts
+    assert_eq!(a.compression(), Some(Compression::Lz4));
+    assert_eq!(b.compression(), Some(Compression::Zstd));
+
+    let batch = log_batch(100);
+    a.write_framed_message(batch.clone()).await.unwrap();
+    assert_eq!(compression_of(&written.lock().unwrap()[1]), Some(Compression::Lz4));
+    assert_eq!(b.read_framed_message().await.unwrap(), Some(batch.clone()));
+    b.write_framed_message(batch.clone()).await.unwrap();
+    assert_eq!(a.read_framed_message().await.unwrap(), Some(batch));
+}
+
+#[tokio::test]
+async fn test_peer_without_compression_gets_plain_frames() {
+    let (tap, written, b) = tapped_pair();
+    let mut a = FramedMessageStream::with_config(tap, FramingConfig::default());
+    let mut b = FramedMessageStream::with_config(b, framing(CompressionConfig::disabled()));
+
+    b.write_framed_message(Bytes::from_static(b"hello")).await.unwrap();
+    a.read_framed_message().await.unwrap();
+    assert_eq!(a.compression(), None);
+
+    let batch = log_batch(100);
+    a.write_framed_message(batch.clone()).await.unwrap();
+    assert_eq!(compression_of(&written.lock().unwrap()[0]), None);
+    assert_eq!(b.read_framed_message().await.unwrap(), Some(batch));
+
+    // A frame compressed anyway is refused
+    let (mut raw, _, b) = tapped_pair();
+    let mut b = FramedMessageStream::with_config(b, framing(algorithms(&[Compression::Zstd])));
+    let payload = lz4_flex::block::compress_prepend_size(&log_batch(10));
+    raw.write_message(compressed_frame(Compression::Lz4 as u8, &payload)).await.unwrap();
+    assert!(matches!(b.read_frame().await, Err(FramingError::UnknownCompression(2))));
+}
+
+#[tokio::test]
+async fn test_decompression_bombs_are_rejected() {
+    let zeros = vec![0u8; 8 * 1024 * 1024];
+
+    let (mut raw, _, b) = tapped_pair();
+    let mut b = FramedMessageStream::with_config(b, FramingConfig::default());
+    let bomb = zstd::bulk::compress(&zeros, 3).unwrap();
+    raw.write_message(compressed_frame(Compression::Zstd as u8, &bomb)).await.unwrap();
+    assert!(matches!(
+        b.read_frame().await,
+        Err(FramingError::DecompressionLimitExceeded(_))
+    ));
+
+    // An lz4 frame lying about its size
+    let (mut raw, _, b) = tapped_pair();
+    let mut b = FramedMessageStream::with_config(b, FramingConfig::default());
+    let mut bomb = lz4_flex::block::compress_prepend_size(b"small");
+    bomb[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+    raw.write_message(compressed_frame(Compression::Lz4 as u8, &bomb)).await.unwrap();
+    assert!(matches!(
+        b.read_frame().await,
+        Err(FramingError::DecompressionLimitExceeded(_))
+    ));
+
+    // The sender does not produce frames the peer would reject
+    let (tap, written, b) = tapped_pair();
+    let mut a = FramedMessageStream::with_config(tap, FramingConfig::default());
+    let mut b = FramedMessageStream::with_config(b, FramingConfig::default());
+    b.write_framed_message(Bytes::from_static(b"hello")).await.unwrap();
+    a.read_framed_message().await.unwrap();
+    a.write_framed_message(Bytes::from(vec![0u8; 1024 * 1024])).await.unwrap();
+    assert_eq!(compression_of(&written.lock().unwrap()[0]), None);
+    assert_eq!(b.read_framed_message().await.unwrap().unwrap().len(), 1024 * 1024);
+}
@@ -0,0 +1,106 @@
+#![cfg(feature = "discovery")]
+
+use aegis_comms::{AgentDiscoveryMessage, DiscoveryConfig, DiscoveryService, Endpoint, PeerRegistry, PeerSource};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+fn announcement(agent_id: &str, capabilities: &[&str]) -> AgentDiscoveryMessage {
+    AgentDiscoveryMessage {
+        agent_id: agent_id.to_string(),
+        capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+        listen_addr: Endpoint::tcp("127.0.0.1", 7000),
+    }
+}
+
+/// Seed-only discovery on a loopback port chosen by the OS
+fn config(seeds: Vec<String>) -> DiscoveryConfig {
+    DiscoveryConfig {
+        group: None,
+        bind: SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+        interval: Duration::from_millis(50),
+        seeds,
+        ..DiscoveryConfig::default()
+    }
+}
+
+async fn wait_for(registry: &PeerRegistry, agent_id: &str) {
+    for _ in 0..100 {
+        if registry.get(agent_id).is_some() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("{} was not discovered", agent_id);
+}
+
+#[test]
+fn test_registry_capabilities_and_expiry() {
+    let registry = PeerRegistry::new();
+    registry.insert("static", Endpoint::memory("static"));
+    registry.record_discovery(&announcement("reviezer", &["audit", "logs"]));
+    registry.record_discovery(&announcement("camplit", &["policy", "logs"]));
+
+    assert_eq!(registry.with_capability("logs"), vec!["camplit".to_string(), "reviezer".to_string()]);
+    assert_eq!(registry.with_capability("audit"), vec!["reviezer".to_string()]);
+    assert!(registry.with_capability("billing").is_empty());
+
+    assert!(registry.expire_discovered(Duration::from_secs(60)).is_empty());
+    assert_eq!(
+        registry.expire_discovered(Duration::ZERO),
+        vec!["camplit".to_string(), "reviezer".to_string()]
+    );
+    // Static entries never expire
+    assert_eq!(registry.get("static").unwrap().source, PeerSource::Static);
+}
+
+#[test]
+fn test_config_from_core_settings() {
+    let mut settings = aegis_core::config::DiscoveryConfig {
+        multicast_group: Some("239.1.2.3:9000".to_string()),
+        seeds: vec!["seed.local:9000".to_string()],
+        ..Default::default()
+    };
+    let config = DiscoveryConfig::from_config(&settings).unwrap();
+    assert_eq!(config.group.unwrap().port(), 9000);
+    assert_eq!(config.bind.port(), 9000);
+    assert_eq!(config.ttl, Duration::from_secs(settings.ttl_secs));
+    assert_eq!(config.seeds, settings.seeds);
+
+    settings.multicast_group = Some("10.0.0.1:9000".to_string());
+    assert!(DiscoveryConfig::from_config(&settings).is_err());
+}
+
+#[tokio::test]
+async fn test_agents_discover_each_other_through_seed() {
+    let seed_peers = PeerRegistry::new();
+    let seed = DiscoveryService::bind(announcement("seed", &["policy"]), config(Vec::new()), seed_peers.clone())
+        .await
+        .unwrap();
+    let seed_addr = seed.local_addr().unwrap();
+    let seed_task = seed.spawn();
+
+    let peers = PeerRegistry::new();
+    let newcomer = DiscoveryService::bind(
+        announcement("newcomer", &["logs"]),
+        config(vec![seed_addr.to_string()]),
+        peers.clone(),
+    )
+    .await
+    .unwrap();
+    let newcomer_task = newcomer.spawn();
+
+    // The seed learns of the newcomer from its announcement and answers it
+    wait_for(&seed_peers, "newcomer").await;
+    wait_for(&peers, "seed").await;
+
+    let entry = peers.get("seed").unwrap();
+    assert_eq!(entry.source, PeerSource::Discovered);
+    assert_eq!(entry.endpoint, Endpoint::tcp("127.0.0.1", 7000));
+    assert_eq!(peers.with_capability("policy"), vec!["seed".to_string()]);
+    assert_eq!(seed_peers.with_capability("logs"), vec!["newcomer".to_string()]);
+    // Agents do not record themselves
+    assert!(peers.get("newcomer").is_none());
+
+    seed_task.abort();
+    newcomer_task.abort();
+}
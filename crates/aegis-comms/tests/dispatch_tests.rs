@@ -0,0 +1,136 @@
+#![cfg(feature = "memory")]
+
+use aegis_comms::{
+    AegisMessage, AgentHeartbeatMessage, AgentStatus, CommsError, ExtensionMessage, FramedMessageStream,
+    FramingConfig, MemoryNetwork, MessageContext, MessageDispatcher, MessageListener, MessageType,
+    NetworkConnector, PeerInfo, StateUpdateMessage, TransportAddr,
+};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Ping {
+    seq: u32,
+}
+
+impl ExtensionMessage for Ping {
+    const MESSAGE_TYPE: &'static str = "test.ping";
+}
+
+/// Everything the test handlers saw
+#[derive(Debug)]
+enum Seen {
+    Heartbeat(String),
+    Ping(u32, Box<MessageContext>),
+}
+
+fn dispatcher(tx: mpsc::UnboundedSender<Seen>) -> MessageDispatcher {
+    let heartbeats = tx.clone();
+    MessageDispatcher::new()
+        .with_handler(move |heartbeat: AgentHeartbeatMessage, _ctx: MessageContext| {
+            let tx = heartbeats.clone();
+            async move {
+                let _ = tx.send(Seen::Heartbeat(heartbeat.agent_id));
+            }
+        })
+        .with_handler(move |ping: Ping, ctx: MessageContext| {
+            let tx = tx.clone();
+            async move {
+                let _ = tx.send(Seen::Ping(ping.seq, Box::new(ctx)));
+            }
+        })
+}
+
+fn peer() -> PeerInfo {
+    PeerInfo {
+        addr: TransportAddr::Memory("peer".to_string()),
+        identity: None,
+        credentials: None,
+    }
+}
+
+fn heartbeat(agent_id: &str) -> AegisMessage {
+    AegisMessage::new(AgentHeartbeatMessage {
+        agent_id: agent_id.to_string(),
+        timestamp: 1,
+        status: AgentStatus::Active,
+    })
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_dispatch_routes_by_message_type() {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let dispatcher = dispatcher(tx);
+    assert!(dispatcher.handles(&MessageType::AgentHeartbeat));
+    assert!(dispatcher.handles(&MessageType::Extension("test.ping".to_string())));
+    assert_eq!(dispatcher.message_types().len(), 2);
+
+    dispatcher.dispatch(heartbeat("agent1"), peer()).await.unwrap();
+    assert!(matches!(rx.recv().await, Some(Seen::Heartbeat(id)) if id == "agent1"));
+
+    dispatcher
+        .dispatch(AegisMessage::new(Ping { seq: 7 }).unwrap(), peer())
+        .await
+        .unwrap();
+    match rx.recv().await {
+        Some(Seen::Ping(7, ctx)) => assert_eq!(ctx.peer.addr, peer().addr),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // Types without a handler are reported
+    let update = AegisMessage::new(StateUpdateMessage {
+        state_version: 1,
+        state_data: Vec::new(),
+        is_delta: false,
+    })
+    .unwrap();
+    assert!(matches!(
+        dispatcher.dispatch(update, peer()).await,
+        Err(CommsError::UnhandledMessageType(MessageType::StateUpdate))
+    ));
+}
+
+#[tokio::test]
+async fn test_dispatch_mixed_messages_on_one_connection() {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let dispatcher = dispatcher(tx);
+
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("dispatch").unwrap();
+    let client = network
+        .connector()
+        .connect(TransportAddr::Memory("dispatch".to_string()))
+        .await
+        .unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    tokio::spawn(async move { dispatcher.serve_connection(server).await });
+
+    let mut framed = FramedMessageStream::with_config(client, FramingConfig::default());
+    let source = aegis_comms::Endpoint::memory("client");
+    let messages = vec![
+        heartbeat("agent1"),
+        AegisMessage::new(Ping { seq: 1 }).unwrap().with_source(source.clone()),
+        heartbeat("agent2"),
+    ];
+    for message in &messages {
+        let bytes = Bytes::from(bincode::serialize(message).unwrap());
+        framed.write_framed_message(bytes).await.unwrap();
+    }
+
+    // Undecodable messages are skipped without closing the connection
+    framed.write_framed_message(Bytes::from_static(b"garbage")).await.unwrap();
+    let bytes = Bytes::from(bincode::serialize(&heartbeat("agent3")).unwrap());
+    framed.write_framed_message(bytes).await.unwrap();
+
+    let mut seen = Vec::new();
+    for _ in 0..4 {
+        seen.push(tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap());
+    }
+    assert!(matches!(&seen[0], Seen::Heartbeat(id) if id == "agent1"));
+    assert!(matches!(&seen[1], Seen::Ping(1, ctx) if ctx.header.source == Some(source.clone())));
+    assert!(matches!(&seen[2], Seen::Heartbeat(id) if id == "agent2"));
+    assert!(matches!(&seen[3], Seen::Heartbeat(id) if id == "agent3"));
+}
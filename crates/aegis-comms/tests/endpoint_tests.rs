@@ -0,0 +1,127 @@
+use aegis_comms::{ConnectorRegistry, Endpoint, NetworkConnector, NetworkError, TransportAddr};
+use aegis_core::error::{AegisError, AegisResult};
+use aegis_core::platform::{Network, TcpListener, TcpStream};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+// Network resolving a fixed set of host names
+struct StaticResolver;
+
+#[async_trait]
+impl Network for StaticResolver {
+    async fn connect_tcp(&self, _host: &str, _port: u16) -> AegisResult<Box<dyn TcpStream>> {
+        Err(AegisError::Platform("connect_tcp".to_string()))
+    }
+
+    async fn listen_tcp(&self, _host: &str, _port: u16) -> AegisResult<Box<dyn TcpListener>> {
+        Err(AegisError::Platform("listen_tcp".to_string()))
+    }
+
+    async fn resolve_host(&self, host: &str) -> AegisResult<Vec<String>> {
+        match host {
+            "policy.aegis.test" => Ok(vec!["10.0.0.7".to_string(), "fd00::7".to_string()]),
+            _ => Err(AegisError::NotFound(host.to_string())),
+        }
+    }
+}
+
+#[test]
+fn test_endpoint_uri_round_trip() {
+    let cases = [
+        ("tcp://10.0.0.1:7000", Endpoint::tcp("10.0.0.1", 7000)),
+        ("tls://policy.aegis.test:7443", Endpoint::tls("policy.aegis.test", 7443)),
+        ("tcp://[::1]:7000", Endpoint::tcp("::1", 7000)),
+        ("unix:///run/aegis/agent.sock", Endpoint::unix("/run/aegis/agent.sock")),
+        ("mem://camplit", Endpoint::memory("camplit")),
+    ];
+
+    for (uri, endpoint) in cases {
+        assert_eq!(uri.parse::<Endpoint>().unwrap(), endpoint);
+        assert_eq!(endpoint.to_string(), uri);
+    }
+
+    // Shorthand forms
+    assert_eq!("localhost:80".parse::<Endpoint>().unwrap(), Endpoint::tcp("localhost", 80));
+    assert_eq!("unix:/tmp/a.sock".parse::<Endpoint>().unwrap(), Endpoint::unix("/tmp/a.sock"));
+    assert_eq!("mem:agent".parse::<Endpoint>().unwrap(), Endpoint::memory("agent"));
+
+    for bad in ["http://host:80", "tcp://host", "tcp://:80", "tcp://host:99999", "unix://", "mem://", "::1:80"] {
+        assert!(bad.parse::<Endpoint>().is_err(), "{} should not parse", bad);
+    }
+}
+
+#[test]
+fn test_endpoint_serializes_as_uri() {
+    let endpoint = Endpoint::tls("policy.aegis.test", 7443);
+    assert_eq!(serde_json::to_string(&endpoint).unwrap(), "\"tls://policy.aegis.test:7443\"");
+
+    let bytes = bincode::serialize(&endpoint).unwrap();
+    assert_eq!(bincode::deserialize::<Endpoint>(&bytes).unwrap(), endpoint);
+    assert!(serde_json::from_str::<Endpoint>("\"bogus://x\"").is_err());
+}
+
+#[tokio::test]
+async fn test_endpoint_resolution() {
+    let endpoint = Endpoint::tcp("policy.aegis.test", 7000);
+    assert_eq!(endpoint.to_transport_addr(), None);
+    assert_eq!(
+        endpoint.resolve(&StaticResolver).await.unwrap(),
+        vec![
+            TransportAddr::Tcp("10.0.0.7:7000".parse().unwrap()),
+            TransportAddr::Tcp("[fd00::7]:7000".parse().unwrap()),
+        ]
+    );
+
+    assert!(matches!(
+        Endpoint::tcp("unknown.aegis.test", 1).resolve(&StaticResolver).await,
+        Err(NetworkError::Resolve(_))
+    ));
+
+    // Literal addresses need no lookup
+    assert_eq!(
+        Endpoint::unix("/tmp/a.sock").resolve(&StaticResolver).await.unwrap(),
+        vec![TransportAddr::unix("/tmp/a.sock")]
+    );
+}
+
+#[tokio::test]
+async fn test_registry_requires_connector_and_resolver() {
+    let registry = ConnectorRegistry::new();
+    assert!(matches!(
+        registry.connect_endpoint(&Endpoint::memory("agent")).await,
+        Err(NetworkError::UnsupportedScheme(_))
+    ));
+
+    let registry = ConnectorRegistry::standard();
+    assert!(matches!(
+        registry.resolve(&Endpoint::tcp("policy.aegis.test", 7000)).await,
+        Err(NetworkError::Resolve(_))
+    ));
+
+    let registry = registry.with_network(Arc::new(StaticResolver));
+    assert_eq!(registry.resolve(&Endpoint::tcp("policy.aegis.test", 7000)).await.unwrap().len(), 2);
+}
+
+#[cfg(feature = "memory")]
+#[tokio::test]
+async fn test_registry_dispatches_by_scheme() {
+    use aegis_comms::{MemoryNetwork, MessageListener};
+    use bytes::Bytes;
+
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("camplit").unwrap();
+    let registry = ConnectorRegistry::new().with_connector("mem", network.connector());
+    assert_eq!(registry.schemes(), vec!["mem".to_string()]);
+
+    let mut client = registry.connect_endpoint(&"mem://camplit".parse().unwrap()).await.unwrap();
+    let (mut server, _) = listener.accept().await.unwrap();
+    client.write_message(Bytes::from_static(b"hello")).await.unwrap();
+    assert_eq!(server.read_message().await.unwrap().unwrap(), Bytes::from_static(b"hello"));
+
+    // Plain transport addresses are dispatched by kind
+    assert!(registry.connect(TransportAddr::Memory("camplit".to_string())).await.is_ok());
+    assert!(matches!(
+        registry.connect(TransportAddr::unix("/tmp/none.sock")).await,
+        Err(NetworkError::UnsupportedScheme(_))
+    ));
+}
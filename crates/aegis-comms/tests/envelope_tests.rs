@@ -0,0 +1,245 @@
+use aegis_comms::{
+    AegisMessage, CommsError, EnvelopeSigner, EnvelopeVerifier, MessageContext, MessageDispatcher, PeerInfo,
+    ReplayConfig, SecurityViolation, SignedEnvelope, StateUpdateMessage, TransportAddr,
+};
+use aegis_core::clock::TestClock;
+use aegis_core::error::AegisError;
+use aegis_core::identity::NodeIdentity;
+use aegis_core::keystore::KeyStore;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+fn node(dir: &std::path::Path, name: &str) -> Arc<NodeIdentity> {
+    let keystore = KeyStore::open(dir.join(name)).unwrap();
+    Arc::new(NodeIdentity::load_or_create(&keystore, name).unwrap())
+}
+
+fn update(version: u64) -> AegisMessage {
+    AegisMessage::new(StateUpdateMessage {
+        state_version: version,
+        state_data: b"policy".to_vec(),
+        is_delta: false,
+    })
+    .unwrap()
+}
+
+fn peer() -> PeerInfo {
+    PeerInfo {
+        addr: TransportAddr::Memory("relay".to_string()),
+        identity: None,
+        credentials: None,
+    }
+}
+
+fn violation(result: Result<AegisMessage, CommsError>) -> SecurityViolation {
+    match result {
+        Err(CommsError::Security(violation)) => violation,
+        other => panic!("expected a security error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_tampered_envelopes_are_rejected_and_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let signer = EnvelopeSigner::new(node(dir.path(), "camplit"));
+    let verifier = EnvelopeVerifier::new(ReplayConfig::default());
+    let mut events = verifier.subscribe();
+
+    let envelope = signer.seal(update(1)).unwrap();
+    assert_eq!(envelope.sender, signer.node_id());
+    assert_eq!(verifier.open(envelope.clone()).unwrap(), update(1));
+
+    // The signature covers the header and the body
+    let mut tampered = signer.seal(update(2)).unwrap();
+    tampered.message.header.destination = Some(aegis_comms::Endpoint::memory("elsewhere"));
+    assert_eq!(violation(verifier.open(tampered)), SecurityViolation::InvalidSignature);
+    let mut tampered = signer.seal(update(2)).unwrap();
+    tampered.message = update(3);
+    assert_eq!(violation(verifier.open(tampered)), SecurityViolation::InvalidSignature);
+
+    let event = events.recv().await.unwrap();
+    assert_eq!(event.sender.as_deref(), Some(signer.node_id()));
+    assert_eq!(event.violation, SecurityViolation::InvalidSignature);
+
+    // Re-signing with another key does not make the envelope pass as the original sender
+    let other = node(dir.path(), "reviezer");
+    let mut forged = EnvelopeSigner::new(other).seal(update(4)).unwrap();
+    forged.sender = signer.node_id().to_string();
+    assert_eq!(violation(verifier.open(forged)), SecurityViolation::InvalidSignature);
+}
+
+#[test]
+fn test_replays_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let signer = EnvelopeSigner::new(node(dir.path(), "camplit"));
+    let config = ReplayConfig {
+        reorder_window: 2,
+        ..ReplayConfig::default()
+    };
+    let verifier = EnvelopeVerifier::new(config);
+
+    let envelopes: Vec<SignedEnvelope> = (0..5).map(|i| signer.seal(update(i)).unwrap()).collect();
+    assert!(envelopes.windows(2).all(|pair| pair[0].sequence < pair[1].sequence));
+
+    verifier.open(envelopes[0].clone()).unwrap();
+    assert_eq!(
+        violation(verifier.open(envelopes[0].clone())),
+        SecurityViolation::Replayed {
+            sequence: envelopes[0].sequence
+        }
+    );
+
+    // Envelopes relayed along different paths may arrive out of order
+    verifier.open(envelopes[3].clone()).unwrap();
+    verifier.open(envelopes[2].clone()).unwrap();
+    verifier.open(envelopes[1].clone()).unwrap();
+    assert!(verifier.open(envelopes[2].clone()).is_err());
+
+    // Too far behind the sequence to tell whether it was seen
+    verifier.open(envelopes[4].clone()).unwrap();
+    let mut late = envelopes[0].clone();
+    late.nonce = late.nonce.wrapping_add(1);
+    assert!(verifier.open(late).is_err());
+}
+
+#[test]
+fn test_timestamps_outside_window_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = Arc::new(TestClock::from_millis(1_700_000_000_000));
+    let signer = EnvelopeSigner::with_clock(node(dir.path(), "camplit"), clock.clone());
+    let verifier = EnvelopeVerifier::with_clock(ReplayConfig::default(), clock.clone());
+
+    let stale = signer.seal(update(1)).unwrap();
+    clock.advance(Duration::from_secs(31));
+    assert_eq!(
+        violation(verifier.open(stale.clone())),
+        SecurityViolation::OutsideWindow {
+            timestamp: stale.timestamp
+        }
+    );
+
+    // A replay of the rejected envelope is still rejected, and fresh ones pass
+    assert!(verifier.open(stale).is_err());
+    verifier.open(signer.seal(update(2)).unwrap()).unwrap();
+}
+
+#[test]
+fn test_remembered_senders_are_capped() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = Arc::new(TestClock::from_millis(1_700_000_000_000));
+    let signer = |name: &str| EnvelopeSigner::with_clock(node(dir.path(), name), clock.clone());
+    let (expired, quiet, busy, late) = (signer("expired"), signer("quiet"), signer("busy"), signer("late"));
+    let config = ReplayConfig {
+        max_senders: 2,
+        ..ReplayConfig::default()
+    };
+    let verifier = EnvelopeVerifier::with_clock(config, clock.clone());
+
+    verifier.open(expired.seal(update(1)).unwrap()).unwrap();
+    clock.advance(Duration::from_secs(31));
+    let quiet_envelope = quiet.seal(update(1)).unwrap();
+    verifier.open(quiet_envelope.clone()).unwrap();
+
+    // A sender outside the acceptance window makes room without weakening replay checks
+    clock.advance(Duration::from_secs(1));
+    let busy_envelope = busy.seal(update(1)).unwrap();
+    verifier.open(busy_envelope.clone()).unwrap();
+    assert!(matches!(
+        violation(verifier.open(quiet_envelope.clone())),
+        SecurityViolation::Replayed { .. }
+    ));
+
+    // Otherwise the least recently seen sender is forgotten
+    clock.advance(Duration::from_secs(1));
+    verifier.open(late.seal(update(1)).unwrap()).unwrap();
+    assert!(matches!(
+        violation(verifier.open(busy_envelope)),
+        SecurityViolation::Replayed { .. }
+    ));
+    verifier.open(quiet_envelope).unwrap();
+}
+
+#[test]
+fn test_only_trusted_senders_are_accepted() {
+    let dir = tempfile::tempdir().unwrap();
+    let camplit = EnvelopeSigner::new(node(dir.path(), "camplit"));
+    let reviezer = EnvelopeSigner::new(node(dir.path(), "reviezer"));
+    let verifier = EnvelopeVerifier::new(ReplayConfig::default()).with_trusted_senders([camplit.node_id()]);
+
+    verifier.open(camplit.seal(update(1)).unwrap()).unwrap();
+    assert_eq!(
+        violation(verifier.open(reviezer.seal(update(1)).unwrap())),
+        SecurityViolation::UntrustedSender
+    );
+
+    let err = verifier.open(reviezer.seal(update(2)).unwrap()).unwrap_err();
+    assert!(matches!(AegisError::from(err), AegisError::Security(_)));
+}
+
+#[tokio::test]
+async fn test_dispatcher_requires_signed_messages() {
+    let dir = tempfile::tempdir().unwrap();
+    let signer = EnvelopeSigner::new(node(dir.path(), "camplit"));
+    let verifier = EnvelopeVerifier::new(ReplayConfig::default());
+    let mut events = verifier.subscribe();
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let dispatcher = MessageDispatcher::new()
+        .with_verifier(verifier)
+        .with_handler(move |update: StateUpdateMessage, ctx: MessageContext| {
+            let tx = tx.clone();
+            async move {
+                let _ = tx.send((update.state_version, ctx.sender));
+            }
+        });
+
+    let signed = AegisMessage::new(signer.seal(update(7)).unwrap()).unwrap();
+    dispatcher.dispatch(signed.clone(), peer()).await.unwrap();
+    assert_eq!(rx.recv().await.unwrap(), (7, Some(signer.node_id().to_string())));
+
+    let err = dispatcher.dispatch(update(8), peer()).await.unwrap_err();
+    assert!(matches!(err, CommsError::Security(SecurityViolation::Unsigned)));
+    let event = events.recv().await.unwrap();
+    assert_eq!(event.sender, None);
+    assert_eq!(event.violation, SecurityViolation::Unsigned);
+
+    // Relaying the same signed message again is a replay
+    assert!(dispatcher.dispatch(signed, peer()).await.is_err());
+    assert!(matches!(
+        events.recv().await.unwrap().violation,
+        SecurityViolation::Replayed { .. }
+    ));
+    assert!(rx.try_recv().is_err());
+}
+
+#[cfg(feature = "memory")]
+#[tokio::test]
+async fn test_client_signs_sent_messages() {
+    use aegis_comms::{CommsClient, Endpoint, MemoryNetwork, MessageServer, ServerConfig};
+
+    let dir = tempfile::tempdir().unwrap();
+    let signer = EnvelopeSigner::new(node(dir.path(), "camplit"));
+    let node_id = signer.node_id().to_string();
+
+    let network = MemoryNetwork::new();
+    let listener = network.bind("reviezer").unwrap();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let dispatcher = MessageDispatcher::new()
+        .with_verifier(EnvelopeVerifier::new(ReplayConfig::default()).with_trusted_senders([node_id.clone()]))
+        .with_handler(move |update: StateUpdateMessage, ctx: MessageContext| {
+            let tx = tx.clone();
+            async move {
+                let _ = tx.send((update.state_version, ctx.sender));
+            }
+        });
+    let server = MessageServer::new(ServerConfig::default());
+    tokio::spawn(async move { server.serve_dispatcher(listener, dispatcher).await });
+
+    let client = CommsClient::new(network.connector()).with_signer(signer);
+    client.peers().insert("reviezer", Endpoint::memory("reviezer"));
+    client.send_message("reviezer", &update(3)).await.unwrap();
+
+    let received = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await.unwrap();
+    assert_eq!(received, Some((3, Some(node_id))));
+}
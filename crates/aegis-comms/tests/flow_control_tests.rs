@@ -0,0 +1,213 @@
+#![cfg(feature = "memory")]
+
+use aegis_comms::{
+    Backpressure, CommsClient, CommsError, ConnectionHandle, ConnectionOptions, FrameType, FrameVersion,
+    FramedMessageStream, FramingConfig, MemoryNetwork, MessageListener, MessageStream, NetworkConnector, Priority,
+    TransportAddr,
+};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize, Serializer};
+use std::time::Duration;
+
+/// Wait until `condition` holds
+async fn eventually(mut condition: impl FnMut() -> bool) {
+    for _ in 0..200 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    panic!("condition not reached");
+}
+
+/// Client handle and the accepted handle of its peer, which grants `window` credits
+async fn connect(
+    client_options: ConnectionOptions,
+    window: usize,
+) -> (ConnectionHandle<u32>, ConnectionHandle<u32>) {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("peer").unwrap();
+    let client = CommsClient::new(network.connector()).with_connection_options(client_options);
+
+    let handle = client
+        .connect_to::<u32>(TransportAddr::Memory("peer".to_string()))
+        .await
+        .unwrap();
+    let (stream, _) = listener.accept().await.unwrap();
+    let peer_options = ConnectionOptions {
+        receive_window: window,
+        ..ConnectionOptions::default()
+    };
+    (handle, ConnectionHandle::from_stream_with_options(stream, peer_options))
+}
+
+#[tokio::test]
+async fn test_sender_stops_at_receive_window() {
+    let options = ConnectionOptions {
+        buffer_size: 4,
+        backpressure: Backpressure::FailFast,
+        ..ConnectionOptions::default()
+    };
+    let (handle, mut peer) = connect(options, 8).await;
+    assert_eq!(handle.send_credit(), None);
+
+    // The peer grants its window once it read a v2 frame, covering what was sent before
+    handle.send(0).await.unwrap();
+    eventually(|| handle.send_credit() == Some(7)).await;
+
+    // The peer is not receiving, so after its window the buffer fills
+    let mut sent = 1;
+    loop {
+        match handle.send(sent).await {
+            Ok(()) => sent += 1,
+            Err(CommsError::BufferFull) => break,
+            Err(e) => panic!("unexpected error {}", e),
+        }
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+    assert_eq!(sent, 12);
+    assert_eq!(handle.send_credit(), Some(0));
+
+    // Receiving grants credit back, and everything arrives in order
+    for expected in 0..sent {
+        assert_eq!(peer.receive().await.unwrap(), Some(expected));
+    }
+    eventually(|| handle.send_credit() == Some(8)).await;
+    handle.send(100).await.unwrap();
+    assert_eq!(peer.receive().await.unwrap(), Some(100));
+}
+
+#[tokio::test]
+async fn test_low_priority_messages_are_shed() {
+    let options = ConnectionOptions {
+        buffer_size: 2,
+        backpressure: Backpressure::ShedLowPriority,
+        ..ConnectionOptions::default()
+    };
+    let (handle, mut peer) = connect(options, 2).await;
+    handle.send(0).await.unwrap();
+    eventually(|| handle.send_credit() == Some(1)).await;
+
+    for msg in 1..4 {
+        handle.send(msg).await.unwrap();
+    }
+    eventually(|| handle.send_credit() == Some(0)).await;
+
+    handle.send_with_priority(99, Priority::Low).await.unwrap();
+    assert_eq!(handle.shed_messages(), 1);
+    // Normal messages wait for room instead
+    assert!(tokio::time::timeout(Duration::from_millis(50), handle.send(4)).await.is_err());
+
+    for expected in 0..4 {
+        assert_eq!(peer.receive().await.unwrap(), Some(expected));
+    }
+}
+
+#[tokio::test]
+async fn test_malformed_message_is_reported() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("legacy").unwrap();
+    let client = CommsClient::new(network.connector());
+    let mut handle = client
+        .connect_to::<String>(TransportAddr::Memory("legacy".to_string()))
+        .await
+        .unwrap();
+
+    // A peer that writes raw frames and never grants credit
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut peer = FramedMessageStream::with_config(stream, FramingConfig::default());
+    peer.write_framed_message(Bytes::from_static(&[0xff; 4])).await.unwrap();
+    peer.write_framed_message(Bytes::from(bincode::serialize("valid").unwrap()))
+        .await
+        .unwrap();
+
+    assert!(matches!(handle.receive().await, Err(CommsError::InvalidMessage(_))));
+    assert_eq!(handle.receive().await.unwrap(), Some("valid".to_string()));
+
+    // Writes to a peer without flow control are not limited
+    assert_eq!(handle.send_credit(), None);
+    for i in 0..100 {
+        handle.send(i.to_string()).await.unwrap();
+    }
+    let mut received = 0;
+    while received < 100 {
+        if peer.read_frame().await.unwrap().unwrap().frame_type == FrameType::Data {
+            received += 1;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_handle_configured_for_v1_reaches_legacy_listener() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("legacy").unwrap();
+    let options = ConnectionOptions {
+        frame_version: FrameVersion::V1,
+        ..ConnectionOptions::default()
+    };
+    let client = CommsClient::new(network.connector()).with_connection_options(options);
+    let handle = client
+        .connect_to::<String>(TransportAddr::Memory("legacy".to_string()))
+        .await
+        .unwrap();
+    handle.send("hello".to_string()).await.unwrap();
+
+    // A v1 listener reads a bare length prefix and never writes first
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let payload = bincode::serialize("hello").unwrap();
+    let prefix = stream.read_message().await.unwrap().unwrap();
+    assert_eq!(&prefix[..], &(payload.len() as u32).to_be_bytes());
+    assert_eq!(&stream.read_message().await.unwrap().unwrap()[..], &payload[..]);
+    assert_eq!(handle.send_credit(), None);
+}
+
+#[tokio::test]
+async fn test_v1_peer_is_not_sent_credit() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("peer").unwrap();
+    let mut legacy = network
+        .connector()
+        .connect(TransportAddr::Memory("peer".to_string()))
+        .await
+        .unwrap();
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut handle = ConnectionHandle::<String>::from_stream(stream);
+
+    // A v1 client writes a bare length prefix and payload
+    let payload = bincode::serialize("hello").unwrap();
+    legacy.write_message(Bytes::copy_from_slice(&(payload.len() as u32).to_be_bytes())).await.unwrap();
+    legacy.write_message(Bytes::from(payload)).await.unwrap();
+    assert_eq!(handle.receive().await.unwrap(), Some("hello".to_string()));
+
+    // Nothing was written back, not even a credit grant
+    assert!(tokio::time::timeout(Duration::from_millis(50), legacy.read_message()).await.is_err());
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Reading {
+    Value(u32),
+    #[serde(serialize_with = "refuse")]
+    Broken(u32),
+}
+
+fn refuse<S: Serializer>(_: &u32, _: S) -> Result<S::Ok, S::Error> {
+    Err(serde::ser::Error::custom("cannot be encoded"))
+}
+
+#[tokio::test]
+async fn test_unencodable_message_is_counted_lost() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("peer").unwrap();
+    let client = CommsClient::new(network.connector());
+    let handle = client
+        .connect_to::<Reading>(TransportAddr::Memory("peer".to_string()))
+        .await
+        .unwrap();
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut peer = ConnectionHandle::<Reading>::from_stream(stream);
+
+    handle.send(Reading::Broken(1)).await.unwrap();
+    handle.send(Reading::Value(2)).await.unwrap();
+    assert_eq!(peer.receive().await.unwrap(), Some(Reading::Value(2)));
+    assert_eq!(handle.lost_messages(), 1);
+}
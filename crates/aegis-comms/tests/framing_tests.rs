@@ -1,72 +1,44 @@
-use aegis_comms::{FramedMessageStream, MessageStream, NetworkError};
-use async_trait::async_trait;
-use bytes::{Bytes, BytesMut};
-use std::net::SocketAddr;
-use tokio::sync::mpsc;
+#![cfg(feature = "memory")]
 
-// Mock stream for testing
-struct MockStream {
-    read_tx: mpsc::UnboundedSender<Bytes>,
-    read_rx: mpsc::UnboundedReceiver<Bytes>,
-    write_tx: mpsc::UnboundedSender<Bytes>,
-    write_rx: mpsc::UnboundedReceiver<Bytes>,
-}
+use aegis_comms::{
+    memory_duplex, Frame, FrameFlags, FrameType, FrameVersion, FramedMessageStream, FramingConfig, FramingError,
+    LinkConfig, MemoryStream, MessageStream, TransportAddr, FRAME_HEADER_SIZE, FRAME_MAGIC,
+};
+use bytes::{Bytes, BytesMut};
 
-impl MockStream {
-    fn new() -> (Self, mpsc::UnboundedSender<Bytes>, mpsc::UnboundedReceiver<Bytes>) {
-        let (read_tx, read_rx) = mpsc::unbounded_channel();
-        let (write_tx, write_rx) = mpsc::unbounded_channel();
-        let stream = MockStream {
-            read_tx: read_tx.clone(),
-            read_rx,
-            write_tx,
-            write_rx,
-        };
-        (stream, read_tx, write_rx)
-    }
+/// A framed stream and the raw far end of its in-memory connection
+fn framed_pair(config: FramingConfig) -> (FramedMessageStream<MemoryStream>, MemoryStream) {
+    let (local, remote) = memory_duplex(
+        TransportAddr::Memory("local".to_string()),
+        TransportAddr::Memory("remote".to_string()),
+        LinkConfig::unlimited(),
+    );
+    (FramedMessageStream::with_config(local, config), remote)
 }
 
-#[async_trait]
-impl MessageStream for MockStream {
-    async fn read_message(&mut self) -> Result<Option<Bytes>, NetworkError> {
-        match self.read_rx.recv().await {
-            Some(bytes) => Ok(Some(bytes)),
-            None => Ok(None),
-        }
-    }
-    
-    async fn write_message(&mut self, msg: Bytes) -> Result<(), NetworkError> {
-        self.write_tx.send(msg).map_err(|_| NetworkError::ConnectionClosed)
-    }
-    
-    fn peer_addr(&self) -> Result<SocketAddr, NetworkError> {
-        Ok("127.0.0.1:8080".parse().unwrap())
-    }
-    
-    async fn shutdown(&mut self) -> Result<(), NetworkError> {
-        Ok(())
-    }
+/// Next packet written to the far end
+async fn next_packet(peer: &mut MemoryStream) -> Bytes {
+    peer.read_message().await.unwrap().unwrap()
 }
 
 #[tokio::test]
 async fn test_framing_single_message() {
-    let (mock_stream, read_tx, mut write_rx) = MockStream::new();
-    let mut framed = FramedMessageStream::new(mock_stream);
+    let (mut framed, mut peer) = framed_pair(FramingConfig::v1());
     
     // Write a message
     let message = Bytes::from_static(b"Hello, World!");
     framed.write_framed_message(message.clone()).await.unwrap();
     
     // Verify the written message format
-    let written = write_rx.recv().await.unwrap();
+    let written = next_packet(&mut peer).await;
     assert_eq!(&written[..4], &(message.len() as u32).to_be_bytes());
     
-    let written_body = write_rx.recv().await.unwrap();
+    let written_body = next_packet(&mut peer).await;
     assert_eq!(written_body, message);
     
     // Read the message back
-    read_tx.send(written).unwrap();
-    read_tx.send(written_body).unwrap();
+    peer.write_message(written).await.unwrap();
+    peer.write_message(written_body).await.unwrap();
     
     let read_message = framed.read_framed_message().await.unwrap().unwrap();
     assert_eq!(read_message, message);
@@ -74,19 +46,18 @@ async fn test_framing_single_message() {
 
 #[tokio::test]
 async fn test_framing_empty_message() {
-    let (mock_stream, read_tx, mut write_rx) = MockStream::new();
-    let mut framed = FramedMessageStream::new(mock_stream);
+    let (mut framed, mut peer) = framed_pair(FramingConfig::v1());
     
     // Write an empty message
     let message = Bytes::new();
     framed.write_framed_message(message).await.unwrap();
     
     // Verify the written message format
-    let written = write_rx.recv().await.unwrap();
+    let written = next_packet(&mut peer).await;
     assert_eq!(&written[..4], &[0, 0, 0, 0]); // Length prefix of 0
     
     // Read the message back
-    read_tx.send(written).unwrap();
+    peer.write_message(written).await.unwrap();
     
     let read_message = framed.read_framed_message().await.unwrap().unwrap();
     assert!(read_message.is_empty());
@@ -94,8 +65,7 @@ async fn test_framing_empty_message() {
 
 #[tokio::test]
 async fn test_framing_multiple_messages() {
-    let (mock_stream, read_tx, mut write_rx) = MockStream::new();
-    let mut framed = FramedMessageStream::new(mock_stream);
+    let (mut framed, mut peer) = framed_pair(FramingConfig::v1());
     
     let messages = vec![
         Bytes::from_static(b"First"),
@@ -111,7 +81,7 @@ async fn test_framing_multiple_messages() {
     // Collect written frames
     let mut written_frames = Vec::new();
     for _ in 0..messages.len() * 2 {
-        written_frames.push(write_rx.recv().await.unwrap());
+        written_frames.push(next_packet(&mut peer).await);
     }
     
     // Send them back in one batch
@@ -119,7 +89,7 @@ async fn test_framing_multiple_messages() {
     for frame in written_frames {
         combined.extend_from_slice(&frame);
     }
-    read_tx.send(combined.freeze()).unwrap();
+    peer.write_message(combined.freeze()).await.unwrap();
     
     // Read them back
     for expected in messages {
@@ -130,11 +100,10 @@ async fn test_framing_multiple_messages() {
 
 #[tokio::test]
 async fn test_framing_partial_message() {
-    let (mock_stream, read_tx, _write_rx) = MockStream::new();
-    let mut framed = FramedMessageStream::new(mock_stream);
+    let (mut framed, mut peer) = framed_pair(FramingConfig::v1());
     
     // Send just the length prefix
-    read_tx.send(Bytes::from_static(&[0, 0, 0, 4])).unwrap();
+    peer.write_message(Bytes::from_static(&[0, 0, 0, 4])).await.unwrap();
     
     // No complete message yet
     tokio::select! {
@@ -147,9 +116,99 @@ async fn test_framing_partial_message() {
     }
     
     // Send the message body
-    read_tx.send(Bytes::from_static(b"test")).unwrap();
+    peer.write_message(Bytes::from_static(b"test")).await.unwrap();
     
     // Now we should get the complete message
     let read = framed.read_framed_message().await.unwrap().unwrap();
     assert_eq!(read, Bytes::from_static(b"test"));
-} 
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_framing_v2_header_layout() {
+    let (mut framed, mut peer) = framed_pair(FramingConfig::default());
+    
+    let frame = Frame::control(Bytes::from_static(b"ping"))
+        .with_flags(FrameFlags::COMPRESSED | FrameFlags::END_OF_STREAM);
+    framed.write_frame(frame.clone()).await.unwrap();
+    
+    // Header and payload are written together
+    let written = next_packet(&mut peer).await;
+    assert_eq!(written.len(), FRAME_HEADER_SIZE + 4);
+    assert_eq!(&written[0..2], &FRAME_MAGIC.to_be_bytes());
+    assert_eq!(written[2], 2);
+    assert_eq!(written[3], 0x05);
+    assert_eq!(written[4], FrameType::Control as u8);
+    assert_eq!(&written[8..12], &4u32.to_be_bytes());
+    
+    peer.write_message(written).await.unwrap();
+    let read = framed.read_frame().await.unwrap().unwrap();
+    assert_eq!(read, frame);
+    assert!(read.flags.contains(FrameFlags::END_OF_STREAM));
+    assert!(!read.flags.contains(FrameFlags::ENCRYPTED));
+    assert_eq!(framed.peer_version(), Some(FrameVersion::V2));
+}
+
+#[tokio::test]
+async fn test_framing_v2_checksum_mismatch() {
+    let (mut framed, mut peer) = framed_pair(FramingConfig::default());
+    
+    framed.write_framed_message(Bytes::from_static(b"payload")).await.unwrap();
+    
+    let mut corrupted = BytesMut::from(&next_packet(&mut peer).await[..]);
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 0xff;
+    peer.write_message(corrupted.freeze()).await.unwrap();
+    
+    assert!(matches!(
+        framed.read_frame().await,
+        Err(FramingError::ChecksumMismatch { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_framing_v2_per_type_limits() {
+    let config = FramingConfig {
+        max_control_frame_size: 8,
+        ..FramingConfig::default()
+    };
+    let (mut framed, _peer) = framed_pair(config);
+    
+    let large = Bytes::from(vec![0u8; 16]);
+    assert!(matches!(
+        framed.write_frame(Frame::control(large.clone())).await,
+        Err(FramingError::MessageTooLarge(16))
+    ));
+    assert!(framed.write_frame(Frame::data(large)).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_framing_v2_detects_v1_peer() {
+    let (mut framed, mut peer) = framed_pair(FramingConfig::default());
+    assert_eq!(framed.write_version(), FrameVersion::V2);
+    
+    // A v1 peer sends a bare length-prefixed message
+    peer.write_message(Bytes::from_static(&[0, 0, 0, 2, b'h', b'i'])).await.unwrap();
+    let read = framed.read_frame().await.unwrap().unwrap();
+    assert_eq!(read, Frame::data(Bytes::from_static(b"hi")));
+    assert_eq!(framed.peer_version(), Some(FrameVersion::V1));
+    
+    // Replies fall back to v1
+    framed.write_framed_message(Bytes::from_static(b"ok")).await.unwrap();
+    assert_eq!(&next_packet(&mut peer).await[..], &[0, 0, 0, 2]);
+    assert_eq!(&next_packet(&mut peer).await[..], b"ok");
+}
+
+#[tokio::test]
+async fn test_framing_v1_rejected_when_disabled() {
+    let config = FramingConfig {
+        accept_v1: false,
+        ..FramingConfig::default()
+    };
+    let (mut framed, mut peer) = framed_pair(config);
+    
+    peer.write_message(Bytes::from_static(&[0, 0, 0, 2, b'h', b'i'])).await.unwrap();
+    assert!(matches!(
+        framed.read_frame().await,
+        Err(FramingError::LegacyFrameRejected)
+    ));
+}
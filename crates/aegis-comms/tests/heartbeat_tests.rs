@@ -0,0 +1,158 @@
+#![cfg(feature = "memory")]
+
+use aegis_comms::{
+    AgentHeartbeatMessage, AgentStatus, CommsClient, DetectorConfig, Endpoint, HealthEvent, HeartbeatMonitor,
+    HeartbeatService, MemoryNetwork, MessageDispatcher, MessageServer, PhiAccrualDetector, ServerConfig,
+};
+use aegis_core::clock::TestClock;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn heartbeat(agent_id: &str, status: AgentStatus) -> AgentHeartbeatMessage {
+    AgentHeartbeatMessage {
+        agent_id: agent_id.to_string(),
+        timestamp: 0,
+        status,
+    }
+}
+
+/// Monitor on a test clock that has seen ten heartbeats a second apart from `agent_id`
+fn monitor_with_history(agent_id: &str, status: AgentStatus) -> (HeartbeatMonitor, TestClock) {
+    let clock = TestClock::default();
+    let monitor = HeartbeatMonitor::with_clock(DetectorConfig::default(), Arc::new(clock.clone()));
+
+    for _ in 0..10 {
+        monitor.record(&heartbeat(agent_id, status));
+        clock.advance(Duration::from_secs(1));
+    }
+    (monitor, clock)
+}
+
+#[test]
+fn test_phi_grows_with_silence() {
+    let mut detector = PhiAccrualDetector::new(DetectorConfig::default());
+    assert_eq!(detector.phi(Duration::ZERO), 0.0);
+
+    let mut now = Duration::ZERO;
+    for _ in 0..20 {
+        detector.heartbeat(now);
+        now += Duration::from_millis(500);
+    }
+    let last = now - Duration::from_millis(500);
+
+    let on_time = detector.phi(last + Duration::from_millis(500));
+    let late = detector.phi(last + Duration::from_millis(1000));
+    let very_late = detector.phi(last + Duration::from_millis(3000));
+
+    assert!(on_time < 1.0, "phi {} on time", on_time);
+    assert!(late > on_time);
+    assert!(very_late > late);
+    assert!(detector.is_available(last + Duration::from_millis(500)));
+    assert!(!detector.is_available(last + Duration::from_millis(3000)));
+}
+
+#[test]
+fn test_acceptable_pause_delays_suspicion() {
+    let config = DetectorConfig {
+        acceptable_pause: Duration::from_secs(2),
+        ..DetectorConfig::default()
+    };
+    let mut strict = PhiAccrualDetector::new(DetectorConfig::default());
+    let mut lenient = PhiAccrualDetector::new(config);
+
+    for i in 0..10 {
+        strict.heartbeat(Duration::from_secs(i));
+        lenient.heartbeat(Duration::from_secs(i));
+    }
+
+    let now = Duration::from_secs(12);
+    assert!(!strict.is_available(now));
+    assert!(lenient.is_available(now));
+}
+
+#[test]
+fn test_monitor_reports_silent_peer_once_and_recovery() {
+    let (monitor, clock) = monitor_with_history("camplit", AgentStatus::Busy);
+    let mut events = monitor.subscribe();
+    assert!(monitor.check().is_empty());
+    assert_eq!(monitor.status("camplit"), Some(AgentStatus::Busy));
+
+    clock.advance(Duration::from_secs(10));
+    let reported = monitor.check();
+    match reported.as_slice() {
+        [HealthEvent::Suspected(failure)] => {
+            assert_eq!(failure.agent_id, "camplit");
+            assert_eq!(failure.last_status, AgentStatus::Busy);
+            assert_eq!(failure.silence, Duration::from_secs(11));
+            assert!(failure.phi >= 8.0);
+            assert_eq!(failure.detected_at, "1970-01-01T00:00:20+00:00");
+        }
+        other => panic!("unexpected events {:?}", other),
+    }
+    assert!(monitor.is_suspected("camplit"));
+
+    // Not reported again while still silent
+    clock.advance(Duration::from_secs(10));
+    assert!(monitor.check().is_empty());
+
+    monitor.record(&heartbeat("camplit", AgentStatus::Active));
+    assert!(!monitor.is_suspected("camplit"));
+
+    let seen = futures::executor::block_on(async {
+        let first = events.recv().await.unwrap();
+        let second = events.recv().await.unwrap();
+        (first, second)
+    });
+    assert!(matches!(seen.0, HealthEvent::Suspected(_)));
+    assert_eq!(
+        seen.1,
+        HealthEvent::Recovered {
+            agent_id: "camplit".to_string()
+        }
+    );
+}
+
+#[test]
+fn test_monitor_forgets_peer_that_shut_down() {
+    let (monitor, clock) = monitor_with_history("reviezer", AgentStatus::ShuttingDown);
+
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(
+        monitor.check(),
+        vec![HealthEvent::Departed {
+            agent_id: "reviezer".to_string()
+        }]
+    );
+    assert!(monitor.peers().is_empty());
+}
+
+#[tokio::test]
+async fn test_service_sends_heartbeats_to_peers() {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("monitor").unwrap();
+
+    let monitor = HeartbeatMonitor::new(DetectorConfig::default());
+    let mut dispatcher = MessageDispatcher::new();
+    monitor.register(&mut dispatcher);
+
+    let server = MessageServer::new(ServerConfig::default());
+    tokio::spawn(async move { server.serve_dispatcher(listener, dispatcher).await });
+
+    let client = CommsClient::new(network.connector());
+    client.peers().insert("monitor", Endpoint::memory("monitor"));
+    client.peers().insert("sender", Endpoint::memory("sender"));
+
+    let service = HeartbeatService::new("sender", Duration::from_millis(10));
+    service.set_status(AgentStatus::Draining);
+    // Only the other peer is sent a heartbeat
+    assert_eq!(service.broadcast(&client).await, 1);
+
+    for _ in 0..100 {
+        if monitor.status("sender").is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(monitor.status("sender"), Some(AgentStatus::Draining));
+    assert_eq!(monitor.peers(), vec!["sender".to_string()]);
+}
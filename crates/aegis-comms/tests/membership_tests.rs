@@ -0,0 +1,406 @@
+#![cfg(all(feature = "membership", feature = "memory"))]
+
+use aegis_comms::{
+    CommsError, Endpoint, Member, MemberMeta, MemberState, Membership, MembershipConfig, MembershipEvent,
+    MembershipEvents, MemoryConnector, MemoryNetwork, MessageListener, MessageStream, NetworkConnector, NetworkError,
+    PeerRegistry, PeerSource, RpcClient, RpcRequest, RpcServer, TransportAddr,
+};
+use aegis_core::identity::NodeIdentity;
+use aegis_core::keystore::KeyStore;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+fn config() -> MembershipConfig {
+    MembershipConfig {
+        probe_interval: Duration::from_millis(20),
+        probe_timeout: Duration::from_millis(20),
+        indirect_probes: 2,
+        suspicion_timeout: Duration::from_millis(200),
+        ..MembershipConfig::default()
+    }
+}
+
+/// Connector that refuses connections to blocked names
+struct FilteredConnector {
+    inner: MemoryConnector,
+    blocked: Arc<Mutex<HashSet<String>>>,
+}
+
+#[async_trait]
+impl NetworkConnector for FilteredConnector {
+    async fn connect(&self, addr: TransportAddr) -> Result<Box<dyn MessageStream>, NetworkError> {
+        if let TransportAddr::Memory(name) = &addr {
+            if self.blocked.lock().unwrap().contains(name) {
+                return Err(NetworkError::ConnectionRefused);
+            }
+        }
+        self.inner.connect(addr).await
+    }
+}
+
+/// A cluster member serving on a memory network
+struct Node {
+    membership: Membership,
+    registry: PeerRegistry,
+    blocked: Arc<Mutex<HashSet<String>>>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Node {
+    fn start(network: &MemoryNetwork, id: &str, meta: MemberMeta, config: MembershipConfig) -> Self {
+        Self::launch(network, id, Member::new(id, Endpoint::memory(id)).with_meta(meta), None, config)
+    }
+
+    /// Start a member signing its records, serving at `name` under its node ID
+    fn start_signed(network: &MemoryNetwork, name: &str, identity: Arc<NodeIdentity>, config: MembershipConfig) -> Self {
+        let local = Member::new(identity.node_id(), Endpoint::memory(name));
+        Self::launch(network, name, local, Some(identity), config)
+    }
+
+    fn launch(
+        network: &MemoryNetwork,
+        name: &str,
+        local: Member,
+        identity: Option<Arc<NodeIdentity>>,
+        config: MembershipConfig,
+    ) -> Self {
+        let blocked = Arc::new(Mutex::new(HashSet::new()));
+        let connector = FilteredConnector {
+            inner: network.connector(),
+            blocked: blocked.clone(),
+        };
+        let registry = PeerRegistry::new();
+        let mut membership = Membership::new(local, Arc::new(connector), config).with_registry(registry.clone());
+        if let Some(identity) = identity {
+            membership = membership.with_identity(identity).unwrap();
+        }
+
+        let mut server = RpcServer::new();
+        membership.register(&mut server);
+
+        // Connection tasks are kept so a crash can be simulated by aborting them
+        let tasks: Arc<Mutex<Vec<JoinHandle<()>>>> = Arc::default();
+        let mut listener = network.bind(name).unwrap();
+        let accepted = tasks.clone();
+        let accept = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let server = server.clone();
+                let task = tokio::spawn(async move { server.serve_connection(stream).await });
+                accepted.lock().unwrap().push(task);
+            }
+        });
+        tasks.lock().unwrap().push(accept);
+        tasks.lock().unwrap().push(membership.spawn());
+
+        Self {
+            membership,
+            registry,
+            blocked,
+            tasks,
+        }
+    }
+
+    /// Refuse outgoing connections to `id`
+    fn block(&self, id: &str) {
+        self.blocked.lock().unwrap().insert(id.to_string());
+    }
+
+    /// Stop serving and probing without leaving
+    fn crash(&self) {
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+    }
+}
+
+fn meta(capabilities: &[&str], version: &str) -> MemberMeta {
+    MemberMeta {
+        capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+        version: version.to_string(),
+    }
+}
+
+/// Wait until `condition` holds
+async fn eventually(mut condition: impl FnMut() -> bool) {
+    for _ in 0..300 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("condition not reached");
+}
+
+/// Wait for the first event matching `predicate`
+async fn wait_for(events: &mut MembershipEvents, predicate: impl Fn(&MembershipEvent) -> bool) -> MembershipEvent {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let event = events.recv().await.expect("membership gone");
+            if predicate(&event) {
+                return event;
+            }
+        }
+    })
+    .await
+    .expect("event not seen")
+}
+
+fn identity(dir: &std::path::Path, name: &str) -> Arc<NodeIdentity> {
+    let keystore = KeyStore::open(dir.join(name)).unwrap();
+    Arc::new(NodeIdentity::load_or_create(&keystore, name).unwrap())
+}
+
+/// Wire form of a gossiped member record, for forging probes
+#[derive(Serialize, Deserialize)]
+struct WireUpdate {
+    member: Member,
+    proof: Option<WireProof>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireProof {
+    public_key: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WirePing {
+    target: String,
+    updates: Vec<WireUpdate>,
+}
+
+impl RpcRequest for WirePing {
+    const METHOD: &'static str = "swim.ping";
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireAck {
+    updates: Vec<WireUpdate>,
+}
+
+fn ids(members: &[Member]) -> Vec<&str> {
+    members.iter().map(|member| member.id.as_str()).collect()
+}
+
+#[tokio::test]
+async fn test_join_spreads_membership_by_gossip() {
+    let network = MemoryNetwork::new();
+    let a = Node::start(&network, "a", meta(&["logs"], "1.0"), config());
+    let b = Node::start(&network, "b", MemberMeta::default(), config());
+    let c = Node::start(&network, "c", meta(&["audit"], "1.0"), config());
+    let mut events = a.membership.subscribe();
+
+    assert_eq!(b.membership.join(&[Endpoint::memory("a")]).await.unwrap(), 1);
+    // C only knows B, and A learns about C through gossip
+    assert_eq!(c.membership.join(&[Endpoint::memory("b")]).await.unwrap(), 2);
+
+    eventually(|| ids(&a.membership.active_members()) == ["b", "c"]).await;
+    eventually(|| ids(&b.membership.active_members()) == ["a", "c"]).await;
+    assert_eq!(ids(&c.membership.active_members()), ["a", "b"]);
+
+    wait_for(&mut events, |event| matches!(event, MembershipEvent::Joined(m) if m.id == "c")).await;
+    let entry = a.registry.get("c").unwrap();
+    assert_eq!(entry.source, PeerSource::Membership);
+    assert_eq!(entry.endpoint, Endpoint::memory("c"));
+    assert_eq!(a.registry.with_capability("audit"), vec!["c".to_string()]);
+}
+
+#[tokio::test]
+async fn test_crashed_member_is_suspected_then_failed() {
+    let network = MemoryNetwork::new();
+    let a = Node::start(&network, "a", MemberMeta::default(), config());
+    let b = Node::start(&network, "b", MemberMeta::default(), config());
+    let c = Node::start(&network, "c", MemberMeta::default(), config());
+    b.membership.join(&[Endpoint::memory("a")]).await.unwrap();
+    c.membership.join(&[Endpoint::memory("a")]).await.unwrap();
+    eventually(|| a.membership.active_members().len() == 2 && b.membership.active_members().len() == 2).await;
+
+    let mut events = a.membership.subscribe();
+    c.crash();
+
+    let suspected = wait_for(&mut events, |event| matches!(event, MembershipEvent::Suspected(_))).await;
+    assert!(matches!(suspected, MembershipEvent::Suspected(m) if m.id == "c"));
+    let failed = wait_for(&mut events, |event| matches!(event, MembershipEvent::Failed(_))).await;
+    assert!(matches!(failed, MembershipEvent::Failed(m) if m.id == "c"));
+
+    assert_eq!(a.membership.member("c").unwrap().state, MemberState::Dead);
+    assert_eq!(a.registry.get("c"), None);
+    // The failure reaches B as well
+    eventually(|| ids(&b.membership.active_members()) == ["a"]).await;
+}
+
+#[tokio::test]
+async fn test_indirect_probe_prevents_false_suspicion() {
+    let network = MemoryNetwork::new();
+    let a = Node::start(&network, "a", MemberMeta::default(), config());
+    let b = Node::start(&network, "b", MemberMeta::default(), config());
+    let c = Node::start(&network, "c", MemberMeta::default(), config());
+    // A cannot reach B directly, but C can
+    a.block("b");
+
+    a.membership.join(&[Endpoint::memory("c")]).await.unwrap();
+    b.membership.join(&[Endpoint::memory("c")]).await.unwrap();
+    eventually(|| a.membership.active_members().len() == 2).await;
+
+    // Many protocol periods, each probing B at least once
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(a.membership.member("b").unwrap().state, MemberState::Alive);
+    assert_eq!(ids(&c.membership.active_members()), ["a", "b"]);
+}
+
+#[tokio::test]
+async fn test_suspected_member_refutes_with_new_incarnation() {
+    let network = MemoryNetwork::new();
+    let config = MembershipConfig {
+        suspicion_timeout: Duration::from_secs(5),
+        ..config()
+    };
+    let a = Node::start(&network, "a", MemberMeta::default(), config);
+    let b = Node::start(&network, "b", MemberMeta::default(), config);
+    // Probes from A to B fail with nobody to probe indirectly, while B still reaches A
+    a.block("b");
+    let mut events = a.membership.subscribe();
+
+    b.membership.join(&[Endpoint::memory("a")]).await.unwrap();
+
+    wait_for(&mut events, |event| matches!(event, MembershipEvent::Suspected(_))).await;
+    // B hears about the suspicion on A's acknowledgements and refutes it
+    let recovered = wait_for(&mut events, |event| matches!(event, MembershipEvent::Recovered(_))).await;
+    match recovered {
+        MembershipEvent::Recovered(member) => {
+            assert_eq!(member.id, "b");
+            assert!(member.incarnation >= 1);
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+    assert!(b.membership.local().incarnation >= 1);
+    assert_eq!(b.membership.local().state, MemberState::Alive);
+}
+
+#[tokio::test]
+async fn test_metadata_update_and_leave_are_disseminated() {
+    let network = MemoryNetwork::new();
+    let a = Node::start(&network, "a", MemberMeta::default(), config());
+    let b = Node::start(&network, "b", meta(&["logs"], "1.0"), config());
+    b.membership.join(&[Endpoint::memory("a")]).await.unwrap();
+    eventually(|| a.membership.member("b").is_some()).await;
+    let mut events = a.membership.subscribe();
+
+    b.membership.set_meta(meta(&["logs", "audit"], "1.1"));
+    let updated = wait_for(&mut events, |event| matches!(event, MembershipEvent::Updated(_))).await;
+    assert!(matches!(&updated, MembershipEvent::Updated(m) if m.meta.version == "1.1"));
+    assert_eq!(a.registry.with_capability("audit"), vec!["b".to_string()]);
+
+    b.membership.leave().await;
+    let left = wait_for(&mut events, |event| matches!(event, MembershipEvent::Left(_))).await;
+    assert!(matches!(left, MembershipEvent::Left(m) if m.id == "b"));
+    assert_eq!(a.membership.member("b").unwrap().state, MemberState::Left);
+    assert!(a.membership.active_members().is_empty());
+    assert_eq!(a.registry.get("b"), None);
+
+    // Stale gossip about B does not bring it back
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(a.membership.member("b").unwrap().state, MemberState::Left);
+}
+
+#[test]
+fn test_identity_must_match_member_id() {
+    let dir = tempfile::tempdir().unwrap();
+    let network = MemoryNetwork::new();
+    let membership = Membership::new(
+        Member::new("a", Endpoint::memory("a")),
+        Arc::new(network.connector()),
+        config(),
+    );
+
+    let err = membership.with_identity(identity(dir.path(), "a")).err().unwrap();
+    assert!(matches!(err, CommsError::IdentityMismatch { ref id, .. } if id == "a"));
+}
+
+#[tokio::test]
+async fn test_forged_updates_are_rejected_with_identities() {
+    let dir = tempfile::tempdir().unwrap();
+    let network = MemoryNetwork::new();
+    // Signature checks are slow in debug builds
+    let config = MembershipConfig {
+        probe_timeout: Duration::from_millis(500),
+        suspicion_timeout: Duration::from_secs(5),
+        ..config()
+    };
+    let a = Node::start_signed(&network, "a", identity(dir.path(), "a"), config);
+    let b_identity = identity(dir.path(), "b");
+    let b_id = b_identity.node_id().to_string();
+    let b = Node::start_signed(&network, "b", b_identity, config);
+    b.membership.join(&[Endpoint::memory("a")]).await.unwrap();
+    eventually(|| a.membership.member(&b_id).is_some()).await;
+    let incarnation = a.membership.member(&b_id).unwrap().incarnation;
+
+    let mallory = identity(dir.path(), "mallory");
+    let forged = |state: MemberState, proof: Option<WireProof>| WireUpdate {
+        member: Member {
+            state,
+            incarnation: incarnation + 10,
+            ..Member::new(b_id.as_str(), Endpoint::memory("b"))
+        },
+        proof,
+    };
+    let updates = vec![
+        // Nobody but B may announce its departure
+        forged(MemberState::Left, None),
+        forged(
+            MemberState::Left,
+            Some(WireProof {
+                public_key: mallory.public_key().to_vec(),
+                signature: mallory.sign(b"forged"),
+            }),
+        ),
+        // Others may not declare B failed at an incarnation it never published
+        forged(MemberState::Dead, None),
+        // Unsigned members cannot join
+        WireUpdate {
+            member: Member::new("mallory", Endpoint::memory("mallory")),
+            proof: None,
+        },
+    ];
+    let ping = WirePing {
+        target: a.membership.local().id,
+        updates,
+    };
+    let client = RpcClient::with_connector(Arc::new(network.connector()));
+    client
+        .call_timeout::<_, WireAck>(&Endpoint::memory("a"), &ping, Duration::from_secs(1))
+        .await
+        .unwrap();
+
+    assert_eq!(a.membership.rejected_updates(), 4);
+    assert_eq!(a.membership.member(&b_id).unwrap().state, MemberState::Alive);
+    assert_eq!(a.membership.member("mallory"), None);
+
+    // B's own departure is signed and accepted
+    let mut events = a.membership.subscribe();
+    b.membership.leave().await;
+    let left = wait_for(&mut events, |event| matches!(event, MembershipEvent::Left(_))).await;
+    assert!(matches!(left, MembershipEvent::Left(m) if m.id == b_id));
+}
+
+#[tokio::test]
+async fn test_departed_members_are_forgotten_after_tombstone_ttl() {
+    let network = MemoryNetwork::new();
+    let config = MembershipConfig {
+        tombstone_ttl: Duration::from_millis(200),
+        ..config()
+    };
+    let a = Node::start(&network, "a", MemberMeta::default(), config);
+    let b = Node::start(&network, "b", MemberMeta::default(), config);
+    b.membership.join(&[Endpoint::memory("a")]).await.unwrap();
+    eventually(|| a.membership.member("b").is_some()).await;
+
+    b.membership.leave().await;
+    eventually(|| a.membership.member("b").is_some_and(|member| member.state == MemberState::Left)).await;
+    eventually(|| a.membership.member("b").is_none()).await;
+    assert!(a.membership.members().is_empty());
+}
@@ -0,0 +1,128 @@
+#![cfg(feature = "memory")]
+
+use aegis_comms::{
+    memory_duplex, FramedMessageStream, FramingConfig, LinkConfig, MemoryNetwork, MessageListener, MessageStream,
+    NetworkConnector, NetworkError, TransportAddr,
+};
+use bytes::Bytes;
+use std::time::{Duration, Instant};
+
+fn mem(name: &str) -> TransportAddr {
+    TransportAddr::Memory(name.to_string())
+}
+
+#[tokio::test]
+async fn test_memory_round_trip() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("policy").unwrap();
+    assert_eq!(listener.local_addr().unwrap(), mem("policy"));
+    assert_eq!(network.endpoints(), vec!["policy".to_string()]);
+
+    let mut client = network.connector().connect(mem("policy")).await.unwrap();
+    let (mut server, client_addr) = listener.accept().await.unwrap();
+    assert_eq!(client.peer_addr().unwrap(), mem("policy"));
+    assert_eq!(server.peer_addr().unwrap(), client_addr);
+
+    client.write_message(Bytes::from_static(b"ping")).await.unwrap();
+    assert_eq!(server.read_message().await.unwrap().unwrap(), Bytes::from_static(b"ping"));
+    server.write_message(Bytes::from_static(b"pong")).await.unwrap();
+    assert_eq!(client.read_message().await.unwrap().unwrap(), Bytes::from_static(b"pong"));
+
+    // Shutting down one end closes the other
+    client.shutdown().await.unwrap();
+    assert!(server.read_message().await.unwrap().is_none());
+    assert!(matches!(
+        client.write_message(Bytes::from_static(b"late")).await,
+        Err(NetworkError::ConnectionClosed)
+    ));
+}
+
+#[tokio::test]
+async fn test_memory_endpoint_registry() {
+    let network = MemoryNetwork::new();
+    let connector = network.connector();
+
+    assert!(matches!(
+        connector.connect(mem("missing")).await,
+        Err(NetworkError::ConnectionRefused)
+    ));
+    assert!(matches!(
+        connector.connect(TransportAddr::Tcp("127.0.0.1:1".parse().unwrap())).await,
+        Err(NetworkError::UnsupportedAddress(_))
+    ));
+
+    let listener = network.bind("agent").unwrap();
+    assert!(network.bind("agent").is_err());
+
+    // Dropping the listener releases the name
+    drop(listener);
+    assert!(network.endpoints().is_empty());
+    assert!(matches!(
+        connector.connect(mem("agent")).await,
+        Err(NetworkError::ConnectionRefused)
+    ));
+    network.bind("agent").unwrap();
+
+    // Separate networks do not share endpoints
+    assert!(MemoryNetwork::new().connector().connect(mem("agent")).await.is_err());
+}
+
+#[tokio::test]
+async fn test_memory_link_splits_at_mtu() {
+    let (mut a, mut b) = memory_duplex(mem("a"), mem("b"), LinkConfig::unlimited().with_mtu(4));
+
+    a.write_message(Bytes::from_static(b"0123456789")).await.unwrap();
+    let mut packets = Vec::new();
+    for _ in 0..3 {
+        packets.push(b.read_message().await.unwrap().unwrap());
+    }
+    assert_eq!(packets, vec![
+        Bytes::from_static(b"0123"),
+        Bytes::from_static(b"4567"),
+        Bytes::from_static(b"89"),
+    ]);
+
+    // A zero MTU set on the field directly is treated as one byte
+    let link = LinkConfig {
+        mtu: Some(0),
+        ..LinkConfig::unlimited()
+    };
+    let (mut a, mut b) = memory_duplex(mem("a"), mem("b"), link);
+    a.write_message(Bytes::from_static(b"01")).await.unwrap();
+    assert_eq!(b.read_message().await.unwrap().unwrap(), Bytes::from_static(b"0"));
+    assert_eq!(b.read_message().await.unwrap().unwrap(), Bytes::from_static(b"1"));
+}
+
+#[tokio::test]
+async fn test_memory_link_latency_and_bandwidth() {
+    let link = LinkConfig::unlimited().with_latency(Duration::from_millis(50));
+    let (mut a, mut b) = memory_duplex(mem("a"), mem("b"), link);
+
+    let start = Instant::now();
+    a.write_message(Bytes::from_static(b"x")).await.unwrap();
+    b.read_message().await.unwrap().unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    // 2000 bytes at 20 kB/s take at least 100ms to transmit
+    let link = LinkConfig::unlimited().with_bandwidth(20_000).with_mtu(500);
+    let (mut a, mut b) = memory_duplex(mem("a"), mem("b"), link);
+
+    let start = Instant::now();
+    a.write_message(Bytes::from(vec![0u8; 2000])).await.unwrap();
+    let mut received = 0;
+    while received < 2000 {
+        received += b.read_message().await.unwrap().unwrap().len();
+    }
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn test_framing_over_small_mtu() {
+    let (a, b) = memory_duplex(mem("a"), mem("b"), LinkConfig::unlimited().with_mtu(7));
+    let mut writer = FramedMessageStream::with_config(a, FramingConfig::default());
+    let mut reader = FramedMessageStream::with_config(b, FramingConfig::default());
+
+    let payload = Bytes::from(vec![7u8; 100]);
+    writer.write_framed_message(payload.clone()).await.unwrap();
+    assert_eq!(reader.read_framed_message().await.unwrap().unwrap(), payload);
+}
@@ -0,0 +1,165 @@
+#![cfg(feature = "memory")]
+
+use aegis_comms::{
+    AegisMessage, AgentDiscoveryMessage, CommsClient, CommsError, ConnectionPool, Endpoint, FrameVersion,
+    FramedMessageStream, FramingConfig, MemoryNetwork, MessageDispatcher, MessageListener, MessageSender,
+    MessageStream, PeerInfo, PeerRegistry, PeerSource, PoolConfig, TransportAddr,
+};
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn discovery(agent_id: &str, endpoint: &str) -> AgentDiscoveryMessage {
+    AgentDiscoveryMessage {
+        agent_id: agent_id.to_string(),
+        capabilities: vec!["logs".to_string()],
+        listen_addr: endpoint.parse().unwrap(),
+    }
+}
+
+#[test]
+fn test_peer_registry_static_and_discovered() {
+    let mut config = BTreeMap::new();
+    config.insert("camplit".to_string(), "tls://10.0.0.5:7000".to_string());
+    let registry = PeerRegistry::from_config(&config).unwrap();
+
+    assert_eq!(registry.lookup("camplit"), Some(Endpoint::tls("10.0.0.5", 7000)));
+    assert_eq!(registry.get("camplit").unwrap().source, PeerSource::Static);
+
+    // Discovery adds new agents but does not override configuration
+    assert!(registry.record_discovery(&discovery("reviezer", "mem://reviezer")));
+    assert!(!registry.record_discovery(&discovery("camplit", "mem://camplit")));
+    assert_eq!(registry.lookup("camplit"), Some(Endpoint::tls("10.0.0.5", 7000)));
+
+    let reviezer = registry.get("reviezer").unwrap();
+    assert_eq!(reviezer.endpoint, Endpoint::memory("reviezer"));
+    assert_eq!(reviezer.capabilities, vec!["logs".to_string()]);
+    assert_eq!(reviezer.source, PeerSource::Discovered);
+    assert_eq!(registry.agents(), vec!["camplit".to_string(), "reviezer".to_string()]);
+
+    registry.remove("reviezer");
+    assert!(registry.lookup("reviezer").is_none());
+
+    let mut invalid = BTreeMap::new();
+    invalid.insert("bad".to_string(), "ftp://host".to_string());
+    assert!(PeerRegistry::from_config(&invalid).is_err());
+}
+
+#[tokio::test]
+async fn test_peer_registry_learns_from_dispatcher() {
+    let registry = PeerRegistry::new();
+    let mut dispatcher = MessageDispatcher::new();
+    registry.register_discovery(&mut dispatcher);
+
+    let peer = PeerInfo {
+        addr: TransportAddr::Memory("peer".to_string()),
+        identity: None,
+        credentials: None,
+    };
+    let message = AegisMessage::new(discovery("manre", "mem://manre")).unwrap();
+    dispatcher.dispatch(message, peer).await.unwrap();
+
+    assert_eq!(registry.lookup("manre"), Some(Endpoint::memory("manre")));
+}
+
+#[tokio::test]
+async fn test_send_by_agent_id_reuses_connection() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("reviezer").unwrap();
+
+    let client = CommsClient::new(network.connector());
+    client.peers().insert("reviezer", Endpoint::memory("reviezer"));
+
+    client.send("reviezer", Bytes::from_static(b"one")).await.unwrap();
+    client.send("reviezer", Bytes::from_static(b"two")).await.unwrap();
+    assert_eq!(client.pool().connection_count(), 1);
+
+    // Both messages arrive on a single connection
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut framed = FramedMessageStream::with_config(stream, FramingConfig::default());
+    assert_eq!(framed.read_framed_message().await.unwrap().unwrap(), Bytes::from_static(b"one"));
+    assert_eq!(framed.read_framed_message().await.unwrap().unwrap(), Bytes::from_static(b"two"));
+    assert!(tokio::time::timeout(Duration::from_millis(50), listener.accept()).await.is_err());
+
+    assert!(matches!(
+        client.send("unknown", Bytes::from_static(b"x")).await,
+        Err(CommsError::UnknownAgent(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_send_reconnects_after_peer_closes() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("camplit").unwrap();
+
+    let client = CommsClient::new(network.connector());
+    client.peers().insert("camplit", Endpoint::memory("camplit"));
+
+    client.send("camplit", Bytes::from_static(b"first")).await.unwrap();
+    let (first, _) = listener.accept().await.unwrap();
+    drop(first);
+
+    // Wait for the pooled connection to notice the close
+    for _ in 0..100 {
+        if client.pool().evict_idle() > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    client.send("camplit", Bytes::from_static(b"second")).await.unwrap();
+    let (second, _) = listener.accept().await.unwrap();
+    let mut framed = FramedMessageStream::with_config(second, FramingConfig::default());
+    assert_eq!(framed.read_framed_message().await.unwrap().unwrap(), Bytes::from_static(b"second"));
+}
+
+#[tokio::test]
+async fn test_pool_configured_for_v1_reaches_legacy_listener() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("legacy").unwrap();
+    let config = PoolConfig {
+        frame_version: FrameVersion::V1,
+        ..PoolConfig::default()
+    };
+    let client = CommsClient::with_config(Arc::new(network.connector()), PeerRegistry::new(), config);
+    client.peers().insert("legacy", Endpoint::memory("legacy"));
+    client.send("legacy", Bytes::from_static(b"hello")).await.unwrap();
+
+    // A v1 listener reads a bare length prefix and never writes first
+    let (mut stream, _) = listener.accept().await.unwrap();
+    assert_eq!(&stream.read_message().await.unwrap().unwrap()[..], &[0, 0, 0, 5]);
+    assert_eq!(&stream.read_message().await.unwrap().unwrap()[..], b"hello");
+}
+
+#[tokio::test]
+async fn test_pool_evicts_idle_and_least_recently_used() {
+    let network = MemoryNetwork::new();
+    let _a = network.bind("a").unwrap();
+    let _b = network.bind("b").unwrap();
+    let _c = network.bind("c").unwrap();
+
+    let config = PoolConfig {
+        idle_timeout: Duration::from_millis(100),
+        max_connections: 2,
+        ..PoolConfig::default()
+    };
+    let pool: ConnectionPool<MessageSender> = ConnectionPool::new(Arc::new(network.connector()), config);
+
+    let first = pool.get(&Endpoint::memory("a")).await.unwrap();
+    let again = pool.get(&Endpoint::memory("a")).await.unwrap();
+    assert!(Arc::ptr_eq(&first, &again));
+
+    pool.get(&Endpoint::memory("b")).await.unwrap();
+    pool.get(&Endpoint::memory("a")).await.unwrap();
+    pool.get(&Endpoint::memory("c")).await.unwrap();
+
+    // "b" was used least recently and made room for "c"
+    let mut endpoints = pool.endpoints();
+    endpoints.sort_by_key(|e| e.to_string());
+    assert_eq!(endpoints, vec![Endpoint::memory("a"), Endpoint::memory("c")]);
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(pool.evict_idle(), 2);
+    assert_eq!(pool.connection_count(), 0);
+}
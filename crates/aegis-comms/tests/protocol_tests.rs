@@ -1,156 +1,169 @@
 use aegis_comms::{
-    AgentDiscoveryMessage, AgentHeartbeatMessage, AgentStatus, ConsensusVoteRequest,
-    ConsensusVoteResponse, ErrorMessage, MessageHeader, MessageType, StateUpdateMessage,
-    PROTOCOL_VERSION,
+    AegisMessage, AgentDiscoveryMessage, AgentHeartbeatMessage, AgentStatus, CommsError, ConsensusVoteRequest,
+    ConsensusVoteResponse, ErrorMessage, ExtensionMessage, MessageBody, MessageType, ProtocolMessage,
+    StateUpdateMessage, PROTOCOL_VERSION,
 };
+use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 
+/// Send a message through an envelope and back
+fn round_trip<M: ProtocolMessage>(message: M, addr: SocketAddr) -> (AegisMessage, M) {
+    let envelope = AegisMessage::new(message)
+        .unwrap()
+        .with_source(addr.into())
+        .with_destination(addr.into());
+
+    let serialized = bincode::serialize(&envelope).unwrap();
+    let deserialized: AegisMessage = bincode::deserialize(&serialized).unwrap();
+    assert_eq!(deserialized, envelope);
+    deserialized.validate().unwrap();
+
+    (envelope, deserialized.decode().unwrap())
+}
+
 #[test]
 fn test_consensus_vote_request_serialization() {
     let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
-    let header = MessageHeader {
-        version: PROTOCOL_VERSION,
-        message_type: MessageType::ConsensusVoteRequest,
-        source: Some(addr),
-        destination: Some(addr),
-    };
-    
     let request = ConsensusVoteRequest {
-        header,
         proposal_id: 42,
         proposal_data: vec![1, 2, 3, 4],
     };
-    
-    let serialized = bincode::serialize(&request).unwrap();
-    let deserialized: ConsensusVoteRequest = bincode::deserialize(&serialized).unwrap();
-    
-    assert_eq!(request.proposal_id, deserialized.proposal_id);
-    assert_eq!(request.proposal_data, deserialized.proposal_data);
-    assert_eq!(request.header.version, deserialized.header.version);
-    assert_eq!(request.header.message_type, deserialized.header.message_type);
-    assert_eq!(request.header.source, deserialized.header.source);
-    assert_eq!(request.header.destination, deserialized.header.destination);
+
+    let (envelope, deserialized) = round_trip(request.clone(), addr);
+
+    assert_eq!(request, deserialized);
+    assert_eq!(envelope.header.version, PROTOCOL_VERSION);
+    assert_eq!(envelope.header.message_type, MessageType::ConsensusVoteRequest);
+    assert_eq!(envelope.header.source, Some(addr.into()));
+    assert_eq!(envelope.header.destination, Some(addr.into()));
 }
 
 #[test]
 fn test_consensus_vote_response_serialization() {
     let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
-    let header = MessageHeader {
-        version: PROTOCOL_VERSION,
-        message_type: MessageType::ConsensusVoteResponse,
-        source: Some(addr),
-        destination: Some(addr),
-    };
-    
     let response = ConsensusVoteResponse {
-        header,
         proposal_id: 42,
         vote: true,
         voter_id: "node1".to_string(),
     };
-    
-    let serialized = bincode::serialize(&response).unwrap();
-    let deserialized: ConsensusVoteResponse = bincode::deserialize(&serialized).unwrap();
-    
-    assert_eq!(response.proposal_id, deserialized.proposal_id);
-    assert_eq!(response.vote, deserialized.vote);
-    assert_eq!(response.voter_id, deserialized.voter_id);
+
+    let (envelope, deserialized) = round_trip(response.clone(), addr);
+
+    assert_eq!(response, deserialized);
+    assert_eq!(envelope.header.message_type, MessageType::ConsensusVoteResponse);
 }
 
 #[test]
 fn test_state_update_message_serialization() {
     let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
-    let header = MessageHeader {
-        version: PROTOCOL_VERSION,
-        message_type: MessageType::StateUpdate,
-        source: Some(addr),
-        destination: Some(addr),
-    };
-    
     let update = StateUpdateMessage {
-        header,
         state_version: 1,
         state_data: vec![5, 6, 7, 8],
         is_delta: true,
     };
-    
-    let serialized = bincode::serialize(&update).unwrap();
-    let deserialized: StateUpdateMessage = bincode::deserialize(&serialized).unwrap();
-    
-    assert_eq!(update.state_version, deserialized.state_version);
-    assert_eq!(update.state_data, deserialized.state_data);
-    assert_eq!(update.is_delta, deserialized.is_delta);
+
+    let (envelope, deserialized) = round_trip(update.clone(), addr);
+
+    assert_eq!(update, deserialized);
+    assert_eq!(envelope.header.message_type, MessageType::StateUpdate);
 }
 
 #[test]
 fn test_agent_discovery_message_serialization() {
     let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
-    let header = MessageHeader {
-        version: PROTOCOL_VERSION,
-        message_type: MessageType::AgentDiscovery,
-        source: Some(addr),
-        destination: None,
-    };
-    
     let discovery = AgentDiscoveryMessage {
-        header,
         agent_id: "agent1".to_string(),
         capabilities: vec!["compute".to_string(), "storage".to_string()],
-        listen_addr: addr,
+        listen_addr: addr.into(),
     };
-    
-    let serialized = bincode::serialize(&discovery).unwrap();
-    let deserialized: AgentDiscoveryMessage = bincode::deserialize(&serialized).unwrap();
-    
-    assert_eq!(discovery.agent_id, deserialized.agent_id);
-    assert_eq!(discovery.capabilities, deserialized.capabilities);
-    assert_eq!(discovery.listen_addr, deserialized.listen_addr);
+
+    let (envelope, deserialized) = round_trip(discovery.clone(), addr);
+
+    assert_eq!(discovery, deserialized);
+    assert_eq!(envelope.header.message_type, MessageType::AgentDiscovery);
 }
 
 #[test]
 fn test_agent_heartbeat_message_serialization() {
     let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
-    let header = MessageHeader {
-        version: PROTOCOL_VERSION,
-        message_type: MessageType::AgentHeartbeat,
-        source: Some(addr),
-        destination: None,
-    };
-    
     let heartbeat = AgentHeartbeatMessage {
-        header,
         agent_id: "agent1".to_string(),
         timestamp: 1234567890,
         status: AgentStatus::Active,
     };
-    
-    let serialized = bincode::serialize(&heartbeat).unwrap();
-    let deserialized: AgentHeartbeatMessage = bincode::deserialize(&serialized).unwrap();
-    
-    assert_eq!(heartbeat.agent_id, deserialized.agent_id);
-    assert_eq!(heartbeat.timestamp, deserialized.timestamp);
-    assert_eq!(heartbeat.status, deserialized.status);
+
+    let (envelope, deserialized) = round_trip(heartbeat.clone(), addr);
+
+    assert_eq!(heartbeat, deserialized);
+    assert_eq!(envelope.header.message_type, MessageType::AgentHeartbeat);
 }
 
 #[test]
 fn test_error_message_serialization() {
     let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
-    let header = MessageHeader {
-        version: PROTOCOL_VERSION,
-        message_type: MessageType::Error,
-        source: Some(addr),
-        destination: Some(addr),
-    };
-    
     let error = ErrorMessage {
-        header,
         error_code: 404,
         error_message: "Not found".to_string(),
     };
-    
-    let serialized = bincode::serialize(&error).unwrap();
-    let deserialized: ErrorMessage = bincode::deserialize(&serialized).unwrap();
-    
-    assert_eq!(error.error_code, deserialized.error_code);
-    assert_eq!(error.error_message, deserialized.error_message);
-} 
\ No newline at end of file
+
+    let (envelope, deserialized) = round_trip(error.clone(), addr);
+
+    assert_eq!(error, deserialized);
+    assert_eq!(envelope.header.message_type, MessageType::Error);
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PolicyQuery {
+    id: String,
+    rules: serde_json::Value,
+}
+
+impl ExtensionMessage for PolicyQuery {
+    const MESSAGE_TYPE: &'static str = "policy.query";
+}
+
+#[test]
+fn test_extension_message_serialization() {
+    let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+    let query = PolicyQuery {
+        id: "p1".to_string(),
+        rules: serde_json::json!({ "max_cpu": 80 }),
+    };
+
+    let (envelope, deserialized) = round_trip(query.clone(), addr);
+
+    assert_eq!(query, deserialized);
+    assert_eq!(
+        envelope.header.message_type,
+        MessageType::Extension("policy.query".to_string())
+    );
+}
+
+#[test]
+fn test_envelope_rejects_mismatches() {
+    let heartbeat = AgentHeartbeatMessage {
+        agent_id: "agent1".to_string(),
+        timestamp: 1,
+        status: AgentStatus::Busy,
+    };
+    let envelope = AegisMessage::new(heartbeat).unwrap();
+
+    // Decoding as another type names both types
+    match envelope.clone().decode::<ErrorMessage>() {
+        Err(CommsError::UnexpectedMessageType { expected, actual }) => {
+            assert_eq!(expected, MessageType::Error);
+            assert_eq!(actual, MessageType::AgentHeartbeat);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    // Other protocol versions are rejected
+    let mut old = envelope.clone();
+    old.header.version = PROTOCOL_VERSION - 1;
+    assert!(matches!(old.validate(), Err(CommsError::VersionMismatch { .. })));
+
+    // A body that does not match the header is rejected
+    let mut forged = envelope;
+    forged.body = MessageBody::Extension(b"{}".to_vec());
+    assert!(matches!(forged.validate(), Err(CommsError::InvalidMessage(_))));
+}
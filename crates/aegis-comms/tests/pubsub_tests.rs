@@ -0,0 +1,375 @@
+#![cfg(all(feature = "pubsub", feature = "memory"))]
+
+use aegis_comms::{
+    AegisMessage, Broker, CommsError, Endpoint, EnvelopeSigner, EnvelopeVerifier, MemoryNetwork, MessageServer,
+    PeerIdentity, PeerRegistry, QoS, ReplayConfig, RpcClient, RpcRequest, RpcServer, SecurityViolation, ServerConfig,
+    SignedEnvelope, SlowConsumerPolicy, Subscription, SubscriptionOptions, TopicMessage, TopicPattern,
+};
+use aegis_core::identity::NodeIdentity;
+use aegis_core::keystore::KeyStore;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Finding {
+    rule: String,
+    severity: u8,
+}
+
+fn finding(rule: &str) -> Finding {
+    Finding {
+        rule: rule.to_string(),
+        severity: 3,
+    }
+}
+
+/// Next delivery, decoded, within a second
+async fn next(subscription: &mut Subscription) -> Finding {
+    let delivery = tokio::time::timeout(Duration::from_secs(1), subscription.recv())
+        .await
+        .expect("nothing delivered")
+        .expect("subscription closed");
+    let finding = delivery.decode().unwrap();
+    delivery.ack();
+    finding
+}
+
+async fn assert_empty(subscription: &mut Subscription) {
+    assert!(tokio::time::timeout(Duration::from_millis(50), subscription.recv()).await.is_err());
+}
+
+#[test]
+fn test_topic_patterns() {
+    let pattern = TopicPattern::parse("findings.*.network").unwrap();
+    assert!(pattern.matches("findings.high.network"));
+    assert!(!pattern.matches("findings.network"));
+    assert!(!pattern.matches("findings.high.network.tcp"));
+
+    let pattern: TopicPattern = "findings.#".parse().unwrap();
+    assert!(pattern.matches("findings"));
+    assert!(pattern.matches("findings.high"));
+    assert!(pattern.matches("findings.high.network"));
+    assert!(!pattern.matches("policy.changed"));
+
+    let pattern = TopicPattern::parse("#.changed").unwrap();
+    assert!(pattern.matches("changed"));
+    assert!(pattern.matches("policy.rules.changed"));
+    assert!(!pattern.matches("policy.changed.twice"));
+
+    for invalid in ["", "findings.", "findings..high", "findings.hi*"] {
+        assert!(matches!(TopicPattern::parse(invalid), Err(CommsError::InvalidTopic(_))), "{}", invalid);
+    }
+
+    let broker = Broker::new();
+    assert!(matches!(
+        broker.publish("findings.*", &finding("r1")),
+        Err(CommsError::InvalidTopic(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_publish_fans_out_to_matching_subscriptions() {
+    let broker = Broker::new();
+    let mut all = broker.subscribe("findings.#", SubscriptionOptions::default()).unwrap();
+    let mut high = broker.subscribe("findings.high", SubscriptionOptions::default()).unwrap();
+    let mut policy = broker.subscribe("policy.*", SubscriptionOptions::default()).unwrap();
+
+    assert_eq!(broker.publish("findings.high", &finding("r1")).unwrap(), 2);
+    assert_eq!(broker.publish("findings.low", &finding("r2")).unwrap(), 1);
+
+    assert_eq!(next(&mut all).await, finding("r1"));
+    assert_eq!(next(&mut all).await, finding("r2"));
+    assert_eq!(next(&mut high).await, finding("r1"));
+    assert_empty(&mut high).await;
+    assert_empty(&mut policy).await;
+
+    drop(high);
+    assert_eq!(broker.subscriptions(), 2);
+    assert_eq!(broker.publish("findings.high", &finding("r3")).unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_slow_consumer_policies() {
+    let broker = Broker::new();
+    let options = |policy| SubscriptionOptions {
+        queue_size: 2,
+        policy,
+        ..SubscriptionOptions::default()
+    };
+    let mut oldest = broker.subscribe("findings", options(SlowConsumerPolicy::DropOldest)).unwrap();
+    let mut newest = broker.subscribe("findings", options(SlowConsumerPolicy::DropNewest)).unwrap();
+    let mut disconnect = broker.subscribe("findings", options(SlowConsumerPolicy::Disconnect)).unwrap();
+
+    for rule in ["r1", "r2", "r3"] {
+        broker.publish("findings", &finding(rule)).unwrap();
+    }
+
+    assert_eq!(next(&mut oldest).await, finding("r2"));
+    assert_eq!(next(&mut oldest).await, finding("r3"));
+    assert_eq!(oldest.dropped(), 1);
+
+    assert_eq!(next(&mut newest).await, finding("r1"));
+    assert_eq!(next(&mut newest).await, finding("r2"));
+    assert_eq!(newest.dropped(), 1);
+
+    // The slow consumer gets what was queued before it was disconnected
+    assert!(disconnect.is_closed());
+    assert_eq!(next(&mut disconnect).await, finding("r1"));
+    assert_eq!(next(&mut disconnect).await, finding("r2"));
+    assert!(disconnect.recv().await.is_none());
+    assert_eq!(broker.subscriptions(), 2);
+}
+
+#[tokio::test]
+async fn test_at_least_once_redelivers_until_acknowledged() {
+    let broker = Broker::new();
+    let options = SubscriptionOptions {
+        qos: QoS::AtLeastOnce,
+        ack_timeout: Duration::from_millis(50),
+        ..SubscriptionOptions::default()
+    };
+    let mut subscription = broker.subscribe("findings", options).unwrap();
+    broker.publish("findings", &finding("r1")).unwrap();
+
+    let first = subscription.recv().await.unwrap();
+    assert!(!first.redelivered);
+    // Not acknowledged, so delivered again after the timeout
+    drop(first);
+
+    let second = tokio::time::timeout(Duration::from_secs(1), subscription.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(second.redelivered);
+    assert_eq!(second.decode::<Finding>().unwrap(), finding("r1"));
+    second.ack();
+
+    assert!(tokio::time::timeout(Duration::from_millis(150), subscription.recv()).await.is_err());
+}
+
+/// Identity the connections of `name` present
+fn identity(name: &str) -> PeerIdentity {
+    PeerIdentity {
+        subject: name.to_string(),
+        dns_names: Vec::new(),
+        uris: Vec::new(),
+        spiffe_id: None,
+        fingerprint: String::new(),
+    }
+}
+
+/// The brokers taking part in the tests
+fn peers() -> PeerRegistry {
+    let peers = PeerRegistry::new();
+    for name in ["camplit", "reviezer"] {
+        peers.insert(name, Endpoint::memory(name));
+    }
+    peers
+}
+
+/// Broker `node_id` connecting as `name`, not serving requests
+fn client(network: &MemoryNetwork, node_id: &str, name: Option<&str>) -> Broker {
+    let connector = match name {
+        Some(name) => network.connector_as(identity(name)),
+        None => network.connector(),
+    };
+    Broker::with_connector(node_id, Endpoint::memory(node_id), Arc::new(connector), peers())
+}
+
+/// Broker linked over RPC, serving on `network` as `name`
+fn node(network: &MemoryNetwork, name: &str) -> Broker {
+    serve(network, name, client(network, name, Some(name)))
+}
+
+/// Serve `broker` on `network` as `name`
+fn serve(network: &MemoryNetwork, name: &str, broker: Broker) -> Broker {
+    let mut server = RpcServer::new();
+    broker.register(&mut server);
+
+    let listener = network.bind(name).unwrap();
+    tokio::spawn(async move { MessageServer::new(ServerConfig::default()).serve_rpc(listener, server).await });
+    broker
+}
+
+#[tokio::test]
+async fn test_messages_are_forwarded_across_nodes() {
+    let network = MemoryNetwork::new();
+    let publisher = node(&network, "camplit");
+    let subscriber = node(&network, "reviezer");
+
+    let mut local = subscriber.subscribe("findings.#", SubscriptionOptions::default()).unwrap();
+    let options = SubscriptionOptions {
+        qos: QoS::AtLeastOnce,
+        ..SubscriptionOptions::default()
+    };
+    subscriber
+        .subscribe_remote(&Endpoint::memory("camplit"), "findings.#", options)
+        .await
+        .unwrap();
+    // Subscribed both ways, which must not loop messages back
+    publisher
+        .subscribe_remote(&Endpoint::memory("reviezer"), "findings.#", options)
+        .await
+        .unwrap();
+    let mut publisher_local = publisher.subscribe("findings.#", SubscriptionOptions::default()).unwrap();
+
+    assert_eq!(publisher.publish("findings.high", &finding("r1")).unwrap(), 2);
+    assert_eq!(next(&mut local).await, finding("r1"));
+    assert_eq!(next(&mut publisher_local).await, finding("r1"));
+    assert_empty(&mut local).await;
+    assert_empty(&mut publisher_local).await;
+
+    // Subscribing again replaces the forwarding instead of duplicating it
+    subscriber
+        .subscribe_remote(&Endpoint::memory("camplit"), "findings.#", options)
+        .await
+        .unwrap();
+    publisher.publish("findings.low", &finding("r2")).unwrap();
+    assert_eq!(next(&mut local).await, finding("r2"));
+    assert_empty(&mut local).await;
+
+    subscriber
+        .unsubscribe_remote(&Endpoint::memory("camplit"), "findings.#", Duration::from_secs(1))
+        .await
+        .unwrap();
+    // Only the local subscription of the publisher is left
+    assert_eq!(publisher.publish("findings.low", &finding("r3")).unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_remote_subscription_needs_linked_broker() {
+    let broker = Broker::new();
+    assert!(broker
+        .subscribe_remote(&Endpoint::memory("camplit"), "findings.#", SubscriptionOptions::default())
+        .await
+        .is_err());
+}
+
+/// Wire form of a forwarded message, for forging forwards
+#[derive(Serialize, Deserialize)]
+enum WireForward {
+    Plain(TopicMessage),
+    Signed(Box<SignedEnvelope>),
+}
+
+fn forged_message() -> TopicMessage {
+    TopicMessage {
+        topic: "findings.high".to_string(),
+        payload: serde_json::to_vec(&finding("forged")).unwrap(),
+    }
+}
+
+impl RpcRequest for WireForward {
+    const METHOD: &'static str = "pubsub.forward";
+}
+
+#[tokio::test]
+async fn test_remote_requests_are_authorized() {
+    let network = MemoryNetwork::new();
+    let publisher = node(&network, "camplit");
+    let subscriber = node(&network, "reviezer");
+    let camplit = Endpoint::memory("camplit");
+    let options = SubscriptionOptions::default();
+
+    // Unauthenticated, impersonating and unregistered subscribers are refused
+    for (node_id, name) in [("reviezer", None), ("reviezer", Some("mallory")), ("mallory", Some("mallory"))] {
+        let forged = client(&network, node_id, name);
+        assert!(forged.subscribe_remote(&camplit, "findings.#", options).await.is_err());
+    }
+    assert_eq!(publisher.publish("findings.high", &finding("r1")).unwrap(), 0);
+
+    // Only the subscriber may stop its forwarding
+    let mut local = subscriber.subscribe("findings.#", options).unwrap();
+    subscriber.subscribe_remote(&camplit, "findings.#", options).await.unwrap();
+    let impostor = client(&network, "reviezer", Some("mallory"));
+    assert!(impostor
+        .unsubscribe_remote(&camplit, "findings.#", Duration::from_secs(1))
+        .await
+        .is_err());
+    assert_eq!(publisher.publish("findings.high", &finding("r2")).unwrap(), 1);
+    assert_eq!(next(&mut local).await, finding("r2"));
+
+    // Forwards are accepted only from brokers subscribed to
+    let forward = WireForward::Plain(forged_message());
+    for connector in [network.connector(), network.connector_as(identity("mallory"))] {
+        let rpc = RpcClient::with_connector(Arc::new(connector));
+        let result = rpc
+            .call_timeout::<_, ()>(&Endpoint::memory("reviezer"), &forward, Duration::from_secs(1))
+            .await;
+        assert!(result.is_err());
+    }
+    assert_empty(&mut local).await;
+}
+
+#[tokio::test]
+async fn test_failed_remote_subscription_refuses_forwards() {
+    let network = MemoryNetwork::new();
+    let subscriber = node(&network, "reviezer");
+    let options = SubscriptionOptions::default();
+    let mut local = subscriber.subscribe("findings.#", options).unwrap();
+
+    // Nothing serves camplit, so the subscription fails
+    assert!(subscriber
+        .subscribe_remote(&Endpoint::memory("camplit"), "findings.#", options)
+        .await
+        .is_err());
+
+    let rpc = RpcClient::with_connector(Arc::new(network.connector_as(identity("camplit"))));
+    let result = rpc
+        .call_timeout::<_, ()>(
+            &Endpoint::memory("reviezer"),
+            &WireForward::Plain(forged_message()),
+            Duration::from_secs(1),
+        )
+        .await;
+    assert!(result.is_err());
+    assert_empty(&mut local).await;
+}
+
+#[tokio::test]
+async fn test_forwards_are_signed_and_verified() {
+    let dir = tempfile::tempdir().unwrap();
+    let signer = |name: &str| {
+        let keystore = KeyStore::open(dir.path().join(name)).unwrap();
+        EnvelopeSigner::new(Arc::new(NodeIdentity::load_or_create(&keystore, name).unwrap()))
+    };
+    let (camplit_signer, mallory_signer) = (signer("camplit"), signer("mallory"));
+    let verifier = EnvelopeVerifier::new(ReplayConfig::default()).with_trusted_senders([camplit_signer.node_id()]);
+    let mut events = verifier.subscribe();
+
+    let network = MemoryNetwork::new();
+    let camplit = Endpoint::memory("camplit");
+    let publisher = serve(
+        &network,
+        "camplit",
+        client(&network, "camplit", Some("camplit")).with_signer(camplit_signer),
+    );
+    let subscriber = serve(
+        &network,
+        "reviezer",
+        client(&network, "reviezer", Some("reviezer")).with_verifier(verifier),
+    );
+    let options = SubscriptionOptions::default();
+    let mut local = subscriber.subscribe("findings.#", options).unwrap();
+    subscriber.subscribe_remote(&camplit, "findings.#", options).await.unwrap();
+
+    assert_eq!(publisher.publish("findings.high", &finding("r1")).unwrap(), 1);
+    assert_eq!(next(&mut local).await, finding("r1"));
+
+    // The subscribed-to broker's connection is not enough without its signature
+    let rpc = RpcClient::with_connector(Arc::new(network.connector_as(identity("camplit"))));
+    let sealed = mallory_signer.seal(AegisMessage::new(forged_message()).unwrap()).unwrap();
+    let forwards = [
+        (WireForward::Plain(forged_message()), SecurityViolation::Unsigned),
+        (WireForward::Signed(Box::new(sealed)), SecurityViolation::UntrustedSender),
+    ];
+    for (forward, violation) in forwards {
+        let result = rpc
+            .call_timeout::<_, ()>(&Endpoint::memory("reviezer"), &forward, Duration::from_secs(1))
+            .await;
+        assert!(result.is_err());
+        assert_eq!(events.recv().await.unwrap().violation, violation);
+    }
+    assert_empty(&mut local).await;
+}
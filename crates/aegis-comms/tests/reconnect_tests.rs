@@ -0,0 +1,287 @@
+#![cfg(feature = "memory")]
+
+use aegis_comms::{
+    CommsClient, CommsError, ConnectionHandle, ConnectionOptions, ConnectionState, DeliveryMode, FrameType, MemoryNetwork,
+    MessageListener, MessageStream, NetworkConnector, NetworkError, ReconnectPolicy, TransportAddr, FRAME_HEADER_SIZE,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+fn fast_policy() -> ReconnectPolicy {
+    ReconnectPolicy::default()
+        .with_initial_delay(Duration::from_millis(10))
+        .with_max_delay(Duration::from_millis(50))
+}
+
+fn options(delivery: DeliveryMode) -> ConnectionOptions {
+    ConnectionOptions {
+        reconnect: fast_policy(),
+        delivery,
+        buffer_size: 8,
+        ..ConnectionOptions::default()
+    }
+}
+
+async fn next_state(events: &mut broadcast::Receiver<ConnectionState>) -> ConnectionState {
+    tokio::time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("no state change")
+        .unwrap()
+}
+
+#[test]
+fn test_backoff_grows_with_jitter() {
+    let policy = ReconnectPolicy::default()
+        .with_initial_delay(Duration::from_millis(100))
+        .with_max_delay(Duration::from_secs(1))
+        .with_jitter(0.0);
+
+    assert_eq!(policy.delay(1), Duration::from_millis(100));
+    assert_eq!(policy.delay(2), Duration::from_millis(200));
+    assert_eq!(policy.delay(3), Duration::from_millis(400));
+    assert_eq!(policy.delay(10), Duration::from_secs(1));
+
+    let jittered = policy.with_jitter(0.5);
+    for _ in 0..100 {
+        let delay = jittered.delay(2);
+        assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(300));
+    }
+}
+
+#[tokio::test]
+async fn test_reconnects_and_replays_buffered_messages() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("peer").unwrap();
+    let client = CommsClient::new(network.connector()).with_connection_options(options(DeliveryMode::AtMostOnce));
+
+    let handle = client
+        .connect_to::<String>(TransportAddr::Memory("peer".to_string()))
+        .await
+        .unwrap();
+    let mut events = handle.events();
+    assert_eq!(handle.state(), ConnectionState::Up);
+
+    // The peer goes away
+    let (first, _) = listener.accept().await.unwrap();
+    drop(first);
+    assert_eq!(next_state(&mut events).await, ConnectionState::Down);
+
+    // Messages sent while down wait in the buffer
+    handle.send("one".to_string()).await.unwrap();
+    handle.send("two".to_string()).await.unwrap();
+
+    assert_eq!(next_state(&mut events).await, ConnectionState::Connecting { attempt: 1 });
+    assert_eq!(next_state(&mut events).await, ConnectionState::Up);
+
+    let (second, _) = listener.accept().await.unwrap();
+    let mut peer = ConnectionHandle::<String>::from_stream(second);
+    assert_eq!(peer.receive().await.unwrap(), Some("one".to_string()));
+    assert_eq!(peer.receive().await.unwrap(), Some("two".to_string()));
+}
+
+#[tokio::test]
+async fn test_gives_up_after_max_attempts() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("peer").unwrap();
+    let mut options = options(DeliveryMode::AtMostOnce);
+    options.reconnect = fast_policy().with_max_attempts(2);
+    let client = CommsClient::new(network.connector()).with_connection_options(options);
+
+    let handle = client
+        .connect_to::<String>(TransportAddr::Memory("peer".to_string()))
+        .await
+        .unwrap();
+    let mut events = handle.events();
+
+    let (stream, _) = listener.accept().await.unwrap();
+    drop(stream);
+    drop(listener);
+
+    assert_eq!(next_state(&mut events).await, ConnectionState::Down);
+    assert_eq!(next_state(&mut events).await, ConnectionState::Connecting { attempt: 1 });
+    assert_eq!(next_state(&mut events).await, ConnectionState::Connecting { attempt: 2 });
+    assert_eq!(next_state(&mut events).await, ConnectionState::GaveUp);
+    assert_eq!(handle.state(), ConnectionState::GaveUp);
+
+    assert!(matches!(handle.send("late".to_string()).await, Err(CommsError::ChannelClosed)));
+}
+
+/// Frames written on each connection opened by a [`ScriptedConnector`]
+type WriteLog = Arc<Mutex<Vec<Vec<Bytes>>>>;
+
+/// Connector whose first connection fails after a number of writes
+struct ScriptedConnector {
+    writes: WriteLog,
+    fail_after: usize,
+    /// Whether connections after the first are refused
+    refuse_reconnect: bool,
+}
+
+struct ScriptedStream {
+    writes: WriteLog,
+    index: usize,
+    fail_after: Option<usize>,
+}
+
+#[async_trait]
+impl MessageStream for ScriptedStream {
+    async fn read_message(&mut self) -> Result<Option<Bytes>, NetworkError> {
+        std::future::pending().await
+    }
+
+    async fn write_message(&mut self, msg: Bytes) -> Result<(), NetworkError> {
+        // Only data frames are recorded, not credit grants
+        if msg[4] != FrameType::Data as u8 {
+            return Ok(());
+        }
+        let mut writes = self.writes.lock().unwrap();
+        if self.fail_after.is_some_and(|n| writes[self.index].len() >= n) {
+            return Err(NetworkError::ConnectionClosed);
+        }
+        writes[self.index].push(msg);
+        Ok(())
+    }
+
+    fn peer_addr(&self) -> Result<TransportAddr, NetworkError> {
+        Ok(TransportAddr::Memory("scripted".to_string()))
+    }
+
+    async fn shutdown(&mut self) -> Result<(), NetworkError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl NetworkConnector for ScriptedConnector {
+    async fn connect(&self, _addr: TransportAddr) -> Result<Box<dyn MessageStream>, NetworkError> {
+        let mut writes = self.writes.lock().unwrap();
+        if self.refuse_reconnect && !writes.is_empty() {
+            return Err(NetworkError::ConnectionRefused);
+        }
+        writes.push(Vec::new());
+        let index = writes.len() - 1;
+
+        Ok(Box::new(ScriptedStream {
+            writes: self.writes.clone(),
+            index,
+            fail_after: (index == 0).then_some(self.fail_after),
+        }))
+    }
+}
+
+/// Send three messages where the second write fails, and return what each connection received
+async fn send_through_failure(delivery: DeliveryMode) -> (Vec<Vec<String>>, u64) {
+    let writes = WriteLog::default();
+    let connector = ScriptedConnector {
+        writes: writes.clone(),
+        fail_after: 1,
+        refuse_reconnect: false,
+    };
+    let client = CommsClient::new(connector).with_connection_options(options(delivery));
+    let handle = client
+        .connect_to::<String>(TransportAddr::Memory("scripted".to_string()))
+        .await
+        .unwrap();
+    let mut events = handle.events();
+
+    for msg in ["a", "b", "c"] {
+        handle.send(msg.to_string()).await.unwrap();
+    }
+    assert_eq!(next_state(&mut events).await, ConnectionState::Down);
+    assert_eq!(next_state(&mut events).await, ConnectionState::Connecting { attempt: 1 });
+    assert_eq!(next_state(&mut events).await, ConnectionState::Up);
+
+    // Wait for the buffered message to be written on the new connection
+    for _ in 0..100 {
+        if writes.lock().unwrap()[1].last().is_some_and(|f| f.ends_with(&bincode::serialize("c").unwrap())) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let received = writes
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|frames| {
+            frames
+                .iter()
+                .map(|frame| bincode::deserialize::<String>(&frame[FRAME_HEADER_SIZE..]).unwrap())
+                .collect()
+        })
+        .collect();
+    (received, handle.lost_messages())
+}
+
+#[tokio::test]
+async fn test_at_least_once_retries_failed_write() {
+    let (received, lost) = send_through_failure(DeliveryMode::AtLeastOnce).await;
+
+    assert_eq!(received[0], vec!["a".to_string()]);
+    assert_eq!(received[1], vec!["b".to_string(), "c".to_string()]);
+    assert_eq!(lost, 0);
+}
+
+#[tokio::test]
+async fn test_at_most_once_drops_failed_write() {
+    let (received, lost) = send_through_failure(DeliveryMode::AtMostOnce).await;
+
+    assert_eq!(received[0], vec!["a".to_string()]);
+    assert_eq!(received[1], vec!["c".to_string()]);
+    assert_eq!(lost, 1);
+}
+
+#[tokio::test]
+async fn test_at_least_once_counts_retry_lost_when_giving_up() {
+    let connector = ScriptedConnector {
+        writes: WriteLog::default(),
+        fail_after: 0,
+        refuse_reconnect: true,
+    };
+    let mut options = options(DeliveryMode::AtLeastOnce);
+    options.reconnect = fast_policy().with_max_attempts(1);
+    let client = CommsClient::new(connector).with_connection_options(options);
+    let handle = client
+        .connect_to::<String>(TransportAddr::Memory("scripted".to_string()))
+        .await
+        .unwrap();
+    let mut events = handle.events();
+
+    // Every write fails, so the message waits for a retry that never succeeds
+    handle.send("a".to_string()).await.unwrap();
+    loop {
+        if next_state(&mut events).await == ConnectionState::GaveUp {
+            break;
+        }
+    }
+    assert_eq!(handle.lost_messages(), 1);
+}
+
+#[tokio::test]
+async fn test_try_send_reports_full_buffer() {
+    let writes = WriteLog::default();
+    let connector = ScriptedConnector {
+        writes,
+        fail_after: 0,
+        refuse_reconnect: false,
+    };
+    let mut options = options(DeliveryMode::AtMostOnce);
+    options.buffer_size = 1;
+    // Never reconnect quickly, so the buffer stays full
+    options.reconnect = ReconnectPolicy::default().with_initial_delay(Duration::from_secs(60));
+    let client = CommsClient::new(connector).with_connection_options(options);
+    let handle = client
+        .connect_to::<String>(TransportAddr::Memory("scripted".to_string()))
+        .await
+        .unwrap();
+    let mut events = handle.events();
+
+    handle.send("lost".to_string()).await.unwrap();
+    assert_eq!(next_state(&mut events).await, ConnectionState::Down);
+
+    handle.try_send("buffered".to_string()).unwrap();
+    assert!(matches!(handle.try_send("overflow".to_string()), Err(CommsError::BufferFull)));
+}
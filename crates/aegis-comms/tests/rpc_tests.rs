@@ -0,0 +1,316 @@
+#![cfg(all(feature = "rpc", feature = "memory"))]
+
+use aegis_comms::{
+    CodecId, Endpoint, ErrorCode, FramingConfig, MemoryNetwork, MessageServer, NetworkConnector, RpcClient,
+    RpcConnection, RpcContext, RpcError, RpcRequest, RpcServer, ServerConfig,
+};
+use aegis_core::error::AegisError;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Echo {
+    text: String,
+}
+
+impl RpcRequest for Echo {
+    const METHOD: &'static str = "echo";
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Lookup {
+    key: String,
+}
+
+impl RpcRequest for Lookup {
+    const METHOD: &'static str = "lookup";
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Sleep {
+    millis: u64,
+}
+
+impl RpcRequest for Sleep {
+    const METHOD: &'static str = "sleep";
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Annotate {
+    note: serde_json::Value,
+}
+
+impl RpcRequest for Annotate {
+    const METHOD: &'static str = "annotate";
+    const CODEC: Option<CodecId> = Some(CodecId::Json);
+}
+
+/// Annotate without a fixed codec
+#[derive(Debug, Serialize, Deserialize)]
+struct Negotiated {
+    note: serde_json::Value,
+}
+
+impl RpcRequest for Negotiated {
+    const METHOD: &'static str = "negotiated";
+}
+
+/// Serve `server` on a fresh memory network and return a client for it
+fn start(server: RpcServer) -> (RpcClient, Endpoint) {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("rpc").unwrap();
+    tokio::spawn(async move { MessageServer::new(ServerConfig::default()).serve_rpc(listener, server).await });
+    (RpcClient::new(network.connector()), Endpoint::memory("rpc"))
+}
+
+#[tokio::test]
+async fn test_rpc_round_trip() {
+    let mut server = RpcServer::new();
+    server.register(|req: Echo, _ctx: RpcContext| async move { Ok(format!("echo: {}", req.text)) });
+    assert_eq!(server.methods(), vec!["echo".to_string()]);
+
+    let (client, endpoint) = start(server);
+
+    // Concurrent calls on one connection are matched to their responses
+    let calls = (0..10).map(|i| {
+        let client = &client;
+        let endpoint = &endpoint;
+        async move {
+            let request = Echo { text: i.to_string() };
+            client.call_timeout::<_, String>(endpoint, &request, Duration::from_secs(5)).await
+        }
+    });
+    let responses = futures::future::join_all(calls).await;
+    for (i, response) in responses.into_iter().enumerate() {
+        assert_eq!(response.unwrap(), format!("echo: {}", i));
+    }
+}
+
+#[tokio::test]
+async fn test_rpc_remote_errors() {
+    let mut server = RpcServer::new();
+    server.register(|req: Lookup, _ctx: RpcContext| async move {
+        Err::<String, _>(AegisError::NotFound(req.key))
+    });
+    let (client, endpoint) = start(server);
+
+    let err = client
+        .call_timeout::<_, String>(&endpoint, &Lookup { key: "missing".into() }, Duration::from_secs(5))
+        .await
+        .unwrap_err();
+    match &err {
+        RpcError::Remote(remote) => assert_eq!(remote.code, ErrorCode::NotFound),
+        other => panic!("unexpected error {:?}", other),
+    }
+    assert!(matches!(AegisError::from(err), AegisError::NotFound(_)));
+
+    let err = client
+        .call_timeout::<_, String>(&endpoint, &Echo { text: "hi".into() }, Duration::from_secs(5))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, RpcError::Remote(ref remote) if remote.code == ErrorCode::UnknownMethod));
+}
+
+#[tokio::test]
+async fn test_rpc_deadline_exceeded() {
+    let finished = Arc::new(AtomicBool::new(false));
+    let mut server = RpcServer::new();
+    let flag = finished.clone();
+    server.register(move |req: Sleep, _ctx: RpcContext| {
+        let flag = flag.clone();
+        async move {
+            tokio::time::sleep(Duration::from_millis(req.millis)).await;
+            flag.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    });
+    let (client, endpoint) = start(server);
+
+    let started = Instant::now();
+    let err = client
+        .call_timeout::<_, ()>(&endpoint, &Sleep { millis: 2000 }, Duration::from_millis(100))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, RpcError::DeadlineExceeded));
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    // The handler is stopped rather than left running
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    assert!(!finished.load(Ordering::SeqCst));
+
+    // An expired deadline fails without sending
+    let err = client
+        .call::<_, ()>(&endpoint, &Sleep { millis: 0 }, Instant::now())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, RpcError::DeadlineExceeded));
+}
+
+#[tokio::test]
+async fn test_rpc_cancellation_aborts_handler() {
+    let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+    let started_tx = Arc::new(std::sync::Mutex::new(Some(started_tx)));
+    let (dropped_tx, dropped_rx) = tokio::sync::oneshot::channel::<()>();
+    let dropped_tx = Arc::new(std::sync::Mutex::new(Some(dropped_tx)));
+
+    /// Signals when the handler future is dropped
+    struct OnDrop(Option<tokio::sync::oneshot::Sender<()>>);
+    impl Drop for OnDrop {
+        fn drop(&mut self) {
+            if let Some(tx) = self.0.take() {
+                let _ = tx.send(());
+            }
+        }
+    }
+
+    let mut server = RpcServer::new();
+    server.register(move |_req: Sleep, _ctx: RpcContext| {
+        let started = started_tx.lock().unwrap().take();
+        let guard = OnDrop(dropped_tx.lock().unwrap().take());
+        async move {
+            let _guard = guard;
+            if let Some(tx) = started {
+                let _ = tx.send(());
+            }
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        }
+    });
+    let (client, endpoint) = start(server);
+
+    let call = client.call_timeout::<_, ()>(&endpoint, &Sleep { millis: 0 }, Duration::from_secs(60));
+    tokio::select! {
+        _ = call => panic!("call should not complete"),
+        _ = started_rx => {}
+    }
+
+    // Dropping the call cancels the handler on the server
+    tokio::time::timeout(Duration::from_secs(5), dropped_rx)
+        .await
+        .expect("handler was not cancelled")
+        .unwrap();
+
+    // The connection is still usable afterwards
+    let err = client
+        .call_timeout::<_, ()>(&endpoint, &Lookup { key: "x".into() }, Duration::from_secs(5))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, RpcError::Remote(ref remote) if remote.code == ErrorCode::UnknownMethod));
+}
+
+#[tokio::test]
+async fn test_rpc_deadline_propagates_to_handler() {
+    let mut server = RpcServer::new();
+    server.register(|_req: Echo, ctx: RpcContext| async move {
+        Ok(ctx.remaining().as_millis() as u64)
+    });
+    let (client, endpoint) = start(server);
+
+    let remaining: u64 = client
+        .call_timeout(&endpoint, &Echo { text: String::new() }, Duration::from_secs(3))
+        .await
+        .unwrap();
+    assert!(remaining > 2000 && remaining <= 3000, "remaining {}", remaining);
+}
+
+#[tokio::test]
+async fn test_rpc_disconnect_fails_pending_calls() {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("flaky").unwrap();
+    let client = RpcClient::new(network.connector());
+
+    // Accept and then drop the connection without answering
+    tokio::spawn(async move {
+        use aegis_comms::MessageListener;
+        let (stream, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(stream);
+    });
+
+    let err = client
+        .call_timeout::<_, String>(&Endpoint::memory("flaky"), &Echo { text: "hi".into() }, Duration::from_secs(5))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, RpcError::Disconnected));
+}
+
+#[tokio::test]
+async fn test_rpc_request_codec_overrides_negotiated() {
+    let mut server = RpcServer::new();
+    server.register(|req: Annotate, _ctx: RpcContext| async move { Ok(req.note) });
+    let (client, endpoint) = start(server);
+
+    // Bincode is negotiated, but cannot decode a serde_json::Value
+    let note = serde_json::json!({"level": "info", "tags": ["a", "b"]});
+    let response: serde_json::Value = client
+        .call_timeout(&endpoint, &Annotate { note: note.clone() }, Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(response, note);
+    assert_eq!(client.connection(&endpoint).await.unwrap().codec(), CodecId::Bincode);
+}
+
+#[tokio::test]
+async fn test_rpc_uses_negotiated_codec() {
+    let mut server = RpcServer::new();
+    server.register(|req: Negotiated, _ctx: RpcContext| async move { Ok(req.note) });
+
+    let network = MemoryNetwork::new();
+    let listener = network.bind("rpc").unwrap();
+    tokio::spawn(async move { MessageServer::new(ServerConfig::default()).serve_rpc(listener, server).await });
+
+    // A client that only decodes JSON is answered in JSON
+    let stream = network.connector().connect_endpoint(&Endpoint::memory("rpc")).await.unwrap();
+    let framing = FramingConfig {
+        codecs: vec![CodecId::Json],
+        ..FramingConfig::default()
+    };
+    let connection = RpcConnection::with_config(stream, framing);
+
+    // Until the server has advertised its codecs the client sends bincode
+    let note = serde_json::json!({"id": 1});
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let first = connection.call::<_, serde_json::Value>(&Negotiated { note: note.clone() }, deadline).await;
+    assert!(matches!(first, Err(RpcError::Remote(ref remote)) if remote.code == ErrorCode::InvalidRequest));
+    assert_eq!(connection.codec(), CodecId::Json);
+
+    let response: serde_json::Value = connection.call(&Negotiated { note: note.clone() }, deadline).await.unwrap();
+    assert_eq!(response, note);
+}
+
+#[tokio::test]
+async fn test_shutdown_lets_running_calls_respond() {
+    let started = Arc::new(AtomicBool::new(false));
+    let mut server = RpcServer::new();
+    let on_start = started.clone();
+    server.register(move |req: Sleep, _ctx: RpcContext| {
+        on_start.store(true, Ordering::SeqCst);
+        async move {
+            tokio::time::sleep(Duration::from_millis(req.millis)).await;
+            Ok(req.millis)
+        }
+    });
+
+    let network = MemoryNetwork::new();
+    let listener = network.bind("rpc").unwrap();
+    let message_server = MessageServer::new(ServerConfig::default());
+    let handle = message_server.handle();
+    let task = tokio::spawn(async move { message_server.serve_rpc(listener, server).await });
+
+    let client = RpcClient::new(network.connector());
+    let endpoint = Endpoint::memory("rpc");
+    let call = tokio::spawn(async move {
+        client.call_timeout::<_, u64>(&endpoint, &Sleep { millis: 200 }, Duration::from_secs(5)).await
+    });
+    while !started.load(Ordering::SeqCst) {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    // The running call is answered before the server stops
+    tokio::time::timeout(Duration::from_secs(5), handle.shutdown()).await.unwrap();
+    assert_eq!(call.await.unwrap().unwrap(), 200);
+    assert_eq!(handle.active_connections(), 0);
+    tokio::time::timeout(Duration::from_secs(1), task).await.unwrap().unwrap().unwrap();
+}
@@ -0,0 +1,214 @@
+#![cfg(feature = "memory")]
+
+use aegis_comms::{
+    CommsClient, CommsError, FrameType, FramedMessageStream, FramingConfig, MemoryNetwork, MessageServer,
+    MessageStream, NetworkConnector, PeerInfo, ServerConfig, ServerHandle, TransportAddr,
+};
+use bytes::Bytes;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Wait until `condition` holds
+async fn eventually(mut condition: impl FnMut() -> bool) {
+    for _ in 0..200 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    panic!("condition not reached");
+}
+
+/// Serve on `network` as "server", handling each message for `handle_time`
+fn start(
+    network: &MemoryNetwork,
+    config: ServerConfig,
+    handle_time: Duration,
+) -> (ServerHandle, JoinHandle<()>, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+    let server = MessageServer::new(config);
+    let handle = server.handle();
+    let started = Arc::new(AtomicUsize::new(0));
+    let finished = Arc::new(AtomicUsize::new(0));
+
+    let listener = network.bind("server").unwrap();
+    let (on_start, on_finish) = (started.clone(), finished.clone());
+    let task = tokio::spawn(async move {
+        let handler = move |_: String, _: PeerInfo| {
+            let (started, finished) = (on_start.clone(), on_finish.clone());
+            async move {
+                started.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(handle_time).await;
+                finished.fetch_add(1, Ordering::SeqCst);
+            }
+        };
+        server.serve(listener, handler).await.unwrap();
+    });
+    (handle, task, started, finished)
+}
+
+async fn connect(network: &MemoryNetwork) -> FramedMessageStream<Box<dyn MessageStream>> {
+    let stream = network
+        .connector()
+        .connect(TransportAddr::Memory("server".to_string()))
+        .await
+        .unwrap();
+    FramedMessageStream::with_config(stream, FramingConfig::default())
+}
+
+async fn send(client: &mut FramedMessageStream<Box<dyn MessageStream>>, message: &str) {
+    let bytes = bincode::serialize(message).unwrap();
+    client.write_framed_message(Bytes::from(bytes)).await.unwrap();
+}
+
+/// Whether the server closed the connection; credit it granted is skipped
+async fn closed(client: &mut FramedMessageStream<Box<dyn MessageStream>>) -> bool {
+    loop {
+        match tokio::time::timeout(Duration::from_secs(1), client.read_frame()).await {
+            Ok(Ok(Some(frame))) if frame.frame_type == FrameType::Control => continue,
+            Ok(Ok(None)) | Ok(Err(_)) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// Mirror of the control messages the server writes
+#[derive(Debug, PartialEq, Deserialize)]
+enum Control {
+    Credit(u32),
+}
+
+/// Next credit granted by the server
+async fn credit(client: &mut FramedMessageStream<Box<dyn MessageStream>>) -> u32 {
+    let frame = tokio::time::timeout(Duration::from_secs(1), client.read_frame())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(frame.frame_type, FrameType::Control);
+    let Control::Credit(credit) = bincode::deserialize(&frame.payload).unwrap();
+    credit
+}
+
+#[tokio::test]
+async fn test_shutdown_waits_for_in_flight_handlers() {
+    let network = MemoryNetwork::new();
+    let (handle, task, started, finished) = start(&network, ServerConfig::default(), Duration::from_millis(100));
+
+    let mut client = connect(&network).await;
+    send(&mut client, "audit").await;
+    eventually(|| started.load(Ordering::SeqCst) == 1).await;
+    assert_eq!(handle.active_connections(), 1);
+
+    handle.shutdown().await;
+    assert!(handle.is_shutting_down());
+    // The handler was allowed to finish, and the server has returned
+    assert_eq!(finished.load(Ordering::SeqCst), 1);
+    assert_eq!(handle.active_connections(), 0);
+    tokio::time::timeout(Duration::from_secs(1), task).await.unwrap().unwrap();
+    assert!(closed(&mut client).await);
+}
+
+#[tokio::test]
+async fn test_drain_timeout_aborts_stuck_handlers() {
+    let network = MemoryNetwork::new();
+    let config = ServerConfig {
+        drain_timeout: Duration::from_millis(50),
+        ..ServerConfig::default()
+    };
+    let (handle, _task, started, finished) = start(&network, config, Duration::from_secs(60));
+
+    let mut client = connect(&network).await;
+    send(&mut client, "audit").await;
+    eventually(|| started.load(Ordering::SeqCst) == 1).await;
+
+    tokio::time::timeout(Duration::from_secs(1), handle.shutdown()).await.unwrap();
+    assert_eq!(finished.load(Ordering::SeqCst), 0);
+    assert_eq!(handle.active_connections(), 0);
+}
+
+#[tokio::test]
+async fn test_connections_beyond_limit_are_rejected() {
+    let network = MemoryNetwork::new();
+    let config = ServerConfig {
+        max_connections: 1,
+        ..ServerConfig::default()
+    };
+    let (handle, _task, started, _) = start(&network, config, Duration::ZERO);
+
+    let mut first = connect(&network).await;
+    eventually(|| handle.active_connections() == 1).await;
+    let mut second = connect(&network).await;
+    assert!(closed(&mut second).await);
+    assert_eq!(handle.rejected_connections(), 1);
+
+    // The first connection is still served, and frees its slot when closed
+    send(&mut first, "audit").await;
+    eventually(|| started.load(Ordering::SeqCst) == 1).await;
+    first.shutdown().await.unwrap();
+    drop(first);
+    eventually(|| handle.active_connections() == 0).await;
+
+    let mut third = connect(&network).await;
+    send(&mut third, "audit").await;
+    eventually(|| started.load(Ordering::SeqCst) == 2).await;
+    assert_eq!(handle.rejected_connections(), 1);
+}
+
+#[tokio::test]
+async fn test_idle_connections_are_closed() {
+    let network = MemoryNetwork::new();
+    let config = ServerConfig {
+        idle_timeout: Some(Duration::from_millis(100)),
+        ..ServerConfig::default()
+    };
+    let (handle, _task, started, _) = start(&network, config, Duration::ZERO);
+
+    let mut busy = connect(&network).await;
+    let mut idle = connect(&network).await;
+    eventually(|| handle.active_connections() == 2).await;
+
+    // Traffic keeps a connection open past the idle timeout
+    for _ in 0..4 {
+        send(&mut busy, "audit").await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(closed(&mut idle).await);
+    assert_eq!(started.load(Ordering::SeqCst), 4);
+    eventually(|| handle.active_connections() == 1).await;
+}
+
+#[tokio::test]
+async fn test_v2_peers_are_granted_credit_as_messages_are_handled() {
+    let network = MemoryNetwork::new();
+    let config = ServerConfig {
+        receive_window: 4,
+        ..ServerConfig::default()
+    };
+    let (_handle, _task, started, _) = start(&network, config, Duration::ZERO);
+
+    // The window is granted once the peer wrote a v2 frame
+    let mut client = connect(&network).await;
+    send(&mut client, "audit").await;
+    assert_eq!(credit(&mut client).await, 4);
+
+    // More is granted once half the window was handled
+    send(&mut client, "audit").await;
+    assert_eq!(credit(&mut client).await, 2);
+    assert_eq!(started.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+#[allow(deprecated)]
+async fn test_start_listener_checks_its_address() {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("server").unwrap();
+    let client = CommsClient::new(network.connector());
+
+    let result = client
+        .start_listener(TransportAddr::Memory("other".to_string()), listener, |_: String, _: PeerInfo| async {})
+        .await;
+    assert!(matches!(result, Err(CommsError::Network(_))));
+}
@@ -0,0 +1,242 @@
+#![cfg(feature = "tls")]
+
+use aegis_comms::{listen_tls, connect_tls, MessageListener, NetworkConnector, NetworkError};
+use aegis_core::config::TlsConfig;
+use bytes::Bytes;
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, Issuer, KeyPair, SanType};
+use std::path::{Path, PathBuf};
+
+// Test CA issuing server and client certificates into a directory
+struct TestPki {
+    dir: PathBuf,
+    issuer: Issuer<'static, KeyPair>,
+}
+
+impl TestPki {
+    fn new(dir: &Path) -> Self {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params.distinguished_name.push(DnType::CommonName, "aegis test ca");
+        let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca_cert.pem()).unwrap();
+
+        TestPki {
+            dir: dir.to_path_buf(),
+            issuer: Issuer::new(ca_params, ca_key),
+        }
+    }
+
+    // Issue a certificate and return a TLS config using it
+    fn issue(&self, name: &str, dns_names: &[&str], uris: &[&str]) -> TlsConfig {
+        let key = KeyPair::generate().unwrap();
+        let mut params =
+            CertificateParams::new(dns_names.iter().map(|n| n.to_string()).collect::<Vec<_>>()).unwrap();
+        params.distinguished_name.push(DnType::CommonName, name);
+        for uri in uris {
+            params.subject_alt_names.push(SanType::URI(uri.to_string().try_into().unwrap()));
+        }
+        let cert = params.signed_by(&key, &self.issuer).unwrap();
+
+        let tls = TlsConfig {
+            cert_path: self.dir.join(format!("{}.pem", name)),
+            key_path: self.dir.join(format!("{}.key", name)),
+            ca_path: Some(self.dir.join("ca.pem")),
+            server_name: Some("node.aegis.test".to_string()),
+            require_client_cert: false,
+            crl_path: None,
+        };
+
+        std::fs::write(&tls.cert_path, cert.pem()).unwrap();
+        std::fs::write(&tls.key_path, key.serialize_pem()).unwrap();
+
+        tls
+    }
+}
+
+#[tokio::test]
+async fn test_tls_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let pki = TestPki::new(dir.path());
+    let tls = pki.issue("server", &["node.aegis.test"], &[]);
+
+    let mut listener = listen_tls("127.0.0.1:0".parse().unwrap(), &tls).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let msg = stream.read_message().await.unwrap().unwrap();
+        stream.write_message(msg).await.unwrap();
+    });
+
+    let connector = connect_tls(&tls).unwrap();
+    let mut stream = connector.connect(addr).await.unwrap();
+    stream.write_message(Bytes::from_static(b"policy")).await.unwrap();
+
+    let echoed = stream.read_message().await.unwrap().unwrap();
+    assert_eq!(echoed, Bytes::from_static(b"policy"));
+
+    // The client always sees the verified server identity
+    let server_identity = stream.peer_identity().unwrap();
+    assert_eq!(server_identity.subject, "server");
+    assert_eq!(server_identity.id(), "node.aegis.test");
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_stalled_handshake_does_not_block_other_clients() {
+    let dir = tempfile::tempdir().unwrap();
+    let pki = TestPki::new(dir.path());
+    let tls = pki.issue("server", &["node.aegis.test"], &[]);
+
+    let listener = listen_tls("127.0.0.1:0".parse().unwrap(), &tls).await.unwrap();
+    let mut listener = listener.with_handshake_timeout(std::time::Duration::from_secs(30));
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        stream.read_message().await.unwrap()
+    });
+
+    // Connects first but never starts the handshake
+    let socket = match addr {
+        aegis_comms::TransportAddr::Tcp(addr) => addr,
+        other => panic!("unexpected address {:?}", other),
+    };
+    let _silent = tokio::net::TcpStream::connect(socket).await.unwrap();
+
+    let mut stream = connect_tls(&tls).unwrap().connect(addr).await.unwrap();
+    stream.write_message(Bytes::from_static(b"policy")).await.unwrap();
+    let received = tokio::time::timeout(std::time::Duration::from_secs(5), server).await.unwrap();
+    assert_eq!(received.unwrap(), Some(Bytes::from_static(b"policy")));
+}
+
+#[tokio::test]
+async fn test_tls_rejects_wrong_server_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let pki = TestPki::new(dir.path());
+    let tls = pki.issue("server", &["other.aegis.test"], &[]);
+
+    let mut listener = listen_tls("127.0.0.1:0".parse().unwrap(), &tls).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let _ = listener.accept().await;
+    });
+
+    let result = connect_tls(&tls).unwrap().connect(addr).await;
+    assert!(matches!(result, Err(NetworkError::Tls(_))));
+}
+
+#[test]
+fn test_client_requires_ca() {
+    let dir = tempfile::tempdir().unwrap();
+    let pki = TestPki::new(dir.path());
+    let mut tls = pki.issue("server", &["node.aegis.test"], &[]);
+    tls.ca_path = None;
+
+    assert!(matches!(connect_tls(&tls), Err(NetworkError::Tls(_))));
+}
+
+#[tokio::test]
+async fn test_mutual_tls_exposes_client_identity() {
+    let dir = tempfile::tempdir().unwrap();
+    let pki = TestPki::new(dir.path());
+    let mut server_tls = pki.issue("server", &["node.aegis.test"], &[]);
+    server_tls.require_client_cert = true;
+    let client_tls = pki.issue("admin", &[], &["spiffe://aegis.test/node/admin"]);
+
+    let mut listener = listen_tls("127.0.0.1:0".parse().unwrap(), &server_tls).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        stream.peer_identity()
+    });
+
+    let _stream = connect_tls(&client_tls).unwrap().connect(addr).await.unwrap();
+
+    let identity = server.await.unwrap().unwrap();
+    assert_eq!(identity.subject, "admin");
+    assert_eq!(identity.spiffe_id.as_deref(), Some("spiffe://aegis.test/node/admin"));
+    assert_eq!(identity.id(), "spiffe://aegis.test/node/admin");
+    assert!(identity.matches_any(&["spiffe://aegis.test/node/admin".to_string()]));
+    assert_eq!(identity.fingerprint.len(), 64);
+}
+
+#[tokio::test]
+async fn test_mutual_tls_rejects_untrusted_client() {
+    let dir = tempfile::tempdir().unwrap();
+    let pki = TestPki::new(dir.path());
+    let mut server_tls = pki.issue("server", &["node.aegis.test"], &[]);
+    server_tls.require_client_cert = true;
+
+    // Client certificate issued by a different CA
+    let other_dir = tempfile::tempdir().unwrap();
+    let other = TestPki::new(other_dir.path());
+    let mut client_tls = other.issue("intruder", &[], &[]);
+    client_tls.ca_path = server_tls.ca_path.clone();
+
+    let mut listener = listen_tls("127.0.0.1:0".parse().unwrap(), &server_tls).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let _ = listener.accept().await;
+    });
+
+    // TLS 1.3 reports client authentication failures on first use
+    let result = match connect_tls(&client_tls).unwrap().connect(addr).await {
+        Ok(mut stream) => match stream.read_message().await {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => Err(NetworkError::ConnectionClosed),
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    assert!(result.is_err());
+}
+
+// Network resolving every host name to loopback
+struct LoopbackResolver;
+
+#[async_trait::async_trait]
+impl aegis_core::platform::Network for LoopbackResolver {
+    async fn connect_tcp(&self, _host: &str, _port: u16) -> aegis_core::error::AegisResult<Box<dyn aegis_core::platform::TcpStream>> {
+        Err(aegis_core::error::AegisError::Platform("connect_tcp".to_string()))
+    }
+
+    async fn listen_tcp(&self, _host: &str, _port: u16) -> aegis_core::error::AegisResult<Box<dyn aegis_core::platform::TcpListener>> {
+        Err(aegis_core::error::AegisError::Platform("listen_tcp".to_string()))
+    }
+
+    async fn resolve_host(&self, _host: &str) -> aegis_core::error::AegisResult<Vec<String>> {
+        Ok(vec!["127.0.0.1".to_string()])
+    }
+}
+
+#[tokio::test]
+async fn test_tls_endpoint_verifies_host_name() {
+    use aegis_comms::{ConnectorRegistry, Endpoint};
+    use std::sync::Arc;
+
+    let dir = tempfile::tempdir().unwrap();
+    let pki = TestPki::new(dir.path());
+    let mut tls = pki.issue("server", &["node.aegis.test"], &[]);
+    tls.server_name = None;
+
+    let mut listener = listen_tls("127.0.0.1:0".parse().unwrap(), &tls).await.unwrap();
+    let port = listener.local_addr().unwrap().as_socket_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let _ = stream.write_message(Bytes::from_static(b"hello")).await;
+        }
+    });
+
+    let registry = ConnectorRegistry::new()
+        .with_connector("tls", connect_tls(&tls).unwrap())
+        .with_network(Arc::new(LoopbackResolver));
+
+    // The host name from the endpoint is checked against the certificate
+    let mut stream = registry.connect_endpoint(&Endpoint::tls("node.aegis.test", port)).await.unwrap();
+    assert_eq!(stream.read_message().await.unwrap().unwrap(), Bytes::from_static(b"hello"));
+
+    let result = registry.connect_endpoint(&Endpoint::tls("other.aegis.test", port)).await;
+    assert!(matches!(result, Err(NetworkError::Tls(_))));
+}
@@ -0,0 +1,100 @@
+#![cfg(all(unix, feature = "unix"))]
+
+use aegis_comms::{
+    connect_unix, listen_unix, MessageListener, NetworkConnector, NetworkError, TokioUnixListener, TransportAddr,
+};
+use bytes::Bytes;
+use std::os::unix::fs::PermissionsExt;
+
+#[tokio::test]
+async fn test_unix_round_trip_with_peer_credentials() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("agent.sock");
+
+    let mut listener = listen_unix(&path).unwrap();
+    assert_eq!(listener.local_addr().unwrap(), TransportAddr::unix(&path));
+
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let credentials = stream.peer_credentials();
+        let msg = stream.read_message().await.unwrap().unwrap();
+        stream.write_message(msg).await.unwrap();
+        credentials
+    });
+
+    let mut stream = connect_unix().connect(TransportAddr::unix(&path)).await.unwrap();
+    stream.write_message(Bytes::from_static(b"policy")).await.unwrap();
+    assert_eq!(stream.read_message().await.unwrap().unwrap(), Bytes::from_static(b"policy"));
+
+    // Both ends see the credentials of this process
+    let credentials = server.await.unwrap().unwrap();
+    assert_eq!(credentials.uid, current_uid(dir.path()));
+    assert_eq!(credentials.pid, Some(std::process::id() as i32));
+    assert_eq!(stream.peer_credentials().unwrap().uid, credentials.uid);
+    assert_eq!(stream.peer_addr().unwrap(), TransportAddr::unix(&path));
+}
+
+#[tokio::test]
+async fn test_unix_socket_is_owner_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("agent.sock");
+
+    let listener = listen_unix(&path).unwrap();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    // A live socket cannot be taken over
+    assert!(TokioUnixListener::bind(&path).is_err());
+    drop(listener);
+    assert!(!path.exists());
+
+    // A stale socket left behind by a dead process is replaced
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+    let listener = TokioUnixListener::bind_with_mode(&path, 0o660).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o660);
+
+    drop(listener);
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn test_unix_listener_rejects_other_uids() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("agent.sock");
+
+    let other_uid = current_uid(dir.path()).wrapping_add(1);
+    let mut listener = listen_unix(&path).unwrap().with_allowed_uids(vec![other_uid]);
+
+    let connector = connect_unix();
+    let mut stream = connector.connect(TransportAddr::unix(&path)).await.unwrap();
+
+    let accepted = tokio::time::timeout(std::time::Duration::from_millis(200), listener.accept()).await;
+    assert!(accepted.is_err());
+    assert_eq!(listener.rejected_connections(), 1);
+
+    // The rejected connection is closed
+    assert!(matches!(stream.read_message().await, Ok(None) | Err(_)));
+}
+
+#[tokio::test]
+async fn test_connectors_reject_foreign_addresses() {
+    let tcp = TransportAddr::Tcp("127.0.0.1:1".parse().unwrap());
+    assert!(matches!(
+        connect_unix().connect(tcp).await,
+        Err(NetworkError::UnsupportedAddress(_))
+    ));
+
+    let missing = tempfile::tempdir().unwrap().path().join("missing.sock");
+    assert!(matches!(
+        connect_unix().connect(TransportAddr::unix(missing)).await,
+        Err(NetworkError::ConnectionRefused)
+    ));
+}
+
+// Uid of this process, as the owner of a directory it created
+fn current_uid(dir: &std::path::Path) -> u32 {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::metadata(dir).unwrap().uid()
+}
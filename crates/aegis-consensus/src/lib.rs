@@ -5,7 +5,8 @@
 
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
-use std::sync::Arc;
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
 use std::marker::PhantomData;
 use aegis_core::error::{AegisError, AegisResult};
 
@@ -30,6 +31,8 @@ pub struct ConsensusClient<S: StateMachine> {
     // This would normally have fields for interacting with the
     // consensus system, but this is a placeholder implementation
     _phantom: PhantomData<S>,
+    /// Agent IDs taking part in consensus, fed by cluster membership
+    members: Arc<RwLock<BTreeSet<String>>>,
 }
 
 impl<S: StateMachine> ConsensusClient<S> {
@@ -37,8 +40,21 @@ impl<S: StateMachine> ConsensusClient<S> {
     pub fn new() -> Self {
         Self {
             _phantom: PhantomData,
+            members: Arc::new(RwLock::new(BTreeSet::new())),
         }
     }
+
+    /// Replace the agents taking part in consensus
+    ///
+    /// Called with the active members whenever cluster membership changes.
+    pub fn set_members(&self, members: impl IntoIterator<Item = String>) {
+        *self.members.write().unwrap() = members.into_iter().collect();
+    }
+
+    /// Agents taking part in consensus, sorted
+    pub fn members(&self) -> Vec<String> {
+        self.members.read().unwrap().iter().cloned().collect()
+    }
     
     /// Submit a command to the consensus system
     pub async fn submit_command(&self, _command: S::Command) -> AegisResult<()> {
@@ -0,0 +1,234 @@
+//! Clock abstraction for the Aegis framework
+//!
+//! Every component that needs the current time should read it through a
+//! [`Clock`] instead of calling `Utc::now()` or `SystemTime::now()` directly.
+//! Production code uses [`SystemClock`] or [`MonotonicClock`], while tests can
+//! substitute a [`TestClock`] and move time forward explicitly.
+
+use chrono::{DateTime, TimeZone, Utc};
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Source of wall-clock and monotonic time
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// Current wall-clock time in UTC
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Time elapsed since the clock was created
+    ///
+    /// Unlike [`Clock::now`], this value never goes backwards and should be
+    /// used for measuring intervals and timeouts.
+    fn elapsed(&self) -> Duration;
+
+    /// Current wall-clock time as milliseconds since the Unix epoch
+    fn now_ms(&self) -> u64 {
+        self.now().timestamp_millis().max(0) as u64
+    }
+
+    /// Current wall-clock time as an RFC 3339 formatted string
+    fn now_rfc3339(&self) -> String {
+        self.now().to_rfc3339()
+    }
+}
+
+/// Clock backed by the operating system's wall clock
+#[derive(Debug, Clone)]
+pub struct SystemClock {
+    /// Instant the clock was created, used for `elapsed`
+    started: Instant,
+}
+
+impl SystemClock {
+    /// Create a new system clock
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// Clock whose wall time is derived from a monotonic source
+///
+/// The wall-clock time is sampled once at creation and then advanced using
+/// `Instant`, so `now()` never jumps backwards when the system time is
+/// adjusted. The trade-off is that it slowly drifts from NTP-corrected time
+/// on long-running processes.
+#[derive(Debug, Clone)]
+pub struct MonotonicClock {
+    /// Wall-clock time when the clock was created
+    anchor: DateTime<Utc>,
+    /// Instant corresponding to `anchor`
+    started: Instant,
+}
+
+impl MonotonicClock {
+    /// Create a new monotonic clock anchored at the current system time
+    pub fn new() -> Self {
+        Self {
+            anchor: Utc::now(),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> DateTime<Utc> {
+        let offset = chrono::Duration::from_std(self.started.elapsed())
+            .unwrap_or_else(|_| chrono::Duration::zero());
+        self.anchor + offset
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// Internal state shared between clones of a `TestClock`
+#[derive(Debug)]
+struct TestClockState {
+    /// Current wall-clock time
+    wall: DateTime<Utc>,
+    /// Current monotonic offset
+    elapsed: Duration,
+}
+
+/// Manually controlled clock for deterministic tests
+///
+/// Time only moves when [`TestClock::advance`] or [`TestClock::set`] is
+/// called. Clones share the same underlying time, so a test can keep one
+/// handle and pass another into the code under test.
+#[derive(Debug, Clone)]
+pub struct TestClock {
+    state: Arc<Mutex<TestClockState>>,
+}
+
+impl TestClock {
+    /// Create a test clock starting at the given time
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(TestClockState {
+                wall: start,
+                elapsed: Duration::ZERO,
+            })),
+        }
+    }
+
+    /// Create a test clock starting at the given Unix timestamp in milliseconds
+    pub fn from_millis(ms: i64) -> Self {
+        Self::new(Utc.timestamp_millis_opt(ms).single().unwrap_or_default())
+    }
+
+    /// Move both the wall clock and the monotonic clock forward
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let offset = chrono::Duration::from_std(duration)
+            .unwrap_or_else(|_| chrono::Duration::zero());
+        state.wall += offset;
+        state.elapsed += duration;
+    }
+
+    /// Set the wall clock to a specific time
+    ///
+    /// The monotonic clock is left untouched, which makes it possible to
+    /// simulate the system time being adjusted backwards.
+    pub fn set(&self, time: DateTime<Utc>) {
+        self.state.lock().unwrap().wall = time;
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::from_millis(0)
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.state.lock().unwrap().wall
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().elapsed
+    }
+}
+
+static SYSTEM_CLOCK: OnceLock<Arc<dyn Clock>> = OnceLock::new();
+
+/// Get the process-wide system clock
+///
+/// This is the clock used by helpers such as
+/// [`current_timestamp`](crate::utils::current_timestamp) that do not take
+/// an explicit clock argument.
+pub fn system_clock() -> Arc<dyn Clock> {
+    SYSTEM_CLOCK.get_or_init(|| Arc::new(SystemClock::new())).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_clock() {
+        let clock = SystemClock::new();
+        let before = Utc::now();
+        let now = clock.now();
+        assert!(now >= before);
+        assert!(clock.now_ms() > 0);
+    }
+
+    #[test]
+    fn test_monotonic_clock_never_goes_backwards() {
+        let clock = MonotonicClock::new();
+        let first = clock.now();
+        let second = clock.now();
+        assert!(second >= first);
+        assert!(clock.elapsed() <= clock.elapsed());
+    }
+
+    #[test]
+    fn test_test_clock_advance() {
+        let clock = TestClock::from_millis(1_000);
+        assert_eq!(clock.now_ms(), 1_000);
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+
+        let handle = clock.clone();
+        handle.advance(Duration::from_millis(250));
+
+        assert_eq!(clock.now_ms(), 1_250);
+        assert_eq!(clock.elapsed(), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_test_clock_set_keeps_monotonic_time() {
+        let clock = TestClock::from_millis(10_000);
+        clock.advance(Duration::from_secs(1));
+        clock.set(Utc.timestamp_millis_opt(5_000).unwrap());
+
+        assert_eq!(clock.now_ms(), 5_000);
+        assert_eq!(clock.elapsed(), Duration::from_secs(1));
+        assert_eq!(clock.now_rfc3339(), "1970-01-01T00:00:05+00:00");
+    }
+}
@@ -0,0 +1,288 @@
+//! Configuration schema versioning and migration
+//!
+//! Every configuration file carries a `config_version`. When the shape of
+//! `AegisConfig` changes, a [`ConfigMigration`] step is added here that
+//! rewrites a file of the previous version into the new shape. Loading an
+//! older file runs the steps in memory; `aegis config migrate` writes the
+//! result back to disk.
+
+use serde_json::Value;
+use std::path::Path;
+
+use crate::error::{AegisError, AegisResult};
+
+/// Version of the configuration format produced by this build
+pub const CURRENT_CONFIG_VERSION: u32 = 1;
+
+/// Name of the version field in configuration files
+pub const VERSION_FIELD: &str = "config_version";
+
+/// A single migration step from `from_version` to `from_version + 1`
+#[derive(Debug, Clone)]
+pub struct ConfigMigration {
+    /// Version this step upgrades from
+    pub from_version: u32,
+    /// Short description of the change
+    pub description: &'static str,
+    /// Function rewriting the configuration in place
+    pub migrate: fn(&mut Value) -> AegisResult<()>,
+}
+
+/// Result of migrating a configuration
+#[derive(Debug, Clone)]
+pub struct MigrationReport {
+    /// Version of the input configuration
+    pub from_version: u32,
+    /// Version of the output configuration
+    pub to_version: u32,
+    /// Descriptions of the steps that were applied, in order
+    pub applied: Vec<String>,
+    /// Configuration before migration
+    pub before: Value,
+    /// Configuration after migration
+    pub after: Value,
+}
+
+impl MigrationReport {
+    /// Whether any migration step was applied
+    pub fn is_noop(&self) -> bool {
+        self.applied.is_empty()
+    }
+
+    /// Unified diff between the configuration before and after migration
+    pub fn diff(&self) -> String {
+        let before = serde_json::to_string_pretty(&self.before).unwrap_or_default() + "\n";
+        let after = serde_json::to_string_pretty(&self.after).unwrap_or_default() + "\n";
+
+        similar::TextDiff::from_lines(&before, &after)
+            .unified_diff()
+            .context_radius(3)
+            .header(
+                &format!("config_version {}", self.from_version),
+                &format!("config_version {}", self.to_version),
+            )
+            .to_string()
+    }
+}
+
+/// Ordered collection of migration steps
+#[derive(Debug, Clone)]
+pub struct MigrationRegistry {
+    /// Steps indexed by their `from_version`
+    steps: Vec<ConfigMigration>,
+    /// Version the registry migrates to
+    target_version: u32,
+}
+
+impl MigrationRegistry {
+    /// Create an empty registry migrating to the given version
+    pub fn new(target_version: u32) -> Self {
+        Self {
+            steps: Vec::new(),
+            target_version,
+        }
+    }
+
+    /// Registry containing all built-in migrations up to `CURRENT_CONFIG_VERSION`
+    pub fn builtin() -> Self {
+        let mut registry = Self::new(CURRENT_CONFIG_VERSION);
+        registry.register(ConfigMigration {
+            from_version: 0,
+            description: "add config_version field",
+            migrate: migrate_v0_to_v1,
+        });
+        registry
+    }
+
+    /// Register a migration step, replacing any step with the same `from_version`
+    pub fn register(&mut self, step: ConfigMigration) {
+        self.steps.retain(|s| s.from_version != step.from_version);
+        self.steps.push(step);
+        self.steps.sort_by_key(|s| s.from_version);
+    }
+
+    /// Version the registry migrates to
+    pub fn target_version(&self) -> u32 {
+        self.target_version
+    }
+
+    /// Migrate a configuration value to the target version
+    pub fn migrate(&self, config: Value) -> AegisResult<MigrationReport> {
+        let from_version = config_version_of(&config)?;
+
+        if from_version > self.target_version {
+            return Err(AegisError::Config(format!(
+                "configuration version {} is newer than the supported version {}",
+                from_version, self.target_version
+            )));
+        }
+
+        let before = config.clone();
+        let mut after = config;
+        let mut applied = Vec::new();
+
+        for version in from_version..self.target_version {
+            let step = self
+                .steps
+                .iter()
+                .find(|s| s.from_version == version)
+                .ok_or_else(|| {
+                    AegisError::Config(format!("no migration registered from config version {}", version))
+                })?;
+
+            (step.migrate)(&mut after)?;
+            set_config_version(&mut after, version + 1)?;
+            applied.push(format!("v{} -> v{}: {}", version, version + 1, step.description));
+        }
+
+        Ok(MigrationReport {
+            from_version,
+            to_version: self.target_version,
+            applied,
+            before,
+            after,
+        })
+    }
+}
+
+impl Default for MigrationRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// Read the version of a configuration value
+///
+/// Files written before versioning was introduced have no version field and
+/// are treated as version 0.
+pub fn config_version_of(config: &Value) -> AegisResult<u32> {
+    let object = config
+        .as_object()
+        .ok_or_else(|| AegisError::Config("configuration must be a JSON object".to_string()))?;
+
+    match object.get(VERSION_FIELD) {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| AegisError::Config(format!("invalid {}: {}", VERSION_FIELD, version))),
+    }
+}
+
+/// Set the version field of a configuration value
+fn set_config_version(config: &mut Value, version: u32) -> AegisResult<()> {
+    config
+        .as_object_mut()
+        .ok_or_else(|| AegisError::Config("configuration must be a JSON object".to_string()))?
+        .insert(VERSION_FIELD.to_string(), Value::from(version));
+    Ok(())
+}
+
+/// v0 files predate versioning and have the same shape as v1
+fn migrate_v0_to_v1(_config: &mut Value) -> AegisResult<()> {
+    Ok(())
+}
+
+/// Migrate a configuration file using the built-in migrations
+///
+/// With `dry_run` the file is left untouched; otherwise, if any step was
+/// applied, the original is saved next to it with a `.bak` extension and the
+/// migrated configuration is written in its place.
+pub fn migrate_config_file(path: &Path, dry_run: bool) -> AegisResult<MigrationReport> {
+    let content = std::fs::read_to_string(path)?;
+    let value: Value = serde_json::from_str(&content)?;
+    let report = MigrationRegistry::builtin().migrate(value)?;
+
+    // Make sure the result is loadable before touching the file
+    serde_json::from_value::<super::AegisConfig>(report.after.clone())?;
+
+    if !dry_run && !report.is_noop() {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+        std::fs::copy(path, &backup)?;
+        std::fs::write(path, serde_json::to_string_pretty(&report.after)?)?;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_config() -> Value {
+        let mut value = serde_json::to_value(super::super::AegisConfig::default()).unwrap();
+        value.as_object_mut().unwrap().remove(VERSION_FIELD);
+        value
+    }
+
+    #[test]
+    fn test_migrate_unversioned_config() {
+        let report = MigrationRegistry::builtin().migrate(legacy_config()).unwrap();
+
+        assert_eq!(report.from_version, 0);
+        assert_eq!(report.to_version, CURRENT_CONFIG_VERSION);
+        assert_eq!(report.applied.len(), 1);
+        assert_eq!(config_version_of(&report.after).unwrap(), CURRENT_CONFIG_VERSION);
+        assert!(report.diff().contains("+  \"config_version\": 1"));
+    }
+
+    #[test]
+    fn test_current_config_is_noop() {
+        let value = serde_json::to_value(super::super::AegisConfig::default()).unwrap();
+        let report = MigrationRegistry::builtin().migrate(value).unwrap();
+
+        assert!(report.is_noop());
+        assert_eq!(report.before, report.after);
+    }
+
+    #[test]
+    fn test_rejects_newer_and_gapped_versions() {
+        let mut value = legacy_config();
+        value[VERSION_FIELD] = Value::from(CURRENT_CONFIG_VERSION + 1);
+        assert!(MigrationRegistry::builtin().migrate(value).is_err());
+
+        // A registry targeting v2 without a v1 -> v2 step cannot migrate
+        let mut registry = MigrationRegistry::builtin();
+        registry.target_version = 2;
+        assert!(registry.migrate(legacy_config()).is_err());
+    }
+
+    #[test]
+    fn test_custom_step() {
+        fn rename_host(config: &mut Value) -> AegisResult<()> {
+            config["network"]["host"] = Value::from("0.0.0.0");
+            Ok(())
+        }
+
+        let mut registry = MigrationRegistry::builtin();
+        registry.target_version = 2;
+        registry.register(ConfigMigration {
+            from_version: 1,
+            description: "bind to all interfaces",
+            migrate: rename_host,
+        });
+
+        let report = registry.migrate(legacy_config()).unwrap();
+        assert_eq!(report.applied.len(), 2);
+        assert_eq!(report.after["network"]["host"], "0.0.0.0");
+        assert_eq!(report.after[VERSION_FIELD], 2);
+    }
+
+    #[test]
+    fn test_migrate_file_dry_run_and_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aegis.json");
+        std::fs::write(&path, serde_json::to_string_pretty(&legacy_config()).unwrap()).unwrap();
+
+        let report = migrate_config_file(&path, true).unwrap();
+        assert!(!report.is_noop());
+        let on_disk: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(config_version_of(&on_disk).unwrap(), 0);
+
+        migrate_config_file(&path, false).unwrap();
+        let on_disk: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(config_version_of(&on_disk).unwrap(), CURRENT_CONFIG_VERSION);
+        assert!(dir.path().join("aegis.json.bak").exists());
+    }
+}
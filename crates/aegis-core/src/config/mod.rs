@@ -3,13 +3,23 @@
 //! This module provides configuration-related functionality that is used across
 //! the framework regardless of platform.
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use crate::error::{AegisError, AegisResult};
 
+pub mod migration;
+
+pub use migration::{migrate_config_file, MigrationReport, CURRENT_CONFIG_VERSION};
+
 /// Core configuration structure for the Aegis framework
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AegisConfig {
+    /// Version of the configuration format, see [`migration`]
+    #[serde(default)]
+    pub config_version: u32,
+    
     /// Unique identifier for this instance
     pub instance_id: String,
     
@@ -27,7 +37,7 @@ pub struct AegisConfig {
 }
 
 /// Logging configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LoggingConfig {
     /// Log level
     pub level: LogLevel,
@@ -43,7 +53,7 @@ pub struct LoggingConfig {
 }
 
 /// Log level
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     /// Trace level (most verbose)
@@ -59,7 +69,7 @@ pub enum LogLevel {
 }
 
 /// Log output destination
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase", tag = "type")]
 pub enum LogOutput {
     /// Output to standard output/console
@@ -73,7 +83,7 @@ pub enum LogOutput {
 }
 
 /// Security configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SecurityConfig {
     /// Enable encryption
     pub encryption_enabled: bool,
@@ -86,7 +96,7 @@ pub struct SecurityConfig {
 }
 
 /// Authorization configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AuthorizationConfig {
     /// Authorization mode
     pub mode: AuthMode,
@@ -96,7 +106,7 @@ pub struct AuthorizationConfig {
 }
 
 /// Authorization mode
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum AuthMode {
     /// No authorization checks
@@ -108,7 +118,7 @@ pub enum AuthMode {
 }
 
 /// Network configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NetworkConfig {
     /// Host to bind to
     pub host: String,
@@ -118,22 +128,81 @@ pub struct NetworkConfig {
     
     /// TLS configuration
     pub tls: Option<TlsConfig>,
+    
+    /// Endpoint URIs of known agents by agent ID, e.g. `tls://10.0.0.5:7000`
+    #[serde(default)]
+    pub peers: BTreeMap<String, String>,
+    
+    /// Discovery of agents on the local network
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
+}
+
+/// Agent discovery configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    /// Announce this agent and listen for announcements from others
+    pub enabled: bool,
+    
+    /// Multicast group and port for announcements, e.g. `239.255.77.77:7947`;
+    /// if unset, announcements are only sent to the seeds
+    pub multicast_group: Option<String>,
+    
+    /// Seconds between announcements
+    pub interval_secs: u64,
+    
+    /// Seconds without an announcement after which an agent is forgotten
+    pub ttl_secs: u64,
+    
+    /// `host:port` addresses of discovery services to announce to directly,
+    /// for networks that do not carry multicast
+    pub seeds: Vec<String>,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            multicast_group: Some("239.255.77.77:7947".to_string()),
+            interval_secs: 5,
+            ttl_secs: 30,
+            seeds: Vec::new(),
+        }
+    }
 }
 
 /// TLS configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TlsConfig {
     /// Path to certificate file
     pub cert_path: PathBuf,
     
     /// Path to private key file
     pub key_path: PathBuf,
+    
+    /// Path to CA certificates used to verify peers
+    #[serde(default)]
+    pub ca_path: Option<PathBuf>,
+    
+    /// Server name expected in the peer certificate, defaults to the host
+    #[serde(default)]
+    pub server_name: Option<String>,
+    
+    /// Require clients to present a certificate signed by `ca_path`
+    #[serde(default)]
+    pub require_client_cert: bool,
+    
+    /// Path to certificate revocation lists checked when verifying peers
+    #[serde(default)]
+    pub crl_path: Option<PathBuf>,
 }
 
 /// Default implementation for AegisConfig
 impl Default for AegisConfig {
     fn default() -> Self {
         Self {
+            config_version: CURRENT_CONFIG_VERSION,
             instance_id: format!("aegis-{}", uuid::Uuid::new_v4()),
             base_dir: PathBuf::from("./data"),
             logging: LoggingConfig {
@@ -154,17 +223,144 @@ impl Default for AegisConfig {
                 host: "127.0.0.1".to_string(),
                 port: 8080,
                 tls: None,
+                peers: BTreeMap::new(),
+                discovery: DiscoveryConfig::default(),
             },
         }
     }
 }
 
+/// Prefix for environment variables that override configuration values
+pub const ENV_PREFIX: &str = "AEGIS_";
+
+impl AegisConfig {
+    /// Check the configuration for values that cannot work at runtime
+    pub fn validate(&self) -> AegisResult<()> {
+        if self.config_version > CURRENT_CONFIG_VERSION {
+            return Err(AegisError::Config(format!(
+                "config_version {} is newer than the supported version {}",
+                self.config_version, CURRENT_CONFIG_VERSION
+            )));
+        }
+        
+        if self.instance_id.trim().is_empty() {
+            return Err(AegisError::Config("instance_id must not be empty".to_string()));
+        }
+        
+        if self.network.host.trim().is_empty() {
+            return Err(AegisError::Config("network.host must not be empty".to_string()));
+        }
+        
+        if self.network.port == 0 {
+            return Err(AegisError::Config("network.port must not be 0".to_string()));
+        }
+        
+        if self.logging.max_file_size == Some(0) {
+            return Err(AegisError::Config("logging.max_file_size must be greater than 0".to_string()));
+        }
+        
+        if self.logging.max_files == Some(0) {
+            return Err(AegisError::Config("logging.max_files must be greater than 0".to_string()));
+        }
+        
+        if let Some(tls) = &self.network.tls {
+            if tls.cert_path.as_os_str().is_empty() || tls.key_path.as_os_str().is_empty() {
+                return Err(AegisError::Config(
+                    "network.tls requires both cert_path and key_path".to_string(),
+                ));
+            }
+            
+            if tls.require_client_cert && tls.ca_path.is_none() {
+                return Err(AegisError::Config(
+                    "network.tls.require_client_cert requires ca_path".to_string(),
+                ));
+            }
+        }
+        
+        let discovery = &self.network.discovery;
+        if discovery.enabled {
+            if discovery.interval_secs == 0 {
+                return Err(AegisError::Config("network.discovery.interval_secs must not be 0".to_string()));
+            }
+            
+            if discovery.ttl_secs <= discovery.interval_secs {
+                return Err(AegisError::Config(
+                    "network.discovery.ttl_secs must be greater than interval_secs".to_string(),
+                ));
+            }
+            
+            if discovery.multicast_group.is_none() && discovery.seeds.is_empty() {
+                return Err(AegisError::Config(
+                    "network.discovery requires a multicast_group or seeds".to_string(),
+                ));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Apply overrides from `AEGIS_*` environment variables
+    ///
+    /// Supported variables are `AEGIS_INSTANCE_ID`, `AEGIS_BASE_DIR`,
+    /// `AEGIS_LOG_LEVEL`, `AEGIS_NETWORK_HOST` and `AEGIS_NETWORK_PORT`.
+    pub fn apply_env_overrides(&mut self) -> AegisResult<()> {
+        self.apply_overrides(|name| std::env::var(format!("{}{}", ENV_PREFIX, name)).ok())
+    }
+    
+    /// Apply overrides using the given variable lookup
+    fn apply_overrides<F>(&mut self, lookup: F) -> AegisResult<()>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(instance_id) = lookup("INSTANCE_ID") {
+            self.instance_id = instance_id;
+        }
+        
+        if let Some(base_dir) = lookup("BASE_DIR") {
+            self.base_dir = PathBuf::from(base_dir);
+        }
+        
+        if let Some(level) = lookup("LOG_LEVEL") {
+            self.logging.level = serde_json::from_value(serde_json::Value::String(level.to_lowercase()))
+                .map_err(|_| AegisError::Config(format!("Invalid {}LOG_LEVEL: {}", ENV_PREFIX, level)))?;
+        }
+        
+        if let Some(host) = lookup("NETWORK_HOST") {
+            self.network.host = host;
+        }
+        
+        if let Some(port) = lookup("NETWORK_PORT") {
+            self.network.port = port
+                .parse()
+                .map_err(|_| AegisError::Config(format!("Invalid {}NETWORK_PORT: {}", ENV_PREFIX, port)))?;
+        }
+        
+        Ok(())
+    }
+}
+
 /// Load configuration from a file
+///
+/// Files written by older versions are migrated in memory and a warning is
+/// logged; run `aegis config migrate` to update the file on disk.
 pub fn load_config(path: &PathBuf) -> AegisResult<AegisConfig> {
     let content = std::fs::read_to_string(path)
         .map_err(|e| AegisError::Io(e))?;
     
-    serde_json::from_str(&content)
+    let value: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| AegisError::Serialization(e))?;
+    
+    let report = migration::MigrationRegistry::builtin().migrate(value)?;
+    if !report.is_noop() {
+        crate::logging::log_warn(&format!(
+            "Configuration file {} uses config_version {}; migrated in memory to {}. Run `aegis config migrate` to update it",
+            path.display(),
+            report.from_version,
+            report.to_version
+        ));
+    }
+    
+    serde_json::from_value(report.after)
         .map_err(|e| AegisError::Serialization(e))
 }
 
@@ -198,4 +394,74 @@ mod tests {
         
         assert_eq!(deserialized.network.port, config.network.port);
     }
-} 
\ No newline at end of file
+    
+    #[test]
+    fn test_validate() {
+        let mut config = AegisConfig::default();
+        assert!(config.validate().is_ok());
+        
+        config.network.port = 0;
+        assert!(config.validate().is_err());
+        
+        let mut config = AegisConfig::default();
+        config.instance_id = " ".to_string();
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_validate_discovery() {
+        let mut config = AegisConfig::default();
+        config.network.discovery.enabled = true;
+        assert!(config.validate().is_ok());
+        
+        config.network.discovery.ttl_secs = config.network.discovery.interval_secs;
+        assert!(config.validate().is_err());
+        
+        let mut config = AegisConfig::default();
+        config.network.discovery.enabled = true;
+        config.network.discovery.multicast_group = None;
+        assert!(config.validate().is_err());
+        
+        config.network.discovery.seeds = vec!["10.0.0.5:7947".to_string()];
+        assert!(config.validate().is_ok());
+        
+        // Older files without a discovery section still load
+        let json = r#"{"host": "0.0.0.0", "port": 7000, "tls": null}"#;
+        let network: NetworkConfig = serde_json::from_str(json).unwrap();
+        assert!(!network.discovery.enabled);
+    }
+    
+    #[test]
+    fn test_overrides() {
+        let mut config = AegisConfig::default();
+        config
+            .apply_overrides(|name| match name {
+                "LOG_LEVEL" => Some("DEBUG".to_string()),
+                "NETWORK_PORT" => Some("9090".to_string()),
+                _ => None,
+            })
+            .unwrap();
+        
+        assert_eq!(config.logging.level, LogLevel::Debug);
+        assert_eq!(config.network.port, 9090);
+        
+        let result = config.apply_overrides(|name| match name {
+            "NETWORK_PORT" => Some("not-a-port".to_string()),
+            _ => None,
+        });
+        assert!(result.is_err());
+    }
+    
+    #[test]
+    fn test_load_migrates_legacy_config() {
+        let mut value = serde_json::to_value(AegisConfig::default()).unwrap();
+        value.as_object_mut().unwrap().remove("config_version");
+        
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(value.to_string().as_bytes()).unwrap();
+        
+        let config = load_config(&file.path().to_path_buf()).unwrap();
+        assert_eq!(config.config_version, CURRENT_CONFIG_VERSION);
+        assert!(config.validate().is_ok());
+    }
+}
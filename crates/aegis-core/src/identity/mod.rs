@@ -0,0 +1,214 @@
+//! Node identity for the Aegis framework
+//!
+//! Each node owns an Ed25519 key pair stored in the key store under
+//! [`IDENTITY_KEY_NAME`]. The node ID is derived from the public key, so it
+//! stays stable across restarts and cannot be claimed by another node
+//! without the private key.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::error::{AegisError, AegisResult};
+use crate::keystore::{key_fingerprint, KeyAlgorithm, KeyStore};
+use crate::utils::{format_hex, sha256_hash};
+
+/// Name of the key store entry holding the node identity key
+pub const IDENTITY_KEY_NAME: &str = "node-identity";
+
+/// Length of an Ed25519 signature in bytes
+pub const SIGNATURE_LENGTH: usize = ed25519_dalek::SIGNATURE_LENGTH;
+
+/// Public description of a node identity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeIdentityInfo {
+    /// Stable node identifier derived from the public key
+    pub node_id: String,
+    /// Instance ID from the configuration
+    pub instance_id: String,
+    /// Ed25519 public key, hex encoded
+    pub public_key: String,
+    /// Fingerprint of the public key
+    pub fingerprint: String,
+    /// Version of the identity key in the key store
+    pub key_version: u32,
+}
+
+/// The identity of this node, including its signing key
+pub struct NodeIdentity {
+    /// Stable node identifier derived from the public key
+    node_id: String,
+    /// Instance ID from the configuration
+    instance_id: String,
+    /// Version of the identity key in the key store
+    key_version: u32,
+    /// Private signing key
+    signing_key: SigningKey,
+}
+
+impl NodeIdentity {
+    /// Load the node identity from the key store
+    ///
+    /// Returns `AegisError::NotFound` if no identity key has been generated.
+    pub fn load(keystore: &KeyStore, instance_id: &str) -> AegisResult<Self> {
+        let material = keystore.active_key(IDENTITY_KEY_NAME)?;
+
+        if material.algorithm != KeyAlgorithm::Ed25519 {
+            return Err(AegisError::Security(format!(
+                "Identity key has algorithm {}, expected ed25519",
+                material.algorithm
+            )));
+        }
+
+        let seed: [u8; 32] = material
+            .bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| AegisError::Security("Invalid identity key length".to_string()))?;
+
+        Ok(Self::from_signing_key(
+            SigningKey::from_bytes(&seed),
+            instance_id,
+            material.version,
+        ))
+    }
+
+    /// Load the node identity, generating a new one if none exists
+    pub fn load_or_create(keystore: &KeyStore, instance_id: &str) -> AegisResult<Self> {
+        if !keystore.contains(IDENTITY_KEY_NAME) {
+            keystore.generate(IDENTITY_KEY_NAME, KeyAlgorithm::Ed25519)?;
+        }
+
+        Self::load(keystore, instance_id)
+    }
+
+    /// Create an identity from an existing signing key
+    pub fn from_signing_key(signing_key: SigningKey, instance_id: &str, key_version: u32) -> Self {
+        Self {
+            node_id: node_id_for(signing_key.verifying_key().as_bytes()),
+            instance_id: instance_id.to_string(),
+            key_version,
+            signing_key,
+        }
+    }
+
+    /// Stable node identifier
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// Instance ID from the configuration
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Ed25519 public key bytes
+    pub fn public_key(&self) -> [u8; 32] {
+        self.signing_key.verifying_key().to_bytes()
+    }
+
+    /// Underlying signing key
+    ///
+    /// Exposed so that other components (such as certificate issuance) can
+    /// use the node key without reading it from the key store again.
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.signing_key
+    }
+
+    /// Sign a message with the node key
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.signing_key.sign(message).to_bytes().to_vec()
+    }
+
+    /// Public description of this identity
+    pub fn info(&self) -> NodeIdentityInfo {
+        let public_key = self.public_key();
+
+        NodeIdentityInfo {
+            node_id: self.node_id.clone(),
+            instance_id: self.instance_id.clone(),
+            public_key: format_hex(&public_key),
+            fingerprint: key_fingerprint(&public_key),
+            key_version: self.key_version,
+        }
+    }
+}
+
+impl fmt::Debug for NodeIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the signing key
+        f.debug_struct("NodeIdentity")
+            .field("node_id", &self.node_id)
+            .field("instance_id", &self.instance_id)
+            .field("key_version", &self.key_version)
+            .finish()
+    }
+}
+
+/// Derive the node ID for an Ed25519 public key
+pub fn node_id_for(public_key: &[u8]) -> String {
+    format!("node-{}", format_hex(&sha256_hash(public_key)[..8]))
+}
+
+/// Verify a signature made by a node identity
+pub fn verify_signature(public_key: &[u8], message: &[u8], signature: &[u8]) -> AegisResult<()> {
+    let public_key: [u8; 32] = public_key
+        .try_into()
+        .map_err(|_| AegisError::Security("Invalid public key length".to_string()))?;
+    let verifying_key = VerifyingKey::from_bytes(&public_key)
+        .map_err(|e| AegisError::Security(format!("Invalid public key: {}", e)))?;
+    let signature = Signature::from_slice(signature)
+        .map_err(|e| AegisError::Security(format!("Invalid signature: {}", e)))?;
+
+    verifying_key
+        .verify(message, &signature)
+        .map_err(|_| AegisError::Security("Signature verification failed".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_or_create_is_stable() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyStore::open(dir.path()).unwrap();
+
+        assert!(matches!(
+            NodeIdentity::load(&store, "aegis-test"),
+            Err(AegisError::NotFound(_))
+        ));
+
+        let first = NodeIdentity::load_or_create(&store, "aegis-test").unwrap();
+        let second = NodeIdentity::load(&store, "aegis-test").unwrap();
+
+        assert_eq!(first.node_id(), second.node_id());
+        assert!(first.node_id().starts_with("node-"));
+        assert_eq!(first.info().key_version, 1);
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyStore::open(dir.path()).unwrap();
+        let identity = NodeIdentity::load_or_create(&store, "aegis-test").unwrap();
+
+        let signature = identity.sign(b"payload");
+        assert_eq!(signature.len(), SIGNATURE_LENGTH);
+
+        assert!(verify_signature(&identity.public_key(), b"payload", &signature).is_ok());
+        assert!(verify_signature(&identity.public_key(), b"tampered", &signature).is_err());
+    }
+
+    #[test]
+    fn test_rejects_non_ed25519_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyStore::open(dir.path()).unwrap();
+        store.generate(IDENTITY_KEY_NAME, KeyAlgorithm::Aes256Gcm).unwrap();
+
+        assert!(matches!(
+            NodeIdentity::load(&store, "aegis-test"),
+            Err(AegisError::Security(_))
+        ));
+    }
+}
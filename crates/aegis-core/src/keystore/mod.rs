@@ -0,0 +1,467 @@
+//! File-based key storage for the Aegis framework
+//!
+//! Keys are stored as one JSON file per key name under the configured
+//! `security.keys_path` (or `<base_dir>/keys` when unset). Every key keeps its
+//! full version history so that data encrypted with a retired version can
+//! still be decrypted after rotation.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::clock::{system_clock, Clock};
+use crate::config::AegisConfig;
+use crate::error::{AegisError, AegisResult};
+use crate::utils::{format_hex, parse_hex, random_bytes, sha256_hash};
+
+/// File extension used for key files
+const KEY_FILE_EXTENSION: &str = "key.json";
+
+/// Algorithm a key is intended for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyAlgorithm {
+    /// 256-bit symmetric key for AES-GCM
+    Aes256Gcm,
+    /// Ed25519 signing key seed
+    Ed25519,
+}
+
+impl KeyAlgorithm {
+    /// Length of the key material in bytes
+    pub fn key_len(&self) -> usize {
+        match self {
+            KeyAlgorithm::Aes256Gcm => 32,
+            KeyAlgorithm::Ed25519 => 32,
+        }
+    }
+}
+
+impl fmt::Display for KeyAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyAlgorithm::Aes256Gcm => write!(f, "aes256gcm"),
+            KeyAlgorithm::Ed25519 => write!(f, "ed25519"),
+        }
+    }
+}
+
+impl std::str::FromStr for KeyAlgorithm {
+    type Err = AegisError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "aes256gcm" | "aes-256-gcm" => Ok(KeyAlgorithm::Aes256Gcm),
+            "ed25519" => Ok(KeyAlgorithm::Ed25519),
+            other => Err(AegisError::Config(format!("Unknown key algorithm: {}", other))),
+        }
+    }
+}
+
+/// A single version of a stored key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyVersion {
+    /// Version number, starting at 1
+    version: u32,
+    /// When this version was created
+    created_at: String,
+    /// When this version was retired by a rotation
+    retired_at: Option<String>,
+    /// Key material, hex encoded
+    material: String,
+}
+
+/// On-disk representation of a key and its history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyRecord {
+    /// Key name
+    name: String,
+    /// Key algorithm
+    algorithm: KeyAlgorithm,
+    /// Currently active version
+    active_version: u32,
+    /// All versions of the key, oldest first
+    versions: Vec<KeyVersion>,
+}
+
+/// Public information about a key, without the key material
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyInfo {
+    /// Key name
+    pub name: String,
+    /// Key algorithm
+    pub algorithm: KeyAlgorithm,
+    /// Active version number
+    pub version: u32,
+    /// Number of versions, including retired ones
+    pub version_count: usize,
+    /// SHA-256 fingerprint of the active key material (first 16 bytes, hex)
+    pub fingerprint: String,
+    /// When the active version was created
+    pub created_at: String,
+}
+
+/// Key material for a specific key version
+#[derive(Clone)]
+pub struct KeyMaterial {
+    /// Key name
+    pub name: String,
+    /// Key algorithm
+    pub algorithm: KeyAlgorithm,
+    /// Version number
+    pub version: u32,
+    /// Raw key bytes
+    pub bytes: Vec<u8>,
+}
+
+impl fmt::Debug for KeyMaterial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the key bytes
+        f.debug_struct("KeyMaterial")
+            .field("name", &self.name)
+            .field("algorithm", &self.algorithm)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+/// Compute the fingerprint used to identify key material
+pub fn key_fingerprint(material: &[u8]) -> String {
+    format_hex(&sha256_hash(material)[..16])
+}
+
+/// File-based key store
+#[derive(Debug, Clone)]
+pub struct KeyStore {
+    /// Directory containing the key files
+    root: PathBuf,
+    /// Clock used for key timestamps
+    clock: Arc<dyn Clock>,
+}
+
+impl KeyStore {
+    /// Open a key store rooted at the given directory, creating it if needed
+    pub fn open(root: impl Into<PathBuf>) -> AegisResult<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        restrict_permissions(&root, 0o700)?;
+
+        Ok(Self {
+            root,
+            clock: system_clock(),
+        })
+    }
+
+    /// Open the key store configured in `security.keys_path`
+    pub fn from_config(config: &AegisConfig) -> AegisResult<Self> {
+        Self::open(keys_dir(config))
+    }
+
+    /// Replace the clock used for key timestamps
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Directory containing the key files
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Check whether a key with the given name exists
+    pub fn contains(&self, name: &str) -> bool {
+        self.key_path(name).map(|p| p.exists()).unwrap_or(false)
+    }
+
+    /// Generate a new key
+    ///
+    /// Fails if a key with the same name already exists; use
+    /// [`KeyStore::rotate`] to replace an existing key.
+    pub fn generate(&self, name: &str, algorithm: KeyAlgorithm) -> AegisResult<KeyInfo> {
+        if self.contains(name) {
+            return Err(AegisError::Config(format!("Key already exists: {}", name)));
+        }
+
+        let record = KeyRecord {
+            name: name.to_string(),
+            algorithm,
+            active_version: 1,
+            versions: vec![self.new_version(1, algorithm)],
+        };
+
+        self.write_record(&record)?;
+        Ok(info_for(&record))
+    }
+
+    /// Rotate a key, making a freshly generated version the active one
+    ///
+    /// Previous versions are kept and marked as retired.
+    pub fn rotate(&self, name: &str) -> AegisResult<KeyInfo> {
+        let mut record = self.read_record(name)?;
+        let now = self.clock.now_rfc3339();
+
+        for version in record.versions.iter_mut().filter(|v| v.retired_at.is_none()) {
+            version.retired_at = Some(now.clone());
+        }
+
+        let next = record.versions.iter().map(|v| v.version).max().unwrap_or(0) + 1;
+        record.versions.push(self.new_version(next, record.algorithm));
+        record.active_version = next;
+
+        self.write_record(&record)?;
+        Ok(info_for(&record))
+    }
+
+    /// List all keys in the store, sorted by name
+    pub fn list(&self) -> AegisResult<Vec<KeyInfo>> {
+        let mut keys = Vec::new();
+
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            let is_key_file = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .map(|n| n.ends_with(&format!(".{}", KEY_FILE_EXTENSION)))
+                .unwrap_or(false);
+
+            if is_key_file {
+                let record: KeyRecord = serde_json::from_str(&fs::read_to_string(&path)?)?;
+                keys.push(info_for(&record));
+            }
+        }
+
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(keys)
+    }
+
+    /// Get information about a key
+    pub fn info(&self, name: &str) -> AegisResult<KeyInfo> {
+        Ok(info_for(&self.read_record(name)?))
+    }
+
+    /// Get the material of the active version of a key
+    pub fn active_key(&self, name: &str) -> AegisResult<KeyMaterial> {
+        let record = self.read_record(name)?;
+        material_for(&record, record.active_version)
+    }
+
+    /// Get the material of a specific version of a key
+    pub fn key_version(&self, name: &str, version: u32) -> AegisResult<KeyMaterial> {
+        material_for(&self.read_record(name)?, version)
+    }
+
+    /// Create a new key version with random material
+    fn new_version(&self, version: u32, algorithm: KeyAlgorithm) -> KeyVersion {
+        KeyVersion {
+            version,
+            created_at: self.clock.now_rfc3339(),
+            retired_at: None,
+            material: format_hex(&random_bytes(algorithm.key_len())),
+        }
+    }
+
+    /// Path of the file storing a key
+    fn key_path(&self, name: &str) -> AegisResult<PathBuf> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+            && !name.starts_with('.');
+
+        if !valid {
+            return Err(AegisError::Config(format!("Invalid key name: {:?}", name)));
+        }
+
+        Ok(self.root.join(format!("{}.{}", name, KEY_FILE_EXTENSION)))
+    }
+
+    /// Read a key record from disk
+    fn read_record(&self, name: &str) -> AegisResult<KeyRecord> {
+        let path = self.key_path(name)?;
+        if !path.exists() {
+            return Err(AegisError::NotFound(format!("Key not found: {}", name)));
+        }
+
+        let content = fs::read_to_string(&path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Write a key record to disk with owner-only permissions
+    fn write_record(&self, record: &KeyRecord) -> AegisResult<()> {
+        let path = self.key_path(&record.name)?;
+        let tmp_path = path.with_extension("tmp");
+        let content = serde_json::to_string_pretty(record)?;
+
+        write_private_file(&tmp_path, content.as_bytes())?;
+        fs::rename(&tmp_path, &path)?;
+
+        Ok(())
+    }
+}
+
+/// Directory holding the keys for a configuration
+pub fn keys_dir(config: &AegisConfig) -> PathBuf {
+    config
+        .security
+        .keys_path
+        .clone()
+        .unwrap_or_else(|| config.base_dir.join("keys"))
+}
+
+/// Build the public key information for a record
+fn info_for(record: &KeyRecord) -> KeyInfo {
+    let active = record
+        .versions
+        .iter()
+        .find(|v| v.version == record.active_version);
+
+    KeyInfo {
+        name: record.name.clone(),
+        algorithm: record.algorithm,
+        version: record.active_version,
+        version_count: record.versions.len(),
+        fingerprint: active
+            .and_then(|v| parse_hex(&v.material).ok())
+            .map(|bytes| key_fingerprint(&bytes))
+            .unwrap_or_default(),
+        created_at: active.map(|v| v.created_at.clone()).unwrap_or_default(),
+    }
+}
+
+/// Extract the material of a key version
+fn material_for(record: &KeyRecord, version: u32) -> AegisResult<KeyMaterial> {
+    let key_version = record
+        .versions
+        .iter()
+        .find(|v| v.version == version)
+        .ok_or_else(|| {
+            AegisError::NotFound(format!("Key {} has no version {}", record.name, version))
+        })?;
+
+    let bytes = parse_hex(&key_version.material)
+        .map_err(|e| AegisError::Security(format!("Corrupt key material for {}: {}", record.name, e)))?;
+
+    if bytes.len() != record.algorithm.key_len() {
+        return Err(AegisError::Security(format!(
+            "Key {} v{} has invalid length {}",
+            record.name, version, bytes.len()
+        )));
+    }
+
+    Ok(KeyMaterial {
+        name: record.name.clone(),
+        algorithm: record.algorithm,
+        version,
+        bytes,
+    })
+}
+
+/// Write a file readable only by the current user
+pub fn write_private_file(path: &Path, contents: &[u8]) -> AegisResult<()> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// Restrict the permissions of a path on platforms that support it
+#[cfg(unix)]
+fn restrict_permissions(path: &Path, mode: u32) -> AegisResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+/// Restrict the permissions of a path on platforms that support it
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path, _mode: u32) -> AegisResult<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+
+    fn test_store() -> (tempfile::TempDir, KeyStore) {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyStore::open(dir.path().join("keys"))
+            .unwrap()
+            .with_clock(Arc::new(TestClock::from_millis(0)));
+        (dir, store)
+    }
+
+    #[test]
+    fn test_generate_and_list() {
+        let (_dir, store) = test_store();
+
+        let info = store.generate("data", KeyAlgorithm::Aes256Gcm).unwrap();
+        assert_eq!(info.version, 1);
+        assert_eq!(info.fingerprint.len(), 32);
+        assert_eq!(info.created_at, "1970-01-01T00:00:00+00:00");
+
+        store.generate("identity", KeyAlgorithm::Ed25519).unwrap();
+
+        let keys = store.list().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].name, "data");
+        assert_eq!(keys[1].name, "identity");
+
+        assert!(store.generate("data", KeyAlgorithm::Aes256Gcm).is_err());
+    }
+
+    #[test]
+    fn test_rotate_keeps_old_versions() {
+        let (_dir, store) = test_store();
+
+        store.generate("data", KeyAlgorithm::Aes256Gcm).unwrap();
+        let v1 = store.active_key("data").unwrap();
+
+        let info = store.rotate("data").unwrap();
+        assert_eq!(info.version, 2);
+        assert_eq!(info.version_count, 2);
+
+        let v2 = store.active_key("data").unwrap();
+        assert_eq!(v2.version, 2);
+        assert_ne!(v1.bytes, v2.bytes);
+
+        let old = store.key_version("data", 1).unwrap();
+        assert_eq!(old.bytes, v1.bytes);
+    }
+
+    #[test]
+    fn test_missing_and_invalid_keys() {
+        let (_dir, store) = test_store();
+
+        assert!(matches!(store.active_key("missing"), Err(AegisError::NotFound(_))));
+        assert!(store.generate("../escape", KeyAlgorithm::Aes256Gcm).is_err());
+        assert!(store.generate("", KeyAlgorithm::Aes256Gcm).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_key_file_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (_dir, store) = test_store();
+        store.generate("data", KeyAlgorithm::Aes256Gcm).unwrap();
+
+        let path = store.root().join("data.key.json");
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
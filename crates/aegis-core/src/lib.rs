@@ -30,6 +30,18 @@ pub mod version;
 /// Logging functionality
 pub mod logging;
 
+/// Clock abstraction for wall-clock and monotonic time
+pub mod clock;
+
+/// File-based key storage
+pub mod keystore;
+
+/// Node identity and signing
+pub mod identity;
+
+/// JSON Schema generation
+pub mod schema;
+
 /// Common imports
 pub mod prelude;
 
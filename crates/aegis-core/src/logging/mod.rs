@@ -3,6 +3,7 @@
 //! This module provides structured logging functionality for the Aegis framework,
 //! allowing for consistent log formatting and filtering across all components.
 
+use crate::clock::Clock;
 use crate::config::{LoggingConfig, LogLevel, LogOutput};
 use crate::error::{AegisError, AegisResult, Severity};
 use std::path::Path;
@@ -124,8 +125,13 @@ pub struct LogEvent {
 impl LogEvent {
     /// Create a new log event
     pub fn new(level: LogLevel, message: String) -> Self {
+        Self::new_with_clock(&*crate::clock::system_clock(), level, message)
+    }
+    
+    /// Create a new log event timestamped by the given clock
+    pub fn new_with_clock(clock: &dyn Clock, level: LogLevel, message: String) -> Self {
         Self {
-            timestamp: crate::utils::current_timestamp(),
+            timestamp: crate::utils::timestamp_from(clock),
             level,
             message,
             module_path: None,
@@ -200,6 +206,14 @@ mod tests {
         assert!(json.contains("42"));
     }
     
+    #[test]
+    fn test_log_event_with_clock() {
+        let clock = crate::clock::TestClock::from_millis(0);
+        let event = LogEvent::new_with_clock(&clock, LogLevel::Warn, "Clocked".to_string());
+        
+        assert_eq!(event.timestamp, "1970-01-01T00:00:00+00:00");
+    }
+    
     #[test]
     fn test_log_event_with_context() {
         let event = LogEvent::new(LogLevel::Info, "Test with context".to_string())
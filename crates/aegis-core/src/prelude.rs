@@ -28,11 +28,26 @@ pub use crate::platform::{
     PlatformFactory,
 };
 
+// Re-export clock types
+pub use crate::clock::{
+    Clock,
+    SystemClock,
+    MonotonicClock,
+    TestClock,
+    system_clock,
+};
+
+// Re-export key store and identity types
+pub use crate::keystore::{KeyStore, KeyAlgorithm, KeyInfo};
+pub use crate::identity::{NodeIdentity, NodeIdentityInfo};
+
 // Re-export utility functions
 pub use crate::utils::{
     generate_uuid,
     current_timestamp,
     current_timestamp_ms,
+    timestamp_from,
+    timestamp_ms_from,
     to_json,
     from_json,
     to_yaml,
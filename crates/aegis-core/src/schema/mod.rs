@@ -0,0 +1,43 @@
+//! JSON Schema generation for Aegis data types
+//!
+//! Schemas are derived from the Rust types with `schemars`, so they always
+//! match what serde actually accepts, including enum tagging and renamed
+//! fields. Editors and CI can use them to check configuration files and
+//! message payloads before they reach a node.
+
+use schemars::JsonSchema;
+
+use crate::config::AegisConfig;
+
+/// Generate the JSON Schema for any type deriving `JsonSchema`
+pub fn schema_for<T: JsonSchema>() -> serde_json::Value {
+    let schema = schemars::schema_for!(T);
+    // RootSchema serialization cannot fail: it only contains JSON values
+    serde_json::to_value(schema).unwrap_or_default()
+}
+
+/// JSON Schema for `AegisConfig` files
+pub fn config_schema() -> serde_json::Value {
+    schema_for::<AegisConfig>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_schema() {
+        let schema = config_schema();
+
+        assert_eq!(schema["title"], "AegisConfig");
+        let properties = schema["properties"].as_object().unwrap();
+        for field in ["instance_id", "base_dir", "logging", "security", "network"] {
+            assert!(properties.contains_key(field), "missing {}", field);
+        }
+
+        // Enum tagging from serde must be reflected in the schema
+        let log_output = serde_json::to_string(&schema["definitions"]["LogOutput"]).unwrap();
+        assert!(log_output.contains("\"type\""));
+        assert!(log_output.contains("\"file\""));
+    }
+}
@@ -4,9 +4,9 @@
 //! throughout the framework for common tasks like string manipulation,
 //! serialization, cryptography and more.
 
+use crate::clock::{system_clock, Clock};
 use crate::error::{AegisError, AegisResult};
 use std::path::Path;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Generate a random UUID v4
 pub fn generate_uuid() -> String {
@@ -16,16 +16,22 @@ pub fn generate_uuid() -> String {
 
 /// Get the current timestamp as an ISO8601 formatted string
 pub fn current_timestamp() -> String {
-    use chrono::Utc;
-    Utc::now().to_rfc3339()
+    timestamp_from(&*system_clock())
 }
 
 /// Get the current timestamp as milliseconds since the Unix epoch
 pub fn current_timestamp_ms() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or(Duration::from_secs(0))
-        .as_millis() as u64
+    timestamp_ms_from(&*system_clock())
+}
+
+/// Get the current timestamp of the given clock as an ISO8601 formatted string
+pub fn timestamp_from(clock: &dyn Clock) -> String {
+    clock.now_rfc3339()
+}
+
+/// Get the current timestamp of the given clock as milliseconds since the Unix epoch
+pub fn timestamp_ms_from(clock: &dyn Clock) -> u64 {
+    clock.now_ms()
 }
 
 /// Serialize a struct to JSON string
@@ -205,6 +211,16 @@ mod tests {
         assert!(timestamp.contains('Z') || timestamp.contains('+'));
     }
 
+    #[test]
+    fn test_timestamp_from_test_clock() {
+        let clock = crate::clock::TestClock::from_millis(1_700_000_000_000);
+        assert_eq!(timestamp_ms_from(&clock), 1_700_000_000_000);
+        assert_eq!(timestamp_from(&clock), "2023-11-14T22:13:20+00:00");
+
+        clock.advance(std::time::Duration::from_secs(1));
+        assert_eq!(timestamp_ms_from(&clock), 1_700_000_001_000);
+    }
+
     #[test]
     fn test_json_serialization() {
         #[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug)]
@@ -9,8 +9,13 @@ use async_trait::async_trait;
 use bytes::Bytes;
 use tracing::{debug, error, info, warn};
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 
 use aegis_agent_framework::{AegisAgent, AgentStatus, AgentContext};
+use aegis_comms::{
+    Codec, CodecId, HealthEvent, HealthEvents, Membership, PeerInfo, ExtensionMessage, RpcContext, RpcRequest,
+    RpcServer, ServerHandle,
+};
 use aegis_core::error::{AegisError, AegisResult};
 use aegis_consensus::ConsensusClient;
 
@@ -18,7 +23,7 @@ use crate::policy::{Policy, PolicyState, PolicyStateCommand};
 use crate::recovery::{RecoveryPolicyEngine, FailureDetails, RecoveryAction};
 
 /// Message types for communication with the Camplit agent
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum CamplitMessage {
     /// Request to retrieve a policy
     GetPolicy { id: String },
@@ -36,8 +41,31 @@ pub enum CamplitMessage {
     GetRecoveryAction { failure: FailureDetails },
 }
 
+impl CamplitMessage {
+    /// Whether the message changes policy state and needs an admin peer
+    pub fn is_policy_mutation(&self) -> bool {
+        matches!(
+            self,
+            CamplitMessage::UpsertPolicy { .. }
+                | CamplitMessage::RemovePolicy { .. }
+                | CamplitMessage::EnablePolicy { .. }
+                | CamplitMessage::DisablePolicy { .. }
+        )
+    }
+}
+
+impl RpcRequest for CamplitMessage {
+    const METHOD: &'static str = "camplit";
+    // Policy rules and recovery parameters are free-form JSON
+    const CODEC: Option<CodecId> = Some(CodecId::Json);
+}
+
+impl ExtensionMessage for CamplitMessage {
+    const MESSAGE_TYPE: &'static str = "camplit";
+}
+
 /// Response message types from the Camplit agent
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum CamplitResponse {
     /// Response with a policy
     Policy { policy: Option<Policy> },
@@ -67,6 +95,15 @@ pub struct CamplitAgent {
     
     /// Recovery policy engine
     recovery_engine: Option<RecoveryPolicyEngine>,
+    
+    /// Peer identities allowed to change policies over the network
+    admin_identities: Vec<String>,
+    
+    /// Codec of messages passed to `handle_message`
+    codec: CodecId,
+    
+    /// Listener serving this agent, drained on shutdown
+    server: Option<ServerHandle>,
 }
 
 impl CamplitAgent {
@@ -78,6 +115,153 @@ impl CamplitAgent {
             consensus_client: None,
             policy_state: Arc::new(Mutex::new(PolicyState::new())),
             recovery_engine: None,
+            admin_identities: Vec::new(),
+            codec: CodecId::Json,
+            server: None,
+        }
+    }
+    
+    /// Set the peer identities allowed to change policies
+    ///
+    /// Entries are matched against the SPIFFE ID, SAN URIs, DNS names and
+    /// subject of the peer certificate.
+    pub fn with_admin_identities(mut self, identities: Vec<String>) -> Self {
+        self.admin_identities = identities;
+        self
+    }
+    
+    /// Set the codec of messages passed to `handle_message`
+    ///
+    /// Defaults to JSON, the format described by the message schemas.
+    pub fn with_codec(mut self, codec: CodecId) -> Self {
+        self.codec = codec;
+        self
+    }
+    
+    /// Set the listener serving this agent
+    ///
+    /// [`CamplitAgent::shutdown_shared`] stops it from accepting and waits for
+    /// in-flight messages; `shutdown` only stops it from accepting.
+    pub fn with_server(mut self, server: ServerHandle) -> Self {
+        self.server = Some(server);
+        self
+    }
+    
+    /// Handle a message received from a remote peer
+    ///
+    /// Policy changes are only accepted from peers whose verified identity is
+    /// listed in the admin identities; everything else is processed as for
+    /// local messages.
+    pub async fn handle_remote_message(&mut self, message: Bytes, peer: &PeerInfo) -> AegisResult<CamplitResponse> {
+        self.dispatch(message, Some(peer)).await
+    }
+    
+    /// Handle a request received over RPC
+    ///
+    /// Unlike [`CamplitAgent::handle_remote_message`], processing errors are
+    /// returned as errors so the caller receives them with their error code.
+    pub async fn handle_request(&mut self, message: CamplitMessage, peer: &PeerInfo) -> AegisResult<CamplitResponse> {
+        self.authorize(&message, Some(peer))?;
+        self.process_message(message).await
+    }
+    
+    /// Shut down a shared agent, letting in-flight requests finish first
+    ///
+    /// The listener is drained without holding the lock, since the handlers
+    /// registered by [`CamplitAgent::register_rpc`] need it to respond.
+    pub async fn shutdown_shared(agent: Arc<tokio::sync::Mutex<CamplitAgent>>) -> AegisResult<()> {
+        let server = {
+            let mut agent = agent.lock().await;
+            agent.status = AgentStatus::ShuttingDown;
+            agent.server.take()
+        };
+        if let Some(server) = server {
+            server.shutdown().await;
+        }
+        
+        agent.lock().await.shutdown().await
+    }
+    
+    /// Serve [`CamplitMessage`] requests on `server` with a shared agent
+    pub fn register_rpc(agent: Arc<tokio::sync::Mutex<CamplitAgent>>, server: &mut RpcServer) {
+        server.register(move |message: CamplitMessage, ctx: RpcContext| {
+            let agent = agent.clone();
+            async move { agent.lock().await.handle_request(message, &ctx.peer).await }
+        });
+    }
+    
+    /// Determine the recovery action for a failure
+    pub fn recover(&self, failure: &FailureDetails) -> AegisResult<RecoveryAction> {
+        let engine = self.recovery_engine.as_ref().ok_or_else(|| {
+            AegisError::Generic("Recovery engine not initialized".to_string())
+        })?;
+        engine.get_action_for_failure(failure)
+    }
+    
+    /// Apply recovery policies to agents the heartbeat monitor suspects have failed
+    ///
+    /// Runs until the monitor is dropped, so it should be spawned next to
+    /// the agent.
+    pub async fn watch_failures(agent: Arc<tokio::sync::Mutex<CamplitAgent>>, mut events: HealthEvents) {
+        while let Some(event) = events.recv().await {
+            match event {
+                HealthEvent::Suspected(suspected) => {
+                    let failure = FailureDetails::from(&suspected);
+                    warn!("Agent {} suspected failed (phi {:.1})", suspected.agent_id, suspected.phi);
+                    
+                    match agent.lock().await.recover(&failure) {
+                        Ok(action) => info!("Recovery action for {}: {}", failure.entity_id, action.description),
+                        Err(e) => error!("No recovery action for {}: {}", failure.entity_id, e),
+                    }
+                }
+                HealthEvent::Recovered { agent_id } => info!("Agent {} is sending heartbeats again", agent_id),
+                HealthEvent::Departed { agent_id } => info!("Agent {} shut down", agent_id),
+            }
+        }
+    }
+    
+    /// Keep the consensus members in step with the cluster membership
+    ///
+    /// The consensus group is set to this node and every active member
+    /// whenever membership changes. Runs until the task is aborted, so it
+    /// should be spawned next to the agent.
+    pub async fn watch_membership(agent: Arc<tokio::sync::Mutex<CamplitAgent>>, membership: Membership) {
+        let mut events = membership.subscribe();
+        loop {
+            let members = std::iter::once(membership.local())
+                .chain(membership.active_members())
+                .map(|member| member.id);
+            match &agent.lock().await.consensus_client {
+                Some(consensus) => consensus.set_members(members),
+                None => warn!("Membership changed before the consensus client was created"),
+            }
+            
+            match events.recv().await {
+                Some(event) => debug!("Membership changed: {:?}", event),
+                None => break,
+            }
+        }
+    }
+    
+    /// Check that the sender of a message may perform it
+    ///
+    /// Messages delivered in-process (`peer` is `None`) are trusted.
+    fn authorize(&self, message: &CamplitMessage, peer: Option<&PeerInfo>) -> AegisResult<()> {
+        let peer = match peer {
+            Some(peer) if message.is_policy_mutation() => peer,
+            _ => return Ok(()),
+        };
+        
+        match &peer.identity {
+            Some(identity) if identity.matches_any(&self.admin_identities) => Ok(()),
+            Some(identity) => Err(AegisError::Security(format!(
+                "peer {} at {} is not an authorised admin node",
+                identity, peer.addr
+            ))),
+            None => Err(AegisError::Security(format!(
+                "policy changes from {} require an authenticated peer",
+                peer.addr
+            ))),
         }
     }
     
@@ -162,6 +346,39 @@ impl CamplitAgent {
         }
     }
     
+    /// Decode, authorize and process a message from a local or remote sender
+    async fn dispatch(&mut self, message: Bytes, peer: Option<&PeerInfo>) -> AegisResult<CamplitResponse> {
+        debug!("Received message of {} bytes", message.len());
+        
+        // Deserialize the message
+        let camplit_message: CamplitMessage = match self.codec.decode(&message) {
+            Ok(msg) => msg,
+            Err(e) => {
+                error!("Failed to deserialize message: {}", e);
+                return Err(e.into());
+            }
+        };
+        
+        debug!("Processing message: {:?}", camplit_message);
+        
+        // Reject policy changes from peers that are not admin nodes
+        if let Err(e) = self.authorize(&camplit_message, peer) {
+            warn!("Rejected message: {}", e);
+            return Err(e);
+        }
+        
+        // Process the message and generate a response
+        match self.process_message(camplit_message).await {
+            Ok(response) => Ok(response),
+            Err(e) => {
+                error!("Error processing message: {}", e);
+                Ok(CamplitResponse::Error {
+                    message: format!("Error processing message: {}", e),
+                })
+            }
+        }
+    }
+    
     /// Update policy state from consensus
     async fn update_policy_state_from_consensus(&mut self) -> AegisResult<()> {
         if let Some(consensus) = &self.consensus_client {
@@ -264,7 +481,11 @@ impl AegisAgent for CamplitAgent {
         // Update status
         self.status = AgentStatus::ShuttingDown;
         
-        // Perform cleanup
+        // In-flight handlers may be waiting for the agent the caller holds,
+        // so only stop accepting; shutdown_shared waits for them
+        if let Some(server) = self.server.take() {
+            server.stop();
+        }
         
         // Update status
         self.status = AgentStatus::Stopped;
@@ -275,46 +496,9 @@ impl AegisAgent for CamplitAgent {
     }
     
     async fn handle_message(&mut self, message: Bytes) -> AegisResult<()> {
-        debug!("Received message of {} bytes", message.len());
-        
-        // Deserialize the message
-        let camplit_message: CamplitMessage = match serde_json::from_slice(&message) {
-            Ok(msg) => msg,
-            Err(e) => {
-                error!("Failed to deserialize message: {}", e);
-                return Err(AegisError::Serialization(e));
-            }
-        };
-        
-        debug!("Processing message: {:?}", camplit_message);
-        
-        // Process the message and generate a response
-        let response = match self.process_message(camplit_message).await {
-            Ok(response) => response,
-            Err(e) => {
-                error!("Error processing message: {}", e);
-                CamplitResponse::Error {
-                    message: format!("Error processing message: {}", e),
-                }
-            }
-        };
-        
-        // If we have a context, send the response
-        if let Some(context) = &self.context {
-            // Serialize the response
-            let response_bytes = match serde_json::to_vec(&response) {
-                Ok(bytes) => Bytes::from(bytes),
-                Err(e) => {
-                    error!("Failed to serialize response: {}", e);
-                    return Err(AegisError::Serialization(e));
-                }
-            };
-            
-            // TODO: Send the response to the appropriate destination
-            // This would involve getting the sender from the message and using
-            // the comms_client to send the response
-        }
-        
+        // In-process senders have no reply channel; use RPC for responses
+        let response = self.dispatch(message, None).await?;
+        debug!("Processed local message: {:?}", response);
         Ok(())
     }
     
@@ -327,4 +511,93 @@ impl Default for CamplitAgent {
     fn default() -> Self {
         Self::new()
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aegis_comms::{PeerIdentity, TransportAddr};
+
+    fn peer(identity: Option<&str>) -> PeerInfo {
+        PeerInfo {
+            addr: TransportAddr::Tcp("10.0.0.2:7000".parse().unwrap()),
+            identity: identity.map(|id| PeerIdentity {
+                subject: "node".to_string(),
+                dns_names: Vec::new(),
+                uris: vec![id.to_string()],
+                spiffe_id: Some(id.to_string()),
+                fingerprint: String::new(),
+            }),
+            credentials: None,
+        }
+    }
+
+    fn upsert() -> CamplitMessage {
+        CamplitMessage::UpsertPolicy {
+            policy: Policy {
+                id: "p1".to_string(),
+                name: "Test".to_string(),
+                description: "Test policy".to_string(),
+                version: "1.0".to_string(),
+                created_at: "2023-01-01T00:00:00Z".to_string(),
+                updated_at: "2023-01-01T00:00:00Z".to_string(),
+                priority: crate::policy::PolicyPriority::Medium,
+                scope: crate::policy::PolicyScope::Global,
+                rules: serde_json::json!({}),
+                enabled: true,
+            },
+        }
+    }
+
+    #[test]
+    fn test_authorize_policy_mutations() {
+        let agent = CamplitAgent::new()
+            .with_admin_identities(vec!["spiffe://aegis/admin".to_string()]);
+
+        // Local messages and admin peers may change policies
+        assert!(agent.authorize(&upsert(), None).is_ok());
+        assert!(agent.authorize(&upsert(), Some(&peer(Some("spiffe://aegis/admin")))).is_ok());
+
+        // Other and unauthenticated peers may not
+        assert!(matches!(
+            agent.authorize(&upsert(), Some(&peer(Some("spiffe://aegis/worker")))),
+            Err(AegisError::Security(_))
+        ));
+        assert!(matches!(
+            agent.authorize(&upsert(), Some(&peer(None))),
+            Err(AegisError::Security(_))
+        ));
+
+        // Reads are allowed from anyone
+        assert!(agent.authorize(&CamplitMessage::GetAllPolicies, Some(&peer(None))).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_handle_request_returns_errors() {
+        let mut agent = CamplitAgent::new();
+
+        // Rejections surface as errors rather than error responses
+        assert!(matches!(
+            agent.handle_request(upsert(), &peer(None)).await,
+            Err(AegisError::Security(_))
+        ));
+        assert!(matches!(
+            agent.handle_request(CamplitMessage::GetAllPolicies, &peer(None)).await,
+            Ok(CamplitResponse::Policies { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_messages_use_configured_codec() {
+        let mut agent = CamplitAgent::new().with_codec(CodecId::MessagePack);
+        let message = Bytes::from(CodecId::MessagePack.encode(&CamplitMessage::GetAllPolicies).unwrap());
+        assert!(matches!(
+            agent.handle_remote_message(message, &peer(None)).await,
+            Ok(CamplitResponse::Policies { .. })
+        ));
+
+        // JSON is no longer understood
+        let json = Bytes::from(serde_json::to_vec(&CamplitMessage::GetAllPolicies).unwrap());
+        assert!(agent.handle_remote_message(json, &peer(None)).await.is_err());
+    }
+}
@@ -7,8 +7,9 @@
 mod policy;
 mod recovery;
 mod agent;
+mod schema;
 
-pub use agent::CamplitAgent;
+pub use agent::{CamplitAgent, CamplitMessage, CamplitResponse};
 pub use policy::{Policy, PolicyState, PolicyStateCommand, PolicyScope, PolicyPriority};
 pub use recovery::{
     RecoveryPolicyEngine,
@@ -18,4 +19,10 @@ pub use recovery::{
     FailureDetails,
     FailureEntityType,
     FailureSeverity,
-}; 
\ No newline at end of file
+};
+pub use schema::{
+    policy_schema,
+    failure_details_schema,
+    camplit_message_schema,
+    camplit_response_schema,
+};
@@ -5,6 +5,7 @@
 
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 use aegis_core::error::AegisResult;
 use aegis_consensus::StateMachine;
 
@@ -12,7 +13,7 @@ use aegis_consensus::StateMachine;
 pub type PolicyId = String;
 
 /// Priority level for a policy
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
 pub enum PolicyPriority {
     /// Lowest priority
     Low,
@@ -25,7 +26,7 @@ pub enum PolicyPriority {
 }
 
 /// Target scope for a policy
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum PolicyScope {
     /// Policy applies to all agents
     Global,
@@ -36,7 +37,7 @@ pub enum PolicyScope {
 }
 
 /// Policy definition
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct Policy {
     /// Unique identifier for the policy
     pub id: PolicyId,
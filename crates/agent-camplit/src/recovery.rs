@@ -4,12 +4,14 @@
 //! actions to take in response to failures in the system.
 
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 use std::sync::Arc;
+use aegis_comms::{AgentStatus, SuspectedFailure};
 use aegis_core::error::AegisResult;
 use crate::policy::{Policy, PolicyState};
 
 /// Type of component or system that failed
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum FailureEntityType {
     /// Agent failure
     Agent,
@@ -26,7 +28,7 @@ pub enum FailureEntityType {
 }
 
 /// Severity of a failure
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum FailureSeverity {
     /// Low severity - can continue operation with minor impact
     Low,
@@ -39,7 +41,7 @@ pub enum FailureSeverity {
 }
 
 /// Detailed information about a failure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FailureDetails {
     /// ID of the entity that failed
     pub entity_id: String,
@@ -57,8 +59,37 @@ pub struct FailureDetails {
     pub context: serde_json::Value,
 }
 
+impl From<&SuspectedFailure> for FailureDetails {
+    /// Describe an agent whose heartbeats stopped arriving
+    fn from(suspected: &SuspectedFailure) -> Self {
+        // A draining agent was already handing off its work
+        let severity = match suspected.last_status {
+            AgentStatus::Draining => FailureSeverity::Medium,
+            _ => FailureSeverity::High,
+        };
+        
+        Self {
+            entity_id: suspected.agent_id.clone(),
+            entity_type: FailureEntityType::Agent,
+            timestamp: suspected.detected_at.clone(),
+            severity,
+            error_code: Some("HEARTBEAT_TIMEOUT".to_string()),
+            error_message: format!(
+                "No heartbeat from agent {} for {} ms",
+                suspected.agent_id,
+                suspected.silence.as_millis()
+            ),
+            context: serde_json::json!({
+                "phi": suspected.phi,
+                "last_status": format!("{:?}", suspected.last_status),
+                "silence_ms": suspected.silence.as_millis() as u64,
+            }),
+        }
+    }
+}
+
 /// Type of recovery action to take
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum RecoveryActionType {
     /// No action required
     NoAction,
@@ -79,7 +110,7 @@ pub enum RecoveryActionType {
 }
 
 /// Priority level for a recovery action
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
 pub enum RecoveryPriority {
     /// Low priority - can be deferred
     Low,
@@ -92,7 +123,7 @@ pub enum RecoveryPriority {
 }
 
 /// Recovery action to take in response to a failure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RecoveryAction {
     /// Type of action to take
     pub action_type: RecoveryActionType,
@@ -238,4 +269,33 @@ mod tests {
         assert_eq!(action.priority, RecoveryPriority::Normal);
         assert_eq!(action.target_entity_id, failure.entity_id);
     }
+    
+    #[test]
+    fn test_failure_from_suspected_heartbeat() {
+        let suspected = SuspectedFailure {
+            agent_id: "reviezer".to_string(),
+            phi: 12.5,
+            last_status: AgentStatus::Active,
+            silence: std::time::Duration::from_secs(5),
+            detected_at: "2023-01-01T00:00:05+00:00".to_string(),
+        };
+        
+        let failure = FailureDetails::from(&suspected);
+        assert_eq!(failure.entity_id, "reviezer");
+        assert_eq!(failure.entity_type, FailureEntityType::Agent);
+        assert_eq!(failure.severity, FailureSeverity::High);
+        assert_eq!(failure.timestamp, suspected.detected_at);
+        assert_eq!(failure.context["silence_ms"], 5000);
+        
+        let engine = RecoveryPolicyEngine::new(Arc::new(create_test_policy_state()));
+        let action = engine.get_action_for_failure(&failure).unwrap();
+        assert_eq!(action.action_type, RecoveryActionType::Failover);
+        assert_eq!(action.target_entity_id, "reviezer");
+        
+        let draining = SuspectedFailure {
+            last_status: AgentStatus::Draining,
+            ..suspected
+        };
+        assert_eq!(FailureDetails::from(&draining).severity, FailureSeverity::Medium);
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,52 @@
+//! JSON Schemas for Camplit policies and messages
+//!
+//! These schemas describe the exact JSON accepted by `CamplitAgent::handle_message`,
+//! including serde's externally tagged enum representation.
+
+use aegis_core::schema::schema_for;
+
+use crate::agent::{CamplitMessage, CamplitResponse};
+use crate::policy::Policy;
+use crate::recovery::FailureDetails;
+
+/// JSON Schema for a `Policy`
+pub fn policy_schema() -> serde_json::Value {
+    schema_for::<Policy>()
+}
+
+/// JSON Schema for `FailureDetails`
+pub fn failure_details_schema() -> serde_json::Value {
+    schema_for::<FailureDetails>()
+}
+
+/// JSON Schema for requests sent to the Camplit agent
+pub fn camplit_message_schema() -> serde_json::Value {
+    schema_for::<CamplitMessage>()
+}
+
+/// JSON Schema for responses sent by the Camplit agent
+pub fn camplit_response_schema() -> serde_json::Value {
+    schema_for::<CamplitResponse>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_schema() {
+        let schema = policy_schema();
+        assert_eq!(schema["title"], "Policy");
+        assert!(schema["required"].as_array().unwrap().contains(&"rules".into()));
+    }
+
+    #[test]
+    fn test_message_schema_lists_variants() {
+        let schema = serde_json::to_string(&camplit_message_schema()).unwrap();
+        for variant in ["GetPolicy", "GetAllPolicies", "UpsertPolicy", "GetRecoveryAction"] {
+            assert!(schema.contains(variant), "missing {}", variant);
+        }
+        // Nested types are included as definitions
+        assert!(schema.contains("FailureDetails"));
+    }
+}
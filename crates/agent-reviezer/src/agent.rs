@@ -9,13 +9,28 @@ use async_trait::async_trait;
 use bytes::Bytes;
 use tracing::{debug, error, info, warn};
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 use chrono::{DateTime, Utc};
 
 use aegis_agent_framework::{AegisAgent, AgentStatus, AgentContext};
+use aegis_comms::{
+    AgentDiscoveryMessage, Codec, CodecId, DiscoveryConfig, DiscoveryService, Endpoint, ExtensionMessage, RpcContext,
+    RpcRequest, RpcServer, ServerHandle,
+};
+use aegis_core::clock::system_clock;
 use aegis_core::error::{AegisError, AegisResult};
 
+/// Capability announced by agents that serve their logs to reviezer
+pub const LOG_SOURCE_CAPABILITY: &str = "logs";
+
+/// Capabilities reviezer announces through discovery
+const REVIEZER_CAPABILITIES: &[&str] = &["audit"];
+
+/// Time an agent has to answer a log request
+const LOG_REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 /// Log entry structure for analysis
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LogEntry {
     /// Timestamp of the log entry
     pub timestamp: DateTime<Utc>,
@@ -30,7 +45,7 @@ pub struct LogEntry {
 }
 
 /// Message types for communication with the Reviezer agent
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum ReviezerMessage {
     /// Request to retrieve logs from a specific agent
     RequestLogs {
@@ -67,8 +82,18 @@ pub enum ReviezerMessage {
     },
 }
 
+impl RpcRequest for ReviezerMessage {
+    const METHOD: &'static str = "reviezer";
+    // Log metadata is a serde_json::Value, which bincode cannot decode
+    const CODEC: Option<CodecId> = Some(CodecId::Json);
+}
+
+impl ExtensionMessage for ReviezerMessage {
+    const MESSAGE_TYPE: &'static str = "reviezer";
+}
+
 /// Response message types from the Reviezer agent
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum ReviezerResponse {
     /// Response with logs
     Logs {
@@ -110,6 +135,15 @@ pub struct ReviezerAgent {
     
     /// Maximum number of log entries to store per agent
     max_logs_per_agent: usize,
+    
+    /// Discovery service task, if discovery is enabled
+    discovery: Option<tokio::task::JoinHandle<()>>,
+    
+    /// Codec of messages passed to `handle_message`
+    codec: CodecId,
+    
+    /// Listener serving this agent, drained on shutdown
+    server: Option<ServerHandle>,
 }
 
 impl ReviezerAgent {
@@ -120,7 +154,70 @@ impl ReviezerAgent {
             context: None,
             logs: Arc::new(Mutex::new(HashMap::new())),
             max_logs_per_agent: 10000, // Store up to 10,000 log entries per agent
+            discovery: None,
+            codec: CodecId::Json,
+            server: None,
+        }
+    }
+    
+    /// Set the codec of messages passed to `handle_message`
+    ///
+    /// Defaults to JSON, the format described by the message schemas.
+    pub fn with_codec(mut self, codec: CodecId) -> Self {
+        self.codec = codec;
+        self
+    }
+    
+    /// Set the listener serving this agent
+    ///
+    /// [`ReviezerAgent::shutdown_shared`] stops it from accepting and waits for
+    /// in-flight messages; `shutdown` only stops it from accepting.
+    pub fn with_server(mut self, server: ServerHandle) -> Self {
+        self.server = Some(server);
+        self
+    }
+    
+    /// Current time according to the agent's clock
+    ///
+    /// Falls back to the system clock before the agent has been initialized.
+    fn now(&self) -> DateTime<Utc> {
+        match &self.context {
+            Some(ctx) => ctx.clock().now(),
+            None => system_clock().now(),
+        }
+    }
+    
+    /// Handle a request received over RPC
+    ///
+    /// Processing errors are returned as errors so the caller receives them
+    /// with their error code.
+    pub async fn handle_request(&mut self, message: ReviezerMessage) -> AegisResult<ReviezerResponse> {
+        self.process_message(message).await
+    }
+    
+    /// Shut down a shared agent, letting in-flight requests finish first
+    ///
+    /// The listener is drained without holding the lock, since the handlers
+    /// registered by [`ReviezerAgent::register_rpc`] need it to respond.
+    pub async fn shutdown_shared(agent: Arc<tokio::sync::Mutex<ReviezerAgent>>) -> AegisResult<()> {
+        let server = {
+            let mut agent = agent.lock().await;
+            agent.status = AgentStatus::ShuttingDown;
+            agent.server.take()
+        };
+        if let Some(server) = server {
+            server.shutdown().await;
         }
+        
+        agent.lock().await.shutdown().await
+    }
+    
+    /// Serve [`ReviezerMessage`] requests on `server` with a shared agent
+    pub fn register_rpc(agent: Arc<tokio::sync::Mutex<ReviezerAgent>>, server: &mut RpcServer) {
+        server.register(move |message: ReviezerMessage, _ctx: RpcContext| {
+            let agent = agent.clone();
+            async move { agent.lock().await.handle_request(message).await }
+        });
     }
     
     /// Process a message and generate a response
@@ -164,20 +261,7 @@ impl ReviezerAgent {
             },
             
             ReviezerMessage::SubmitLogs { source_agent_id, logs } => {
-                // Store the logs
-                let mut log_store = self.logs.lock().unwrap();
-                let agent_logs = log_store.entry(source_agent_id.clone()).or_insert_with(VecDeque::new);
-                
-                // Add new logs
-                for log in logs {
-                    agent_logs.push_back(log);
-                }
-                
-                // Trim logs if we exceed the maximum
-                while agent_logs.len() > self.max_logs_per_agent {
-                    agent_logs.pop_front();
-                }
-                
+                self.store_logs(&source_agent_id, logs);
                 Ok(ReviezerResponse::Success)
             },
             
@@ -192,7 +276,7 @@ impl ReviezerAgent {
                 let report = serde_json::json!({
                     "report_type": report_type,
                     "target": target,
-                    "generated_at": chrono::Utc::now().to_rfc3339(),
+                    "generated_at": self.now().to_rfc3339(),
                     "period": {
                         "start": start_time.map(|t| t.to_rfc3339()),
                         "end": end_time.map(|t| t.to_rfc3339()),
@@ -213,7 +297,38 @@ impl ReviezerAgent {
         }
     }
     
-    /// Request logs from a specific agent
+    /// Announce this agent and learn about others if discovery is enabled
+    ///
+    /// Discovered agents are recorded in the comms client's peer registry.
+    async fn start_discovery(&mut self, context: &AgentContext) -> AegisResult<()> {
+        let settings = &context.config.network.discovery;
+        if !settings.enabled {
+            return Ok(());
+        }
+        
+        let network = &context.config.network;
+        let listen_addr = match network.tls {
+            Some(_) => Endpoint::tls(network.host.clone(), network.port),
+            None => Endpoint::tcp(network.host.clone(), network.port),
+        };
+        let announcement = AgentDiscoveryMessage {
+            agent_id: context.agent_id.clone(),
+            capabilities: REVIEZER_CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+            listen_addr,
+        };
+        
+        let config = DiscoveryConfig::from_config(settings)
+            .map_err(|e| AegisError::Config(format!("Invalid discovery settings: {}", e)))?;
+        let service = DiscoveryService::bind(announcement, config, context.comms_client.peers().clone())
+            .await
+            .map_err(|e| AegisError::Communication(format!("Failed to start discovery: {}", e)))?;
+        
+        info!("Discovery started on {:?}", service.local_addr());
+        self.discovery = Some(service.spawn());
+        Ok(())
+    }
+    
+    /// Request logs from a specific agent and store the ones it returns
     async fn request_agent_logs(&self, agent_id: &str) -> AegisResult<()> {
         // Verify context is available
         let context = match &self.context {
@@ -226,28 +341,50 @@ impl ReviezerAgent {
         // Create a request message
         let request = ReviezerMessage::RequestLogs {
             agent_id: agent_id.to_string(),
-            start_time: Some(chrono::Utc::now() - chrono::Duration::hours(1)),
+            start_time: Some(context.clock().now() - chrono::Duration::hours(1)),
             end_time: None,
             limit: Some(100),
             level: None,
         };
         
-        // Serialize the request
-        let request_bytes = match serde_json::to_vec(&request) {
-            Ok(bytes) => Bytes::from(bytes),
+        let deadline = std::time::Instant::now() + LOG_REQUEST_TIMEOUT;
+        let response: ReviezerResponse = match context.comms_client.call(agent_id, &request, deadline).await {
+            Ok(response) => response,
             Err(e) => {
-                error!("Failed to serialize log request: {}", e);
-                return Err(AegisError::Serialization(e));
+                error!("Log request to {} failed: {}", agent_id, e);
+                return Err(e.into());
             }
         };
         
-        // Send the request
-        if let Err(e) = context.comms_client.send(agent_id, request_bytes).await {
-            error!("Failed to send log request to {}: {}", agent_id, e);
-            return Err(e);
+        match response {
+            ReviezerResponse::Logs { source_agent_id, logs } => {
+                debug!("Received {} log entries from {}", logs.len(), source_agent_id);
+                self.store_logs(&source_agent_id, logs);
+                Ok(())
+            }
+            ReviezerResponse::Error { message } => Err(AegisError::Communication(format!(
+                "{} could not provide logs: {}",
+                agent_id, message
+            ))),
+            other => Err(AegisError::Communication(format!(
+                "Unexpected response to log request from {}: {:?}",
+                agent_id, other
+            ))),
         }
+    }
+    
+    /// Store logs of an agent, dropping the oldest beyond the per-agent limit
+    fn store_logs(&self, source_agent_id: &str, logs: Vec<LogEntry>) {
+        let mut log_store = self.logs.lock().unwrap();
+        let agent_logs = log_store.entry(source_agent_id.to_string()).or_default();
         
-        Ok(())
+        // Add new logs
+        agent_logs.extend(logs);
+        
+        // Trim logs if we exceed the maximum
+        while agent_logs.len() > self.max_logs_per_agent {
+            agent_logs.pop_front();
+        }
     }
 }
 
@@ -256,6 +393,8 @@ impl AegisAgent for ReviezerAgent {
     async fn initialize(&mut self, context: AgentContext) -> AegisResult<()> {
         info!("Initializing Reviezer agent");
         
+        self.start_discovery(&context).await?;
+        
         // Store the context
         self.context = Some(context);
         
@@ -289,14 +428,14 @@ impl AegisAgent for ReviezerAgent {
             interval_count += 1;
             debug!("Reviezer agent heartbeat: {}", interval_count);
             
-            // Periodically request logs from other agents
-            // This is a simplified example - in a real implementation we would:
-            // 1. Maintain a list of active agents
-            // 2. Request logs on a staggered schedule
-            // 3. Process and analyze the logs
+            // Periodically request logs from the agents that serve them
             if interval_count % 10 == 0 {
-                // Example: request logs from a few known agents
-                for agent_id in &["manre_agent", "camplit_agent", "devopsi_agent"] {
+                let sources = context.comms_client.peers().with_capability(LOG_SOURCE_CAPABILITY);
+                if sources.is_empty() {
+                    debug!("No agents with the {} capability known yet", LOG_SOURCE_CAPABILITY);
+                }
+                
+                for agent_id in &sources {
                     if let Err(e) = self.request_agent_logs(agent_id).await {
                         warn!("Failed to request logs from {}: {}", agent_id, e);
                     }
@@ -320,7 +459,16 @@ impl AegisAgent for ReviezerAgent {
         // Update status
         self.status = AgentStatus::ShuttingDown;
         
-        // Perform cleanup
+        // Stop announcing this agent
+        if let Some(discovery) = self.discovery.take() {
+            discovery.abort();
+        }
+        
+        // In-flight handlers may be waiting for the agent the caller holds,
+        // so only stop accepting; shutdown_shared waits for them
+        if let Some(server) = self.server.take() {
+            server.stop();
+        }
         
         // Update status
         self.status = AgentStatus::Stopped;
@@ -334,11 +482,11 @@ impl AegisAgent for ReviezerAgent {
         debug!("Received message of {} bytes", message.len());
         
         // Deserialize the message
-        let reviezer_message: ReviezerMessage = match serde_json::from_slice(&message) {
+        let reviezer_message: ReviezerMessage = match self.codec.decode(&message) {
             Ok(msg) => msg,
             Err(e) => {
                 error!("Failed to deserialize message: {}", e);
-                return Err(AegisError::Serialization(e));
+                return Err(e.into());
             }
         };
         
@@ -355,21 +503,8 @@ impl AegisAgent for ReviezerAgent {
             }
         };
         
-        // If we have a context, send the response
-        if let Some(context) = &self.context {
-            // Serialize the response
-            let response_bytes = match serde_json::to_vec(&response) {
-                Ok(bytes) => Bytes::from(bytes),
-                Err(e) => {
-                    error!("Failed to serialize response: {}", e);
-                    return Err(AegisError::Serialization(e));
-                }
-            };
-            
-            // TODO: Send the response to the appropriate destination
-            // This would involve getting the sender from the message and using
-            // the comms_client to send the response
-        }
+        // In-process senders have no reply channel; use RPC for responses
+        debug!("Processed local message: {:?}", response);
         
         Ok(())
     }
@@ -4,14 +4,19 @@
 //! agent behavior, and security compliance.
 
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 use std::time::SystemTime;
 
+use aegis_core::clock::{system_clock, Clock};
+
 use crate::LogEntry;
 
 /// Represents the type of audit being performed
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, JsonSchema)]
 pub enum AuditType {
     /// Security audit for checking access controls and permissions
     Security,
@@ -26,7 +31,7 @@ pub enum AuditType {
 }
 
 /// Severity level of an audit finding
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, JsonSchema)]
 pub enum FindingSeverity {
     /// Informational finding, no action needed
     Info,
@@ -41,7 +46,7 @@ pub enum FindingSeverity {
 }
 
 /// Represents a single finding in an audit
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AuditFinding {
     /// Unique identifier for the finding
     pub id: String,
@@ -64,7 +69,7 @@ pub struct AuditFinding {
 }
 
 /// A recommendation from the audit process
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AuditRecommendation {
     /// Unique identifier for the recommendation
     pub id: String,
@@ -85,7 +90,7 @@ pub struct AuditRecommendation {
 }
 
 /// Comprehensive audit report
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AuditReport {
     /// Unique report identifier
     pub id: String,
@@ -117,6 +122,8 @@ pub struct AuditReport {
 pub struct AuditService {
     /// Analyzers used for different audit types
     analyzers: HashMap<AuditType, Box<dyn LogAnalyzer>>,
+    /// Clock used for report timestamps and IDs
+    clock: Arc<dyn Clock>,
 }
 
 /// Trait for analyzing logs and generating audit findings
@@ -138,11 +145,25 @@ pub struct AuditContext {
     pub target: String,
     /// Additional parameters for the audit
     pub parameters: HashMap<String, String>,
+    /// Clock used for finding IDs
+    pub clock: Arc<dyn Clock>,
+}
+
+impl AuditContext {
+    /// Get the clock used for this audit
+    pub fn clock(&self) -> &dyn Clock {
+        &*self.clock
+    }
 }
 
 impl AuditService {
     /// Create a new audit service with default analyzers
     pub fn new() -> Self {
+        Self::with_clock(system_clock())
+    }
+    
+    /// Create a new audit service with default analyzers and the given clock
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
         let mut analyzers = HashMap::new();
         
         // Add default analyzers
@@ -166,7 +187,7 @@ impl AuditService {
             Box::new(AgentBehaviorAnalyzer::new()) as Box<dyn LogAnalyzer>
         );
         
-        Self { analyzers }
+        Self { analyzers, clock }
     }
     
     /// Register a new log analyzer
@@ -190,6 +211,7 @@ impl AuditService {
             end_time,
             target: target.clone(),
             parameters: HashMap::new(),
+            clock: self.clock.clone(),
         };
         
         // Generate findings using the appropriate analyzer
@@ -211,11 +233,11 @@ impl AuditService {
         
         // Create the report
         AuditReport {
-            id: generate_report_id(),
+            id: generate_report_id(&*self.clock),
             audit_type: audit_type.clone(),
             title: format!("{:?} Audit for {}", audit_type, target),
             target,
-            generated_at: Utc::now(),
+            generated_at: self.clock.now(),
             period_start: start_time,
             period_end: end_time,
             summary: self.generate_summary(&findings, &recommendations),
@@ -234,7 +256,7 @@ impl AuditService {
         for log in logs {
             if log.level.eq_ignore_ascii_case("error") {
                 findings.push(AuditFinding {
-                    id: generate_finding_id(),
+                    id: generate_finding_id(context.clock()),
                     title: "Error Log Detected".to_string(),
                     description: format!("Error log detected: {}", log.message),
                     severity: FindingSeverity::Medium,
@@ -246,7 +268,7 @@ impl AuditService {
                 });
             } else if log.level.eq_ignore_ascii_case("warn") || log.level.eq_ignore_ascii_case("warning") {
                 findings.push(AuditFinding {
-                    id: generate_finding_id(),
+                    id: generate_finding_id(context.clock()),
                     title: "Warning Log Detected".to_string(),
                     description: format!("Warning log detected: {}", log.message),
                     severity: FindingSeverity::Low,
@@ -289,7 +311,7 @@ impl AuditService {
             
             if critical_count > 0 {
                 recommendations.push(AuditRecommendation {
-                    id: generate_recommendation_id(),
+                    id: generate_recommendation_id(&*self.clock),
                     title: format!("Address Critical Issues in {}", entity),
                     description: format!(
                         "There are {} critical issues that need immediate attention in {}.",
@@ -309,7 +331,7 @@ impl AuditService {
             
             if high_count > 0 {
                 recommendations.push(AuditRecommendation {
-                    id: generate_recommendation_id(),
+                    id: generate_recommendation_id(&*self.clock),
                     title: format!("Address High Severity Issues in {}", entity),
                     description: format!(
                         "There are {} high severity issues that need prompt attention in {}.",
@@ -434,7 +456,7 @@ impl LogAnalyzer for SecurityLogAnalyzer {
             for keyword in &security_keywords {
                 if log.message.to_lowercase().contains(&keyword.to_lowercase()) {
                     findings.push(AuditFinding {
-                        id: generate_finding_id(),
+                        id: generate_finding_id(context.clock()),
                         title: format!("Security Issue: {}", keyword),
                         description: format!("Security keyword '{}' found in log: {}", keyword, log.message),
                         severity: match *keyword {
@@ -487,7 +509,7 @@ impl LogAnalyzer for PerformanceLogAnalyzer {
             for keyword in &performance_keywords {
                 if log.message.to_lowercase().contains(&keyword.to_lowercase()) {
                     findings.push(AuditFinding {
-                        id: generate_finding_id(),
+                        id: generate_finding_id(context.clock()),
                         title: format!("Performance Issue: {}", keyword),
                         description: format!("Performance keyword '{}' found in log: {}", keyword, log.message),
                         severity: match *keyword {
@@ -538,7 +560,7 @@ impl LogAnalyzer for ComplianceLogAnalyzer {
             for keyword in &compliance_keywords {
                 if log.message.to_lowercase().contains(&keyword.to_lowercase()) {
                     findings.push(AuditFinding {
-                        id: generate_finding_id(),
+                        id: generate_finding_id(context.clock()),
                         title: format!("Compliance Issue: {}", keyword),
                         description: format!("Compliance keyword '{}' found in log: {}", keyword, log.message),
                         severity: match *keyword {
@@ -590,7 +612,7 @@ impl LogAnalyzer for AgentBehaviorAnalyzer {
             for keyword in &behavior_keywords {
                 if log.message.to_lowercase().contains(&keyword.to_lowercase()) {
                     findings.push(AuditFinding {
-                        id: generate_finding_id(),
+                        id: generate_finding_id(context.clock()),
                         title: format!("Agent Behavior Issue: {}", keyword),
                         description: format!("Agent behavior keyword '{}' found in log: {}", keyword, log.message),
                         severity: match *keyword {
@@ -617,19 +639,33 @@ impl LogAnalyzer for AgentBehaviorAnalyzer {
     }
 }
 
+/// Sequence number appended to generated IDs
+///
+/// The clock alone is not enough to keep IDs unique: several IDs are usually
+/// generated within the same nanosecond, and a `TestClock` does not move at all
+/// unless the test advances it.
+static ID_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Generate an ID from a prefix, the clock and the ID sequence
+fn generate_id(prefix: &str, clock: &dyn Clock) -> String {
+    let nanos = clock.now().timestamp_nanos_opt().unwrap_or_default();
+    let seq = ID_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    format!("{}_{}_{}", prefix, nanos, seq)
+}
+
 /// Generate a unique ID for an audit report
-fn generate_report_id() -> String {
-    format!("report_{}", chrono::Utc::now().timestamp_nanos())
+fn generate_report_id(clock: &dyn Clock) -> String {
+    generate_id("report", clock)
 }
 
 /// Generate a unique ID for an audit finding
-fn generate_finding_id() -> String {
-    format!("finding_{}", chrono::Utc::now().timestamp_nanos())
+fn generate_finding_id(clock: &dyn Clock) -> String {
+    generate_id("finding", clock)
 }
 
 /// Generate a unique ID for an audit recommendation
-fn generate_recommendation_id() -> String {
-    format!("rec_{}", chrono::Utc::now().timestamp_nanos())
+fn generate_recommendation_id(clock: &dyn Clock) -> String {
+    generate_id("rec", clock)
 }
 
 /// Represents a log entry from any agent in the system
@@ -740,6 +776,7 @@ mod tests {
             end_time: Some(now),
             target: "test-system".to_string(),
             parameters: HashMap::new(),
+            clock: system_clock(),
         };
         
         let findings = analyzer.analyze(&logs, &context);
@@ -784,6 +821,31 @@ mod tests {
         assert!(report.risk_score.is_some());
     }
 
+    #[test]
+    fn test_audit_service_uses_clock() {
+        let clock = aegis_core::clock::TestClock::from_millis(1_700_000_000_000);
+        let service = AuditService::with_clock(Arc::new(clock.clone()));
+        
+        let first = service.generate_report(
+            AuditType::Security,
+            "test-system".to_string(),
+            &[],
+            None,
+            None,
+        );
+        let second = service.generate_report(
+            AuditType::Security,
+            "test-system".to_string(),
+            &[],
+            None,
+            None,
+        );
+        
+        assert_eq!(first.generated_at, clock.now());
+        assert!(first.id.starts_with("report_1700000000000000000_"));
+        assert_ne!(first.id, second.id);
+    }
+
     #[test]
     fn test_log_analyzer_basic() {
         let analyzer = LogAnalyzer::new();
@@ -1,4 +1,10 @@
+mod agent;
 mod audit;
+mod schema;
+
+pub use agent::{ReviezerMessage, ReviezerResponse, LOG_SOURCE_CAPABILITY};
+pub use audit::AuditReport;
+pub use schema::{reviezer_message_schema, reviezer_response_schema, audit_report_schema};
 
 use aegis_agent_framework::{
     agent::{AegisAgent, AgentContext, AgentError, AgentResult},
@@ -24,9 +30,9 @@ pub struct ReviezerAgent {
 impl ReviezerAgent {
     pub fn new(context: AgentContext, comms: Arc<CommsClient>) -> Self {
         Self {
+            audit_service: AuditService::with_clock(context.clock.clone()),
             context,
             comms,
-            audit_service: AuditService::new(),
         }
     }
 
@@ -40,12 +46,13 @@ impl ReviezerAgent {
 
     async fn process_received_logs(&self, logs: Vec<AgentLogEntry>) -> AgentResult<AuditRecord> {
         // Create audit context
-        let now = Utc::now();
+        let now = self.context.clock().now();
         let context = AuditContext {
             start_time: Some(now - chrono::Duration::hours(1)),
             end_time: Some(now),
             target: self.context.agent_id().to_string(),
             parameters: HashMap::new(),
+            clock: self.context.clock.clone(),
         };
 
         // Generate comprehensive audit report using the audit service
@@ -70,7 +77,7 @@ impl ReviezerAgent {
 
         // Convert findings to AuditRecord format
         Ok(AuditRecord {
-            timestamp: std::time::SystemTime::now(),
+            timestamp: now.into(),
             agent_id: self.context.agent_id().to_string(),
             findings: report.findings.into_iter().map(|f| audit::AuditFinding {
                 severity: match f.severity {
@@ -169,7 +176,7 @@ mod tests {
         let agent = ReviezerAgent::new(context, comms);
         
         let logs = vec![AgentLogEntry {
-            timestamp: std::time::SystemTime::now(),
+            timestamp: agent.context().clock().now().into(),
             agent_id: "test_agent".to_string(),
             level: LogLevel::Error,
             message: "Test error".to_string(),
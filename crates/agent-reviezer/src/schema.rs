@@ -0,0 +1,46 @@
+//! JSON Schemas for Reviezer messages and audit reports
+//!
+//! These schemas describe the exact JSON accepted by `ReviezerAgent::handle_message`
+//! and produced in audit reports.
+
+use aegis_core::schema::schema_for;
+
+use crate::agent::{ReviezerMessage, ReviezerResponse};
+use crate::audit::AuditReport;
+
+/// JSON Schema for requests sent to the Reviezer agent
+pub fn reviezer_message_schema() -> serde_json::Value {
+    schema_for::<ReviezerMessage>()
+}
+
+/// JSON Schema for responses sent by the Reviezer agent
+pub fn reviezer_response_schema() -> serde_json::Value {
+    schema_for::<ReviezerResponse>()
+}
+
+/// JSON Schema for an `AuditReport`
+pub fn audit_report_schema() -> serde_json::Value {
+    schema_for::<AuditReport>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_schema_lists_variants() {
+        let schema = serde_json::to_string(&reviezer_message_schema()).unwrap();
+        for variant in ["RequestLogs", "SubmitLogs", "RequestAuditReport"] {
+            assert!(schema.contains(variant), "missing {}", variant);
+        }
+        // Timestamps are described as RFC 3339 strings
+        assert!(schema.contains("date-time"));
+    }
+
+    #[test]
+    fn test_audit_report_schema() {
+        let schema = audit_report_schema();
+        assert_eq!(schema["title"], "AuditReport");
+        assert!(schema["properties"]["findings"].is_object());
+    }
+}